@@ -0,0 +1,56 @@
+//! Grouping ports into rows on a switch where most ports are LACP members
+use std::collections::HashSet;
+use std::hint::black_box;
+use std::sync::Arc;
+use criterion::{criterion_group, criterion_main, Criterion};
+use switch_vlan_diagram::grouping::{GroupMode, Grouping};
+use switch_vlan_diagram::{LacpInfo, PortConfig};
+
+/// `lags` aggregators of `members` ports each, every one carrying `vlans`
+/// tagged VLANs. With `shared`, members hold one copy of their aggregator's
+/// VLANs as the collector builds them; otherwise each member has its own.
+fn trunk_heavy_switch(lags: u32, members: u32, vlans: u32, shared: bool) -> Vec<PortConfig> {
+    let mut ports = Vec::new();
+    for lag in 0..lags {
+        // Neighbouring aggregators differ only in their last VLAN
+        let tagged: HashSet<u32> = (1..vlans).chain([vlans + lag]).collect();
+        let untagged = HashSet::from([1]);
+        let agg_vlans = Arc::new((tagged.clone(), untagged.clone()));
+        for member in 0..members {
+            let port_num = lag * members + member + 1;
+            ports.push(PortConfig {
+                port_num,
+                pvid: Some(1),
+                vlan_memberships: tagged.clone(),
+                untagged_vlans: untagged.clone(),
+                lacp_info: Some(LacpInfo {
+                    selected_agg_id: 1000 + lag,
+                    agg_name: Some(format!("Trk{}", lag + 1)),
+                    agg_vlans: Some(if shared { Arc::clone(&agg_vlans) } else { Arc::new((*agg_vlans).clone()) }),
+                    load_balance: None,
+                }),
+                uplink: true,
+                ..PortConfig::default()
+            });
+        }
+    }
+    ports
+}
+
+/// 12 aggregators of 8 members with 500 VLANs, as on a distribution switch
+fn trunk_heavy(c: &mut Criterion) {
+    let mut group = c.benchmark_group("group/96 LACP members, 500 VLANs");
+    for (name, shared) in [("shared aggregator VLANs", true), ("copied aggregator VLANs", false)] {
+        let ports = trunk_heavy_switch(12, 8, 500, shared);
+        for mode in [GroupMode::Adjacent, GroupMode::Dedupe] {
+            let grouping = Grouping { mode, ..Grouping::default() };
+            group.bench_function(format!("{:?}, {}", mode, name), |b| {
+                b.iter(|| grouping.group(black_box(&ports)))
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, trunk_heavy);
+criterion_main!(benches);
@@ -0,0 +1,40 @@
+//! Decoding Q-BRIDGE PortLists and assembling port memberships from them
+#[path = "../tests/generators/mod.rs"]
+mod generators;
+
+use std::hint::black_box;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use switch_vlan_diagram::snmp_utils::decode_port_list;
+use switch_vlan_diagram::{port_in_list, vlan_sets};
+use generators::{port_list, sample, vlan_bitmaps};
+
+/// 64 bytes cover a 512-port chassis, 512 bytes the full 4096-port PortList
+fn port_lists(c: &mut Criterion) {
+    let mut group = c.benchmark_group("port_list");
+    for len in [64, 512] {
+        let list = sample(port_list(len));
+        // A port in the middle of the list
+        let port = len as u32 * 4;
+        group.bench_with_input(BenchmarkId::new("decode_port_list", len), &list, |b, list| {
+            b.iter(|| decode_port_list(black_box(list)))
+        });
+        group.bench_with_input(BenchmarkId::new("port_in_list", len), &list, |b, list| {
+            b.iter(|| port_in_list(black_box(port), black_box(list)))
+        });
+    }
+    group.finish();
+}
+
+fn memberships(c: &mut Criterion) {
+    let (egress, untagged) = sample(vlan_bitmaps(96, 500));
+    c.bench_function("vlan_sets/96 ports, 500 VLANs", |b| {
+        b.iter(|| {
+            for port in 1..=96 {
+                black_box(vlan_sets(port, black_box(&egress), black_box(&untagged)));
+            }
+        })
+    });
+}
+
+criterion_group!(benches, port_lists, memberships);
+criterion_main!(benches);
@@ -0,0 +1,16 @@
+use std::process::Command;
+
+/// Record `git describe` for report footers when building from a git checkout
+fn main() {
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/index");
+    let describe = Command::new("git")
+        .args(["describe", "--tags", "--always", "--dirty"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok());
+    if let Some(describe) = describe.map(|d| d.trim().to_string()).filter(|d| !d.is_empty()) {
+        println!("cargo:rustc-env=SWITCH_VLAN_DOC_GIT_DESCRIBE={}", describe);
+    }
+}
@@ -0,0 +1,172 @@
+use std::collections::{BTreeMap, HashMap};
+use std::net::Ipv4Addr;
+use anyhow::{Result, anyhow};
+use crate::PortRange;
+use crate::snapshot::{Snapshot, VirtualInterface};
+
+/// Pseudonymous addresses come from the benchmarking range 198.18.0.0/15,
+/// which no site routes
+const PSEUDONYM_NETWORK: Ipv4Addr = Ipv4Addr::new(198, 18, 0, 0);
+
+/// Replaces site-specific names with pseudonyms for --anonymize and remembers
+/// what each pseudonym stands for
+#[derive(Debug, Default)]
+pub struct Anonymizer {
+    /// Pseudonym -> the original names it replaced
+    mapping: BTreeMap<String, Vec<String>>,
+    /// Original -> pseudonymous address, so that each address keeps one pseudonym
+    addresses: BTreeMap<Ipv4Addr, Ipv4Addr>,
+}
+
+impl Anonymizer {
+    /// Stable pseudonym for a device, derived from its address so the same
+    /// switch gets the same name in every run
+    pub fn device(&mut self, ip: &str, names: &[&str]) -> String {
+        // FNV-1a; std's hashers are not guaranteed to be stable between releases
+        let hash = ip.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+        let pseudonym = format!("switch-{:08x}", hash as u32);
+        for original in std::iter::once(ip).chain(names.iter().copied()) {
+            self.remember(&pseudonym, original);
+        }
+        pseudonym
+    }
+
+    pub fn alias(&mut self, port: u32, alias: &str) -> String {
+        let pseudonym = format!("port-{}-label", port);
+        self.remember(&pseudonym, alias);
+        pseudonym
+    }
+
+    pub fn vlan_name(&mut self, vlan_id: u32, name: &str) -> String {
+        let pseudonym = format!("vlan-{}", vlan_id);
+        self.remember(&pseudonym, name);
+        pseudonym
+    }
+
+    pub fn vtp_domain(&mut self, domain: &str) -> String {
+        let pseudonym = "vtp-domain".to_string();
+        self.remember(&pseudonym, domain);
+        pseudonym
+    }
+
+    pub fn address(&mut self, address: Ipv4Addr) -> Ipv4Addr {
+        let next = u32::from(PSEUDONYM_NETWORK) + 1 + self.addresses.len() as u32;
+        let pseudonym = *self.addresses.entry(address).or_insert(Ipv4Addr::from(next));
+        self.remember(&pseudonym.to_string(), &address.to_string());
+        pseudonym
+    }
+
+    fn remember(&mut self, pseudonym: &str, original: &str) {
+        if !original.is_empty() && original != pseudonym {
+            let originals = self.mapping.entry(pseudonym.to_string()).or_default();
+            if !originals.iter().any(|known| known == original) {
+                originals.push(original.to_string());
+            }
+        }
+    }
+
+    pub fn ranges(&mut self, ranges: &mut [PortRange]) {
+        for range in ranges {
+            for (port, alias) in &mut range.port_aliases {
+                *alias = self.alias(*port, alias);
+            }
+            if let Some(alias) = &range.alias {
+                range.alias = Some(self.alias(range.first_port, alias));
+            }
+        }
+    }
+
+    /// ifNames such as "Vlan10" say little, but addresses locate the site
+    pub fn virtual_interfaces(&mut self, interfaces: &mut [VirtualInterface]) {
+        for interface in interfaces {
+            if let Some(name) = &interface.name {
+                let pseudonym = format!("interface-{}", interface.index);
+                self.remember(&pseudonym, name);
+                interface.name = Some(pseudonym);
+            }
+            for address in &mut interface.addresses {
+                *address = self.address(*address);
+            }
+        }
+    }
+
+    pub fn vlan_names(&mut self, vlan_names: &mut HashMap<u32, String>) {
+        for (&vlan_id, name) in vlan_names.iter_mut() {
+            *name = self.vlan_name(vlan_id, name);
+        }
+    }
+
+    /// The snapshot goes through the same mapping, so JSON and tables agree
+    pub fn snapshot(&mut self, snapshot: &mut Snapshot, device: &str) {
+        snapshot.device.ip = device.to_string();
+        snapshot.device.sys_name = snapshot.device.sys_name.as_ref().map(|_| device.to_string());
+        snapshot.device.sys_descr = None;
+        // Neighbors name other devices, which have no pseudonyms of their own
+        snapshot.device.chassis_id = None;
+        snapshot.neighbors.clear();
+        for port in &mut snapshot.ports {
+            if let Some(alias) = &port.alias {
+                port.alias = Some(self.alias(port.port, alias));
+            }
+        }
+        for (&vlan_id, name) in snapshot.vlan_names.iter_mut() {
+            *name = self.vlan_name(vlan_id, name);
+        }
+        self.virtual_interfaces(&mut snapshot.virtual_interfaces);
+        snapshot.update_config_hashes();
+    }
+
+    /// Rewrite quoted names in free text such as warnings, e.g. `alias "Studio A"`
+    pub fn text(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for (pseudonym, originals) in &self.mapping {
+            for original in originals {
+                text = text.replace(&format!("\"{}\"", original), &format!("\"{}\"", pseudonym));
+            }
+        }
+        text
+    }
+
+    /// The pseudonym -> originals mapping as JSON
+    pub fn mapping_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(&self.mapping)
+            .map_err(|e| anyhow!("Failed to serialize the pseudonym mapping: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pseudonyms_are_stable_and_reversible() {
+        let mut anonymizer = Anonymizer::default();
+        let device = anonymizer.device("10.1.0.23", &["sw-acc-4"]);
+        assert_eq!(device, Anonymizer::default().device("10.1.0.23", &[]));
+        assert_ne!(device, Anonymizer::default().device("10.1.0.24", &[]));
+
+        assert_eq!(anonymizer.alias(12, "Studio A"), "port-12-label");
+        assert_eq!(anonymizer.vlan_name(10, "office"), "vlan-10");
+        assert_eq!(anonymizer.text("port(s) 12, 14: alias \"Studio A\" is used more than once"),
+            "port(s) 12, 14: alias \"port-12-label\" is used more than once");
+        assert_eq!(anonymizer.mapping[&device], ["10.1.0.23", "sw-acc-4"]);
+    }
+
+    #[test]
+    fn addresses_keep_one_pseudonym_each() {
+        let mut anonymizer = Anonymizer::default();
+        let mut interfaces = vec![VirtualInterface {
+            index: 100,
+            name: Some("Vlan10".to_string()),
+            if_type: 136,
+            addresses: vec![Ipv4Addr::new(10, 0, 10, 1), Ipv4Addr::new(10, 0, 10, 2)],
+        }];
+        anonymizer.virtual_interfaces(&mut interfaces);
+        assert_eq!(interfaces[0].name.as_deref(), Some("interface-100"));
+        assert_eq!(interfaces[0].addresses, [Ipv4Addr::new(198, 18, 0, 1), Ipv4Addr::new(198, 18, 0, 2)]);
+        assert_eq!(anonymizer.address(Ipv4Addr::new(10, 0, 10, 2)), Ipv4Addr::new(198, 18, 0, 2));
+        assert_eq!(anonymizer.mapping["198.18.0.1"], ["10.0.10.1"]);
+    }
+}
@@ -0,0 +1,27 @@
+//! Cutting a collection short, e.g. on SIGINT. Once cancelled, requests not
+//! yet sent to a live agent answer as if it had nothing more, so that the
+//! collection finishes at once with the rows fetched so far.
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static CANCELLED: AtomicBool = AtomicBool::new(false);
+static SIGNALLED: AtomicBool = AtomicBool::new(false);
+
+/// Cancel every collection in progress and to come
+pub fn cancel() {
+    CANCELLED.store(true, Ordering::SeqCst);
+}
+
+pub fn is_cancelled() -> bool {
+    CANCELLED.load(Ordering::SeqCst)
+}
+
+/// Note that a signal arrived without cancelling anything. It breaks off the
+/// wait for the answer in flight, which is then asked for once more.
+pub fn signalled() {
+    SIGNALLED.store(true, Ordering::SeqCst);
+}
+
+/// Whether a signal arrived since the last call
+pub(crate) fn take_signal() -> bool {
+    SIGNALLED.swap(false, Ordering::SeqCst)
+}
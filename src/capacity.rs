@@ -0,0 +1,68 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use crate::{is_default_config, PortConfig};
+
+/// What makes a port count as free in the capacity report
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FreeCriterion {
+    /// ifAdminStatus is down
+    AdminDown,
+    /// Default configuration in the default (or quarantine) VLAN
+    Default,
+}
+
+impl FromStr for FreeCriterion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "admin-down" => Ok(FreeCriterion::AdminDown),
+            "default" => Ok(FreeCriterion::Default),
+            _ => Err(format!("Unknown free-port criterion '{}'. Expected: admin-down or default", s)),
+        }
+    }
+}
+
+/// Free and used port counts for one switch
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct CapacityReport {
+    pub free_ports: Vec<u32>,
+    /// Used access ports per untagged VLAN
+    pub access_vlans: BTreeMap<u32, usize>,
+    /// Used ports that are trunks or LACP members
+    pub other_used: usize,
+}
+
+impl CapacityReport {
+    pub fn new(ports: &[PortConfig], criteria: &[FreeCriterion], default_vlan: u32) -> CapacityReport {
+        let mut report = CapacityReport::default();
+        for port in ports {
+            let free = criteria.iter().any(|criterion| match criterion {
+                FreeCriterion::AdminDown => port.admin_down,
+                FreeCriterion::Default => is_default_config(port, default_vlan),
+            });
+            if free {
+                report.free_ports.push(port.port_num);
+            } else if let Some(&vlan_id) = access_vlan(port) {
+                *report.access_vlans.entry(vlan_id).or_default() += 1;
+            } else {
+                report.other_used += 1;
+            }
+        }
+        report.free_ports.sort_unstable();
+        report
+    }
+}
+
+/// The single untagged VLAN of an access port
+fn access_vlan(port: &PortConfig) -> Option<&u32> {
+    if port.lacp_info.is_some() || port.untagged_vlans.len() != 1 {
+        return None;
+    }
+    let vlan_id = port.untagged_vlans.iter().next()?;
+    port.vlan_memberships.iter()
+        .all(|v| v == vlan_id)
+        .then_some(vlan_id)
+}
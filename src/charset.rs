@@ -0,0 +1,169 @@
+//! Decoding of DisplayStrings such as ifAlias and VLAN names. Agents return
+//! whatever bytes were configured: usually UTF-8, sometimes a legacy
+//! encoding, and after the odd firmware bug, binary garbage.
+use std::fmt;
+use std::str::FromStr;
+
+/// Legacy encoding to try for strings that are not valid UTF-8
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// ISO-8859-1: every byte is the code point of the same number
+    Latin1,
+}
+
+impl FromStr for Encoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "latin1" | "latin-1" | "iso-8859-1" => Ok(Encoding::Latin1),
+            _ => Err(format!("Unknown encoding '{}'. Expected: latin1", s)),
+        }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Encoding::Latin1 => f.write_str("latin1"),
+        }
+    }
+}
+
+impl Encoding {
+    fn decode(self, bytes: &[u8]) -> String {
+        match self {
+            Encoding::Latin1 => bytes.iter().map(|&b| char::from(b)).collect(),
+        }
+    }
+}
+
+/// An OctetString as the agent sent it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DisplayString(pub Vec<u8>);
+
+/// How a DisplayString turned into text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decoded {
+    Utf8(String),
+    /// Not UTF-8, but printable in the fallback encoding
+    Fallback(String),
+    /// Printable in no encoding tried; the bytes as e.g. "0x6162ff"
+    Hex(String),
+}
+
+impl Decoded {
+    pub fn into_string(self) -> String {
+        match self {
+            Decoded::Utf8(text) | Decoded::Fallback(text) | Decoded::Hex(text) => text,
+        }
+    }
+}
+
+impl DisplayString {
+    /// UTF-8 if the bytes are printable UTF-8, then `fallback`, then hex
+    pub fn decode(&self, fallback: Option<Encoding>) -> Decoded {
+        // Some agents pad or NUL-terminate their strings
+        let end = self.0.iter().rposition(|&b| b != 0).map_or(0, |last| last + 1);
+        let bytes = &self.0[..end];
+        if let Ok(text) = std::str::from_utf8(bytes) {
+            if is_printable(text) {
+                return Decoded::Utf8(text.to_string());
+            }
+        }
+        if let Some(text) = fallback.map(|encoding| encoding.decode(bytes)).filter(|text| is_printable(text)) {
+            return Decoded::Fallback(text);
+        }
+        let hex: String = self.0.iter().map(|b| format!("{:02x}", b)).collect();
+        Decoded::Hex(format!("0x{}", hex))
+    }
+}
+
+/// No control characters other than whitespace such as tabs and line breaks,
+/// which `normalize_whitespace` deals with
+fn is_printable(text: &str) -> bool {
+    text.chars().all(|c| c.is_whitespace() || !c.is_control())
+}
+
+/// Trim, collapse runs of whitespace into one space and drop ASCII control
+/// characters, e.g. the tabs and carriage returns of aliases pasted from a spreadsheet
+pub fn normalize_whitespace(text: &str) -> String {
+    let mut normalized = String::with_capacity(text.len());
+    for word in text.split(|c: char| c.is_whitespace()).filter(|word| !word.is_empty()) {
+        let word: String = word.chars().filter(|c| !c.is_ascii_control()).collect();
+        if word.is_empty() {
+            continue;
+        }
+        if !normalized.is_empty() {
+            normalized.push(' ');
+        }
+        normalized.push_str(&word);
+    }
+    normalized
+}
+
+/// Field sizes devices commonly cut ifAlias to, in bytes
+const ALIAS_LIMITS: [usize; 3] = [32, 48, 64];
+
+/// An alias exactly as long as a common field size that ends mid-word was
+/// probably cut short when it was typed into the switch
+pub fn likely_truncated(alias: &str) -> bool {
+    ALIAS_LIMITS.contains(&alias.len()) && alias.chars().last().is_some_and(char::is_alphanumeric)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn utf8_is_preferred_over_the_fallback() {
+        let alias = DisplayString("Sävel".as_bytes().to_vec());
+        assert_eq!(alias.decode(Some(Encoding::Latin1)), Decoded::Utf8("Sävel".to_string()));
+        assert_eq!(DisplayString(b"desk 1\0\0".to_vec()).decode(None), Decoded::Utf8("desk 1".to_string()));
+    }
+
+    #[test]
+    fn latin1_is_used_only_when_asked_for() {
+        let alias = DisplayString(b"S\xe4vel".to_vec());
+        assert_eq!(alias.decode(Some(Encoding::Latin1)), Decoded::Fallback("Sävel".to_string()));
+        assert_eq!(alias.decode(None), Decoded::Hex("0x53e476656c".to_string()));
+    }
+
+    #[test]
+    fn garbage_is_shown_as_hex() {
+        // C1 control characters are not printable in Latin-1 either
+        let garbage = DisplayString(vec![0x61, 0x62, 0xff, 0x01, 0x85]);
+        assert_eq!(garbage.decode(Some(Encoding::Latin1)), Decoded::Hex("0x6162ff0185".to_string()));
+        assert_eq!(DisplayString(b"ab\x07".to_vec()).decode(None), Decoded::Hex("0x616207".to_string()));
+    }
+
+    #[test]
+    fn whitespace_is_trimmed_collapsed_and_controls_dropped() {
+        assert_eq!(normalize_whitespace("  desk 1\t\r\n"), "desk 1");
+        assert_eq!(normalize_whitespace("studio \t  mixer"), "studio mixer");
+        assert_eq!(normalize_whitespace("core\x07 \x1b uplink"), "core uplink");
+        assert_eq!(normalize_whitespace(" \r\n"), "");
+        assert_eq!(normalize_whitespace("Sävel"), "Sävel");
+    }
+
+    #[test]
+    fn line_breaks_do_not_make_text_unprintable() {
+        assert_eq!(DisplayString(b"desk 1\r\n".to_vec()).decode(None), Decoded::Utf8("desk 1\r\n".to_string()));
+    }
+
+    #[test]
+    fn aliases_cut_at_a_field_size_mid_word_look_truncated() {
+        assert!(likely_truncated("studio 2 mixer desk, left side m"));
+        assert!(likely_truncated(&"a".repeat(64)));
+        // Ends on a word boundary, or is not a known field size
+        assert!(!likely_truncated("studio 2 mixer desk, left side ("));
+        assert!(!likely_truncated("studio 2 mixer desk, left side"));
+        assert!(!likely_truncated(""));
+    }
+
+    #[test]
+    fn encodings_parse_case_insensitively() {
+        assert_eq!("ISO-8859-1".parse::<Encoding>(), Ok(Encoding::Latin1));
+        assert!("koi8-r".parse::<Encoding>().is_err());
+    }
+}
@@ -0,0 +1,392 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+use crate::PortConfig;
+use crate::warnings::Warning;
+
+/// Consistency checks run on the collected port configuration
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Check {
+    /// The PVID is not one of the port's untagged VLANs
+    PvidNotUntagged,
+    /// An untagged VLAN is missing from the port's egress list
+    UntaggedNotEgress,
+    /// Members of one aggregator disagree on their own VLAN configuration
+    TrunkMemberMismatch,
+    /// The port is in no egress list and has no PVID
+    NoVlans,
+    /// A named VLAN that no port or LAG interface carries
+    OrphanVlan,
+    /// Several ports outside one aggregator share an alias
+    DuplicateAlias,
+    /// Switches polled together give one VLAN id different names
+    VlanNameConflict,
+    /// A port configured otherwise than in the `--desired` file
+    Drift,
+}
+
+impl Check {
+    pub const ALL: [Check; 8] = [
+        Check::PvidNotUntagged, Check::UntaggedNotEgress, Check::TrunkMemberMismatch, Check::NoVlans, Check::OrphanVlan,
+        Check::DuplicateAlias, Check::VlanNameConflict, Check::Drift,
+    ];
+
+    /// Whether --strict fails on the check. A VLAN nobody carries is clutter, or
+    /// a clue, rather than a broken configuration, so it only warns.
+    pub fn fails_strict(self) -> bool {
+        self != Check::OrphanVlan
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Check::PvidNotUntagged => "pvid-not-untagged",
+            Check::UntaggedNotEgress => "untagged-not-egress",
+            Check::TrunkMemberMismatch => "trunk-member-mismatch",
+            Check::NoVlans => "no-vlans",
+            Check::OrphanVlan => "orphan-vlan",
+            Check::DuplicateAlias => "duplicate-alias",
+            Check::VlanNameConflict => "vlan-name-conflict",
+            Check::Drift => "drift",
+        }
+    }
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Check {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Check::ALL.into_iter()
+            .find(|check| check.name() == s)
+            .ok_or_else(|| {
+                let names: Vec<&str> = Check::ALL.iter().map(|check| check.name()).collect();
+                format!("Unknown check '{}'. Expected one of: {}", s, names.join(", "))
+            })
+    }
+}
+
+/// One failed check
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    pub check: Check,
+    pub ports: Vec<u32>,
+    pub vlan: Option<u32>,
+    pub detail: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Warning::from(self).fmt(f)
+    }
+}
+
+/// Aggregator members whose own Q-BRIDGE rows differ. Has to run before the
+/// aggregator's VLANs are copied onto the members.
+pub fn trunk_member_mismatches(ports: &[PortConfig]) -> Vec<Violation> {
+    let mut aggregators: BTreeMap<u32, Vec<&PortConfig>> = BTreeMap::new();
+    for port in ports {
+        if let Some(lacp) = &port.lacp_info {
+            aggregators.entry(lacp.selected_agg_id).or_default().push(port);
+        }
+    }
+
+    let mut violations = Vec::new();
+    for (agg_id, members) in aggregators {
+        let Some((first, rest)) = members.split_first() else {
+            continue;
+        };
+        let mut differing = Vec::new();
+        if rest.iter().any(|p| p.pvid != first.pvid) {
+            differing.push("PVID");
+        }
+        if rest.iter().any(|p| p.vlan_memberships != first.vlan_memberships) {
+            differing.push("VLAN memberships");
+        }
+        if rest.iter().any(|p| p.untagged_vlans != first.untagged_vlans) {
+            differing.push("untagged VLANs");
+        }
+        if !differing.is_empty() {
+            violations.push(Violation {
+                check: Check::TrunkMemberMismatch,
+                ports: members.iter().map(|p| p.port_num).collect(),
+                vlan: None,
+                detail: format!("members of aggregator {} differ in {}", agg_id, differing.join(", ")),
+            });
+        }
+
+        // The aggregator's own VLANs are what the members get in the report
+        let Some((agg_tagged, agg_untagged)) = first.lacp_info.as_ref().and_then(|lacp| lacp.agg_vlans.as_deref()) else {
+            continue;
+        };
+        for (attribute, differs) in [
+            ("VLAN memberships", members.iter().filter(|p| &p.vlan_memberships != agg_tagged).collect::<Vec<_>>()),
+            ("untagged VLANs", members.iter().filter(|p| &p.untagged_vlans != agg_untagged).collect()),
+        ] {
+            if !differs.is_empty() {
+                violations.push(Violation {
+                    check: Check::TrunkMemberMismatch,
+                    ports: differs.iter().map(|p| p.port_num).collect(),
+                    vlan: None,
+                    detail: format!("{} differ from aggregator {}", attribute, agg_id),
+                });
+            }
+        }
+    }
+    violations
+}
+
+/// Per-port checks on the final configuration
+pub fn port_violations(ports: &[PortConfig]) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    for port in ports {
+        if port.vlan_memberships.is_empty() && matches!(port.pvid, None | Some(0)) {
+            violations.push(Violation {
+                check: Check::NoVlans,
+                ports: vec![port.port_num],
+                vlan: None,
+                detail: "port is in no VLAN and has no PVID; check the bridge port mapping".to_string(),
+            });
+            continue;
+        }
+        if let Some(pvid) = port.pvid.filter(|&pvid| pvid != 0 && !port.untagged_vlans.contains(&pvid)) {
+            violations.push(Violation {
+                check: Check::PvidNotUntagged,
+                ports: vec![port.port_num],
+                vlan: Some(pvid),
+                detail: format!("PVID {} is not among the untagged VLANs [{}]", pvid, format_vlans(&port.untagged_vlans)),
+            });
+        }
+        let mut missing: Vec<u32> = port.untagged_vlans.difference(&port.vlan_memberships).copied().collect();
+        missing.sort_unstable();
+        for vlan in missing {
+            violations.push(Violation {
+                check: Check::UntaggedNotEgress,
+                ports: vec![port.port_num],
+                vlan: Some(vlan),
+                detail: "untagged VLAN is missing from the egress list".to_string(),
+            });
+        }
+    }
+    violations
+}
+
+/// Named VLANs whose egress bitmap is empty. The bitmaps include LAG
+/// interfaces, so VLANs carried only on a trunk are not reported.
+pub fn orphan_vlans(vlan_names: &HashMap<u32, String>, egress_ports: &HashMap<u32, Vec<u8>>) -> Vec<Violation> {
+    let mut orphans: Vec<(u32, &String)> = vlan_names.iter()
+        .filter(|(vlan_id, _)| !egress_ports.get(vlan_id).is_some_and(|ports| ports.iter().any(|&byte| byte != 0)))
+        .map(|(vlan_id, name)| (*vlan_id, name))
+        .collect();
+    orphans.sort_unstable();
+    orphans.into_iter()
+        .map(|(vlan_id, name)| Violation {
+            check: Check::OrphanVlan,
+            ports: Vec::new(),
+            vlan: Some(vlan_id),
+            detail: format!("\"{}\" is defined but carried on no port", name),
+        })
+        .collect()
+}
+
+/// Aliases used by more than one port, compared case-insensitively with
+/// whitespace collapsed. Members of one aggregator may share their alias.
+pub fn duplicate_aliases(ports: &[PortConfig]) -> Vec<Violation> {
+    let mut by_alias: BTreeMap<String, Vec<&PortConfig>> = BTreeMap::new();
+    for port in ports {
+        let Some(alias) = port.alias.as_deref() else {
+            continue;
+        };
+        let normalized = alias.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+        if !normalized.is_empty() {
+            by_alias.entry(normalized).or_default().push(port);
+        }
+    }
+
+    let mut violations = Vec::new();
+    for users in by_alias.into_values() {
+        // Each aggregator counts once, every other port on its own
+        let mut owners: Vec<(Option<u32>, u32)> = users.iter()
+            .map(|port| match &port.lacp_info {
+                Some(lacp) => (Some(lacp.selected_agg_id), 0),
+                None => (None, port.port_num),
+            })
+            .collect();
+        owners.sort_unstable();
+        owners.dedup();
+        if owners.len() > 1 {
+            let mut port_nums: Vec<u32> = users.iter().map(|port| port.port_num).collect();
+            port_nums.sort_unstable();
+            violations.push(Violation {
+                check: Check::DuplicateAlias,
+                ports: port_nums,
+                vlan: None,
+                detail: format!("alias \"{}\" is used more than once", users[0].alias.as_deref().unwrap_or_default()),
+            });
+        }
+    }
+    violations
+}
+
+/// VLAN ids that several switches name differently, compared case-insensitively
+/// with whitespace collapsed. `switches` pairs a switch's name with its VLAN
+/// names; a VLAN without a real name, i.e. empty or just its id, conflicts with nothing.
+pub fn vlan_name_conflicts(switches: &[(String, BTreeMap<u32, String>)]) -> Vec<Violation> {
+    // Per VLAN, each spelling in the order first seen: normalized, as shown, and the switches using it
+    type Spelling<'a> = (String, &'a str, Vec<&'a str>);
+    let mut by_vlan: BTreeMap<u32, Vec<Spelling>> = BTreeMap::new();
+    for (switch, names) in switches {
+        for (&vlan_id, name) in names {
+            let normalized = name.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+            if normalized.is_empty() || normalized == vlan_id.to_string() {
+                continue;
+            }
+            let spellings = by_vlan.entry(vlan_id).or_default();
+            match spellings.iter_mut().find(|(known, _, _)| *known == normalized) {
+                Some((_, _, users)) => users.push(switch),
+                None => spellings.push((normalized, name.trim(), vec![switch])),
+            }
+        }
+    }
+
+    by_vlan.into_iter()
+        .filter(|(_, spellings)| spellings.len() > 1)
+        .map(|(vlan_id, spellings)| {
+            let named: Vec<String> = spellings.iter()
+                .map(|(_, name, users)| format!("\"{}\" on {}", name, users.join(", ")))
+                .collect();
+            Violation {
+                check: Check::VlanNameConflict,
+                ports: Vec::new(),
+                vlan: Some(vlan_id),
+                detail: format!("{} but {}", named[0], named[1..].join(" and ")),
+            }
+        })
+        .collect()
+}
+
+/// Sorted, comma separated VLAN ids
+fn format_vlans(vlans: &HashSet<u32>) -> String {
+    let mut vlans: Vec<u32> = vlans.iter().copied().collect();
+    vlans.sort_unstable();
+    vlans.iter().map(u32::to_string).collect::<Vec<_>>().join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::fixture_ports;
+    use std::sync::Arc;
+    use crate::LacpInfo;
+
+    fn port(port_num: u32, pvid: u32, tagged: &[u32], untagged: &[u32]) -> PortConfig {
+        PortConfig {
+            port_num,
+            pvid: Some(pvid),
+            vlan_memberships: tagged.iter().chain(untagged).copied().collect(),
+            untagged_vlans: untagged.iter().copied().collect(),
+            ..PortConfig::default()
+        }
+    }
+
+    #[test]
+    fn pvid_outside_untagged_set_is_reported() {
+        let violations = port_violations(&[port(1, 10, &[], &[10]), port(2, 10, &[], &[531])]);
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].to_string(),
+            "pvid-not-untagged: port 2, VLAN 10: PVID 10 is not among the untagged VLANs [531]");
+    }
+
+    #[test]
+    fn untagged_vlan_missing_from_egress_is_reported() {
+        let ports = fixture_ports(include_str!("../tests/fixtures/untagged-not-egress.walk"), 1..=4);
+
+        let violations: Vec<Violation> = port_violations(&ports).into_iter()
+            .filter(|v| v.check == Check::UntaggedNotEgress)
+            .collect();
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].ports, vec![3]);
+        assert_eq!(violations[0].vlan, Some(20));
+    }
+
+    #[test]
+    fn trunk_members_are_compared_with_each_other_and_the_aggregator() {
+        let lacp = LacpInfo {
+            selected_agg_id: 50,
+            agg_name: Some("Trk1".to_string()),
+            agg_vlans: Some(Arc::new(([10, 20].into(), [10].into()))),
+            load_balance: None,
+        };
+        let mut members = vec![port(21, 10, &[20], &[10]), port(22, 10, &[], &[10])];
+        for member in &mut members {
+            member.lacp_info = Some(lacp.clone());
+        }
+
+        let details: Vec<String> = trunk_member_mismatches(&members).iter().map(|v| v.to_string()).collect();
+        assert_eq!(details, vec![
+            "trunk-member-mismatch: ports 21, 22: members of aggregator 50 differ in VLAN memberships".to_string(),
+            "trunk-member-mismatch: port 22: VLAN memberships differ from aggregator 50".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn vlans_without_egress_ports_are_orphans() {
+        let names = HashMap::from([(10, "office".to_string()), (20, "trunk-only".to_string()), (30, "legacy".to_string())]);
+        // VLAN 20 is only on the LAG interface, somewhere past the physical ports
+        let egress = HashMap::from([(10, vec![0x80, 0x00]), (20, vec![0x00, 0x01]), (30, vec![0x00, 0x00])]);
+        let violations = orphan_vlans(&names, &egress);
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].to_string(), "orphan-vlan: VLAN 30: \"legacy\" is defined but carried on no port");
+    }
+
+    #[test]
+    fn duplicate_aliases_ignore_case_whitespace_and_trunks() {
+        let lacp = LacpInfo { selected_agg_id: 50, agg_name: None, agg_vlans: None, load_balance: None };
+        let mut ports = vec![port(1, 1, &[], &[1]), port(2, 1, &[], &[1]), port(21, 1, &[], &[1]), port(22, 1, &[], &[1])];
+        ports[0].alias = Some("Server room AP".to_string());
+        ports[1].alias = Some(" server  room ap".to_string());
+        for trunk_member in &mut ports[2..] {
+            trunk_member.alias = Some("Uplink".to_string());
+            trunk_member.lacp_info = Some(lacp.clone());
+        }
+
+        let violations = duplicate_aliases(&ports);
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].to_string(),
+            "duplicate-alias: ports 1, 2: alias \"Server room AP\" is used more than once");
+    }
+
+    #[test]
+    fn missing_or_zero_pvid_is_no_mismatch() {
+        let ports = fixture_ports(include_str!("../tests/fixtures/missing-pvid.walk"), 1..=4);
+        assert_eq!(ports.iter().map(|p| p.pvid).collect::<Vec<_>>(), vec![Some(10), Some(0), None, None]);
+        assert!(port_violations(&ports).is_empty());
+    }
+
+    #[test]
+    fn check_names_round_trip() {
+        for check in Check::ALL {
+            assert_eq!(check.name().parse::<Check>(), Ok(check));
+        }
+        assert!("pvid".parse::<Check>().is_err());
+    }
+
+    #[test]
+    fn vlan_names_conflict_across_switches_unless_only_spelled_differently() {
+        let names = |pairs: &[(u32, &str)]| pairs.iter().map(|(id, name)| (*id, name.to_string())).collect();
+        let switches = vec![
+            ("sw-1".to_string(), names(&[(10, "Studio"), (531, "storage"), (20, "20")])),
+            ("sw-2".to_string(), names(&[(10, " studio "), (531, "storage")])),
+            ("sw-3".to_string(), names(&[(10, "STUDIO"), (531, "legacy-san"), (20, "office")])),
+            ("sw-4".to_string(), names(&[(531, "san")])),
+        ];
+        let conflicts = vlan_name_conflicts(&switches);
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!((conflicts[0].check, conflicts[0].vlan), (Check::VlanNameConflict, Some(531)));
+        assert_eq!(conflicts[0].detail, "\"storage\" on sw-1, sw-2 but \"legacy-san\" on sw-3 and \"san\" on sw-4");
+    }
+}
@@ -0,0 +1,1125 @@
+//! Polling one switch into a [`SwitchSnapshot`]
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::Duration;
+use std::sync::Arc;
+use crate::charset::{likely_truncated, normalize_whitespace, Decoded, DisplayString, Encoding};
+use crate::cancel;
+use crate::checks::{self, Violation};
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::logging::Event;
+use crate::session::SessionPool;
+use crate::snapshot::{Device, Lag, LagSource, Neighbor, VirtualInterface};
+use crate::snmp_utils::{format_mac, get_raw_table, get_ip_addresses, get_raw_table_at, get_rows, get_stack_table, get_string_scalar,
+    get_string_table, get_u32_columns, get_u32_scalar, get_u32_table, SnmpSource, SnmpValue};
+use crate::stats::{CollectionStats, SourceStatus, TableOutcome};
+use crate::warnings::{Warning, Warnings};
+use crate::vendor::{cisco_load_balance_mode, JuniperVlanMap, Vendor, VtpInfo, CLAG_DISTRIBUTION_ADDRESS_MODE,
+    CLAG_DISTRIBUTION_PROTOCOL, JNX_EX_VLAN_NAME, JNX_EX_VLAN_TAG, VTP_DOMAIN_LOCAL_MODE, VTP_DOMAIN_NAME,
+    VTP_VLAN_STATE, VTP_VLAN_SUSPENDED};
+use crate::{is_physical_port, port_in_list, vlan_sets, Dot1xInfo, FramePolicy, AggregatorVlans, LacpInfo, LacpOverride, LastChange, PortConfig, PortIndices};
+
+// SNMPv2-MIB OIDs
+pub(crate) const SYS_DESCR: &[u32] = &[1,3,6,1,2,1,1,1,0];  // sysDescr.0
+const SYS_UPTIME: &[u32] = &[1,3,6,1,2,1,1,3,0];  // sysUpTime.0
+const SYS_NAME: &[u32] = &[1,3,6,1,2,1,1,5,0];  // sysName.0
+/// ifType ieee8023adLag
+const IF_TYPE_LAG: u32 = 161;
+/// l3ipvlan, softwareLoopback and propVirtual: VLAN interfaces, loopbacks and most management ports
+pub const DEFAULT_VIRTUAL_IF_TYPES: [u32; 3] = [136, 24, 53];
+
+// Column numbers within dot1qPortVlanEntry
+const PORT_VLAN_FRAME_TYPES_COLUMN: u32 = 2;  // dot1qPortAcceptableFrameTypes
+const PORT_VLAN_INGRESS_FILTERING_COLUMN: u32 = 3;  // dot1qPortIngressFiltering
+
+// lldpRemTable is indexed by lldpRemTimeMark.lldpRemLocalPortNum.lldpRemIndex
+const LLDP_REM_LOCAL_PORT_POSITION: usize = 1;
+// LldpSystemCapabilitiesMap bit for bridge (bit 2, counted from the most significant bit)
+const LLDP_CAP_BRIDGE: u8 = 0x20;
+// LLDP-MIB objects for --topology: the local chassis, and columns of lldpRemEntry
+const LLDP_LOC_CHASSIS_ID_SUBTYPE: &[u32] = &[1,0,8802,1,1,2,1,3,1,0];  // lldpLocChassisIdSubtype.0
+const LLDP_LOC_CHASSIS_ID: &[u32] = &[1,0,8802,1,1,2,1,3,2,0];  // lldpLocChassisId.0
+const LLDP_REM_CHASSIS_ID_SUBTYPE_COLUMN: u32 = 4;
+const LLDP_REM_CHASSIS_ID_COLUMN: u32 = 5;
+const LLDP_REM_PORT_ID_SUBTYPE_COLUMN: u32 = 6;
+const LLDP_REM_PORT_ID_COLUMN: u32 = 7;
+const LLDP_REM_SYS_NAME_COLUMN: u32 = 9;
+// LldpChassisIdSubtype macAddress(4) and LldpPortIdSubtype macAddress(3)
+const LLDP_CHASSIS_ID_MAC: u32 = 4;
+const LLDP_PORT_ID_MAC: u32 = 3;
+
+/// Optional tables, walked only when something shows their data
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Tables {
+    /// ifPhysAddress
+    pub mac: bool,
+    /// ifLastChange and sysUpTime
+    pub last_change: bool,
+    /// dot1qPortAcceptableFrameTypes and dot1qPortIngressFiltering
+    pub frames: bool,
+    /// IEEE8021-PAE-MIB port control and status
+    pub dot1x: bool,
+    /// ifAdminStatus
+    pub admin_status: bool,
+    /// LLDP neighbors and the local chassis id
+    pub lldp: bool,
+    /// ifStackStatus, for aggregators the LAG MIB does not list, and the aggregator list
+    pub lags: bool,
+    /// dot1dBasePortIfIndex, to show bridge port numbers next to ifIndex
+    pub bridge_ports: bool,
+    /// ipAdEntIfIndex, and the interfaces of `CollectOptions::virtual_if_types`
+    pub virtual_interfaces: bool,
+}
+
+/// How to poll a switch and what to correct in what it reports
+#[derive(Debug)]
+pub struct CollectOptions {
+    pub community: String,
+    pub timeout: Duration,
+    /// UDP port of the agent
+    pub port: u16,
+    /// Vendor profile; detected from sysDescr when None
+    pub vendor: Option<Vendor>,
+    /// Table OID overrides per vendor profile and host
+    pub config: Config,
+    /// ifType values that count as ports
+    pub if_types: Vec<u32>,
+    /// ifType values of the interfaces listed when `Tables::virtual_interfaces` asks for them
+    pub virtual_if_types: Vec<u32>,
+    pub tables: Tables,
+    /// Applied in order, so a later override wins for the same target port
+    pub lacp_overrides: Vec<LacpOverride>,
+    /// Names replacing what the switch reports, for VLANs that exist on it
+    pub vlan_names: BTreeMap<u32, String>,
+    /// Aliases replacing what the switch reports, by port number
+    pub alias_overrides: BTreeMap<u32, String>,
+    /// Tried for aliases and names that are not UTF-8 before showing them as hex
+    pub fallback_encoding: Option<Encoding>,
+    /// Keep the members' own VLANs instead of the aggregator's
+    pub keep_member_vlans: bool,
+    /// Ports tagged in more VLANs than this count as uplinks
+    pub uplink_min_vlans: usize,
+    /// Fail with `Error::TooFewPorts` when fewer physical ports are collected
+    pub min_ports: usize,
+    /// Progress messages and a progress bar on stderr
+    pub progress: bool,
+    /// Start messages on stderr with the host, for switches collected concurrently
+    pub prefix_host: bool,
+    /// Explain adjustments, e.g. overridden aliases, on stderr
+    pub verbose: bool,
+}
+
+impl Default for CollectOptions {
+    fn default() -> CollectOptions {
+        CollectOptions {
+            community: "public".to_string(),
+            timeout: Duration::from_secs(2),
+            port: 161,
+            vendor: None,
+            config: Config::default(),
+            if_types: vec![6, 117],
+            virtual_if_types: DEFAULT_VIRTUAL_IF_TYPES.to_vec(),
+            tables: Tables::default(),
+            lacp_overrides: Vec::new(),
+            vlan_names: BTreeMap::new(),
+            alias_overrides: BTreeMap::new(),
+            fallback_encoding: None,
+            keep_member_vlans: false,
+            uplink_min_vlans: 3,
+            min_ports: 0,
+            progress: false,
+            prefix_host: false,
+            verbose: false,
+        }
+    }
+}
+
+/// Everything collected from one switch, before any filtering or grouping
+#[derive(Debug, Clone)]
+pub struct SwitchSnapshot {
+    pub device: Device,
+    /// VLAN names by id, including configured ones
+    pub vlans: HashMap<u32, String>,
+    /// Physical ports sorted by port number, with overrides applied
+    pub ports: Vec<PortConfig>,
+    /// Every interface by ifIndex, including aggregators; LACP overrides take
+    /// their alias and VLANs from here
+    pub interfaces: BTreeMap<u32, Interface>,
+    pub vtp: Option<VtpInfo>,
+    /// How each table walk went
+    pub sources: Vec<SourceStatus>,
+    /// What looked wrong while collecting, e.g. ports without a PVID row
+    pub warnings: Warnings,
+    /// Failed consistency checks, before any are allowed
+    pub violations: Vec<Violation>,
+    /// LLDP neighbors, when `Tables::lldp` asked for them
+    pub neighbors: Vec<Neighbor>,
+    /// Every aggregator with its members, when `Tables::lags` asked for them
+    pub lags: Vec<Lag>,
+    /// Interfaces that are not ports, when `Tables::virtual_interfaces` asked for them
+    pub virtual_interfaces: Vec<VirtualInterface>,
+}
+
+/// What the switch reports for any interface, physical or not
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Interface {
+    /// ifAlias, or ifName when the agent has no aliases
+    pub alias: Option<String>,
+    pub tagged: HashSet<u32>,
+    pub untagged: HashSet<u32>,
+}
+
+/// Number of tables a collection is expected to walk, for the progress bar.
+/// Only an estimate: LAG details are walked only when the switch has LACP.
+fn planned_tables(tables: &Tables, vendor: Vendor) -> u64 {
+    // ifIndex, ifName, ifType, ifAlias, bridge ports, VLAN name/egress/untagged, PVID, LAG, LLDP
+    let mut planned = 12;
+    planned += tables.mac as u64 + tables.admin_status as u64 + tables.last_change as u64 + tables.lags as u64;
+    planned += tables.virtual_interfaces as u64;
+    planned += if tables.frames { 2 } else { 0 };
+    planned += if tables.dot1x { 2 } else { 0 };
+    // The other LLDP columns that --topology records
+    planned += if tables.lldp { 3 } else { 0 };
+    planned += match vendor {
+        Vendor::Juniper => 2,
+        Vendor::Cisco => 3,
+        _ => 0,
+    };
+    planned
+}
+
+/// Which of an interface table's or a VLAN table's rows a warning is about
+#[derive(Clone, Copy)]
+enum RowSubject {
+    Port,
+    Vlan,
+    /// Indices that mean nothing to the reader, e.g. VTP management domains
+    Neither,
+}
+
+/// Decode a DisplayString table, with a warning for each row shown as hex
+fn decode_table(
+    table: HashMap<u32, DisplayString>,
+    name: &str,
+    subject: RowSubject,
+    fallback: Option<Encoding>,
+    warnings: &mut Warnings,
+) -> HashMap<u32, String> {
+    let mut hex_rows = Vec::new();
+    let decoded = table.into_iter()
+        .map(|(index, value)| {
+            let decoded = value.decode(fallback);
+            if matches!(decoded, Decoded::Hex(_)) {
+                hex_rows.push(index);
+            }
+            (index, decoded.into_string())
+        })
+        .collect();
+    let tried = match fallback {
+        Some(encoding) => format!("UTF-8 or {}", encoding),
+        None => "UTF-8".to_string(),
+    };
+    hex_rows.sort_unstable();
+    for index in hex_rows {
+        let (ports, vlan) = match subject {
+            RowSubject::Port => (vec![index], None),
+            RowSubject::Vlan => (Vec::new(), Some(index)),
+            RowSubject::Neither => (Vec::new(), None),
+        };
+        let warning = Warning::new("undecodable-string", ports, vlan, format!("{} is not printable {} text; shown in hex", name, tried));
+        // Aggregator names usually come from ifName again
+        if !warnings.iter().any(|seen| *seen == warning) {
+            warnings.extend([warning]);
+        }
+    }
+    decoded
+}
+
+/// Poll the switch at `host`, an address or name, on `options.port`
+pub fn collect_switch(host: &str, options: &CollectOptions) -> Result<SwitchSnapshot> {
+    collect_switch_with(host, options, &SessionPool::new())
+}
+
+/// Like [`collect_switch`], reusing the pool's session to the agent from an
+/// earlier poll
+pub fn collect_switch_with(host: &str, options: &CollectOptions, sessions: &SessionPool) -> Result<SwitchSnapshot> {
+    let agent_addr = format!("{}:{}", host, options.port);
+    sessions.with_session(&agent_addr, options.community.as_bytes(), options.timeout, |source, earlier| {
+        if let (true, Some(latency)) = (options.verbose, earlier.latency) {
+            Event::info("session-reused", format!("{} answered {} earlier requests in {:.1} ms on average",
+                agent_addr, earlier.requests, latency.as_secs_f64() * 1000.0))
+                .host(host, options.prefix_host)
+                .field("latency_ms", latency.as_secs_f64() * 1000.0)
+                .emit();
+        }
+        collect_from(source, host, options)
+    })
+}
+
+/// Only the VLAN names of the switch at `host`, from the tables a full poll reads
+/// them from, with configured names replacing those of VLANs the switch names.
+/// For checks across switches whose ports could not be collected.
+pub fn collect_vlan_names_with(host: &str, options: &CollectOptions, sessions: &SessionPool) -> Result<BTreeMap<u32, String>> {
+    let agent_addr = format!("{}:{}", host, options.port);
+    sessions.with_session(&agent_addr, options.community.as_bytes(), options.timeout, |source, _| {
+        let vendor = match options.vendor {
+            Some(vendor) => vendor,
+            None => get_string_scalar(source, SYS_DESCR)?.as_deref().map_or(Vendor::Generic, Vendor::detect),
+        };
+        // Decoding problems are reported by the full poll, if it gets that far
+        let mut warnings = Warnings::default();
+        let decode = |table, name, warnings: &mut Warnings| {
+            decode_table(table, name, RowSubject::Vlan, options.fallback_encoding, warnings)
+        };
+        let oids = options.config.table_oids(host, vendor);
+        let mut vlan_names = decode(get_string_table(source, &oids.vlan_names)?.rows, "dot1qVlanStaticName", &mut warnings);
+        if vendor == Vendor::Juniper {
+            let jnx_tags = get_u32_table(source, JNX_EX_VLAN_TAG)?.rows;
+            if !jnx_tags.is_empty() {
+                let jnx_names = JuniperVlanMap::new(jnx_tags).translate_keys(get_string_table(source, JNX_EX_VLAN_NAME)?.rows);
+                vlan_names = decode(jnx_names, "jnxExVlanName", &mut warnings);
+            }
+        }
+        for (vlan_id, name) in &options.vlan_names {
+            if let Some(reported) = vlan_names.get_mut(vlan_id) {
+                reported.clone_from(name);
+            }
+        }
+        Ok(vlan_names.into_iter().collect())
+    })
+}
+
+/// Collect from any SNMP source, e.g. a [`RecordedWalk`](crate::snmp_utils::RecordedWalk).
+/// `host` names the switch in the snapshot and selects host-specific OIDs.
+pub fn collect_from(source: &mut dyn SnmpSource, host: &str, options: &CollectOptions) -> Result<SwitchSnapshot> {
+    if options.progress {
+        Event::info("collection-started", "Fetching VLAN information...\n").host(host, options.prefix_host).emit();
+    }
+
+    let sys_descr = get_string_scalar(source, SYS_DESCR)?;
+    let vendor = match options.vendor {
+        Some(vendor) => vendor,
+        None => sys_descr.as_deref()
+            .map(Vendor::detect)
+            .unwrap_or(Vendor::Generic),
+    };
+    let oids = options.config.table_oids(host, vendor);
+
+    // Get all tables first, keeping track of which ones the agent provided
+    let planned = planned_tables(&options.tables, vendor);
+    let mut stats = CollectionStats::with_progress(host, planned, options.progress);
+    let port_indices = stats.walk(source, "ifIndex", &oids.if_index, get_u32_table)?;
+    // Without IF-MIB there are no ports to document
+    if stats.sources.last().is_some_and(|status| status.outcome == TableOutcome::Unsupported) {
+        stats.finish();
+        return Err(Error::UnsupportedTable { name: "ifIndex".to_string() });
+    }
+    // Collection problems, e.g. aliases shown in hex; checks add theirs later
+    let mut warnings = Warnings::default();
+    let decode = |table, name, subject, warnings: &mut Warnings| {
+        decode_table(table, name, subject, options.fallback_encoding, warnings)
+    };
+    let port_names = decode(stats.walk(source, "ifName", &oids.if_name, get_string_table)?, "ifName", RowSubject::Port, &mut warnings);
+    let port_types = stats.walk(source, "ifType", &oids.if_type, get_u32_table)?;
+    let aliases = decode(stats.walk(source, "ifAlias", &oids.if_alias, get_string_table)?, "ifAlias", RowSubject::Port, &mut warnings);
+    let mut port_aliases: HashMap<u32, String> = if !aliases.is_empty() {
+        aliases
+    } else {
+        port_names.clone()
+    };
+    // Stray whitespace would split ranges over differences nobody can see; keep what changed
+    let mut raw_aliases = HashMap::new();
+    for (&index, alias) in port_aliases.iter_mut() {
+        let normalized = normalize_whitespace(alias);
+        if normalized != *alias {
+            raw_aliases.insert(index, std::mem::replace(alias, normalized));
+        }
+    }
+    let port_macs = if options.tables.mac {
+        stats.walk(source, "ifPhysAddress", &oids.if_phys_address, get_raw_table)?
+    } else {
+        HashMap::new()
+    };
+    // ifIndex by bridge port, for the columns of dot1qPortVlanEntry and --debug-indices
+    let base_ports = stats.walk(source, "dot1dBasePortIfIndex", &oids.base_port_if_index, get_u32_table)?;
+    // Bridge port by ifIndex; the lowest one should a port have several
+    let mut bridge_ports: HashMap<u32, u32> = HashMap::new();
+    for (&bridge_port, &if_index) in &base_ports {
+        bridge_ports.entry(if_index)
+            .and_modify(|lowest| *lowest = (*lowest).min(bridge_port))
+            .or_insert(bridge_port);
+    }
+    // The bit of an ifIndex in the Q-BRIDGE port bitmaps, which count bridge ports;
+    // 0, which no bitmap has, for interfaces that are no bridge port
+    let bitmap_port = |if_index: u32| -> u32 {
+        if base_ports.is_empty() {
+            return if_index;
+        }
+        bridge_ports.get(&if_index).copied().unwrap_or(0)
+    };
+    let port_admin_status = if options.tables.admin_status {
+        stats.walk(source, "ifAdminStatus", &oids.if_admin_status, get_u32_table)?
+    } else {
+        HashMap::new()
+    };
+    let (port_last_changes, sys_uptime) = if options.tables.last_change {
+        (stats.walk(source, "ifLastChange", &oids.if_last_change, get_u32_table)?, get_u32_scalar(source, SYS_UPTIME)?)
+    } else {
+        (HashMap::new(), None)
+    };
+
+    let mut vlan_names = decode(stats.walk(source, "dot1qVlanStaticName", &oids.vlan_names, get_string_table)?,
+        "dot1qVlanStaticName", RowSubject::Vlan, &mut warnings);
+    let mut vlan_egress_ports = stats.walk(source, "dot1qVlanStaticEgressPorts", &oids.egress_ports, get_raw_table)?;
+    let mut vlan_untagged_ports = stats.walk(source, "dot1qVlanStaticUntaggedPorts", &oids.untagged_ports, get_raw_table)?;
+
+    // dot1qPvid, dot1qPortAcceptableFrameTypes and dot1qPortIngressFiltering share
+    // dot1qPortVlanEntry, so walk the whole entry once when the frame flags are wanted.
+    // All three columns are by bridge port; agents without dot1dBasePortIfIndex
+    // are taken to number bridge ports like ifIndex
+    let by_if_index = |column: HashMap<u32, u32>| -> HashMap<u32, u32> {
+        if base_ports.is_empty() {
+            return column;
+        }
+        column.into_iter()
+            .filter_map(|(bridge_port, value)| base_ports.get(&bridge_port).map(|&if_index| (if_index, value)))
+            .collect()
+    };
+    let (mut port_vlans, port_frame_types, port_ingress_filtering) = if options.tables.frames {
+        let mut columns = get_u32_columns(source, oids.port_vlan_entry())?;
+        for (name, column) in [
+            ("dot1qPvid", *oids.pvid.last().unwrap_or(&1)),
+            ("dot1qPortAcceptableFrameTypes", PORT_VLAN_FRAME_TYPES_COLUMN),
+            ("dot1qPortIngressFiltering", PORT_VLAN_INGRESS_FILTERING_COLUMN),
+        ] {
+            let outcome = match columns.get(&column) {
+                Some(rows) if !rows.is_empty() => TableOutcome::Rows(rows.len()),
+                _ if columns.is_empty() => TableOutcome::Unsupported,
+                _ => TableOutcome::Empty,
+            };
+            stats.record(name, outcome);
+        }
+        (
+            by_if_index(columns.remove(oids.pvid.last().unwrap_or(&1)).unwrap_or_default()),
+            by_if_index(columns.remove(&PORT_VLAN_FRAME_TYPES_COLUMN).unwrap_or_default()),
+            by_if_index(columns.remove(&PORT_VLAN_INGRESS_FILTERING_COLUMN).unwrap_or_default()),
+        )
+    } else {
+        (by_if_index(stats.walk(source, "dot1qPvid", &oids.pvid, get_u32_table)?), HashMap::new(), HashMap::new())
+    };
+
+    // Juniper EX keys Q-BRIDGE by an internal VLAN index; translate it into real 802.1Q tags
+    if vendor == Vendor::Juniper {
+        let jnx_tags = stats.walk(source, "jnxExVlanTag", JNX_EX_VLAN_TAG, get_u32_table)?;
+        if !jnx_tags.is_empty() {
+            let vlan_map = JuniperVlanMap::new(jnx_tags);
+            // Warn about the 802.1Q tag rather than Juniper's internal index
+            let jnx_names = vlan_map.translate_keys(stats.walk(source, "jnxExVlanName", JNX_EX_VLAN_NAME, get_string_table)?);
+            vlan_names = decode(jnx_names, "jnxExVlanName", RowSubject::Vlan, &mut warnings);
+            vlan_egress_ports = vlan_map.translate_keys(vlan_egress_ports);
+            vlan_untagged_ports = vlan_map.translate_keys(vlan_untagged_ports);
+            for pvid in port_vlans.values_mut() {
+                *pvid = vlan_map.tag(*pvid);
+            }
+        }
+    }
+
+    // Configured VLAN names win over what the switch reports
+    for (&vlan_id, name) in &options.vlan_names {
+        let exists = vlan_names.contains_key(&vlan_id)
+            || vlan_egress_ports.contains_key(&vlan_id)
+            || vlan_untagged_ports.contains_key(&vlan_id);
+        if exists {
+            vlan_names.insert(vlan_id, name.clone());
+        } else if options.verbose {
+            stats.log(Event::info("vlan-name-ignored",
+                format!("Configured name {:?} ignored: VLAN {} does not exist on this switch", name, vlan_id))
+                .host(host, options.prefix_host)
+                .field("vlan", vlan_id));
+        }
+    }
+
+    // Cisco VLAN databases are usually VTP managed; note the domain and mark suspended VLANs
+    let mut vtp = None;
+    if vendor == Vendor::Cisco {
+        let domain_names = decode(stats.walk(source, "managementDomainName", VTP_DOMAIN_NAME, get_string_table).unwrap_or_default(),
+            "managementDomainName", RowSubject::Neither, &mut warnings);
+        let domain_modes = stats.walk(source, "managementDomainLocalMode", VTP_DOMAIN_LOCAL_MODE, get_u32_table).unwrap_or_default();
+        let vlan_states = stats.walk(source, "vtpVlanState", VTP_VLAN_STATE, get_u32_table).unwrap_or_default();
+        if let Some((index, domain)) = domain_names.into_iter().min_by_key(|(index, _)| *index) {
+            vtp = Some(VtpInfo {
+                domain,
+                mode: domain_modes.get(&index).copied().unwrap_or(0),
+                suspended_vlans: vlan_states.into_iter()
+                    .filter(|&(_, state)| state == VTP_VLAN_SUSPENDED)
+                    .map(|(vlan_id, _)| vlan_id)
+                    .collect(),
+            });
+        }
+    }
+
+    // Get LACP information
+    let lag_selected_agg_ids = stats.walk(source, "dot3adAggPortSelectedAggID", &oids.lag_port_selected, get_u32_table)?;
+    let lag_agg_names = decode(stats.walk(source, "ifName (aggregators)", &oids.lag_agg_name, get_string_table)?,
+        "ifName", RowSubject::Port, &mut warnings);
+    // Agents without the LAG MIB may still stack ports under an aggregator interface
+    let stack_agg_ids: HashMap<u32, u32> = if options.tables.lags {
+        let stack = stats.walk(source, "ifStackStatus", &oids.if_stack_status, get_stack_table).unwrap_or_default();
+        stack.into_iter()
+            .filter(|(higher, _)| port_types.get(higher) == Some(&IF_TYPE_LAG))
+            .flat_map(|(higher, lowers)| lowers.into_iter().map(move |lower| (lower, higher)))
+            .filter(|(lower, _)| lag_selected_agg_ids.get(lower).is_none_or(|&agg_id| agg_id == 0))
+            .collect()
+    } else {
+        HashMap::new()
+    };
+
+    // Load-balancing details per aggregator: individual links from the LAG MIB,
+    // the hashing mode from vendor-specific objects where the profile knows them
+    let has_lacp = lag_selected_agg_ids.values().any(|&agg_id| agg_id > 0);
+    let lag_aggregate_or_individual = if has_lacp {
+        stats.walk(source, "dot3adAggAggregateOrIndividual", &oids.lag_aggregate_or_individual, get_u32_table).unwrap_or_default()
+    } else {
+        HashMap::new()
+    };
+    let load_balance_mode = match vendor {
+        Vendor::Cisco if has_lacp => {
+            let protocol = get_u32_scalar(source, CLAG_DISTRIBUTION_PROTOCOL).unwrap_or_default();
+            let address_mode = get_u32_scalar(source, CLAG_DISTRIBUTION_ADDRESS_MODE).unwrap_or_default();
+            protocol.zip(address_mode)
+                .and_then(|(protocol, address_mode)| cisco_load_balance_mode(protocol, address_mode))
+        }
+        // ProCurve/ArubaOS-Switch set trunk-load-balance in the running config
+        // only, with no MIB object to read; only individual links get marked
+        Vendor::Aruba => None,
+        _ => None,
+    };
+    let lag_load_balance = |agg_id: u32| -> Option<String> {
+        // TruthValue: true(1) aggregate, false(2) individual
+        if lag_aggregate_or_individual.get(&agg_id) == Some(&2) {
+            Some("individual".to_string())
+        } else {
+            load_balance_mode.clone()
+        }
+    };
+
+    // Get 802.1X information. Agents without the PAE MIB just leave the column empty.
+    let (dot1x_control, dot1x_status) = if options.tables.dot1x {
+        (
+            stats.walk(source, "dot1xAuthAuthControlledPortControl", &oids.dot1x_port_control, get_u32_table).unwrap_or_default(),
+            stats.walk(source, "dot1xAuthAuthControlledPortStatus", &oids.dot1x_port_status, get_u32_table).unwrap_or_default(),
+        )
+    } else {
+        (HashMap::new(), HashMap::new())
+    };
+
+    // LLDP neighbors advertising the bridge capability indicate links to other switches.
+    // For --topology the whole lldpRemEntry is walked once instead of that column.
+    let (lldp_capabilities, neighbors, chassis_id) = if options.tables.lldp {
+        let rows = get_rows(source, oids.lldp_rem_entry()).unwrap_or_default();
+        let capability_column = *oids.lldp_rem_sys_cap_enabled.last().unwrap_or(&12);
+        for (name, column) in [
+            ("lldpRemSysCapEnabled", capability_column),
+            ("lldpRemChassisId", LLDP_REM_CHASSIS_ID_COLUMN),
+            ("lldpRemPortId", LLDP_REM_PORT_ID_COLUMN),
+            ("lldpRemSysName", LLDP_REM_SYS_NAME_COLUMN),
+        ] {
+            let count = rows.values().filter(|row| row.contains_key(&column)).count();
+            let outcome = match count {
+                0 if rows.is_empty() => TableOutcome::Unsupported,
+                0 => TableOutcome::Empty,
+                count => TableOutcome::Rows(count),
+            };
+            stats.record(name, outcome);
+        }
+        let bytes = |row: &HashMap<u32, SnmpValue>, column| match row.get(&column) {
+            Some(SnmpValue::Bytes(bytes)) => Some(bytes.clone()),
+            _ => None,
+        };
+        let subtype = |row: &HashMap<u32, SnmpValue>, column| match row.get(&column) {
+            Some(SnmpValue::Integer(n)) => Some(*n),
+            _ => None,
+        };
+        let mut capabilities = HashMap::new();
+        let mut neighbors = Vec::new();
+        for (index, row) in &rows {
+            let Some(&port) = index.get(LLDP_REM_LOCAL_PORT_POSITION) else {
+                continue;
+            };
+            if let Some(caps) = bytes(row, capability_column) {
+                capabilities.insert(port, caps);
+            }
+            neighbors.push(Neighbor {
+                port,
+                chassis_id: bytes(row, LLDP_REM_CHASSIS_ID_COLUMN)
+                    .and_then(|id| lldp_id(&id, subtype(row, LLDP_REM_CHASSIS_ID_SUBTYPE_COLUMN) == Some(LLDP_CHASSIS_ID_MAC))),
+                port_id: bytes(row, LLDP_REM_PORT_ID_COLUMN)
+                    .and_then(|id| lldp_id(&id, subtype(row, LLDP_REM_PORT_ID_SUBTYPE_COLUMN) == Some(LLDP_PORT_ID_MAC))),
+                sys_name: bytes(row, LLDP_REM_SYS_NAME_COLUMN)
+                    .map(|name| DisplayString(name).decode(options.fallback_encoding).into_string())
+                    .filter(|name| !name.is_empty()),
+            });
+        }
+        // Rows are in index order, which starts with lldpRemTimeMark
+        neighbors.sort_by_key(|neighbor| neighbor.port);
+        let chassis_subtype = get_u32_scalar(source, LLDP_LOC_CHASSIS_ID_SUBTYPE).unwrap_or_default();
+        let chassis_id = match source.get(LLDP_LOC_CHASSIS_ID) {
+            Ok(Some(SnmpValue::Bytes(id))) => lldp_id(&id, chassis_subtype == Some(LLDP_CHASSIS_ID_MAC)),
+            _ => None,
+        };
+        (capabilities, neighbors, chassis_id)
+    } else {
+        let capabilities = stats.walk(source, "lldpRemSysCapEnabled", &oids.lldp_rem_sys_cap_enabled, |source, oid| {
+            get_raw_table_at(source, oid, LLDP_REM_LOCAL_PORT_POSITION)
+        }).unwrap_or_default();
+        (capabilities, Vec::new(), None)
+    };
+    // Addresses of the virtual interfaces; agents without ipAddrTable just leave them out
+    let ip_addresses = if options.tables.virtual_interfaces {
+        stats.walk(source, "ipAdEntIfIndex", &oids.ip_ad_ent_if_index, get_ip_addresses).unwrap_or_default()
+    } else {
+        HashMap::new()
+    };
+    stats.finish();
+    let bridge_neighbor_ports: HashSet<u32> = lldp_capabilities.into_iter()
+        .filter(|(_, caps)| caps.first().is_some_and(|b| b & LLDP_CAP_BRIDGE != 0))
+        .map(|(port_num, _)| port_num)
+        .collect();
+
+    // Get VLAN information for LACP interfaces
+    let mut lag_vlan_info: HashMap<u32, AggregatorVlans> = HashMap::new();
+    for agg_id in lag_selected_agg_ids.values().chain(stack_agg_ids.values()) {
+        // Every member names its aggregator; look each one up once
+        if *agg_id > 0 && !lag_vlan_info.contains_key(agg_id) {
+            let mut tagged = HashSet::new();
+            let mut untagged = HashSet::new();
+            
+            // Check VLAN memberships for the LACP interface using the LAG interface number
+            for (vlan_id, ports_data) in &vlan_egress_ports {
+                if port_in_list(bitmap_port(*agg_id), ports_data) {
+                    tagged.insert(*vlan_id);
+                }
+            }
+
+            // Check untagged VLANs for the LACP interface using the LAG interface number
+            for (vlan_id, ports_data) in &vlan_untagged_ports {
+                if port_in_list(bitmap_port(*agg_id), ports_data) {
+                    untagged.insert(*vlan_id);
+                }
+            }
+
+            if !tagged.is_empty() || !untagged.is_empty() {
+                lag_vlan_info.insert(*agg_id, Arc::new((tagged, untagged)));
+            }
+        }
+    }
+
+    // First, collect all individual port configurations
+    let mut port_configs: Vec<PortConfig> = Vec::new();
+
+    for &port_num in port_indices.values() {
+        // Skip non-physical ports based on ifType
+        let port_type = port_types.get(&port_num).copied().unwrap_or(0);
+        if !is_physical_port(port_type, &options.if_types) {
+            continue;
+        }
+        
+        // Only use alias if it's not just the port number
+        let alias = port_aliases.get(&port_num)
+            .filter(|&a| a != &port_num.to_string())
+            .cloned();
+        let raw_alias = alias.as_ref().and_then(|_| raw_aliases.get(&port_num).cloned());
+
+        let pvid = port_vlans.get(&port_num).copied();
+
+        let (vlan_memberships, untagged_vlans) = vlan_sets(bitmap_port(port_num), &vlan_egress_ports, &vlan_untagged_ports);
+
+        // Check if port is part of an LACP trunk
+        let lacp_info = lag_selected_agg_ids.get(&port_num).copied()
+            .filter(|&selected_agg_id| selected_agg_id > 0)
+            .or_else(|| stack_agg_ids.get(&port_num).copied())
+            .map(|selected_agg_id| LacpInfo {
+                selected_agg_id,
+                agg_name: lag_agg_names.get(&selected_agg_id).cloned(),
+                agg_vlans: lag_vlan_info.get(&selected_agg_id).cloned(),
+                load_balance: lag_load_balance(selected_agg_id),
+            });
+
+        // 802.1X port control and authorization state
+        let dot1x = dot1x_control.get(&port_num).map(|&port_control| Dot1xInfo {
+            port_control,
+            port_status: dot1x_status.get(&port_num).copied().unwrap_or(0),
+        });
+
+        // Acceptable frame types and ingress filtering
+        let frame_policy = if options.tables.frames
+            && (port_frame_types.contains_key(&port_num) || port_ingress_filtering.contains_key(&port_num)) {
+            Some(FramePolicy {
+                tagged_only: port_frame_types.get(&port_num) == Some(&2),
+                ingress_filter: port_ingress_filtering.get(&port_num) == Some(&1),
+                deviates: false,
+            })
+        } else {
+            None
+        };
+
+        let mac = port_macs.get(&port_num).and_then(|bytes| format_mac(bytes));
+
+        let last_change = match (port_last_changes.get(&port_num), sys_uptime) {
+            (Some(&changed), Some(uptime)) => Some(LastChange::from_ticks(changed, uptime)),
+            _ => None,
+        };
+
+        port_configs.push(PortConfig {
+            port_num,
+            alias,
+            raw_alias,
+            pvid,
+            vlan_memberships,
+            untagged_vlans,
+            lacp_info,
+            dot1x,
+            frame_policy,
+            uplink: false,
+            inferred_trunk: false,
+            // ifAdminStatus: up(1), down(2), testing(3)
+            admin_down: port_admin_status.get(&port_num) == Some(&2),
+            inherited_vlans: false,
+            mac,
+            last_change,
+            indices: options.tables.bridge_ports.then(|| PortIndices {
+                if_index: port_num,
+                bridge_port: bridge_ports.get(&port_num).copied(),
+            }),
+        });
+    }
+
+    // port_indices is a HashMap; fix the order before anything reports per port
+    port_configs.sort_by_key(|config| config.port_num);
+
+    // An empty table is more likely a restricted view than an empty switch,
+    // unless collection was cut short
+    if port_configs.len() < options.min_ports && !cancel::is_cancelled() {
+        return Err(Error::TooFewPorts {
+            found: port_configs.len(),
+            required: options.min_ports,
+            tables: stats.sources.iter()
+                .filter(|source| !matches!(source.outcome, TableOutcome::Rows(_)))
+                .map(|source| format!("{} ({})", source.name, source.outcome))
+                .collect(),
+        });
+    }
+
+    if options.verbose {
+        for port in &port_configs {
+            if let (Some(raw), Some(alias)) = (&port.raw_alias, &port.alias) {
+                Event::info("alias-normalized", format!("Alias of port {} normalized: {:?} -> {:?}", port.port_num, raw, alias))
+                    .host(host, options.prefix_host)
+                    .field("ports", vec![port.port_num])
+                    .emit();
+            }
+        }
+    }
+
+    // A missing dot1qPvid row is not the same as PVID 0; say so once rather than per port
+    let unknown_pvids: Vec<u32> = port_configs.iter().filter(|p| p.pvid.is_none()).map(|p| p.port_num).collect();
+    if !unknown_pvids.is_empty() {
+        warnings.push("missing-pvid", unknown_pvids, None, "dot1qPvid has no row; the PVID is shown as unknown");
+    }
+
+    // Mark frame policies that differ from the switch-wide majority as likely misconfigurations
+    let mut policy_counts: HashMap<(bool, bool), usize> = HashMap::new();
+    for policy in port_configs.iter().filter_map(|p| p.frame_policy.as_ref()) {
+        *policy_counts.entry((policy.tagged_only, policy.ingress_filter)).or_default() += 1;
+    }
+    let majority_policy = policy_counts.iter()
+        .max_by_key(|(policy, count)| (**count, std::cmp::Reverse(**policy)))
+        .map(|(policy, _)| *policy);
+    if let Some(majority) = majority_policy {
+        for policy in port_configs.iter_mut().filter_map(|p| p.frame_policy.as_mut()) {
+            policy.deviates = (policy.tagged_only, policy.ingress_filter) != majority;
+        }
+    }
+
+    // Listed on their own, so they never join port ranges or the capacity counts
+    let mut virtual_interfaces = Vec::new();
+    if options.tables.virtual_interfaces {
+        for (&index, &if_type) in &port_types {
+            if options.virtual_if_types.contains(&if_type) && !is_physical_port(if_type, &options.if_types) {
+                virtual_interfaces.push(VirtualInterface {
+                    index,
+                    name: port_names.get(&index).filter(|name| !name.is_empty()).cloned(),
+                    if_type,
+                    addresses: ip_addresses.get(&index).cloned().unwrap_or_default(),
+                });
+            }
+        }
+        virtual_interfaces.sort_by_key(|interface| interface.index);
+    }
+
+    let interfaces = port_indices.values()
+        .map(|&index| {
+            let (tagged, untagged) = vlan_sets(bitmap_port(index), &vlan_egress_ports, &vlan_untagged_ports);
+            (index, Interface { alias: port_aliases.get(&index).cloned(), tagged, untagged })
+        })
+        .collect();
+    let orphans = checks::orphan_vlans(&vlan_names, &vlan_egress_ports);
+
+    let mut switch = SwitchSnapshot {
+        device: Device {
+            ip: host.to_string(),
+            sys_name: get_string_scalar(source, SYS_NAME)?,
+            sys_descr,
+            vendor: vendor.to_string(),
+            chassis_id,
+        },
+        vlans: vlan_names,
+        ports: port_configs,
+        interfaces,
+        vtp,
+        sources: stats.sources,
+        warnings,
+        violations: Vec::new(),
+        neighbors,
+        lags: Vec::new(),
+        virtual_interfaces,
+    };
+    apply_overrides(&mut switch, &options.lacp_overrides)?;
+    if options.tables.lags {
+        let source = |port: u32| {
+            if options.lacp_overrides.iter().any(|lacp_override| lacp_override.target_ports.contains(&port)) {
+                LagSource::Override
+            } else if stack_agg_ids.contains_key(&port) {
+                LagSource::IfStack
+            } else {
+                LagSource::LagMib
+            }
+        };
+        let aggregators = port_types.iter()
+            .filter(|(_, &if_type)| if_type == IF_TYPE_LAG)
+            .map(|(&index, _)| index);
+        switch.lags = lags(&switch, aggregators, &lag_agg_names, &port_vlans, source);
+    }
+    let port_configs = &mut switch.ports;
+
+    // Members are compared before they inherit the aggregator's VLANs below
+    let mut violations = checks::trunk_member_mismatches(port_configs);
+
+    // Update VLAN memberships based on LACP info, unless the raw member state was asked for
+    if !options.keep_member_vlans {
+        for port_config in port_configs.iter_mut() {
+            if let Some(lacp_info) = &port_config.lacp_info {
+                if let Some((tagged, untagged)) = lacp_info.agg_vlans.as_deref() {
+                    // The copy stays: ranges take ownership of a port's sets, and checks,
+                    // snapshots and every output read them as plain sets. It reuses the
+                    // member's allocation where big enough; grouping compares the Arc instead.
+                    port_config.vlan_memberships.clone_from(tagged);
+                    port_config.untagged_vlans.clone_from(untagged);
+                    port_config.inherited_vlans = true;
+                }
+            }
+        }
+    }
+
+    violations.extend(checks::port_violations(port_configs));
+    violations.extend(orphans);
+
+    // The length limit applies to what the switch stores, not the normalized text
+    let mut truncated: Vec<u32> = port_configs.iter()
+        .filter(|p| p.raw_alias.as_deref().or(p.alias.as_deref()).is_some_and(likely_truncated))
+        .map(|p| p.port_num)
+        .collect();
+
+    // Replace aliases before anything looks at them, so corrected labels filter and group naturally
+    for (&port, alias) in &options.alias_overrides {
+        match port_configs.iter_mut().find(|p| p.port_num == port) {
+            Some(port_config) => {
+                let was_truncated = truncated.contains(&port);
+                if options.verbose {
+                    let (kind, reason) = if was_truncated {
+                        ("truncated-alias-overridden", ", replacing one likely truncated on the switch")
+                    } else {
+                        ("alias-overridden", "")
+                    };
+                    Event::info(kind, format!("Alias of port {} overridden{}: {:?} -> {:?}",
+                        port, reason, port_config.alias.as_deref().unwrap_or_default(), alias))
+                        .host(host, options.prefix_host)
+                        .field("ports", vec![port])
+                        .emit();
+                }
+                truncated.retain(|&p| p != port);
+                port_config.alias = Some(alias.clone());
+                port_config.raw_alias = None;
+            }
+            None => switch.warnings.push("alias-override-no-port", vec![port], None,
+                "alias override matches no port on this switch"),
+        }
+    }
+    if !truncated.is_empty() {
+        switch.warnings.push("alias-truncated", truncated, None,
+            "alias is exactly a common field size and ends mid-word, so the device may have truncated it; \
+             supply the full text with --alias-override or alias_overrides");
+    }
+
+    // Aliases are final now, so duplicates are real
+    violations.extend(checks::duplicate_aliases(port_configs));
+
+    // Label likely uplinks
+    for port_config in port_configs.iter_mut() {
+        let tagged_count = port_config.vlan_memberships.difference(&port_config.untagged_vlans).count();
+        port_config.uplink = port_config.lacp_info.is_some()
+            || tagged_count > options.uplink_min_vlans
+            || bridge_neighbor_ports.contains(&port_config.port_num);
+    }
+
+    switch.violations = violations;
+    Ok(switch)
+}
+
+/// Every aggregator interface, and every interface a port is a member of, with
+/// its members, VLANs and where the memberships came from
+fn lags(
+    switch: &SwitchSnapshot,
+    aggregators: impl Iterator<Item = u32>,
+    names: &HashMap<u32, String>,
+    pvids: &HashMap<u32, u32>,
+    source: impl Fn(u32) -> LagSource,
+) -> Vec<Lag> {
+    let mut members: BTreeMap<u32, Vec<&PortConfig>> = aggregators.map(|index| (index, Vec::new())).collect();
+    for port in &switch.ports {
+        if let Some(lacp) = &port.lacp_info {
+            members.entry(lacp.selected_agg_id).or_default().push(port);
+        }
+    }
+    members.into_iter()
+        .map(|(index, ports)| {
+            let interface = switch.interfaces.get(&index).cloned().unwrap_or_default();
+            let mut sources: Vec<LagSource> = ports.iter().map(|port| source(port.port_num)).collect();
+            sources.sort_unstable();
+            sources.dedup();
+            Lag {
+                index,
+                // Overrides name the interfaces they borrow from only when the switch does not
+                name: names.get(&index).cloned().or_else(|| {
+                    ports.iter().find_map(|port| port.lacp_info.as_ref().and_then(|lacp| lacp.agg_name.clone()))
+                }),
+                members: ports.iter().map(|port| port.port_num).collect(),
+                pvid: pvids.get(&index).copied(),
+                vlans: interface.tagged.into_iter().collect(),
+                untagged: interface.untagged.into_iter().collect(),
+                sources,
+                stale: ports.is_empty(),
+            }
+        })
+        .collect()
+}
+
+/// An LLDP chassis or port id: a MAC address when its subtype says so, otherwise
+/// the text it usually is (an interface name, a number or a host name)
+fn lldp_id(id: &[u8], mac: bool) -> Option<String> {
+    if mac {
+        return format_mac(id);
+    }
+    Some(DisplayString(id.to_vec()).decode(None).into_string()).filter(|id| !id.is_empty())
+}
+
+/// Make the target ports of each override members of its source interface,
+/// taking over the source's alias and VLANs. Later overrides win for the same port.
+pub fn apply_overrides(switch: &mut SwitchSnapshot, overrides: &[LacpOverride]) -> Result<()> {
+    for override_info in overrides {
+        let Some(source) = switch.interfaces.get(&override_info.source_interface) else {
+            return Err(Error::InvalidArgument(format!(
+                "LACP override refers to source interface {}, which does not exist on {}",
+                override_info.source_interface, switch.device.ip)));
+        };
+
+        let agg_vlans = Arc::new((source.tagged.clone(), source.untagged.clone()));
+        for target_port in &override_info.target_ports {
+            match switch.ports.iter_mut().find(|p| p.port_num == *target_port) {
+                Some(port_config) => {
+                    port_config.alias = source.alias.clone();
+                    port_config.lacp_info = Some(LacpInfo {
+                        selected_agg_id: override_info.source_interface,
+                        agg_name: Some(override_info.name.clone()
+                            .unwrap_or_else(|| format!("Trk{}", override_info.source_interface))),
+                        agg_vlans: Some(Arc::clone(&agg_vlans)),
+                        load_balance: None,
+                    });
+                }
+                None => switch.warnings.push("lacp-override-no-port", vec![*target_port], None,
+                    format!("LACP override target port does not exist on {}", switch.device.ip)),
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::{render_switch, Columns, OutputFormat, ReportInfo};
+    use crate::snmp_utils::RecordedWalk;
+
+    const WALK: &str = include_str!("../tests/fixtures/generic-8port.walk");
+
+    #[test]
+    fn collects_ports_vlans_and_lacp_from_a_recorded_walk() {
+        let mut walk = RecordedWalk::parse(WALK).unwrap();
+        let switch = collect_from(&mut walk, "10.1.0.50", &CollectOptions::default()).unwrap();
+
+        assert_eq!(switch.device.sys_name.as_deref(), Some("sw-test"));
+        assert_eq!(switch.device.vendor, "generic");
+        assert_eq!(switch.vlans[&20], "studio");
+        // The aggregator itself is not a physical port
+        let ports: Vec<u32> = switch.ports.iter().map(|port| port.port_num).collect();
+        assert_eq!(ports, (1..=8).collect::<Vec<u32>>());
+        assert_eq!(switch.ports[0].alias.as_deref(), Some("desk 1"));
+        assert_eq!(switch.ports[0].untagged_vlans, HashSet::from([10]));
+
+        let member = &switch.ports[4];
+        let lacp = member.lacp_info.as_ref().unwrap();
+        assert_eq!((lacp.selected_agg_id, lacp.agg_name.as_deref()), (9, Some("Trk1")));
+        assert_eq!(member.vlan_memberships, HashSet::from([1, 10, 20]));
+        assert!(member.uplink);
+        // Both members share one copy of the aggregator's VLANs
+        let shared = |port: &PortConfig| port.lacp_info.as_ref().and_then(|lacp| lacp.agg_vlans.clone());
+        assert!(Arc::ptr_eq(&shared(member).unwrap(), &shared(&switch.ports[5]).unwrap()));
+        assert!(switch.violations.is_empty(), "{:?}", switch.violations);
+        assert!(switch.sources.iter().any(|source| source.name == "ifIndex" && source.outcome == TableOutcome::Rows(9)));
+
+        let markdown = render_switch(&switch, OutputFormat::Markdown, &Columns::default(), &ReportInfo::default());
+        assert!(markdown.contains("| 5\\* | uplink | Tagged:[1, staff (10), studio (20)] Untagged:[1] | Trk1 |"), "{}", markdown);
+    }
+
+    #[test]
+    fn suspended_vlans_keep_their_names() {
+        let mut walk = RecordedWalk::parse(include_str!("../tests/fixtures/cisco-2960-bridge-ports.walk")).unwrap();
+        let switch = collect_from(&mut walk, "10.1.0.60", &CollectOptions::default()).unwrap();
+        assert_eq!(switch.vlans[&20], "backstage");
+        assert_eq!(switch.vtp.map(|vtp| vtp.suspended_vlans), Some(std::collections::BTreeSet::from([20])));
+    }
+
+    #[test]
+    fn frame_flags_are_mapped_from_bridge_ports_to_if_index() {
+        let walk = format!("{}\n{}", include_str!("../tests/fixtures/cisco-2960-bridge-ports.walk"),
+            ".1.3.6.1.2.1.17.7.1.4.5.1.2.1 = INTEGER: admitOnlyVlanTagged(2)\n\
+             .1.3.6.1.2.1.17.7.1.4.5.1.2.2 = INTEGER: admitAll(1)\n\
+             .1.3.6.1.2.1.17.7.1.4.5.1.3.2 = INTEGER: true(1)");
+        let options = CollectOptions { tables: Tables { frames: true, ..Tables::default() }, ..CollectOptions::default() };
+        let switch = collect_from(&mut RecordedWalk::parse(&walk).unwrap(), "10.1.0.60", &options).unwrap();
+        let policy = |if_index: u32| switch.ports.iter()
+            .find(|port| port.port_num == if_index)
+            .and_then(|port| port.frame_policy)
+            .map(|policy| (policy.tagged_only, policy.ingress_filter));
+        assert_eq!(policy(10101), Some((true, false)));
+        assert_eq!(policy(10102), Some((false, true)));
+        assert_eq!(policy(10103), None);
+
+        // dot1qPvid is by bridge port too, with or without the frame flags
+        let plain = collect_from(&mut RecordedWalk::parse(&walk).unwrap(), "10.1.0.60", &CollectOptions::default()).unwrap();
+        for switch in [&switch, &plain] {
+            let pvid = |if_index: u32| switch.ports.iter().find(|port| port.port_num == if_index).and_then(|port| port.pvid);
+            assert_eq!(pvid(10101), Some(1));
+            assert_eq!(pvid(10106), Some(20));
+            assert_eq!(pvid(10108), Some(1));
+        }
+        // So are the bits of the VLAN port bitmaps
+        let uplink = plain.ports.iter().find(|port| port.port_num == 10108).unwrap();
+        assert_eq!(uplink.vlan_memberships, HashSet::from([1, 10, 20]));
+        assert!(uplink.untagged_vlans.is_empty());
+    }
+
+    #[test]
+    fn options_correct_what_the_switch_reports() {
+        let options = CollectOptions {
+            vlan_names: BTreeMap::from([(10, "office".to_string()), (99, "nowhere".to_string())]),
+            alias_overrides: BTreeMap::from([(8, "spare".to_string())]),
+            lacp_overrides: vec![LacpOverride { source_interface: 9, target_ports: vec![7], name: Some("core".to_string()) }],
+            keep_member_vlans: true,
+            ..CollectOptions::default()
+        };
+        let mut walk = RecordedWalk::parse(WALK).unwrap();
+        let switch = collect_from(&mut walk, "10.1.0.50", &options).unwrap();
+
+        assert_eq!(switch.vlans[&10], "office");
+        assert!(!switch.vlans.contains_key(&99));
+        assert_eq!(switch.ports[7].alias.as_deref(), Some("spare"));
+        assert_eq!(switch.ports[6].lacp_info.as_ref().and_then(|lacp| lacp.agg_name.as_deref()), Some("core"));
+        // The override target keeps its own VLANs, as the real members do
+        assert_eq!(switch.ports[6].vlan_memberships, HashSet::from([20]));
+    }
+
+    #[test]
+    fn aliases_are_normalized_but_the_raw_value_is_kept() {
+        let walk = WALK
+            .replace(r#"18.2 = STRING: "desk 2""#, "18.2 = STRING: \"  desk \t 2 \"")
+            .replace(r#"18.6 = STRING: "uplink""#, "18.6 = Hex-STRING: 75 70 6C 69 6E 6B 0D 0A");
+        let mut walk = RecordedWalk::parse(&walk).unwrap();
+        let switch = collect_from(&mut walk, "10.1.0.50", &CollectOptions::default()).unwrap();
+
+        assert_eq!(switch.ports[1].alias.as_deref(), Some("desk 2"));
+        assert_eq!(switch.ports[1].raw_alias.as_deref(), Some("  desk \t 2 "));
+        assert_eq!(switch.ports[5].alias, switch.ports[4].alias);
+        assert_eq!(switch.ports[5].raw_alias.as_deref(), Some("uplink\r\n"));
+        assert_eq!(switch.ports[0].raw_alias, None);
+        // The members still group into one range
+        let ranges = crate::Grouping::default().group(&switch.ports);
+        assert!(ranges.iter().any(|range| (range.first_port, range.last_port) == (5, 6)));
+    }
+
+    #[test]
+    fn aliases_cut_at_a_field_size_are_flagged_until_overridden() {
+        let walk = WALK.replace(r#"18.2 = STRING: "desk 2""#, r#"18.2 = STRING: "desk 2, studio A mixer left chan""#);
+        let mut recorded = RecordedWalk::parse(&walk).unwrap();
+        let switch = collect_from(&mut recorded, "10.1.0.50", &CollectOptions::default()).unwrap();
+        let warning = switch.warnings.iter().find(|w| w.code == "alias-truncated").expect("truncation warning");
+        assert_eq!(warning.ports, vec![2]);
+
+        let options = CollectOptions {
+            alias_overrides: BTreeMap::from([(2, "desk 2, studio A mixer left channel".to_string())]),
+            ..CollectOptions::default()
+        };
+        let mut recorded = RecordedWalk::parse(&walk).unwrap();
+        let switch = collect_from(&mut recorded, "10.1.0.50", &options).unwrap();
+        assert!(switch.warnings.iter().all(|w| w.code != "alias-truncated"));
+        assert_eq!(switch.ports[1].alias.as_deref(), Some("desk 2, studio A mixer left channel"));
+    }
+
+    #[test]
+    fn overrides_borrow_the_source_interface() {
+        let mut walk = RecordedWalk::parse(WALK).unwrap();
+        let mut switch = collect_from(&mut walk, "10.1.0.50", &CollectOptions::default()).unwrap();
+        let overrides = [
+            LacpOverride { source_interface: 9, target_ports: vec![7, 8], name: None },
+            LacpOverride { source_interface: 6, target_ports: vec![8], name: Some("spare".to_string()) },
+        ];
+        apply_overrides(&mut switch, &overrides).unwrap();
+
+        let lacp = switch.ports[6].lacp_info.as_ref().unwrap();
+        assert_eq!((lacp.selected_agg_id, lacp.agg_name.as_deref()), (9, Some("Trk9")));
+        assert_eq!(lacp.agg_vlans.as_deref(), Some(&(HashSet::from([1, 10, 20]), HashSet::from([1]))));
+        // The later override wins
+        assert_eq!(switch.ports[7].lacp_info.as_ref().and_then(|lacp| lacp.agg_name.as_deref()), Some("spare"));
+        assert_eq!(switch.ports[7].alias.as_deref(), Some("uplink"));
+    }
+
+    #[test]
+    fn agent_without_if_mib_is_unsupported() {
+        // The whole ifTable goes, not just ifIndex
+        let walk: Vec<&str> = WALK.lines().filter(|line| !line.starts_with(".1.3.6.1.2.1.2.2.1.")).collect();
+        let mut walk = RecordedWalk::parse(&walk.join("\n")).unwrap();
+        let err = collect_from(&mut walk, "10.1.0.50", &CollectOptions::default()).unwrap_err();
+        assert!(matches!(&err, Error::UnsupportedTable { name } if name == "ifIndex"), "{}", err);
+    }
+
+    #[test]
+    fn unknown_override_source_is_an_error() {
+        let options = CollectOptions {
+            lacp_overrides: vec![LacpOverride { source_interface: 42, target_ports: vec![1], name: None }],
+            ..CollectOptions::default()
+        };
+        let mut walk = RecordedWalk::parse(WALK).unwrap();
+        let err = collect_from(&mut walk, "10.1.0.50", &options).unwrap_err();
+        assert!(err.to_string().contains("source interface 42"), "{}", err);
+    }
+}
@@ -0,0 +1,527 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use anyhow::{Result, anyhow, bail};
+use serde::{Deserialize, Serialize};
+use crate::logging::Event;
+use crate::oids::TableOids;
+use crate::output::{Column, OutputFormat};
+use crate::panel::PanelBlock;
+use crate::rules::HighlightRule;
+use crate::vendor::Vendor;
+
+/// Keys accepted both at the top level and in `[hosts."..."]` tables
+const SETTINGS_KEYS: &[&str] = &[
+    "community", "timeout", "format", "highlight_vlans", "columns", "override_lacp", "lacp_overrides",
+    "alias_overrides", "vlan_names", "panel_labels", "panel_blocks", "vendor", "if_types", "virtual_if_types",
+    "allow_no_vlans",
+    "title", "device_name", "labels", "min_ports",
+];
+
+/// Options that can be set globally or per host; unset values fall through to
+/// the next layer (CLI > host > global > built-in default)
+#[derive(Debug, Default, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Settings {
+    pub community: Option<String>,
+    pub timeout: Option<u64>,
+    pub format: Option<String>,
+    /// Access VLANs whose rows are coloured in HTML output
+    pub highlight_vlans: Option<Vec<u32>>,
+    /// Column ids, see `Column::id`. A list naming any of the default columns
+    /// (port, alias, vlans, lacp) is the whole layout; otherwise its columns
+    /// follow the defaults
+    pub columns: Option<Vec<String>>,
+    /// LACP overrides in --override-lacp syntax
+    pub override_lacp: Option<Vec<String>>,
+    /// LACP overrides with a human readable trunk name
+    pub lacp_overrides: Option<Vec<NamedLacpOverride>>,
+    /// Aliases replacing what the switch reports, keyed by port number
+    pub alias_overrides: Option<BTreeMap<String, String>>,
+    /// VLAN names replacing or adding to what the switch reports, keyed by VLAN id
+    pub vlan_names: Option<BTreeMap<String, String>>,
+    /// Patch-panel label per port number, e.g. `5 = "PP2-B07"`
+    pub panel_labels: Option<BTreeMap<String, String>>,
+    /// Patch-panel labels generated for blocks of ports
+    pub panel_blocks: Option<Vec<PanelBlock>>,
+    /// Vendor profile forced instead of detecting it from sysDescr
+    pub vendor: Option<String>,
+    /// ifType values reported as ports, e.g. [6, 117]
+    pub if_types: Option<Vec<u32>>,
+    /// ifType values listed as virtual interfaces with --include-virtual, e.g. [136, 24, 53]
+    pub virtual_if_types: Option<Vec<u32>>,
+    /// Ports expected to be in no VLAN, e.g. routed ports; not warned about
+    pub allow_no_vlans: Option<Vec<u32>>,
+    /// Report heading, e.g. "Rack 4 access switch"
+    pub title: Option<String>,
+    /// Name shown for the device instead of its sysName or IP address
+    pub device_name: Option<String>,
+    /// Column header labels keyed by column id, e.g. `alias = "Description"`
+    pub labels: Option<BTreeMap<String, String>>,
+    /// Fail when fewer physical ports than this are collected
+    pub min_ports: Option<usize>,
+}
+
+impl Settings {
+    /// Replace every value that is set in `other`
+    pub fn overlay(&mut self, other: &Settings) {
+        if other.community.is_some() {
+            self.community = other.community.clone();
+        }
+        if other.timeout.is_some() {
+            self.timeout = other.timeout;
+        }
+        if other.format.is_some() {
+            self.format = other.format.clone();
+        }
+        if other.highlight_vlans.is_some() {
+            self.highlight_vlans = other.highlight_vlans.clone();
+        }
+        if other.columns.is_some() {
+            self.columns = other.columns.clone();
+        }
+        if other.override_lacp.is_some() {
+            self.override_lacp = other.override_lacp.clone();
+        }
+        if other.lacp_overrides.is_some() {
+            self.lacp_overrides = other.lacp_overrides.clone();
+        }
+        if other.alias_overrides.is_some() {
+            self.alias_overrides = other.alias_overrides.clone();
+        }
+        if other.vlan_names.is_some() {
+            self.vlan_names = other.vlan_names.clone();
+        }
+        if other.panel_labels.is_some() {
+            self.panel_labels = other.panel_labels.clone();
+        }
+        if other.panel_blocks.is_some() {
+            self.panel_blocks = other.panel_blocks.clone();
+        }
+        if other.vendor.is_some() {
+            self.vendor = other.vendor.clone();
+        }
+        if other.if_types.is_some() {
+            self.if_types = other.if_types.clone();
+        }
+        if other.virtual_if_types.is_some() {
+            self.virtual_if_types = other.virtual_if_types.clone();
+        }
+        if other.allow_no_vlans.is_some() {
+            self.allow_no_vlans = other.allow_no_vlans.clone();
+        }
+        if other.title.is_some() {
+            self.title = other.title.clone();
+        }
+        if other.device_name.is_some() {
+            self.device_name = other.device_name.clone();
+        }
+        if other.labels.is_some() {
+            self.labels = other.labels.clone();
+        }
+        if other.min_ports.is_some() {
+            self.min_ports = other.min_ports;
+        }
+    }
+
+    /// Whether the column with this id was selected
+    pub fn has_column(&self, id: &str) -> bool {
+        self.columns.as_ref().is_some_and(|columns| columns.iter().any(|c| c == id))
+    }
+
+    /// Configured header labels
+    pub fn column_labels(&self) -> HashMap<Column, String> {
+        self.labels.iter().flatten()
+            .filter_map(|(id, label)| Some((id.parse().ok()?, label.clone())))
+            .collect()
+    }
+
+    /// Columns selected by --columns or the config file, in order
+    pub fn selected_columns(&self) -> Vec<Column> {
+        // Ids were validated when the settings were loaded or parsed
+        self.columns.iter().flatten()
+            .filter_map(|id| id.parse().ok())
+            .collect()
+    }
+
+    /// Columns to render, in order
+    pub fn column_layout(&self) -> Vec<Column> {
+        let selected = self.selected_columns();
+        if selected.iter().any(|column| Column::DEFAULT.contains(column)) {
+            return selected;
+        }
+        let mut layout = Column::DEFAULT.to_vec();
+        layout.extend(selected);
+        layout
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let mut seen = Vec::new();
+        for column in self.columns.iter().flatten() {
+            let column: Column = column.parse()?;
+            if seen.contains(&column) {
+                return Err(format!("column '{}' is listed twice", column.id()));
+            }
+            seen.push(column);
+        }
+        for id in self.labels.iter().flat_map(|labels| labels.keys()) {
+            id.parse::<Column>().map_err(|e| format!("labels: {}", e))?;
+        }
+        for port in self.alias_overrides.iter().flat_map(|overrides| overrides.keys()) {
+            port.parse::<u32>().map_err(|_| format!("alias_overrides: '{}' is not a port number", port))?;
+        }
+        for vlan in self.vlan_names.iter().flat_map(|names| names.keys()) {
+            vlan.parse::<u32>().map_err(|_| format!("vlan_names: '{}' is not a VLAN id", vlan))?;
+        }
+        for port in self.panel_labels.iter().flat_map(|labels| labels.keys()) {
+            port.parse::<u32>().map_err(|_| format!("panel_labels: '{}' is not a port number", port))?;
+        }
+        if let Some(vendor) = &self.vendor {
+            vendor.parse::<Vendor>()?;
+        }
+        if let Some(format) = &self.format {
+            format.parse::<OutputFormat>()?;
+        }
+        Ok(())
+    }
+}
+
+/// Trunk given in `lacp_overrides`, e.g.
+/// `{ source = 26, ports = [21, 22], name = "Uplink to core" }`
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct NamedLacpOverride {
+    /// Interface whose VLANs the trunk members get
+    pub source: u32,
+    pub ports: Vec<u32>,
+    /// Shown in the LACP column instead of "Trk<source>"
+    pub name: Option<String>,
+}
+
+/// Contents of the TOML configuration file
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct Config {
+    /// Defaults applied to every host
+    #[serde(flatten)]
+    pub settings: Settings,
+
+    /// Table OID overrides applied to every host
+    #[serde(default)]
+    pub oids: HashMap<String, String>,
+
+    /// Settings per vendor profile, e.g. `[vendors.cisco]`
+    #[serde(default)]
+    pub vendors: BTreeMap<String, VendorConfig>,
+
+    /// Settings per host address, e.g. `[hosts."10.1.0.23"]`
+    #[serde(default)]
+    pub hosts: HashMap<String, HostConfig>,
+
+    /// VLANs that switches polled together may name differently on purpose
+    #[serde(default)]
+    pub allow_vlan_name_conflicts: Vec<u32>,
+
+    /// `[[highlight_rules]]`: HTML rows to colour, first matching rule wins
+    #[serde(default)]
+    pub highlight_rules: Vec<HighlightRuleConfig>,
+}
+
+/// One `[[highlight_rules]]` entry, e.g. `when = "tagged contains 666"`
+#[derive(Debug, Clone, Deserialize)]
+pub struct HighlightRuleConfig {
+    pub name: String,
+    pub when: String,
+    pub color: String,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct VendorConfig {
+    #[serde(default)]
+    pub oids: HashMap<String, String>,
+}
+
+/// Settings of one host. There is no SNMP version: only SNMPv2c is supported.
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct HostConfig {
+    #[serde(flatten)]
+    pub settings: Settings,
+
+    #[serde(default)]
+    pub oids: HashMap<String, String>,
+
+    /// Ports never flagged by --infer-trunks
+    #[serde(default)]
+    pub no_infer_trunk_ports: Vec<u32>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Config> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("Failed to read config file {}: {}", path.display(), e))?;
+        let config: Config = toml::from_str(&text)
+            .map_err(|e| anyhow!("Failed to parse config file {}: {}", path.display(), e))?;
+        for warning in unknown_keys(&text) {
+            Event::warning("config-unknown-key", format!("{}: {}", path.display(), warning)).emit();
+        }
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// `$XDG_CONFIG_HOME/switch-vlan-doc/config.toml`, falling back to `~/.config`
+    pub fn default_path() -> Option<PathBuf> {
+        let base = match std::env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+            Some(dir) => PathBuf::from(dir),
+            None => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+        };
+        Some(base.join("switch-vlan-doc").join("config.toml"))
+    }
+
+    /// Settings for a host: global values with the host's own on top
+    pub fn settings_for(&self, host: &str) -> Settings {
+        let mut settings = self.settings.clone();
+        if let Some(host_config) = self.hosts.get(host) {
+            settings.overlay(&host_config.settings);
+        }
+        settings
+    }
+
+    /// Check every OID override up front so mistakes surface at startup
+    fn validate(&self) -> Result<()> {
+        self.settings.validate().map_err(|e| anyhow!("config: {}", e))?;
+        let mut scratch = TableOids::default();
+        scratch.apply_overrides(&self.oids).map_err(|e| anyhow!("[oids]: {}", e))?;
+        let mut profiles: Vec<(Vendor, &str)> = Vec::new();
+        for (vendor, vendor_config) in &self.vendors {
+            let profile = vendor.parse::<Vendor>().map_err(|e| anyhow!("[vendors.{}]: {}", vendor, e))?;
+            // Aliases like "hp" and "aruba" name one profile; which overrides won would be arbitrary
+            if let Some((_, other)) = profiles.iter().find(|(seen, _)| *seen == profile) {
+                bail!("[vendors.{}]: same vendor profile as [vendors.{}]; merge the two", vendor, other);
+            }
+            profiles.push((profile, vendor));
+            scratch.apply_overrides(&vendor_config.oids).map_err(|e| anyhow!("[vendors.{}.oids]: {}", vendor, e))?;
+        }
+        for (host, host_config) in &self.hosts {
+            host_config.settings.validate().map_err(|e| anyhow!("[hosts.\"{}\"]: {}", host, e))?;
+            scratch.apply_overrides(&host_config.oids).map_err(|e| anyhow!("[hosts.\"{}\".oids]: {}", host, e))?;
+        }
+        self.highlight_rules()?;
+        Ok(())
+    }
+
+    /// Parsed `[[highlight_rules]]`, failing on the first invalid or duplicate rule
+    pub fn highlight_rules(&self) -> Result<Vec<HighlightRule>> {
+        let mut rules: Vec<HighlightRule> = Vec::new();
+        for (number, rule) in (1..).zip(&self.highlight_rules) {
+            let parsed = HighlightRule::parse(&rule.name, &rule.when, &rule.color)
+                .map_err(|e| anyhow!("highlight_rules #{} '{}': {}", number, rule.name, e))?;
+            if rules.iter().any(|other| other.name == parsed.name) {
+                bail!("highlight_rules #{} '{}': name already used by an earlier rule", number, rule.name);
+            }
+            rules.push(parsed);
+        }
+        Ok(rules)
+    }
+
+    /// Ports of a host excluded from trunk inference
+    pub fn no_infer_trunk_ports(&self, host: &str) -> &[u32] {
+        self.hosts.get(host)
+            .map(|h| h.no_infer_trunk_ports.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// Table OIDs for a host: defaults < global < vendor profile < host
+    pub fn table_oids(&self, host: &str, vendor: Vendor) -> TableOids {
+        let mut oids = TableOids::default();
+        // Overrides were validated in load(), so these cannot fail
+        let _ = oids.apply_overrides(&self.oids);
+        for (name, vendor_config) in &self.vendors {
+            if name.parse::<Vendor>().ok() == Some(vendor) {
+                let _ = oids.apply_overrides(&vendor_config.oids);
+            }
+        }
+        if let Some(host_config) = self.hosts.get(host) {
+            let _ = oids.apply_overrides(&host_config.oids);
+        }
+        oids
+    }
+}
+
+/// Keys the configuration does not know about, with the line they are on.
+/// They would otherwise be silently ignored, hiding typos like `comunity`.
+fn unknown_keys(text: &str) -> Vec<String> {
+    let Ok(document) = toml_edit::ImDocument::parse(text) else {
+        return Vec::new();
+    };
+    let mut warnings = Vec::new();
+    let top_keys: Vec<&str> = SETTINGS_KEYS.iter().copied().chain(["oids", "vendors", "hosts", "allow_vlan_name_conflicts", "highlight_rules"]).collect();
+    let host_keys: Vec<&str> = SETTINGS_KEYS.iter().copied().chain(["oids", "no_infer_trunk_ports"]).collect();
+    check_keys(text, document.as_table(), "", &top_keys, &mut warnings);
+    for (section, allowed) in [("vendors", vec!["oids"]), ("hosts", host_keys)] {
+        let Some(entries) = document.get(section).and_then(|item| item.as_table_like()) else {
+            continue;
+        };
+        for (name, entry) in entries.iter() {
+            if let Some(table) = entry.as_table_like() {
+                check_keys(text, table, &format!("{}.\"{}\".", section, name), &allowed, &mut warnings);
+            }
+        }
+    }
+    warnings
+}
+
+fn check_keys(text: &str, table: &dyn toml_edit::TableLike, prefix: &str, allowed: &[&str], warnings: &mut Vec<String>) {
+    for (key, _) in table.iter() {
+        if allowed.contains(&key) {
+            continue;
+        }
+        let line = table.get_key_value(key)
+            .and_then(|(key, _)| key.span())
+            .map(|span| text[..span.start].matches('\n').count() + 1);
+        // Not a typo, but still not something the tool can honour
+        let hint = if ["snmp_version", "version"].contains(&key) { " (only SNMPv2c is supported)" } else { "" };
+        match line {
+            Some(line) => warnings.push(format!("unknown key '{}{}' on line {}{}", prefix, key, line, hint)),
+            None => warnings.push(format!("unknown key '{}{}'{}", prefix, key, hint)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+community = "global"
+timeout = 5
+highlight_vlans = [10]
+
+[hosts."sw-core-1"]
+community = "core"
+vendor = "cisco"
+override_lacp = ["26:21,22"]
+title = "Core switch"
+device_name = "sw-core-1"
+min_ports = 48
+
+[hosts."sw-edge-1"]
+if_types = [6]
+virtual_if_types = [136]
+lacp_overrides = [{ source = 49, ports = [47, 48], name = "Uplink to core" }]
+"#;
+
+    #[test]
+    fn host_settings_override_global_ones() {
+        let config: Config = toml::from_str(CONFIG).unwrap();
+        let settings = config.settings_for("sw-core-1");
+        assert_eq!(settings.community.as_deref(), Some("core"));
+        assert_eq!(settings.timeout, Some(5));
+        assert_eq!(settings.highlight_vlans, Some(vec![10]));
+        assert_eq!(settings.vendor.as_deref(), Some("cisco"));
+        assert_eq!(settings.override_lacp, Some(vec!["26:21,22".to_string()]));
+        assert_eq!(settings.title.as_deref(), Some("Core switch"));
+        assert_eq!(settings.device_name.as_deref(), Some("sw-core-1"));
+        assert_eq!(settings.min_ports, Some(48));
+    }
+
+    #[test]
+    fn unknown_host_gets_global_settings() {
+        let config: Config = toml::from_str(CONFIG).unwrap();
+        assert_eq!(config.settings_for("10.1.0.23"), config.settings);
+        assert_eq!(config.settings_for("sw-edge-1").if_types, Some(vec![6]));
+        assert_eq!(config.settings_for("sw-edge-1").virtual_if_types, Some(vec![136]));
+        assert_eq!(config.settings_for("sw-edge-1").lacp_overrides, Some(vec![NamedLacpOverride {
+            source: 49,
+            ports: vec![47, 48],
+            name: Some("Uplink to core".to_string()),
+        }]));
+    }
+
+    #[test]
+    fn command_line_overrides_host_settings() {
+        let config: Config = toml::from_str(CONFIG).unwrap();
+        let mut settings = config.settings_for("sw-core-1");
+        settings.overlay(&Settings {
+            community: Some("cli".to_string()),
+            vendor: Some("generic".to_string()),
+            ..Settings::default()
+        });
+        assert_eq!(settings.community.as_deref(), Some("cli"));
+        assert_eq!(settings.vendor.as_deref(), Some("generic"));
+        // Values not given on the command line still come from the config
+        assert_eq!(settings.timeout, Some(5));
+        assert_eq!(settings.override_lacp, Some(vec!["26:21,22".to_string()]));
+    }
+
+    #[test]
+    fn unknown_keys_are_reported_with_their_line() {
+        let text = "community = \"x\"\ncomunity = \"y\"\n[hosts.\"sw1\"]\nvendr = \"cisco\"\n";
+        assert_eq!(unknown_keys(text), vec![
+            "unknown key 'comunity' on line 2".to_string(),
+            "unknown key 'hosts.\"sw1\".vendr' on line 4".to_string(),
+        ]);
+        assert_eq!(unknown_keys("[hosts.\"sw1\"]\nsnmp_version = 3\n"),
+            vec!["unknown key 'hosts.\"sw1\".snmp_version' on line 2 (only SNMPv2c is supported)".to_string()]);
+    }
+
+    #[test]
+    fn column_lists_add_to_or_replace_the_defaults() {
+        let settings = Settings { columns: Some(vec!["mac".to_string()]), ..Settings::default() };
+        assert_eq!(settings.column_layout(), [Column::Port, Column::Alias, Column::Vlans, Column::Lacp, Column::Mac]);
+        let settings = Settings { columns: Some(vec!["vlans".to_string(), "port".to_string()]), ..Settings::default() };
+        assert_eq!(settings.column_layout(), [Column::Vlans, Column::Port]);
+
+        let err = toml::from_str::<Config>("columns = [\"mac\", \"mac\"]\n").unwrap().validate().unwrap_err();
+        assert!(err.to_string().contains("listed twice"), "{}", err);
+        let err = toml::from_str::<Config>("columns = [\"status\"]\n").unwrap().validate().unwrap_err();
+        assert!(err.to_string().contains("Valid columns: port, alias"), "{}", err);
+    }
+
+    #[test]
+    fn alias_overrides_need_port_numbers() {
+        let config: Config = toml::from_str("[alias_overrides]\n12 = \"Studio A\"\n").unwrap();
+        assert!(config.validate().is_ok());
+        let config: Config = toml::from_str("[alias_overrides]\nsome-port = \"Studio A\"\n").unwrap();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn vlan_names_accept_inline_tables() {
+        let config: Config = toml::from_str("vlan_names = { 531 = \"legacy-storage\", 10 = \"office\" }\n").unwrap();
+        assert!(config.validate().is_ok());
+        let names = config.settings.vlan_names.unwrap();
+        assert_eq!(names.get("531").map(String::as_str), Some("legacy-storage"));
+    }
+
+    #[test]
+    fn invalid_host_vendor_is_rejected() {
+        let config: Config = toml::from_str("[hosts.\"sw1\"]\nvendor = \"nokia\"\n").unwrap();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn vendor_aliases_cannot_both_have_a_section() {
+        let config: Config = toml::from_str("[vendors.aruba.oids]\nif_name = \"1.3.6.1.2.1.31.1.1.1.1\"\n").unwrap();
+        assert!(config.validate().is_ok());
+        let config: Config = toml::from_str("[vendors.aruba]\n[vendors.hp]\n").unwrap();
+        assert_eq!(config.validate().unwrap_err().to_string(),
+            "[vendors.hp]: same vendor profile as [vendors.aruba]; merge the two");
+    }
+
+    #[test]
+    fn format_is_checked_case_insensitively() {
+        let config: Config = toml::from_str("format = \"HTML\"\n").unwrap();
+        assert!(config.validate().is_ok());
+        let config: Config = toml::from_str("[hosts.\"sw1\"]\nformat = \"docx\"\n").unwrap();
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("Expected one of: markdown, html, pdf"), "{}", err);
+    }
+
+    #[test]
+    fn highlight_rules_are_checked_at_load() {
+        let rule = |name: &str, when: &str| format!("[[highlight_rules]]\nname = \"{}\"\nwhen = \"{}\"\ncolor = \"#fdd\"\n", name, when);
+        let config: Config = toml::from_str(&(rule("quarantine", "tagged contains 666") + &rule("unnamed", "alias is-empty"))).unwrap();
+        assert_eq!(config.highlight_rules().unwrap().len(), 2);
+        let config: Config = toml::from_str(&(rule("quarantine", "tagged contains 666") + &rule("oops", "tagd contains 1"))).unwrap();
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.starts_with("highlight_rules #2 'oops': unknown field 'tagd'"), "{}", err);
+        let config: Config = toml::from_str(&(rule("twice", "uplink == true") + &rule("twice", "uplink == false"))).unwrap();
+        assert!(config.validate().unwrap_err().to_string().contains("name already used"));
+    }
+}
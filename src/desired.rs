@@ -0,0 +1,397 @@
+//! The intended per-port configuration, kept in YAML, and how a switch drifted from it.
+//!
+//! ```yaml
+//! ports:
+//!   1:                    # a port number, or a list like "2-8,11" sharing one entry
+//!     alias: Studio 1     # optional; when left out the alias is not compared
+//!     access: 10          # untagged in VLAN 10 and nothing else
+//!   "2-8,11":
+//!     access: 20
+//!   24:
+//!     alias: Uplink
+//!     trunk: [10, 20, 531]  # tagged VLANs
+//!     native: 1           # optional untagged VLAN of the trunk, also the PVID
+//! ```
+//!
+//! Every port takes either `access` or `trunk`, VLANs are 1-4094, and a port
+//! may be listed only once. Errors point at the line of the offending entry.
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::path::Path;
+use anyhow::{Result, anyhow};
+use serde::de::{self, DeserializeSeed, Deserializer, MapAccess, Visitor};
+use serde::Deserialize;
+use crate::PortList;
+use crate::checks::{Check, Violation};
+use crate::output::format_port_set;
+use crate::snapshot::{Snapshot, SnapshotPort};
+
+/// How a port should carry its VLANs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PortMode {
+    /// Untagged in this VLAN only
+    Access(u32),
+    /// Tagged in `vlans`, and untagged in `native` if given
+    Trunk { vlans: BTreeSet<u32>, native: Option<u32> },
+}
+
+impl fmt::Display for PortMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PortMode::Access(vlan) => write!(f, "access VLAN {}", vlan),
+            PortMode::Trunk { vlans, native } => {
+                let vlans: Vec<u32> = vlans.iter().copied().collect();
+                write!(f, "trunk VLANs [{}]", format_port_set(&vlans))?;
+                match native {
+                    Some(native) => write!(f, " native {}", native),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DesiredPort {
+    pub alias: Option<String>,
+    pub mode: PortMode,
+}
+
+/// Contents of a `--desired` YAML file
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct DesiredState {
+    pub ports: BTreeMap<u32, DesiredPort>,
+}
+
+impl DesiredState {
+    pub fn load(path: &Path) -> Result<DesiredState> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("Failed to read desired state {}: {}", path.display(), e))?;
+        DesiredState::from_yaml(&text).map_err(|e| anyhow!("{}: {}", path.display(), e))
+    }
+
+    pub fn from_yaml(text: &str) -> Result<DesiredState> {
+        let file: DesiredFile = serde_yaml::from_str(text).map_err(|e| anyhow!("{}", e))?;
+        Ok(DesiredState { ports: file.ports.0 })
+    }
+
+    /// Ports configured otherwise than desired, listed but missing from the
+    /// switch, or configured on the switch without being listed. Unlisted
+    /// ports still in the default VLAN with no alias are not drift.
+    pub fn drift(&self, snapshot: &Snapshot, default_vlan: u32) -> Vec<Violation> {
+        let violation = |ports: Vec<u32>, detail: String| Violation { check: Check::Drift, ports, vlan: None, detail };
+        let actual: BTreeMap<u32, &SnapshotPort> = snapshot.ports.iter().map(|port| (port.port, port)).collect();
+        let mut violations = Vec::new();
+        let mut missing = Vec::new();
+        for (&port, desired) in &self.ports {
+            match actual.get(&port) {
+                Some(actual) => {
+                    let differences = differences(desired, actual);
+                    if !differences.is_empty() {
+                        violations.push(violation(vec![port], differences.join("; ")));
+                    }
+                }
+                None => missing.push(port),
+            }
+        }
+        if !missing.is_empty() {
+            violations.push(violation(missing, "in the desired state but not on the switch".to_string()));
+        }
+        let unlisted: Vec<u32> = snapshot.ports.iter()
+            .filter(|port| !self.ports.contains_key(&port.port) && !is_unconfigured(port, default_vlan))
+            .map(|port| port.port)
+            .collect();
+        if !unlisted.is_empty() {
+            violations.push(violation(unlisted, "configured on the switch but not in the desired state".to_string()));
+        }
+        violations
+    }
+}
+
+/// What the port carries, in the terms of the desired state
+fn actual_mode(port: &SnapshotPort) -> Option<PortMode> {
+    let tagged: BTreeSet<u32> = port.vlans.difference(&port.untagged).copied().collect();
+    let mut untagged = port.untagged.iter().copied();
+    match (untagged.next(), untagged.next()) {
+        (Some(vlan), None) if tagged.is_empty() => Some(PortMode::Access(vlan)),
+        (native, None) => Some(PortMode::Trunk { vlans: tagged, native }),
+        // Several untagged VLANs fit neither
+        _ => None,
+    }
+}
+
+fn differences(desired: &DesiredPort, actual: &SnapshotPort) -> Vec<String> {
+    let mut differences = Vec::new();
+    if let Some(alias) = &desired.alias {
+        let actual_alias = actual.alias.as_deref().unwrap_or_default();
+        if actual_alias != alias {
+            differences.push(format!("alias \"{}\", expected \"{}\"", actual_alias, alias));
+        }
+    }
+    let mode = actual_mode(actual);
+    if mode.as_ref() != Some(&desired.mode) {
+        let mode = match mode {
+            Some(mode) => mode.to_string(),
+            None => {
+                let untagged: Vec<u32> = actual.untagged.iter().copied().collect();
+                format!("untagged in VLANs [{}]", format_port_set(&untagged))
+            }
+        };
+        differences.push(format!("{}, expected {}", mode, desired.mode));
+    }
+    // The PVID decides where untagged frames go in, whatever the egress lists say
+    let pvid = match desired.mode {
+        PortMode::Access(vlan) => Some(vlan),
+        PortMode::Trunk { native, .. } => native,
+    };
+    if let (Some(pvid), Some(actual_pvid)) = (pvid, actual.pvid) {
+        if actual_pvid != pvid {
+            differences.push(format!("PVID {}, expected {}", actual_pvid, pvid));
+        }
+    }
+    differences
+}
+
+/// Only in the default VLAN, untagged, with no alias and no aggregation
+fn is_unconfigured(port: &SnapshotPort, default_vlan: u32) -> bool {
+    port.alias.as_deref().unwrap_or_default().is_empty()
+        && port.lacp.is_none()
+        && port.pvid.is_none_or(|pvid| pvid == default_vlan)
+        && port.untagged.iter().all(|&vlan| vlan == default_vlan)
+        && port.vlans.iter().all(|&vlan| vlan == default_vlan)
+}
+
+// The schema is checked while it is read rather than afterwards, because
+// serde_yaml only knows the line of an error raised during deserialization
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DesiredFile {
+    ports: DesiredPorts,
+}
+
+struct DesiredPorts(BTreeMap<u32, DesiredPort>);
+
+impl<'de> Deserialize<'de> for DesiredPorts {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct PortsVisitor;
+
+        impl<'de> Visitor<'de> for PortsVisitor {
+            type Value = DesiredPorts;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a mapping from ports to their configuration")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<DesiredPorts, A::Error> {
+                let mut ports = BTreeMap::new();
+                while let Some(keys) = map.next_key_seed(PortKeys(&ports))? {
+                    let port: DesiredPort = map.next_value()?;
+                    for key in keys {
+                        ports.insert(key, port.clone());
+                    }
+                }
+                Ok(DesiredPorts(ports))
+            }
+        }
+
+        deserializer.deserialize_map(PortsVisitor)
+    }
+}
+
+/// A port number or port list, rejecting ports listed before
+struct PortKeys<'a>(&'a BTreeMap<u32, DesiredPort>);
+
+impl PortKeys<'_> {
+    fn check<E: de::Error>(&self, ports: BTreeSet<u32>) -> Result<BTreeSet<u32>, E> {
+        let repeated: Vec<u32> = ports.iter().copied().filter(|port| self.0.contains_key(port)).collect();
+        if repeated.is_empty() {
+            Ok(ports)
+        } else {
+            Err(E::custom(format!("port {} is listed more than once", format_port_set(&repeated))))
+        }
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for PortKeys<'_> {
+    type Value = BTreeSet<u32>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for PortKeys<'_> {
+    type Value = BTreeSet<u32>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a port number or a list like \"1-8,11\"")
+    }
+
+    fn visit_u64<E: de::Error>(self, port: u64) -> Result<Self::Value, E> {
+        let port = u32::try_from(port).map_err(|_| E::custom(format!("port {} is out of range", port)))?;
+        self.check(BTreeSet::from([port]))
+    }
+
+    fn visit_str<E: de::Error>(self, ports: &str) -> Result<Self::Value, E> {
+        let PortList(ports) = ports.parse::<PortList>().map_err(E::custom)?;
+        self.check(ports)
+    }
+}
+
+/// The fields of a port as written, before `access` and `trunk` are checked against each other
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PortFields {
+    alias: Option<String>,
+    access: Option<Vlan>,
+    trunk: Option<Vec<Vlan>>,
+    native: Option<Vlan>,
+}
+
+impl<'de> Deserialize<'de> for DesiredPort {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct PortVisitor;
+
+        impl<'de> Visitor<'de> for PortVisitor {
+            type Value = DesiredPort;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a port configuration with alias, access or trunk and native")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<DesiredPort, A::Error> {
+                let fields = PortFields::deserialize(de::value::MapAccessDeserializer::new(map))?;
+                let mode = match (fields.access, fields.trunk, fields.native) {
+                    (Some(Vlan(vlan)), None, None) => PortMode::Access(vlan),
+                    (None, Some(vlans), native) => PortMode::Trunk {
+                        vlans: vlans.into_iter().map(|Vlan(vlan)| vlan).collect(),
+                        native: native.map(|Vlan(vlan)| vlan),
+                    },
+                    (Some(_), Some(_), _) => return Err(de::Error::custom("give either access or trunk, not both")),
+                    (Some(_), None, Some(_)) => return Err(de::Error::custom("native goes with trunk, not access")),
+                    (None, None, _) => return Err(de::Error::custom("give either access or trunk")),
+                };
+                Ok(DesiredPort { alias: fields.alias, mode })
+            }
+        }
+
+        deserializer.deserialize_map(PortVisitor)
+    }
+}
+
+/// A VLAN id, 1-4094
+struct Vlan(u32);
+
+impl<'de> Deserialize<'de> for Vlan {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let vlan = u32::deserialize(deserializer)?;
+        if (1..=4094).contains(&vlan) {
+            Ok(Vlan(vlan))
+        } else {
+            Err(de::Error::custom(format!("VLAN {} is not between 1 and 4094", vlan)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snapshot::Device;
+
+    const DESIRED: &str = "\
+ports:
+  1:
+    alias: Studio 1
+    access: 10
+  2-3:
+    access: 20
+  24:
+    alias: Uplink
+    trunk: [10, 20]
+    native: 1
+";
+
+    fn port(port: u32, alias: &str, vlans: &[u32], untagged: &[u32]) -> SnapshotPort {
+        SnapshotPort {
+            port,
+            alias: Some(alias.to_string()).filter(|alias| !alias.is_empty()),
+            raw_alias: None,
+            pvid: untagged.first().copied(),
+            vlans: vlans.iter().copied().collect(),
+            untagged: untagged.iter().copied().collect(),
+            lacp: None,
+            dot1x: None,
+            frame_policy: None,
+            uplink: false,
+            admin_down: false,
+            mac: None,
+            config_hash: None,
+            indices: None,
+            panel: None,
+        }
+    }
+
+    fn switch(ports: Vec<SnapshotPort>) -> Snapshot {
+        Snapshot {
+            schema_version: 1,
+            device: Device { ip: "192.0.2.1".to_string(), sys_name: None, sys_descr: None, vendor: "generic".to_string(),
+                chassis_id: None },
+            vlan_names: BTreeMap::new(),
+            ports,
+            neighbors: Vec::new(),
+            lags: Vec::new(),
+            virtual_interfaces: Vec::new(),
+            warnings: Vec::new(),
+            sources: BTreeMap::new(),
+            capacity: None,
+            config_hash: None,
+            meta: None,
+        }
+    }
+
+    fn error(yaml: &str) -> String {
+        DesiredState::from_yaml(yaml).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn port_lists_share_one_entry() {
+        let desired = DesiredState::from_yaml(DESIRED).unwrap();
+        assert_eq!(desired.ports.keys().copied().collect::<Vec<_>>(), [1, 2, 3, 24]);
+        assert_eq!(desired.ports[&3], DesiredPort { alias: None, mode: PortMode::Access(20) });
+        assert_eq!(desired.ports[&24].mode, PortMode::Trunk { vlans: BTreeSet::from([10, 20]), native: Some(1) });
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        assert_eq!(error("ports:\n  1:\n    access: 10\n  2:\n    access: 10\n    trunk: [20]\n"),
+            "ports.2: give either access or trunk, not both at line 5 column 5");
+        assert_eq!(error("ports:\n  1:\n    access: 10\n  2:\n    acess: 10\n"),
+            "ports.2: unknown field `acess`, expected one of `alias`, `access`, `trunk`, `native` at line 5 column 5");
+        assert_eq!(error("ports:\n  1-4:\n    access: 10\n  3:\n    access: 20\n"),
+            "ports: port 3 is listed more than once at line 4 column 3");
+        assert_eq!(error("ports:\n  1:\n    trunk: [10, 5000]\n"),
+            "ports.1.trunk: VLAN 5000 is not between 1 and 4094 at line 3 column 12");
+        assert!(error("ports:\n  uplink:\n    access: 10\n").ends_with("at line 2 column 3"));
+    }
+
+    #[test]
+    fn drift_lists_deviating_missing_and_unlisted_ports() {
+        let desired = DesiredState::from_yaml(DESIRED).unwrap();
+        let snapshot = switch(vec![
+            port(1, "Studio 1", &[10], &[10]),
+            // Port 2 is gone, and 3 moved to the wrong VLAN and renamed
+            port(3, "Spare", &[30], &[30]),
+            port(5, "", &[1], &[1]),
+            port(6, "", &[1, 30], &[1]),
+            port(24, "Uplink", &[1, 10], &[1]),
+        ]);
+        let drift: Vec<String> = desired.drift(&snapshot, 1).iter().map(Violation::to_string).collect();
+        assert_eq!(drift, [
+            "drift: port 3: access VLAN 30, expected access VLAN 20; PVID 30, expected 20",
+            "drift: port 24: trunk VLANs [10] native 1, expected trunk VLANs [10, 20] native 1",
+            "drift: port 2: in the desired state but not on the switch",
+            "drift: port 6: configured on the switch but not in the desired state",
+        ]);
+    }
+}
@@ -0,0 +1,114 @@
+//! Errors from talking to an agent and collecting a switch
+use snmp::SnmpError;
+use thiserror::Error;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+// Exit statuses of the binary; `main` adds 4 (--strict), 5 (some of several
+// switches failed), 6 (--exit-code-on-change), 7 (--compare-vlans) and 8 (--check)
+/// A usage error, or a failure of no more specific class
+pub const EXIT_FAILURE: i32 = 1;
+/// SNMP transport error or timeout
+pub const EXIT_UNREACHABLE: i32 = 2;
+/// The agent denied access
+pub const EXIT_ACCESS_DENIED: i32 = 3;
+/// Interrupted by SIGINT, like shells report it; a partial report may have been written
+pub const EXIT_INTERRUPTED: i32 = 130;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    /// The request could not be sent, e.g. the name does not resolve
+    #[error("cannot reach the agent: {0}")]
+    Transport(String),
+    /// Nothing came back in time; SNMPv2c agents also stay silent on a wrong community
+    #[error("no response from the agent (wrong address or community, or a firewall in between)")]
+    Timeout,
+    #[error("the agent denied access to {oid}; check the community")]
+    AuthFailed { oid: String },
+    #[error("the agent does not implement {name}")]
+    UnsupportedTable { name: String },
+    #[error("malformed value for {oid}: {reason}")]
+    Decode { oid: String, reason: String },
+    #[error("line {line} of the walk is not `OID = TYPE: value`: {text}")]
+    InvalidWalk { line: usize, text: String },
+    /// Fewer physical ports than expected, e.g. because the community only
+    /// sees a restricted view; `tables` are the walks that returned nothing
+    #[error("collected {found} physical port(s), expected at least {required}{}", describe_tables(.tables))]
+    TooFewPorts { found: usize, required: usize, tables: Vec<String> },
+    #[error("{0}")]
+    InvalidArgument(String),
+    /// A bug: collecting this switch panicked
+    #[error("collection panicked: {0}")]
+    Panicked(String),
+}
+
+impl Error {
+    /// Exit status of the binary: 2 when the agent could not be reached,
+    /// 3 when it refused access and 1 for everything else
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Transport(_) | Error::Timeout => EXIT_UNREACHABLE,
+            Error::AuthFailed { .. } => EXIT_ACCESS_DENIED,
+            Error::InvalidArgument(_)
+            | Error::Decode { .. }
+            | Error::InvalidWalk { .. }
+            | Error::UnsupportedTable { .. }
+            | Error::TooFewPorts { .. }
+            | Error::Panicked(_) => EXIT_FAILURE,
+        }
+    }
+
+    /// Classify an error of the snmp crate for a request on `oid`
+    pub(crate) fn from_snmp(error: SnmpError, oid: &[u32]) -> Error {
+        match error {
+            SnmpError::SendError => Error::Transport("sending the request failed".to_string()),
+            // The snmp crate reports a read timeout as a receive error
+            SnmpError::ReceiveError => Error::Timeout,
+            SnmpError::CommunityMismatch => Error::AuthFailed { oid: format_oid(oid) },
+            other => Error::Decode { oid: format_oid(oid), reason: format!("{:?}", other) },
+        }
+    }
+}
+
+fn describe_tables(tables: &[String]) -> String {
+    if tables.is_empty() {
+        String::new()
+    } else {
+        format!("; no rows from {}", tables.join(", "))
+    }
+}
+
+pub(crate) fn format_oid(oid: &[u32]) -> String {
+    oid.iter().map(|n| n.to_string()).collect::<Vec<_>>().join(".")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snmp_errors_are_classified() {
+        let oid = [1, 3, 6, 1, 2, 1, 1, 5, 0];
+        assert!(matches!(Error::from_snmp(SnmpError::ReceiveError, &oid), Error::Timeout));
+        let denied = Error::from_snmp(SnmpError::CommunityMismatch, &oid);
+        assert_eq!(denied.to_string(), "the agent denied access to 1.3.6.1.2.1.1.5.0; check the community");
+        assert!(matches!(Error::from_snmp(SnmpError::AsnEof, &oid), Error::Decode { .. }));
+    }
+
+    #[test]
+    fn exit_codes_follow_the_failure_class() {
+        assert_eq!(Error::Transport(String::new()).exit_code(), 2);
+        assert_eq!(Error::Timeout.exit_code(), 2);
+        assert_eq!(Error::AuthFailed { oid: String::new() }.exit_code(), 3);
+        for other in [
+            Error::UnsupportedTable { name: String::new() },
+            Error::Decode { oid: String::new(), reason: String::new() },
+            Error::InvalidWalk { line: 1, text: String::new() },
+            Error::InvalidArgument(String::new()),
+            Error::Panicked(String::new()),
+            Error::TooFewPorts { found: 0, required: 1, tables: Vec::new() },
+        ] {
+            assert_eq!(other.exit_code(), 1, "{:?}", other);
+        }
+    }
+}
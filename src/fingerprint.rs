@@ -0,0 +1,170 @@
+//! Short, stable fingerprints of port configuration, so monitoring can tell
+//! that a port or a whole switch changed without diffing reports.
+//!
+//! # Canonical form
+//!
+//! A port is serialized as five `key=value` lines, each ending in `\n`, in
+//! this order:
+//!
+//! ```text
+//! pvid=<PVID, or nothing when unknown>
+//! tagged=<VLANs the port is a tagged member of, ascending, comma separated>
+//! untagged=<untagged VLANs, ascending, comma separated>
+//! alias=<alias, or nothing when there is none>
+//! lacp=<ifIndex of the LACP aggregator, or nothing>
+//! ```
+//!
+//! Numbers are decimal without padding. In the alias, `\` is written as `\\`
+//! and a line break as `\n`, so no alias can fake another line. A port's
+//! fingerprint is the first 16 hex digits (lowercase) of the SHA-256 of its
+//! canonical form.
+//!
+//! The switch fingerprint is made the same way from one `<port> <fingerprint>\n`
+//! line per port, in ascending port order.
+//!
+//! Monitoring compares fingerprints across versions of this program: changing
+//! anything above changes every fingerprint, so don't.
+use std::collections::BTreeSet;
+use std::fmt::Write;
+use sha2::{Digest, Sha256};
+use crate::PortRange;
+use crate::snapshot::SnapshotPort;
+
+/// The parts of a port's configuration that go into its fingerprint
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortFingerprint<'a> {
+    pub pvid: Option<u32>,
+    /// Every VLAN the port is a member of, tagged or not
+    pub vlans: BTreeSet<u32>,
+    pub untagged: BTreeSet<u32>,
+    pub alias: Option<&'a str>,
+    pub aggregator: Option<u32>,
+}
+
+impl<'a> PortFingerprint<'a> {
+    /// The text that is hashed, as described in the module documentation
+    pub fn canonical(&self) -> String {
+        let join = |vlans: &mut dyn Iterator<Item = &u32>| {
+            vlans.map(u32::to_string).collect::<Vec<_>>().join(",")
+        };
+        let tagged = join(&mut self.vlans.difference(&self.untagged));
+        let untagged = join(&mut self.untagged.iter());
+        let alias = self.alias.unwrap_or_default().replace('\\', "\\\\").replace('\n', "\\n");
+        let optional = |number: Option<u32>| number.map(|n| n.to_string()).unwrap_or_default();
+        format!("pvid={}\ntagged={}\nuntagged={}\nalias={}\nlacp={}\n",
+            optional(self.pvid), tagged, untagged, alias, optional(self.aggregator))
+    }
+
+    pub fn hash(&self) -> String {
+        short_hash(&self.canonical())
+    }
+
+    /// The configuration a report row shows, with `alias` for one of its ports
+    pub fn of_range(range: &'a PortRange, alias: Option<&'a str>) -> PortFingerprint<'a> {
+        PortFingerprint {
+            pvid: range.pvid,
+            vlans: range.vlan_memberships.iter().copied().collect(),
+            untagged: range.untagged_vlans.iter().copied().collect(),
+            alias,
+            aggregator: range.lacp_info.as_ref().map(|lacp| lacp.selected_agg_id),
+        }
+    }
+}
+
+impl<'a> From<&'a SnapshotPort> for PortFingerprint<'a> {
+    fn from(port: &'a SnapshotPort) -> Self {
+        PortFingerprint {
+            pvid: port.pvid,
+            vlans: port.vlans.clone(),
+            untagged: port.untagged.clone(),
+            alias: port.alias.as_deref(),
+            aggregator: port.lacp.as_ref().map(|lacp| lacp.aggregator),
+        }
+    }
+}
+
+/// Fingerprint of a whole switch from the fingerprints of its ports
+pub fn switch_hash<'a>(ports: impl IntoIterator<Item = (u32, &'a str)>) -> String {
+    let mut ports: Vec<(u32, &str)> = ports.into_iter().collect();
+    ports.sort_unstable();
+    let mut canonical = String::new();
+    for (port, hash) in ports {
+        let _ = writeln!(canonical, "{} {}", port, hash);
+    }
+    short_hash(&canonical)
+}
+
+/// Fingerprints of the ports of a report row: one per distinct alias when
+/// the row groups ports with different aliases
+pub fn range_hashes(range: &PortRange) -> Vec<(u32, String)> {
+    if range.port_aliases.is_empty() {
+        return vec![(range.first_port, PortFingerprint::of_range(range, range.alias.as_deref()).hash())];
+    }
+    range.port_aliases.iter()
+        .map(|(port, alias)| (*port, PortFingerprint::of_range(range, Some(alias.as_str()).filter(|alias| !alias.is_empty())).hash()))
+        .collect()
+}
+
+fn short_hash(text: &str) -> String {
+    Sha256::digest(text.as_bytes())[..8].iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn desk() -> PortFingerprint<'static> {
+        PortFingerprint {
+            pvid: Some(10),
+            vlans: BTreeSet::from([30, 10, 20]),
+            untagged: BTreeSet::from([10]),
+            alias: Some("desk 1"),
+            aggregator: None,
+        }
+    }
+
+    #[test]
+    fn canonical_form_is_documented_layout() {
+        assert_eq!(desk().canonical(), "pvid=10\ntagged=20,30\nuntagged=10\nalias=desk 1\nlacp=\n");
+        let unknown = PortFingerprint { pvid: None, vlans: BTreeSet::new(), untagged: BTreeSet::new(),
+            alias: Some("a\\b\nlacp=1"), aggregator: Some(49) };
+        assert_eq!(unknown.canonical(), "pvid=\ntagged=\nuntagged=\nalias=a\\\\b\\nlacp=1\nlacp=49\n");
+    }
+
+    #[test]
+    fn hashes_are_pinned() {
+        // These values are part of the interface: if they change, every
+        // fingerprint stored by monitoring changes with them
+        assert_eq!(desk().hash(), "363edc559b1aa810");
+        assert_eq!(switch_hash([(2, "363edc559b1aa810"), (1, "0000000000000000")]),
+            switch_hash([(1, "0000000000000000"), (2, "363edc559b1aa810")]));
+    }
+
+    #[test]
+    fn every_field_changes_the_hash() {
+        let base = desk().hash();
+        assert_ne!(PortFingerprint { pvid: Some(20), ..desk() }.hash(), base);
+        assert_ne!(PortFingerprint { vlans: BTreeSet::from([10, 20]), ..desk() }.hash(), base);
+        assert_ne!(PortFingerprint { untagged: BTreeSet::from([10, 20]), ..desk() }.hash(), base);
+        assert_ne!(PortFingerprint { alias: None, ..desk() }.hash(), base);
+        assert_ne!(PortFingerprint { aggregator: Some(49), ..desk() }.hash(), base);
+    }
+
+    #[test]
+    fn ranges_hash_like_their_ports() {
+        let range = PortRange {
+            first_port: 1,
+            last_port: 2,
+            alias: Some("desk 1".to_string()),
+            pvid: Some(10),
+            vlan_memberships: HashSet::from([10, 20, 30]),
+            untagged_vlans: HashSet::from([10]),
+            port_aliases: vec![(1, "desk 1".to_string()), (2, String::new())],
+            ..Default::default()
+        };
+        let hashes = range_hashes(&range);
+        assert_eq!(hashes[0], (1, desk().hash()));
+        assert_eq!(hashes[1], (2, PortFingerprint { alias: None, ..desk() }.hash()));
+    }
+}
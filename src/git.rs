@@ -0,0 +1,120 @@
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use anyhow::{Result, anyhow};
+
+/// Git work tree that holds the generated documentation. Everything goes
+/// through the git CLI so the user's own configuration (hooks, signing,
+/// author) applies. Nothing is ever pushed.
+pub struct GitRepo {
+    /// Top level of the work tree; git runs here and paths are relative to it
+    dir: PathBuf,
+}
+
+impl GitRepo {
+    /// The work tree containing `file`, which need not exist yet
+    pub fn containing(file: &Path) -> Result<GitRepo> {
+        let output = GitRepo { dir: parent_dir(file)? }.git(&["rev-parse", "--show-toplevel"])?;
+        if !output.status.success() {
+            return Err(anyhow!("{} is not inside a git work tree", file.display()));
+        }
+        let top = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        Ok(GitRepo { dir: PathBuf::from(top) })
+    }
+
+    /// `file` relative to the top of the work tree
+    fn relative(&self, file: &Path) -> Result<String> {
+        let name = file.file_name().ok_or_else(|| anyhow!("{} is not a file name", file.display()))?;
+        let absolute = parent_dir(file)?.join(name);
+        absolute.strip_prefix(&self.dir)
+            .map(|relative| relative.to_string_lossy().into_owned())
+            .map_err(|_| anyhow!("{} is outside the git work tree {}", file.display(), self.dir.display()))
+    }
+
+    /// Fail if changes are already staged, unless the caller allows it
+    pub fn check_index_clean(&self, allow_dirty: bool) -> Result<()> {
+        if allow_dirty {
+            return Ok(());
+        }
+        let output = self.git(&["diff", "--cached", "--quiet"])?;
+        match output.status.code() {
+            Some(0) => Ok(()),
+            Some(1) => Err(anyhow!("The git index in {} has staged changes; commit them first or pass --git-allow-dirty",
+                self.dir.display())),
+            _ => Err(self.failure("diff --cached", &output)),
+        }
+    }
+
+    /// Contents of `file` as of HEAD, if it is tracked there
+    pub fn head_contents(&self, file: &Path) -> Option<String> {
+        let spec = format!("HEAD:{}", self.relative(file).ok()?);
+        let output = self.git(&["show", &spec]).ok()?;
+        output.status.success().then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    /// Stage `files` and commit only them. Returns false when they match
+    /// HEAD and there was nothing to commit.
+    pub fn commit(&self, files: &[&Path], message: &str) -> Result<bool> {
+        let names = files.iter()
+            .map(|file| self.relative(file))
+            .collect::<Result<Vec<String>>>()?;
+        let mut args = vec!["add", "--"];
+        args.extend(names.iter().map(String::as_str));
+        self.run(&args)?;
+
+        let mut args = vec!["diff", "--cached", "--quiet", "--"];
+        args.extend(names.iter().map(String::as_str));
+        let output = self.git(&args)?;
+        match output.status.code() {
+            Some(0) => return Ok(false),
+            Some(1) => {}
+            _ => return Err(self.failure("diff --cached", &output)),
+        }
+
+        // Naming the paths keeps anything else in the index out of the commit
+        let mut args = vec!["commit", "--quiet", "--message", message, "--"];
+        args.extend(names.iter().map(String::as_str));
+        self.run(&args)?;
+        Ok(true)
+    }
+
+    fn git(&self, args: &[&str]) -> Result<Output> {
+        Command::new("git")
+            .arg("-C")
+            .arg(&self.dir)
+            .args(args)
+            .output()
+            .map_err(|e| anyhow!("Failed to run git: {}", e))
+    }
+
+    fn run(&self, args: &[&str]) -> Result<()> {
+        let output = self.git(args)?;
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(self.failure(args.first().copied().unwrap_or_default(), &output))
+        }
+    }
+
+    fn failure(&self, command: &str, output: &Output) -> anyhow::Error {
+        anyhow!("git {} failed in {}: {}", command, self.dir.display(),
+            String::from_utf8_lossy(&output.stderr).trim())
+    }
+}
+
+/// Canonical directory holding `file`
+fn parent_dir(file: &Path) -> Result<PathBuf> {
+    let parent = match file.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    parent.canonicalize().map_err(|e| anyhow!("Cannot resolve {}: {}", parent.display(), e))
+}
+
+/// Commit subject, e.g. "sw-core-1: update port documentation (3 ports changed)"
+pub fn commit_message(device: &str, changed_ports: Option<usize>) -> String {
+    match changed_ports {
+        Some(1) => format!("{}: update port documentation (1 port changed)", device),
+        Some(count) => format!("{}: update port documentation ({} ports changed)", device, count),
+        None => format!("{}: update port documentation", device),
+    }
+}
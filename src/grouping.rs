@@ -0,0 +1,232 @@
+//! Grouping ports with identical configuration into ranges
+use std::sync::Arc;
+use crate::{LastChange, PortConfig, PortRange};
+
+/// How ports are combined into rows
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GroupMode {
+    /// Runs of ports with the same configuration
+    #[default]
+    Adjacent,
+    /// One row per distinct configuration, wherever its ports are
+    Dedupe,
+    /// One row per port
+    PerPort,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Grouping {
+    pub mode: GroupMode,
+    /// Let a run continue across missing port numbers, listing them as exceptions
+    pub allow_gaps: bool,
+    /// Group ports whose aliases differ
+    pub ignore_alias: bool,
+}
+
+/// Whether both ports took their VLANs from the same aggregator
+fn shared_aggregator_vlans(a: &PortConfig, b: &PortConfig) -> bool {
+    let agg_vlans = |port: &PortConfig| port.lacp_info.as_ref().and_then(|lacp| lacp.agg_vlans.clone());
+    matches!((agg_vlans(a), agg_vlans(b)), (Some(a), Some(b)) if Arc::ptr_eq(&a, &b))
+}
+
+impl Grouping {
+    fn configs_match(&self, a: &PortConfig, b: &PortConfig) -> bool {
+        // Cheap fields first, so that only likely matches compare whole VLAN sets.
+        // Members of one aggregator share its VLANs, which then compare by identity.
+        let similar = a.pvid == b.pvid &&
+            a.uplink == b.uplink &&
+            a.inferred_trunk == b.inferred_trunk &&
+            a.dot1x == b.dot1x &&
+            a.frame_policy == b.frame_policy &&
+            (self.ignore_alias || a.alias == b.alias) &&
+            a.lacp_info == b.lacp_info;
+        if !similar {
+            return false;
+        }
+        if a.inherited_vlans && b.inherited_vlans && shared_aggregator_vlans(a, b) {
+            return true;
+        }
+        a.vlan_memberships == b.vlan_memberships &&
+        a.untagged_vlans == b.untagged_vlans
+    }
+
+    /// Group ports, which must be sorted by port number, into ranges
+    pub fn group(&self, ports: &[PortConfig]) -> Vec<PortRange> {
+        match self.mode {
+            GroupMode::PerPort => ports.iter()
+                .map(|config| config.clone().into_range(config.port_num, config.port_num))
+                .collect(),
+            GroupMode::Dedupe => self.dedupe(ports),
+            GroupMode::Adjacent => self.adjacent(ports),
+        }
+    }
+
+    fn dedupe(&self, ports: &[PortConfig]) -> Vec<PortRange> {
+        // Bucket ports by configuration regardless of adjacency; buckets stay in order of their lowest port
+        // (first config seen, member ports, member aliases)
+        type Bucket = (PortConfig, Vec<u32>, Vec<(u32, String)>);
+        let mut buckets: Vec<Bucket> = Vec::new();
+        for config in ports {
+            let port_num = config.port_num;
+            let alias = config.alias.clone().map(|alias| (port_num, alias));
+            match buckets.iter_mut().find(|(first, _, _)| self.configs_match(first, config)) {
+                Some((first, ports, aliases)) => {
+                    first.last_change = LastChange::most_recent(first.last_change, config.last_change);
+                    ports.push(port_num);
+                    aliases.extend(alias);
+                }
+                None => buckets.push((config.clone(), vec![port_num], alias.into_iter().collect())),
+            }
+        }
+        buckets.into_iter()
+            .map(|(config, ports, aliases)| {
+                let first = ports.first().copied().unwrap_or(config.port_num);
+                let last = ports.last().copied().unwrap_or(config.port_num);
+                let mut range = config.into_range(first, last);
+                range.port_aliases = aliases;
+                range.member_ports = Some(ports);
+                range
+            })
+            .collect()
+    }
+
+    fn adjacent(&self, ports: &[PortConfig]) -> Vec<PortRange> {
+        let mut port_ranges = Vec::new();
+        let mut current_config: Option<PortConfig> = None;
+        let mut current_start: u32 = 0;
+        let mut current_end: u32 = 0;
+        let mut current_except: Vec<u32> = Vec::new();
+        let mut current_aliases: Vec<(u32, String)> = Vec::new();
+
+        for config in ports {
+            let port_num = config.port_num;
+            match &mut current_config {
+                Some(current) => {
+                    let adjacent = port_num == current_end + 1;
+                    if self.configs_match(current, config) && (adjacent || self.allow_gaps) {
+                        // Extend current range, remembering skipped port numbers
+                        current_except.extend(current_end + 1..port_num);
+                        current_aliases.extend(config.alias.iter().map(|alias| (port_num, alias.clone())));
+                        current_end = port_num;
+                        current.last_change = LastChange::most_recent(current.last_change, config.last_change);
+                    } else {
+                        // End current range and start new one
+                        if let Some(current) = current_config.take() {
+                            let mut range = current.into_range(current_start, current_end);
+                            range.except_ports = std::mem::take(&mut current_except);
+                            range.port_aliases = std::mem::take(&mut current_aliases);
+                            port_ranges.push(range);
+                        }
+                        current_aliases.extend(config.alias.iter().map(|alias| (port_num, alias.clone())));
+                        current_config = Some(config.clone());
+                        current_start = port_num;
+                        current_end = port_num;
+                    }
+                }
+                None => {
+                    current_aliases.extend(config.alias.iter().map(|alias| (port_num, alias.clone())));
+                    current_config = Some(config.clone());
+                    current_start = port_num;
+                    current_end = port_num;
+                }
+            }
+        }
+
+        // Add the last range if it exists
+        if let Some(current) = current_config {
+            let mut range = current.into_range(current_start, current_end);
+            range.except_ports = current_except;
+            range.port_aliases = current_aliases;
+            port_ranges.push(range);
+        }
+        port_ranges
+    }
+}
+
+/// Group runs of adjacent, identically configured ports, which must be sorted by port number
+pub fn group_ranges(ports: &[PortConfig]) -> Vec<PortRange> {
+    Grouping::default().group(ports)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collector::collect_from;
+    use crate::output::{generate_port_table, Columns, OutputFormat, ReportInfo};
+    use crate::snmp_utils::RecordedWalk;
+    use crate::CollectOptions;
+
+    const WALK: &str = include_str!("../tests/fixtures/generic-8port.walk");
+
+    fn ports() -> Vec<PortConfig> {
+        let mut walk = RecordedWalk::parse(WALK).unwrap();
+        collect_from(&mut walk, "10.1.0.50", &CollectOptions::default()).unwrap().ports
+    }
+
+    fn spans(ranges: &[PortRange]) -> Vec<(u32, u32)> {
+        ranges.iter().map(|range| (range.first_port, range.last_port)).collect()
+    }
+
+    #[test]
+    fn adjacent_ports_group_unless_their_aliases_differ() {
+        assert_eq!(spans(&group_ranges(&ports())), [(1, 1), (2, 2), (3, 3), (4, 4), (5, 6), (7, 7), (8, 8)]);
+
+        let grouping = Grouping { ignore_alias: true, ..Grouping::default() };
+        let ranges = grouping.group(&ports());
+        assert_eq!(spans(&ranges), [(1, 4), (5, 6), (7, 7), (8, 8)]);
+        assert_eq!(ranges[0].port_aliases.len(), 4);
+    }
+
+    #[test]
+    fn gaps_and_dedupe_join_separated_ports() {
+        let mut ports = ports();
+        ports.retain(|port| port.port_num != 2);
+        let ignore_alias = Grouping { ignore_alias: true, ..Grouping::default() };
+        assert_eq!(spans(&ignore_alias.group(&ports))[..2], [(1, 1), (3, 4)]);
+
+        let gaps = Grouping { allow_gaps: true, ..ignore_alias };
+        let ranges = gaps.group(&ports);
+        assert_eq!((ranges[0].first_port, ranges[0].last_port, &ranges[0].except_ports), (1, 4, &vec![2]));
+
+        let dedupe = Grouping { mode: GroupMode::Dedupe, ..ignore_alias };
+        let ranges = dedupe.group(&ports);
+        assert_eq!(ranges[0].member_ports, Some(vec![1, 3, 4]));
+    }
+
+    #[test]
+    fn per_port_mode_keeps_every_port() {
+        let grouping = Grouping { mode: GroupMode::PerPort, ignore_alias: true, ..Grouping::default() };
+        assert_eq!(grouping.group(&ports()).len(), 8);
+    }
+
+    #[test]
+    fn aggregator_members_match_by_their_shared_vlans() {
+        let ports = ports();
+        let members: Vec<&PortConfig> = ports.iter().filter(|port| port.inherited_vlans).collect();
+        assert_eq!(members.iter().map(|port| port.port_num).collect::<Vec<_>>(), [5, 6]);
+        assert!(shared_aggregator_vlans(members[0], members[1]));
+        // Sets inherited from the same aggregator are not compared again
+        let mut tampered = members[1].clone();
+        tampered.vlan_memberships.clear();
+        assert!(Grouping::default().configs_match(members[0], &tampered));
+        tampered.inherited_vlans = false;
+        assert!(!Grouping::default().configs_match(members[0], &tampered));
+    }
+
+    /// Rendered before grouping moved out of the binary; the output must not change
+    #[test]
+    fn rendering_matches_the_checked_in_report() {
+        let mut walk = RecordedWalk::parse(WALK).unwrap();
+        let switch = collect_from(&mut walk, "10.1.0.50", &CollectOptions::default()).unwrap();
+        let info = ReportInfo {
+            ip_address: "10.1.0.50".to_string(),
+            device_name: "sw-test".to_string(),
+            title: crate::output::DEFAULT_TITLE.to_string(),
+            sources: switch.sources.clone(),
+            ..ReportInfo::default()
+        };
+        let markdown = generate_port_table(&group_ranges(&switch.ports), &switch.vlans, OutputFormat::Markdown,
+            &Columns::default(), &info);
+        assert_eq!(markdown, include_str!("../tests/fixtures/generic-8port.md"));
+    }
+}
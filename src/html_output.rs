@@ -1,16 +1,47 @@
 use std::collections::HashMap;
+use std::io::{self, Write};
 use crate::PortRange;
-use chrono::Local;
+use crate::rules::HighlightRule;
+use crate::snapshot::{PortChange, SnapshotPort};
+use crate::output::{lag_cells, lag_vlan_cell, topology_cells, virtual_cells, ReportInfo, LAG_HEADERS, TOPOLOGY_HEADERS, VIRTUAL_HEADERS, footer_lines, format_alias, PARTIAL_BANNER, format_config_hash, format_device, format_distinct, format_dot1x, format_port_range, format_port_set, format_sources, format_frame_policy, format_if_index, format_bridge_port, indices_mismatched, format_last_change, format_panel, format_range_mac, is_recent_change, Column, Columns};
+use crate::vlan_cell::{format_vlan_cell, format_vlan_label};
+use crate::matrix::VlanMatrix;
+use crate::topology::Topology;
 
-pub fn generate_port_table(
+/// Number of distinct row colours available for highlighted VLANs
+const HIGHLIGHT_PALETTES: usize = 5;
+
+/// Make text safe inside HTML elements and attribute values
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+pub fn write_port_table(
+    w: &mut dyn Write,
     port_ranges: &[PortRange],
     vlan_names: &HashMap<u32, String>,
-    ip_address: &str,
-) -> String {
-    let mut table = String::new();
-    
+    columns: &Columns,
+    info: &ReportInfo,
+) -> io::Result<()> {
+    // Names, aliases and descriptions come from the switch; escape all of them
+    let vlan_names: HashMap<u32, String> = vlan_names.iter()
+        .map(|(&vlan_id, name)| (vlan_id, escape_html(name)))
+        .collect();
+    let vlan_names = &vlan_names;
+    writeln!(w, "<title>{} ({})</title>", escape_html(&info.title), escape_html(&info.device_name))?;
     // Start HTML with CSS styling
-    table.push_str(r#"<style>
+    w.write_all(br#"<style>
     body {
         max-width: 1200px;
         margin: 0 auto;
@@ -32,7 +63,45 @@ pub fn generate_port_table(
         color: #666;
         font-size: 18px;
     }
-    .generated-time {
+    .badge {
+        display: inline-block;
+        margin-left: 6px;
+        padding: 1px 6px;
+        border-radius: 8px;
+        font-size: 11px;
+        color: white;
+    }
+    .badge.uplink {
+        background-color: #5a6fd6;
+    }
+    .pvid-mismatch {
+        color: #b00020;
+        font-weight: bold;
+    }
+    .no-vlans {
+        color: #b00020;
+        font-weight: bold;
+    }
+    .warnings {
+        color: #b00020;
+    }
+    .report-note {
+        color: #8a5a00;
+        font-size: 14px;
+        margin-bottom: 10px;
+    }
+    .data-sources {
+        color: #666;
+        font-size: 12px;
+    }
+    .report-footer {
+        margin-top: 30px;
+        padding-top: 10px;
+        border-top: 2px solid #eee;
+        color: #666;
+        font-size: 12px;
+    }
+    .generated-time, .vtp-info {
         color: #666;
         font-size: 14px;
         margin-bottom: 20px;
@@ -64,30 +133,66 @@ pub fn generate_port_table(
         padding-top: 24px;
         padding-bottom: 24px;
     }
-    .port-table tr.vlan-10 {
+    .port-table tr.highlight-1 {
         background-color: #e6f3ff;
     }
-    .port-table tr.vlan-10:hover {
+    .port-table tr.highlight-1:hover {
         background-color: #d9edff;
     }
-    .port-table tr.vlan-531 {
+    .port-table tr.highlight-2 {
         background-color: #e6ffe6;
     }
-    .port-table tr.vlan-531:hover {
+    .port-table tr.highlight-2:hover {
         background-color: #d9ffd9;
     }
-    .port-table tr.vlan-10.even {
+    .port-table tr.highlight-3 {
+        background-color: #fffbe6;
+    }
+    .port-table tr.highlight-3:hover {
+        background-color: #fff6cc;
+    }
+    .port-table tr.highlight-4 {
+        background-color: #f9e6ff;
+    }
+    .port-table tr.highlight-4:hover {
+        background-color: #f2ccff;
+    }
+    .port-table tr.highlight-5 {
+        background-color: #e6fffb;
+    }
+    .port-table tr.highlight-5:hover {
+        background-color: #ccfff6;
+    }
+    .port-table tr.highlight-1.even {
         background-color: #d9edff;
     }
-    .port-table tr.vlan-10.even:hover {
+    .port-table tr.highlight-1.even:hover {
         background-color: #cce7ff;
     }
-    .port-table tr.vlan-531.even {
+    .port-table tr.highlight-2.even {
         background-color: #d9ffd9;
     }
-    .port-table tr.vlan-531.even:hover {
+    .port-table tr.highlight-2.even:hover {
         background-color: #ccffcc;
     }
+    .port-table tr.highlight-3.even {
+        background-color: #fff6cc;
+    }
+    .port-table tr.highlight-3.even:hover {
+        background-color: #fff0b3;
+    }
+    .port-table tr.highlight-4.even {
+        background-color: #f2ccff;
+    }
+    .port-table tr.highlight-4.even:hover {
+        background-color: #ebb3ff;
+    }
+    .port-table tr.highlight-5.even {
+        background-color: #ccfff6;
+    }
+    .port-table tr.highlight-5.even:hover {
+        background-color: #b3fff0;
+    }
     .port-table tr.multi-tagged {
         background-color: #fff3e6;
     }
@@ -112,98 +217,106 @@ pub fn generate_port_table(
     .port-table tr.lacp.even:hover {
         background-color: #ccccff;
     }
+    .port-table td.deviates {
+        color: #b30000;
+        font-weight: bold;
+    }
+    .port-table td.recent-change {
+        background-color: #fff8c4;
+        font-weight: bold;
+    }
+    .lag-table tr.stale td {
+        color: #b30000;
+        font-weight: bold;
+    }
+    .port-table tr.changed td:first-child {
+        border-left: 4px solid #e69500;
+    }
+    .port-table tr.added td {
+        background-color: #d9ffd9;
+    }
+    .removed-table tr td {
+        color: #808080;
+        text-decoration: line-through;
+    }
 </style>
-<div class="device-header">
-    <h1>Switch Port Configuration</h1>
-    <h2>Device: "#);
-
-    table.push_str(ip_address);
-    table.push_str(r#"</h2>
-    <div class="generated-time">Generated on: "#);
-
-    let now = Local::now();
-    table.push_str(&format!("{}</div>", now.format("%Y-%m-%d %H:%M:%S")));
-    table.push_str(r#"</div>
-<table class="port-table">
-    <thead>
-        <tr>
-            <th>Port</th>
-            <th>Alias</th>
-            <th>VLAN(s)</th>
-            <th>LACP</th>
-        </tr>
-    </thead>
-    <tbody>"#);
+"#)?;
+    // Later rules first, so that the earliest matching rule's colour wins
+    if !info.highlight_rules.is_empty() {
+        writeln!(w, "<style>")?;
+        for rule in info.highlight_rules.iter().rev() {
+            writeln!(w, "    .port-table tr.{} td {{", rule.class())?;
+            writeln!(w, "        background-color: {};", rule.color)?;
+            writeln!(w, "    }}")?;
+        }
+        writeln!(w, "</style>")?;
+    }
+    if info.interrupted {
+        w.write_all(br#"<style>
+    .partial-banner {
+        background-color: #ffe0e0;
+        border: 2px solid #b30000;
+        color: #b30000;
+        font-size: 20px;
+        font-weight: bold;
+        padding: 10px;
+    }
+</style>
+"#)?;
+    }
+    // One element per line, indented by nesting, so that a changed port is a changed line
+    writeln!(w, r#"<div class="device-header">"#)?;
+    writeln!(w, "    <h1>{}</h1>", escape_html(&info.title))?;
+    if info.interrupted {
+        writeln!(w, r#"    <div class="partial-banner">{}</div>"#, PARTIAL_BANNER)?;
+    }
+    writeln!(w, "    <h2>Device: {}</h2>", escape_html(&format_device(info)))?;
+    if let Some(generated_on) = &info.generated_on {
+        writeln!(w, r#"    <div class="generated-time">Generated on: {}</div>"#, escape_html(generated_on))?;
+    }
+    if let Some(config_hash) = &info.config_hash {
+        writeln!(w, r#"    <div class="config-hash">Config hash: {}</div>"#, config_hash)?;
+    }
+    if let Some(vtp) = &info.vtp {
+        writeln!(w, r#"    <div class="vtp-info">VTP domain: {} (mode: {})</div>"#, escape_html(&vtp.domain), vtp.mode_name())?;
+    }
+    for note in &info.notes {
+        writeln!(w, r#"    <div class="report-note">Note: {}</div>"#, escape_html(note))?;
+    }
+    writeln!(w, "</div>")?;
+    writeln!(w, r#"<table class="port-table">"#)?;
+    writeln!(w, "    <thead>")?;
+    writeln!(w, "        <tr>")?;
+    for &column in &columns.layout {
+        writeln!(w, "            <th>{}</th>", escape_html(columns.header(column)))?;
+    }
+    writeln!(w, "        </tr>")?;
+    writeln!(w, "    </thead>")?;
+    writeln!(w, "    <tbody>")?;
 
     for (index, range) in port_ranges.iter().enumerate() {
-        if range.first_port > 52 {
-            continue;
+        // Port number/range, with a badge on likely uplinks
+        let mut port = escape_html(&format_port_range(range));
+        if range.uplink {
+            port.push_str(r#"<span class="badge uplink">uplink</span>"#);
         }
 
-        // Port number/range
-        let port = if range.first_port == range.last_port {
-            format!("{}", range.first_port)
-        } else {
-            format!("{}-{}", range.first_port, range.last_port)
-        };
-
         // Alias (if available)
-        let alias = range.alias.as_deref().unwrap_or_default();
+        let alias = escape_html(&format_alias(range));
 
-        // VLAN information
-        let mut vlan_info = Vec::new();
-        if !range.vlan_memberships.is_empty() {
-            let mut tagged_vlans: Vec<u32> = range.vlan_memberships.iter().copied().collect();
-            tagged_vlans.sort_unstable();
-            let tagged_vlans: Vec<String> = tagged_vlans.iter()
-                .map(|&vlan_id| {
-                    if vlan_id == 1 {
-                        vlan_id.to_string()
-                    } else if let Some(name) = vlan_names.get(&vlan_id) {
-                        format!("{} ({})", name, vlan_id)
-                    } else {
-                        vlan_id.to_string()
-                    }
-                })
-                .collect();
-            vlan_info.push(format!("Tagged:[{}]", tagged_vlans.join(", ")));
-        }
-        if !range.untagged_vlans.is_empty() {
-            let mut untagged_vlans: Vec<u32> = range.untagged_vlans.iter().copied().collect();
-            untagged_vlans.sort_unstable();
-            let untagged_vlans: Vec<String> = untagged_vlans.iter()
-                .map(|&vlan_id| {
-                    if vlan_id == 1 {
-                        vlan_id.to_string()
-                    } else if let Some(name) = vlan_names.get(&vlan_id) {
-                        format!("{} ({})", name, vlan_id)
-                    } else {
-                        vlan_id.to_string()
-                    }
-                })
-                .collect();
-            vlan_info.push(format!("Untagged:[{}]", untagged_vlans.join(", ")));
-        }
-        let vlans = if range.untagged_vlans.len() == 1 
-            && range.vlan_memberships.len() <= 1  // Allow the same VLAN to be tagged and untagged
-            && range.pvid == *range.untagged_vlans.iter().next().unwrap() {
-            // If only one untagged VLAN exists and PVID matches it
-            let vlan_id = range.untagged_vlans.iter().next().unwrap();
-            if *vlan_id == 1 {
-                vlan_id.to_string()
-            } else if let Some(name) = vlan_names.get(vlan_id) {
-                format!("{} ({})", name, vlan_id)
-            } else {
-                vlan_id.to_string()
-            }
-        } else {
-            vlan_info.join(" ")
-        };
+        let vlans = format_vlan_cell(range, vlan_names, &info.vlan_style)
+            .render(|class, text| format!(r#"<span class="{}">{}</span>"#, class, text));
 
         // LACP information
         let lacp = if let Some(lacp_info) = &range.lacp_info {
-            let agg_name = lacp_info.agg_name.as_deref().unwrap_or("Unknown");
-            agg_name.to_string()
+            let agg_name = escape_html(lacp_info.agg_name.as_deref().unwrap_or("Unknown"));
+            match &lacp_info.load_balance {
+                Some(mode) => format!("{} ({})", agg_name, escape_html(mode)),
+                None => agg_name,
+            }
+        } else if range.inferred_trunk {
+            // Not read from the device, so make that obvious
+            "<em>possible trunk group (inferred)</em>".to_string()
         } else {
             String::new()
         };
@@ -216,13 +329,13 @@ pub fn generate_port_table(
             row_classes.push("multi-port");
         }
         
-        // VLAN-specific classes
+        // Highlighted access VLANs, colours assigned in configured order
+        let highlight_class;
         if range.untagged_vlans.len() == 1 {
             let untagged_vlan = *range.untagged_vlans.iter().next().unwrap();
-            if untagged_vlan == 10 {
-                row_classes.push("vlan-10");
-            } else if untagged_vlan == 531 {
-                row_classes.push("vlan-531");
+            if let Some(index) = info.highlight_vlans.iter().position(|&v| v == untagged_vlan) {
+                highlight_class = format!("highlight-{}", index % HIGHLIGHT_PALETTES + 1);
+                row_classes.push(highlight_class.as_str());
             }
         }
 
@@ -241,30 +354,348 @@ pub fn generate_port_table(
             row_classes.push("even");
         }
 
+        // Configured highlight rules, named in data-rules too
+        let matched: Vec<&HighlightRule> = info.highlight_rules.iter().filter(|rule| rule.matches(range)).collect();
+        let rule_classes: Vec<String> = matched.iter().map(|rule| rule.class()).collect();
+        row_classes.extend(rule_classes.iter().map(String::as_str));
+
+        // Changes since --diff, described in a tooltip
+        let changes: Vec<(u32, &PortChange)> = match &info.changes {
+            Some(changes) => range.ports().into_iter()
+                .filter_map(|port| changes.get(&port).map(|change| (port, change)))
+                .collect(),
+            None => Vec::new(),
+        };
+        if changes.iter().any(|(_, change)| matches!(change, PortChange::Changed(_))) {
+            row_classes.push("changed");
+        }
+        if changes.iter().any(|(_, change)| matches!(change, PortChange::Added)) {
+            row_classes.push("added");
+        }
+
         // Add row to table with classes
-        let class_str = if !row_classes.is_empty() {
+        let mut class_str = if !row_classes.is_empty() {
             format!(" class=\"{}\"", row_classes.join(" "))
         } else {
             String::new()
         };
+        let tooltip: Vec<String> = match changes.as_slice() {
+            [(_, change)] if range.ports().len() == 1 => vec![change.to_string()],
+            changes => changes.iter().map(|(port, change)| format!("port {}: {}", port, change)).collect(),
+        };
+        if !tooltip.is_empty() {
+            class_str.push_str(&format!(" title=\"{}\"", escape_html(&tooltip.join("; "))));
+        }
+        if !matched.is_empty() {
+            let names: Vec<&str> = matched.iter().map(|rule| rule.name.as_str()).collect();
+            class_str.push_str(&format!(" data-rules=\"{}\"", names.join(" ")));
+        }
 
-        table.push_str(&format!(r#"        <tr{}>
-            <td>{}</td>
-            <td>{}</td>
-            <td>{}</td>
-            <td>{}</td>
-        </tr>"#,
-            class_str,
-            port,
-            alias,
-            vlans,
-            lacp
-        ));
+        writeln!(w, "        <tr{}>", class_str)?;
+        for column in &columns.layout {
+            let (cell_class, cell) = match column {
+                Column::Port => ("", port.clone()),
+                Column::Alias => ("", alias.clone()),
+                Column::Vlans => ("", vlans.clone()),
+                Column::Lacp => ("", lacp.clone()),
+                Column::Dot1x => ("", escape_html(&format_dot1x(range.dot1x.as_ref()))),
+                // Highlight policies that differ from the switch-wide majority
+                Column::Frames => (
+                    if range.frame_policy.is_some_and(|p| p.deviates) { r#" class="deviates""# } else { "" },
+                    escape_html(&format_frame_policy(range.frame_policy.as_ref())),
+                ),
+                Column::Mac => ("", escape_html(&format_range_mac(range))),
+                // Highlight ports that changed within the last 24 hours
+                Column::LastChange => (
+                    if is_recent_change(range.last_change) { r#" class="recent-change""# } else { "" },
+                    escape_html(&format_last_change(range.last_change)),
+                ),
+                Column::Comment => ("", escape_html(&format_distinct(&range.comments, "comments").unwrap_or_default())),
+                Column::Location => ("", escape_html(&format_distinct(&range.locations, "locations").unwrap_or_default())),
+                Column::Panel => ("", escape_html(&format_panel(range))),
+                Column::ConfigHash => ("", format_config_hash(range)),
+                // Highlight numberings that do not line up
+                Column::IfIndex | Column::BridgePort => (
+                    if indices_mismatched(range) { r#" class="deviates""# } else { "" },
+                    if *column == Column::IfIndex { format_if_index(range) } else { format_bridge_port(range) },
+                ),
+            };
+            writeln!(w, "            <td{}>{}</td>", cell_class, cell)?;
+        }
+        writeln!(w, "        </tr>")?;
     }
+    writeln!(w, "    </tbody>")?;
+    writeln!(w, "</table>")?;
 
-    // Close HTML table
-    table.push_str(r#"    </tbody>
-</table>"#);
+    // Ports gone since --diff, as they were
+    let removed: Vec<&SnapshotPort> = info.changes.iter().flatten()
+        .filter_map(|(_, change)| match change {
+            PortChange::Removed(port) => Some(port.as_ref()),
+            _ => None,
+        })
+        .collect();
+    if !removed.is_empty() {
+        writeln!(w, "<h2>Removed ports</h2>")?;
+        writeln!(w, r#"<table class="port-table removed-table">"#)?;
+        writeln!(w, "    <thead>")?;
+        writeln!(w, "        <tr>")?;
+        for column in [Column::Port, Column::Alias, Column::Vlans] {
+            writeln!(w, "            <th>{}</th>", escape_html(columns.header(column)))?;
+        }
+        writeln!(w, "        </tr>")?;
+        writeln!(w, "    </thead>")?;
+        writeln!(w, "    <tbody>")?;
+        for port in removed {
+            let range = PortRange {
+                first_port: port.port,
+                last_port: port.port,
+                alias: port.alias.clone(),
+                pvid: port.pvid,
+                vlan_memberships: port.vlans.iter().copied().collect(),
+                untagged_vlans: port.untagged.iter().copied().collect(),
+                ..Default::default()
+            };
+            let vlans = format_vlan_cell(&range, vlan_names, &info.vlan_style)
+                .render(|class, text| format!(r#"<span class="{}">{}</span>"#, class, text));
+            writeln!(w, "        <tr>")?;
+            for cell in [port.port.to_string(), escape_html(&format_alias(&range)), vlans] {
+                writeln!(w, "            <td>{}</td>", cell)?;
+            }
+            writeln!(w, "        </tr>")?;
+        }
+        writeln!(w, "    </tbody>")?;
+        writeln!(w, "</table>")?;
+    }
+
+    if !info.warnings.is_empty() {
+        writeln!(w, "<h2>Warnings</h2>")?;
+        writeln!(w, r#"<ul class="warnings">"#)?;
+        for warning in &info.warnings {
+            writeln!(w, "    <li>{}</li>", escape_html(&warning.to_string()))?;
+        }
+        writeln!(w, "</ul>")?;
+    }
+
+    if let Some(capacity) = &info.capacity {
+        writeln!(w, "<h2>Capacity</h2>")?;
+        writeln!(w, "<p>Free ports: {} ({})</p>", capacity.free_ports.len(), format_port_set(&capacity.free_ports))?;
+        writeln!(w, r#"<table class="port-table capacity-table">"#)?;
+        w.write_all(br#"    <thead>
+        <tr>
+            <th>Access VLAN</th>
+            <th>Used ports</th>
+        </tr>
+    </thead>
+    <tbody>
+"#)?;
+        let rows = capacity.access_vlans.iter()
+            .map(|(vlan_id, count)| (format_vlan_label(*vlan_id, vlan_names, &info.vlan_style), *count))
+            .chain([("Trunk/LACP".to_string(), capacity.other_used)]);
+        for (label, count) in rows {
+            writeln!(w, "        <tr>")?;
+            writeln!(w, "            <td>{}</td>", label)?;
+            writeln!(w, "            <td>{}</td>", count)?;
+            writeln!(w, "        </tr>")?;
+        }
+        writeln!(w, "    </tbody>")?;
+        writeln!(w, "</table>")?;
+    }
+
+    if let Some(lags) = &info.lags {
+        writeln!(w, "<h2>Link aggregation groups</h2>")?;
+        if lags.is_empty() {
+            writeln!(w, "<p>No aggregators found.</p>")?;
+        } else {
+            writeln!(w, r#"<table class="port-table lag-table">"#)?;
+            writeln!(w, "    <thead>")?;
+            writeln!(w, "        <tr>")?;
+            for header in LAG_HEADERS {
+                writeln!(w, "            <th>{}</th>", header)?;
+            }
+            writeln!(w, "        </tr>")?;
+            writeln!(w, "    </thead>")?;
+            writeln!(w, "    <tbody>")?;
+            for lag in lags {
+                let [name, index, members, sources] = lag_cells(lag);
+                let vlans = lag_vlan_cell(lag, vlan_names, &info.vlan_style)
+                    .render(|class, text| format!(r#"<span class="{}">{}</span>"#, class, text));
+                writeln!(w, "        <tr{}>", if lag.stale { r#" class="stale""# } else { "" })?;
+                for cell in [escape_html(&name), index, escape_html(&members), vlans, escape_html(&sources)] {
+                    writeln!(w, "            <td>{}</td>", cell)?;
+                }
+                writeln!(w, "        </tr>")?;
+            }
+            writeln!(w, "    </tbody>")?;
+            writeln!(w, "</table>")?;
+        }
+    }
+
+    if let Some(interfaces) = &info.virtual_interfaces {
+        writeln!(w, "<h2>Virtual interfaces</h2>")?;
+        if interfaces.is_empty() {
+            writeln!(w, "<p>No virtual interfaces found.</p>")?;
+        } else {
+            writeln!(w, r#"<table class="port-table virtual-table">"#)?;
+            writeln!(w, "    <thead>")?;
+            writeln!(w, "        <tr>")?;
+            for header in VIRTUAL_HEADERS {
+                writeln!(w, "            <th>{}</th>", header)?;
+            }
+            writeln!(w, "        </tr>")?;
+            writeln!(w, "    </thead>")?;
+            writeln!(w, "    <tbody>")?;
+            for interface in interfaces {
+                writeln!(w, "        <tr>")?;
+                for cell in virtual_cells(interface) {
+                    writeln!(w, "            <td>{}</td>", escape_html(&cell))?;
+                }
+                writeln!(w, "        </tr>")?;
+            }
+            writeln!(w, "    </tbody>")?;
+            writeln!(w, "</table>")?;
+        }
+    }
+
+    if !info.sources.is_empty() {
+        writeln!(w, r#"<div class="data-sources">Data sources: {}</div>"#, escape_html(&format_sources(&info.sources)))?;
+    }
+
+    if let Some(meta) = &info.meta {
+        writeln!(w, r#"<footer class="report-footer">"#)?;
+        for line in footer_lines(meta) {
+            writeln!(w, "    <div>{}</div>", escape_html(&line))?;
+        }
+        writeln!(w, "</footer>")?;
+    }
+
+    Ok(())
+}
+
+/// The VLAN presence matrix as an HTML table, with the switches that carry a
+/// VLAN on no port marked in red
+pub fn write_vlan_matrix(w: &mut dyn Write, matrix: &VlanMatrix) -> io::Result<()> {
+    writeln!(w, "<title>VLAN presence</title>")?;
+    w.write_all(br#"<style>
+    body {
+        margin: 0 auto;
+        padding: 20px;
+        font-family: Arial, sans-serif;
+    }
+    .vlan-matrix {
+        border-collapse: collapse;
+        margin: 20px 0;
+    }
+    .vlan-matrix th, .vlan-matrix td {
+        border: 1px solid #ddd;
+        padding: 6px 12px;
+        text-align: left;
+    }
+    .vlan-matrix th {
+        background-color: #f2f2f2;
+        color: #333;
+    }
+    .vlan-matrix td.count {
+        text-align: right;
+    }
+    .vlan-matrix td.absent {
+        background-color: #ffd6d6;
+        color: #b00020;
+        font-weight: bold;
+    }
+</style>
+"#)?;
+    writeln!(w, "<h1>VLAN presence</h1>")?;
+    writeln!(w, "<p>Ports carrying each VLAN, as tagged/untagged</p>")?;
+    writeln!(w, r#"<table class="vlan-matrix">"#)?;
+    writeln!(w, "    <thead>")?;
+    writeln!(w, "        <tr>")?;
+    writeln!(w, "            <th>VLAN</th>")?;
+    writeln!(w, "            <th>Name</th>")?;
+    for switch in &matrix.switches {
+        writeln!(w, "            <th>{}</th>", escape_html(switch))?;
+    }
+    writeln!(w, "        </tr>")?;
+    writeln!(w, "    </thead>")?;
+    writeln!(w, "    <tbody>")?;
+    for row in &matrix.vlans {
+        writeln!(w, "        <tr>")?;
+        writeln!(w, "            <td>{}</td>", row.vlan)?;
+        writeln!(w, "            <td>{}</td>", escape_html(row.name.as_deref().unwrap_or_default()))?;
+        for counts in &row.ports {
+            match counts.is_absent() {
+                true => writeln!(w, r#"            <td class="count absent">0</td>"#)?,
+                false => writeln!(w, r#"            <td class="count">{}/{}</td>"#, counts.tagged, counts.untagged)?,
+            }
+        }
+        writeln!(w, "        </tr>")?;
+    }
+    writeln!(w, "    </tbody>")?;
+    writeln!(w, "</table>")
+}
 
-    table
-} 
\ No newline at end of file
+/// The links found between switches as an HTML table, with links whose ends
+/// disagree on the tagged VLANs in red and neighbors that were not polled in italics
+pub fn write_topology(w: &mut dyn Write, topology: &Topology) -> io::Result<()> {
+    writeln!(w, "<title>Topology</title>")?;
+    w.write_all(br#"<style>
+    body {
+        margin: 0 auto;
+        padding: 20px;
+        font-family: Arial, sans-serif;
+    }
+    .topology {
+        border-collapse: collapse;
+        margin: 20px 0;
+    }
+    .topology th, .topology td {
+        border: 1px solid #ddd;
+        padding: 6px 12px;
+        text-align: left;
+    }
+    .topology th {
+        background-color: #f2f2f2;
+        color: #333;
+    }
+    .topology tr.leaf td.neighbor {
+        font-style: italic;
+        color: #666;
+    }
+    .topology tr.mismatch {
+        background-color: #ffd6d6;
+    }
+    .topology tr.mismatch td.differ {
+        color: #b00020;
+        font-weight: bold;
+    }
+</style>
+"#)?;
+    writeln!(w, "<h1>Topology</h1>")?;
+    if topology.links.is_empty() {
+        return writeln!(w, "<p>No LLDP neighbors were found on {}</p>", escape_html(&topology.switches.join(", ")));
+    }
+    writeln!(w, r#"<table class="topology">"#)?;
+    writeln!(w, "    <thead>")?;
+    writeln!(w, "        <tr>")?;
+    for header in TOPOLOGY_HEADERS {
+        writeln!(w, "            <th>{}</th>", header)?;
+    }
+    writeln!(w, "        </tr>")?;
+    writeln!(w, "    </thead>")?;
+    writeln!(w, "    <tbody>")?;
+    // Cells with a class of their own: the neighbor, and the tagged VLANs that differ
+    let classes = ["", "", r#" class="neighbor""#, "", "", "", r#" class="differ""#];
+    for link in &topology.links {
+        let row_class = match (link.leaf, link.mismatch()) {
+            (true, _) => r#" class="leaf""#,
+            (false, true) => r#" class="mismatch""#,
+            (false, false) => "",
+        };
+        writeln!(w, "        <tr{}>", row_class)?;
+        for (cell, class) in topology_cells(link).iter().zip(classes) {
+            writeln!(w, "            <td{}>{}</td>", class, escape_html(cell))?;
+        }
+        writeln!(w, "        </tr>")?;
+    }
+    writeln!(w, "    </tbody>")?;
+    writeln!(w, "</table>")
+}
@@ -0,0 +1,485 @@
+//! Collection and documentation of VLAN port configuration on SNMP managed switches.
+//!
+//! [`collect_switch`] polls one switch and returns a [`SwitchSnapshot`]; the
+//! [`output`] module renders snapshots as markdown or HTML tables.
+//!
+//! # Stability
+//!
+//! The crate follows semver with the usual 0.x caveat: until 1.0, a minor
+//! release (0.x -> 0.y) may change the public API, while patch releases do
+//! not. Public structs may gain fields in any minor release, so build them
+//! with `..Default::default()` where they implement `Default`. The binary's
+//! command line and the JSON snapshot schema are versioned separately from
+//! the library API.
+
+pub mod snmp_utils;
+pub mod charset;
+pub mod output;
+pub mod html_output;
+#[cfg(feature = "pdf")]
+pub mod pdf_output;
+pub mod vlan_cell;
+pub mod vendor;
+pub mod oids;
+pub mod config;
+pub mod stats;
+pub mod cancel;
+pub mod capacity;
+pub mod metadata;
+pub mod panel;
+pub mod checks;
+pub mod snapshot;
+pub mod timestamp;
+pub mod anonymize;
+pub mod collector;
+pub mod error;
+pub mod grouping;
+pub mod report;
+pub mod parallel;
+pub mod warnings;
+pub mod fingerprint;
+pub mod session;
+pub mod logging;
+pub mod traps;
+pub mod matrix;
+pub mod topology;
+pub mod desired;
+pub mod rules;
+pub mod probe;
+#[cfg(feature = "serde")]
+mod serde_sets;
+#[cfg(test)]
+mod testutil;
+use std::collections::{BTreeSet, HashSet, HashMap};
+use std::str::FromStr;
+use std::sync::Arc;
+use snmp_utils::decode_port_list;
+
+pub use collector::{apply_overrides, collect_from, collect_switch, collect_switch_with, collect_vlan_names_with, CollectOptions, DEFAULT_VIRTUAL_IF_TYPES, Interface, SwitchSnapshot, Tables};
+pub use error::Error;
+pub use grouping::{group_ranges, GroupMode, Grouping};
+pub use warnings::{Warning, Warnings};
+
+/// One physical port as collected from the switch
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PortConfig {
+    #[cfg_attr(feature = "serde", serde(rename = "port"))]
+    pub port_num: u32,
+    pub alias: Option<String>,
+    /// ifAlias as the switch reported it, when whitespace normalization changed it.
+    /// Not part of the grouping identity.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub raw_alias: Option<String>,
+    pub pvid: Option<u32>,  // None if dot1qPvid has no row for the port
+    #[cfg_attr(feature = "serde", serde(rename = "vlans", serialize_with = "serde_sets::set"))]
+    pub vlan_memberships: HashSet<u32>,
+    #[cfg_attr(feature = "serde", serde(rename = "untagged", serialize_with = "serde_sets::set"))]
+    pub untagged_vlans: HashSet<u32>,
+    #[cfg_attr(feature = "serde", serde(rename = "lacp"))]
+    pub lacp_info: Option<LacpInfo>,
+    pub dot1x: Option<Dot1xInfo>,
+    pub frame_policy: Option<FramePolicy>,
+    pub uplink: bool,  // Heuristic: LACP member, trunk with many VLANs, or a bridge as LLDP neighbor
+    pub inferred_trunk: bool,  // Looks like a trunk member although the device reports no LACP
+    pub admin_down: bool,  // Not part of the grouping identity
+    /// The VLAN sets were copied from `lacp_info.agg_vlans`, so members of one
+    /// aggregator match without comparing them
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub inherited_vlans: bool,
+    pub mac: Option<String>,  // Not part of the grouping identity
+    pub last_change: Option<LastChange>,  // Not part of the grouping identity
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub indices: Option<PortIndices>,  // --debug-indices; not part of the grouping identity
+}
+
+/// Both numberings of a port, collected for --debug-indices
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PortIndices {
+    /// ifIndex from ifTable, which is also the port number of the report
+    pub if_index: u32,
+    /// Bridge port whose dot1dBasePortIfIndex is the port; None without a row
+    pub bridge_port: Option<u32>,
+}
+
+impl PortIndices {
+    /// Port lists count bridge ports but are read by ifIndex, so the VLANs of
+    /// this port were read from another port's bit, or none at all
+    pub fn mismatched(&self) -> bool {
+        self.bridge_port != Some(self.if_index)
+    }
+}
+
+/// Tagged and untagged VLANs of an aggregator, shared by all of its members.
+/// Equality checks identity first, so members of one aggregator compare cheaply.
+pub type AggregatorVlans = Arc<(HashSet<u32>, HashSet<u32>)>;
+
+/// Link aggregation a port is a member of
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LacpInfo {
+    #[cfg_attr(feature = "serde", serde(rename = "aggregator"))]
+    pub selected_agg_id: u32,
+    #[cfg_attr(feature = "serde", serde(rename = "name"))]
+    pub agg_name: Option<String>,
+    #[cfg_attr(feature = "serde", serde(rename = "vlans", serialize_with = "serde_sets::optional_pair",
+        deserialize_with = "serde_sets::deserialize_optional_pair"))]
+    pub agg_vlans: Option<AggregatorVlans>,
+    pub load_balance: Option<String>,  // e.g. "src-dst-ip", or "individual"
+}
+
+/// 802.1X state of a port
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Dot1xInfo {
+    pub port_control: u32,  // forceUnauthorized(1), auto(2), forceAuthorized(3)
+    pub port_status: u32,   // authorized(1), unauthorized(2)
+}
+
+/// Acceptable frame types and ingress filtering of a port
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FramePolicy {
+    pub tagged_only: bool,     // dot1qPortAcceptableFrameTypes = admitOnlyVlanTagged(2)
+    pub ingress_filter: bool,  // dot1qPortIngressFiltering = true(1)
+    pub deviates: bool,        // Differs from the most common policy on this switch
+}
+
+/// When an interface last changed its operational state
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum LastChange {
+    /// No change since the agent booted, or ifLastChange is past sysUpTime
+    SinceBoot,
+    /// Seconds between the last change and the time of collection
+    Ago(u32),
+}
+
+impl LastChange {
+    pub(crate) fn from_ticks(if_last_change: u32, sys_uptime: u32) -> LastChange {
+        if if_last_change == 0 || if_last_change > sys_uptime {
+            LastChange::SinceBoot
+        } else {
+            LastChange::Ago((sys_uptime - if_last_change) / 100)
+        }
+    }
+
+    /// The more recent of two changes
+    pub fn most_recent(a: Option<LastChange>, b: Option<LastChange>) -> Option<LastChange> {
+        match (a, b) {
+            (Some(LastChange::Ago(x)), Some(LastChange::Ago(y))) => Some(LastChange::Ago(x.min(y))),
+            (Some(LastChange::Ago(x)), _) | (_, Some(LastChange::Ago(x))) => Some(LastChange::Ago(x)),
+            (a, b) => a.or(b),
+        }
+    }
+}
+
+/// LACP membership forced onto ports the switch does not report as members,
+/// e.g. `26:21,22` for --override-lacp
+#[derive(Debug, Clone)]
+pub struct LacpOverride {
+    pub source_interface: u32,
+    pub target_ports: Vec<u32>,
+    pub name: Option<String>,
+}
+
+/// Ports with identical configuration, shown as one table row
+#[derive(Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PortRange {
+    pub first_port: u32,
+    pub last_port: u32,
+    pub alias: Option<String>,
+    pub pvid: Option<u32>,
+    #[cfg_attr(feature = "serde", serde(rename = "vlans", serialize_with = "serde_sets::set"))]
+    pub vlan_memberships: HashSet<u32>,
+    #[cfg_attr(feature = "serde", serde(rename = "untagged", serialize_with = "serde_sets::set"))]
+    pub untagged_vlans: HashSet<u32>,
+    #[cfg_attr(feature = "serde", serde(rename = "lacp"))]
+    pub lacp_info: Option<LacpInfo>,
+    pub dot1x: Option<Dot1xInfo>,
+    pub frame_policy: Option<FramePolicy>,
+    pub uplink: bool,
+    pub inferred_trunk: bool,
+    pub mac: Option<String>,  // MAC of the first port in the range
+    pub last_change: Option<LastChange>,  // Most recent change within the range
+    pub except_ports: Vec<u32>,  // Port numbers inside first..=last that are not part of the range
+    pub port_aliases: Vec<(u32, String)>,  // Alias of every member port, differing with --group-ignore-alias
+    pub member_ports: Option<Vec<u32>>,  // Non-contiguous member ports (--dedupe); None for plain ranges
+    pub comments: Vec<(u32, String)>,  // --metadata comment of every member port that has one
+    pub locations: Vec<(u32, String)>,  // --metadata location of every member port that has one
+    pub panel_labels: Vec<(u32, String)>,  // Patch-panel label of every member port that is patched
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Vec::is_empty"))]
+    pub indices: Vec<(u32, PortIndices)>,  // --debug-indices numbering of every member port
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Vec::is_empty"))]
+    pub admin_down: Vec<u32>,  // Member ports administratively down, when ifAdminStatus was walked
+}
+
+impl PortRange {
+    /// Every port number the range covers
+    pub fn ports(&self) -> Vec<u32> {
+        match &self.member_ports {
+            Some(ports) => ports.clone(),
+            None => (self.first_port..=self.last_port)
+                .filter(|port| !self.except_ports.contains(port))
+                .collect(),
+        }
+    }
+}
+
+pub(crate) fn is_physical_port(port_type: u32, if_types: &[u32]) -> bool {
+    // By default only keep 100M and 1G ports, hosts can configure their own list
+    // ifType 6 = ethernetCsmacd (100M)
+    // ifType 117 = gigabitEthernet (1G)
+    if_types.contains(&port_type)
+}
+
+/// Parse an override in --override-lacp syntax, e.g. "26:21-22,24"
+pub fn parse_lacp_override(override_str: &str) -> Result<LacpOverride, String> {
+    let parts: Vec<&str> = override_str.split(':').collect();
+    if parts.len() != 2 {
+        return Err("Invalid format. Expected: source_interface:target_ports".to_string());
+    }
+
+    let source_interface = parts[0].trim().parse::<u32>()
+        .map_err(|e| format!("Invalid source interface number: {}", e))?;
+
+    // Accepts single ports, ranges and mixes like "21-22,24"; duplicates collapse
+    if parts[1].trim().is_empty() {
+        return Err("No target ports given".to_string());
+    }
+    let PortList(target_ports) = parts[1].parse::<PortList>()?;
+    if target_ports.contains(&0) {
+        return Err("Port 0 is not a valid target port".to_string());
+    }
+    let target_ports: Vec<u32> = target_ports.into_iter().collect();
+
+    Ok(LacpOverride {
+        source_interface,
+        target_ports,
+        name: None,
+    })
+}
+
+impl PortConfig {
+    /// A range covering `first_port..=last_port` with this port's configuration
+    pub fn into_range(self, first_port: u32, last_port: u32) -> PortRange {
+        PortRange {
+            first_port,
+            last_port,
+            port_aliases: self.alias.iter().map(|alias| (first_port, alias.clone())).collect(),
+            alias: self.alias,
+            pvid: self.pvid,
+            vlan_memberships: self.vlan_memberships,
+            untagged_vlans: self.untagged_vlans,
+            lacp_info: self.lacp_info,
+            dot1x: self.dot1x,
+            frame_policy: self.frame_policy,
+            uplink: self.uplink,
+            inferred_trunk: self.inferred_trunk,
+            mac: self.mac,
+            last_change: self.last_change,
+            except_ports: Vec::new(),
+            member_ports: None,
+            comments: Vec::new(),
+            locations: Vec::new(),
+            panel_labels: Vec::new(),
+            indices: Vec::new(),
+            admin_down: Vec::new(),
+        }
+    }
+}
+
+/// Widest range a PortList accepts; more than any switch has ports, and
+/// small enough that a typo does not fill memory
+pub const MAX_PORT_RANGE: u32 = 65535;
+
+/// A set of port numbers given as a list of ports and ranges, e.g. "1-8,21,24"
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortList(pub BTreeSet<u32>);
+
+impl FromStr for PortList {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut ports = BTreeSet::new();
+        for part in s.split(',').map(str::trim) {
+            if let Some((first, last)) = part.split_once('-') {
+                let first = first.trim().parse::<u32>()
+                    .map_err(|e| format!("Invalid port number '{}': {}", first, e))?;
+                let last = last.trim().parse::<u32>()
+                    .map_err(|e| format!("Invalid port number '{}': {}", last, e))?;
+                if first > last {
+                    return Err(format!("Reversed port range '{}'", part));
+                }
+                if last - first >= MAX_PORT_RANGE {
+                    return Err(format!("Port range '{}' is wider than {} ports", part, MAX_PORT_RANGE));
+                }
+                ports.extend(first..=last);
+            } else {
+                let port = part.parse::<u32>()
+                    .map_err(|e| format!("Invalid port number '{}': {}", part, e))?;
+                ports.insert(port);
+            }
+        }
+        Ok(PortList(ports))
+    }
+}
+
+/// Whether a port is in "factory default" state: only in the default VLAN
+/// (untagged, as PVID), no alias and no LACP
+pub fn is_default_config(port: &PortConfig, default_vlan: u32) -> bool {
+    port.pvid == Some(default_vlan)
+        && port.untagged_vlans.len() == 1
+        && port.untagged_vlans.contains(&default_vlan)
+        && port.vlan_memberships.iter().all(|&vlan_id| vlan_id == default_vlan)
+        && port.alias.as_deref().unwrap_or_default().is_empty()
+        && port.lacp_info.is_none()
+}
+
+/// Whether `port_num` is set in a Q-BRIDGE PortList bitmap
+pub fn port_in_list(port_num: u32, ports_data: &[u8]) -> bool {
+    decode_port_list(ports_data)
+        .split(", ")
+        .any(|p| p.parse::<u32>().is_ok_and(|p| p == port_num))
+}
+
+/// Egress and untagged VLANs of a port from the Q-BRIDGE port bitmaps
+pub fn vlan_sets(
+    port_num: u32,
+    egress_ports: &HashMap<u32, Vec<u8>>,
+    untagged_ports: &HashMap<u32, Vec<u8>>,
+) -> (HashSet<u32>, HashSet<u32>) {
+    let members = |bitmaps: &HashMap<u32, Vec<u8>>| bitmaps.iter()
+        .filter(|(_, ports_data)| port_in_list(port_num, ports_data))
+        .map(|(vlan_id, _)| *vlan_id)
+        .collect();
+    (members(egress_ports), members(untagged_ports))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lacp_override_accepts_lists_and_ranges() {
+        assert_eq!(parse_lacp_override("26:21,22").unwrap().target_ports, vec![21, 22]);
+        assert_eq!(parse_lacp_override("26:21-24").unwrap().target_ports, vec![21, 22, 23, 24]);
+        assert_eq!(parse_lacp_override("26:21-22,24").unwrap().target_ports, vec![21, 22, 24]);
+        assert_eq!(parse_lacp_override("26:21-22,24").unwrap().source_interface, 26);
+    }
+
+    #[test]
+    fn port_lists_mix_ports_and_ranges() {
+        let PortList(ports) = "1-3, 8,2".parse().unwrap();
+        assert_eq!(ports.into_iter().collect::<Vec<_>>(), [1, 2, 3, 8]);
+        assert_eq!("x".parse::<PortList>().unwrap_err(), "Invalid port number 'x': invalid digit found in string");
+        assert_eq!("5-2".parse::<PortList>().unwrap_err(), "Reversed port range '5-2'");
+    }
+
+    #[test]
+    fn port_lists_reject_huge_ranges() {
+        assert_eq!("1-65535".parse::<PortList>().unwrap().0.len(), 65535);
+        assert_eq!("1-4000000000".parse::<PortList>().unwrap_err(), "Port range '1-4000000000' is wider than 65535 ports");
+        assert!("0-65535".parse::<PortList>().is_err());
+    }
+
+    #[test]
+    fn default_config_is_only_the_default_vlan() {
+        let port = PortConfig {
+            port_num: 5,
+            pvid: Some(1),
+            vlan_memberships: HashSet::from([1]),
+            untagged_vlans: HashSet::from([1]),
+            ..PortConfig::default()
+        };
+        assert!(is_default_config(&port, 1));
+        assert!(!is_default_config(&port, 10));
+        assert!(!is_default_config(&PortConfig { alias: Some("printer".to_string()), ..port.clone() }, 1));
+        assert!(is_default_config(&PortConfig { alias: Some(String::new()), ..port.clone() }, 1));
+        assert!(!is_default_config(&PortConfig { vlan_memberships: HashSet::from([1, 10]), ..port.clone() }, 1));
+        assert!(!is_default_config(&PortConfig { pvid: None, ..port }, 1));
+    }
+
+    #[test]
+    fn lacp_override_ignores_whitespace() {
+        let parsed = parse_lacp_override(" 26 : 21 - 22 , 24 ").unwrap();
+        assert_eq!(parsed.source_interface, 26);
+        assert_eq!(parsed.target_ports, vec![21, 22, 24]);
+    }
+
+    #[test]
+    fn lacp_override_collapses_duplicates() {
+        assert_eq!(parse_lacp_override("26:21,21-22,22").unwrap().target_ports, vec![21, 22]);
+    }
+
+    #[test]
+    fn lacp_override_rejects_bad_ports() {
+        assert_eq!(parse_lacp_override("26:24-21").unwrap_err(), "Reversed port range '24-21'");
+        assert_eq!(parse_lacp_override("26:0,1").unwrap_err(), "Port 0 is not a valid target port");
+        assert_eq!(parse_lacp_override("26:0-2").unwrap_err(), "Port 0 is not a valid target port");
+        assert_eq!(parse_lacp_override("26:").unwrap_err(), "No target ports given");
+        assert!(parse_lacp_override("26").is_err());
+        assert!(parse_lacp_override("x:1").is_err());
+    }
+
+    #[test]
+    fn lacp_override_accepts_ports_beyond_switch_size() {
+        // Whether the port exists is only known once the switch has been polled
+        assert_eq!(parse_lacp_override("26:9999").unwrap().target_ports, vec![9999]);
+    }
+
+    /// A trunk range exercising every nested type
+    #[cfg(feature = "serde")]
+    fn trunk_range() -> PortRange {
+        let lacp = LacpInfo {
+            selected_agg_id: 25,
+            agg_name: Some("Trk1".to_string()),
+            agg_vlans: Some(Arc::new((HashSet::from([531, 10, 1]), HashSet::from([1])))),
+            load_balance: Some("src-dst-ip".to_string()),
+        };
+        let port = PortConfig {
+            port_num: 21,
+            alias: Some("core".to_string()),
+            pvid: Some(1),
+            vlan_memberships: HashSet::from([531, 10, 1]),
+            untagged_vlans: HashSet::from([1]),
+            lacp_info: Some(lacp),
+            dot1x: Some(Dot1xInfo { port_control: 3, port_status: 1 }),
+            frame_policy: Some(FramePolicy { tagged_only: false, ingress_filter: true, deviates: false }),
+            uplink: true,
+            mac: Some("00:11:22:33:44:55".to_string()),
+            last_change: Some(LastChange::Ago(3600)),
+            ..PortConfig::default()
+        };
+        let mut range = port.into_range(21, 22);
+        range.port_aliases.push((22, "core".to_string()));
+        range
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn core_types_round_trip_through_json() {
+        let range = trunk_range();
+        let json = serde_json::to_string(&range).unwrap();
+        assert_eq!(serde_json::from_str::<PortRange>(&json).unwrap(), range);
+        // Sets come out sorted whatever the hash order
+        assert!(json.contains(r#""vlans":[1,10,531]"#), "{}", json);
+
+        let port = PortConfig { last_change: Some(LastChange::SinceBoot), ..PortConfig::default() };
+        let json = serde_json::to_string(&port).unwrap();
+        assert_eq!(serde_json::from_str::<PortConfig>(&json).unwrap(), port);
+    }
+
+    /// Changing this fixture is a schema break for anyone storing the JSON
+    #[test]
+    #[cfg(feature = "serde")]
+    fn json_matches_the_checked_in_schema() {
+        let fixture = include_str!("../tests/fixtures/port-range.json");
+        assert_eq!(serde_json::from_str::<PortRange>(fixture).unwrap(), trunk_range());
+        assert_eq!(serde_json::to_string_pretty(&trunk_range()).unwrap(), fixture.trim_end());
+    }
+}
@@ -0,0 +1,207 @@
+//! Messages on stderr: plain text for people, or one JSON object per line for
+//! log pipelines (--log-format json)
+use std::fmt;
+use std::sync::OnceLock;
+use std::time::Duration;
+use chrono::{SecondsFormat, Utc};
+use clap::ValueEnum;
+use serde_json::{Map, Value};
+use crate::error::format_oid;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    /// Fields `ts`, `level`, `kind` and `message`, and where known `host`,
+    /// `table`, `oid`, `duration_ms` and details such as `ports`
+    Json,
+}
+
+impl fmt::Display for LogFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.to_possible_value() {
+            Some(value) => f.write_str(value.get_name()),
+            None => Ok(()),
+        }
+    }
+}
+
+static FORMAT: OnceLock<LogFormat> = OnceLock::new();
+
+/// Choose the format for the rest of the process; later calls change nothing
+pub fn set_format(format: LogFormat) {
+    let _ = FORMAT.set(format);
+}
+
+pub fn format() -> LogFormat {
+    FORMAT.get().copied().unwrap_or_default()
+}
+
+/// Whether messages go out as JSON, which also keeps progress bars away
+pub fn json() -> bool {
+    format() == LogFormat::Json
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Info,
+    Warning,
+    Error,
+}
+
+impl Level {
+    fn name(self) -> &'static str {
+        match self {
+            Level::Info => "info",
+            Level::Warning => "warning",
+            Level::Error => "error",
+        }
+    }
+
+    fn text_prefix(self) -> &'static str {
+        match self {
+            Level::Info => "",
+            Level::Warning => "Warning: ",
+            Level::Error => "Error: ",
+        }
+    }
+}
+
+/// One message, e.g. `Event::warning("upload-retry", text).field("retry_in_s", 4).emit()`
+#[must_use = "an event is only written by emit()"]
+#[derive(Debug, Clone)]
+pub struct Event {
+    level: Level,
+    kind: String,
+    message: String,
+    /// Shown instead of the level prefix and message in the text format
+    text: Option<String>,
+    host: Option<String>,
+    host_in_text: bool,
+    fields: Map<String, Value>,
+}
+
+impl Event {
+    pub fn new(level: Level, kind: &str, message: impl Into<String>) -> Event {
+        Event {
+            level,
+            kind: kind.to_string(),
+            message: message.into(),
+            text: None,
+            host: None,
+            host_in_text: false,
+            fields: Map::new(),
+        }
+    }
+
+    pub fn info(kind: &str, message: impl Into<String>) -> Event {
+        Event::new(Level::Info, kind, message)
+    }
+
+    pub fn warning(kind: &str, message: impl Into<String>) -> Event {
+        Event::new(Level::Warning, kind, message)
+    }
+
+    pub fn error(kind: &str, message: impl Into<String>) -> Event {
+        Event::new(Level::Error, kind, message)
+    }
+
+    /// The switch the event is about. With `prefix`, text lines start with it
+    /// too, as they do when several switches are polled at once.
+    pub fn host(mut self, host: &str, prefix: bool) -> Event {
+        self.host = Some(host.to_string());
+        self.host_in_text = prefix;
+        self
+    }
+
+    pub fn table(self, name: &str) -> Event {
+        self.field("table", name)
+    }
+
+    pub fn oid(self, oid: &[u32]) -> Event {
+        self.field("oid", format_oid(oid))
+    }
+
+    pub fn duration(self, duration: Duration) -> Event {
+        self.field("duration_ms", duration.as_millis() as u64)
+    }
+
+    /// Any other detail, e.g. the ports a warning is about; JSON only
+    pub fn field(mut self, key: &str, value: impl Into<Value>) -> Event {
+        self.fields.insert(key.to_string(), value.into());
+        self
+    }
+
+    /// The whole text line, when it says more than the message, e.g. with a timestamp
+    pub fn text(mut self, text: impl Into<String>) -> Event {
+        self.text = Some(text.into());
+        self
+    }
+
+    /// The line as written to stderr
+    pub fn render(&self, format: LogFormat) -> String {
+        match format {
+            LogFormat::Text => {
+                let host = match &self.host {
+                    Some(host) if self.host_in_text => format!("{}: ", host),
+                    _ => String::new(),
+                };
+                match &self.text {
+                    Some(text) => format!("{}{}", host, text),
+                    None => format!("{}{}{}", host, self.level.text_prefix(), self.message),
+                }
+            }
+            LogFormat::Json => {
+                let mut object = self.fields.clone();
+                object.insert("ts".to_string(), Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true).into());
+                object.insert("level".to_string(), self.level.name().into());
+                object.insert("kind".to_string(), self.kind.clone().into());
+                object.insert("message".to_string(), self.message.trim_end().into());
+                if let Some(host) = &self.host {
+                    object.insert("host".to_string(), host.clone().into());
+                }
+                Value::Object(object).to_string()
+            }
+        }
+    }
+
+    pub fn emit(self) {
+        eprintln!("{}", self.render(format()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_lines_look_as_before() {
+        let warning = Event::warning("upload-retry", "HTTP 503; retrying in 4s").host("sw1", false);
+        assert_eq!(warning.render(LogFormat::Text), "Warning: HTTP 503; retrying in 4s");
+        assert_eq!(warning.host("sw1", true).render(LogFormat::Text), "sw1: Warning: HTTP 503; retrying in 4s");
+        let cycle = Event::info("watch-cycle", "written").text("2024-05-01 12:00:00 written in 1.2s");
+        assert_eq!(cycle.render(LogFormat::Text), "2024-05-01 12:00:00 written in 1.2s");
+    }
+
+    #[test]
+    fn json_lines_carry_the_fields() {
+        let event = Event::error("table-walk", "timeout\n")
+            .host("10.0.0.1", false)
+            .table("ifAlias")
+            .oid(&[1, 3, 6, 1, 2, 1, 31, 1, 1, 1, 18])
+            .duration(Duration::from_millis(2003))
+            .field("ports", vec![1, 2]);
+        let line = event.render(LogFormat::Json);
+        assert!(!line.contains('\n'));
+        let value: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["level"], "error");
+        assert_eq!(value["kind"], "table-walk");
+        assert_eq!(value["message"], "timeout");
+        assert_eq!(value["host"], "10.0.0.1");
+        assert_eq!(value["table"], "ifAlias");
+        assert_eq!(value["oid"], "1.3.6.1.2.1.31.1.1.1.18");
+        assert_eq!(value["duration_ms"], 2003);
+        assert_eq!(value["ports"], serde_json::json!([1, 2]));
+        assert!(value["ts"].as_str().unwrap().ends_with('Z'));
+    }
+}
@@ -1,383 +1,1614 @@
-mod snmp_utils;
-mod output;
-mod html_output;
-use snmp_utils::{get_u32_table, get_string_table, create_session, decode_port_list, get_raw_table};
-use std::collections::{HashSet, HashMap};
-use std::time::Duration;
-use anyhow::Result;
-use output::{generate_port_table, OutputFormat};
-use clap::Parser;
-
-// Q-BRIDGE-MIB OIDs
-const VLAN_STATIC_NAME: &[u32] = &[1,3,6,1,2,1,17,7,1,4,3,1,1];  // dot1qVlanStaticName
-const VLAN_STATIC_EGRESS_PORTS: &[u32] = &[1,3,6,1,2,1,17,7,1,4,3,1,2];  // dot1qVlanStaticEgressPorts
-const VLAN_STATIC_UNTAGGED_PORTS: &[u32] = &[1,3,6,1,2,1,17,7,1,4,3,1,4];  // dot1qVlanStaticUntaggedPorts
-const PORT_VLAN_TABLE: &[u32] = &[1,3,6,1,2,1,17,7,1,4,5,1,1];  // dot1qPvid
-
-// IF-MIB OIDs
-const IF_INDEX: &[u32] = &[1,3,6,1,2,1,2,2,1,1];  // ifIndex
-const IF_ALIAS: &[u32] = &[1,3,6,1,2,1,31,1,1,1,18];  // ifAlias
-const IF_NAME: &[u32] = &[1,3,6,1,2,1,31,1,1,1,1];  // ifName
-const IF_TYPE: &[u32] = &[1,3,6,1,2,1,2,2,1,3];  // ifType
-
-// IEEE8023-LAG-MIB OIDs
-const LAG_PORT_SELECTED: &[u32] = &[1,2,840,10006,300,43,1,2,1,1,13];  // dot3adAggPortSelectedAggID
-const LAG_AGG_NAME: &[u32] = &[1,3,6,1,2,1,31,1,1,1,1];  // ifName for LACP interfaces
-
-#[derive(Debug, PartialEq, Eq)]
-pub struct PortConfig {
-    port_num: u32,
-    alias: Option<String>,
-    pvid: u32,
-    vlan_memberships: HashSet<u32>,
-    untagged_vlans: HashSet<u32>,
-    lacp_info: Option<LacpInfo>,
-}
-
-#[derive(Debug, PartialEq, Eq, Clone)]
-pub struct LacpInfo {
-    selected_agg_id: u32,
-    agg_name: Option<String>,
-    agg_vlans: Option<(HashSet<u32>, HashSet<u32>)>, // (tagged, untagged)
-}
-
-#[derive(Debug)]
-struct LacpOverride {
-    source_interface: u32,
-    target_ports: Vec<u32>,
-}
+mod watch;
+mod git;
+mod upload;
+mod webhook;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, BufWriter, Write};
+use std::net::ToSocketAddrs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use chrono::Utc;
+use anyhow::{Result, anyhow};
+use clap::{CommandFactory, Parser};
+use clap_complete::Shell;
+use regex::Regex;
+use switch_vlan_diagram::{cancel, collect_switch_with, collect_vlan_names_with, parallel, Warning, parse_lacp_override, output, html_output, CollectOptions,
+    LacpOverride, PortList, PortRange, SwitchSnapshot, Tables, DEFAULT_VIRTUAL_IF_TYPES};
+use switch_vlan_diagram::output::{Column, Columns, OutputFormat, ReportInfo};
+use switch_vlan_diagram::vlan_cell::{VlanDisplay, VlanStyle};
+use switch_vlan_diagram::config::{Config, Settings};
+use switch_vlan_diagram::metadata::Metadata;
+use switch_vlan_diagram::panel::panel_label;
+use switch_vlan_diagram::checks::{self, Check, Violation};
+use switch_vlan_diagram::probe::probe_switch_with;
+use switch_vlan_diagram::session::SessionPool;
+use switch_vlan_diagram::logging::{self, Event, LogFormat};
+use switch_vlan_diagram::snapshot::{self, Meta, Snapshot, SnapshotSource};
+use switch_vlan_diagram::matrix::VlanMatrix;
+use switch_vlan_diagram::topology::Topology;
+use switch_vlan_diagram::desired::DesiredState;
+use switch_vlan_diagram::timestamp::{self, format_timestamp, TimestampFormat, Zone};
+use switch_vlan_diagram::anonymize::Anonymizer;
+use switch_vlan_diagram::report::{self, PortTable, ReportOptions, SortKey};
+use switch_vlan_diagram::capacity::{CapacityReport, FreeCriterion};
+use switch_vlan_diagram::error::{EXIT_FAILURE, EXIT_INTERRUPTED};
+use switch_vlan_diagram::vendor::Vendor;
+use switch_vlan_diagram::charset::Encoding;
+use git::GitRepo;
+use upload::UploadMethod;
+use webhook::{Outcome, Webhook, WebhookFormat, WebhookOn};
+
+/// Exit status of --strict when a consistency check failed
+const EXIT_STRICT_VIOLATIONS: i32 = 4;
+/// Exit status when some, but not all, of several switches failed
+const EXIT_PARTIAL_FAILURE: i32 = 5;
+/// Exit status of --exit-code-on-change when the switch differs from the snapshot
+const EXIT_CHANGES_DETECTED: i32 = 6;
+/// Exit status of --compare-vlans when the two sides define different VLANs
+const EXIT_VLAN_MISMATCH: i32 = 7;
+/// Exit status of --check when a table collection cannot do without is missing
+const EXIT_CHECK_FAILED: i32 = 8;
+/// The exit statuses and the --desired file, for --help
+const AFTER_HELP: &str = "\
+Exit status:
+  0  success
+  1  usage error, or any failure not listed below
+  2  the agent could not be reached, or did not answer in time
+  3  the agent denied access, e.g. a wrong community
+  4  --strict and a consistency check failed, or a port drifted from --desired
+  5  some of several switches failed
+  6  --exit-code-on-change and the switch changed
+  7  --compare-vlans and the VLANs differ
+  8  --check and a mandatory table is missing
+130  interrupted by Ctrl-C; what was collected is written, marked as partial
+
+--desired file (YAML):
+  ports:
+    1:                # a port number, or a list like \"2-8,11\" sharing one entry
+      alias: Studio 1 # optional; not compared when left out
+      access: 10      # untagged in this VLAN only
+    24:
+      trunk: [10, 20] # or tagged in these VLANs,
+      native: 1       # and optionally untagged in this one, also the PVID";
+/// Stands for the switch address in --output and --snapshot when polling several
+const HOST_PLACEHOLDER: &str = "{host}";
 
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, after_help = AFTER_HELP)]
 struct Args {
-    /// IP address of the SNMP agent (e.g., 10.1.0.23)
-    #[arg(short, long)]
-    ip: String,
+    /// IP address of the SNMP agent (e.g., 10.1.0.23). Repeat, or separate with
+    /// commas, to poll several switches; their reports follow in this order.
+    #[arg(short, long, required_unless_present_any = ["generate_completions", "print_schema"], value_delimiter = ',')]
+    ip: Vec<String>,
+
+    /// Poll up to N switches at a time
+    #[arg(short, long, value_name = "N", default_value_t = 4, value_parser = clap::value_parser!(u16).range(1..))]
+    jobs: u16,
 
-    /// SNMP community string
-    #[arg(short, long, default_value = "public")]
-    community: String,
+    /// Write a shell completion script to stdout and exit
+    #[arg(long, hide = true, value_name = "SHELL")]
+    generate_completions: Option<Shell>,
 
-    /// Ignore interface aliases
-    #[arg(short = 'n', long)]
+    /// Write the JSON Schema of --snapshot files to stdout and exit. Their schema_version
+    /// goes up whenever a field changes meaning or goes away
+    #[arg(long)]
+    print_schema: bool,
+
+    /// SNMP community string [default: public]
+    #[arg(short, long)]
+    community: Option<String>,
+
+    /// Deprecated and without effect; see --group-ignore-alias
+    #[arg(short = 'n', long, hide = true)]
     ignore_alias: bool,
 
-    /// SNMP timeout in seconds
-    #[arg(short, long, default_value = "2")]
-    timeout: u64,
+    /// SNMP timeout in seconds [default: 2]
+    #[arg(short, long)]
+    timeout: Option<u64>,
+
+    /// UDP port of the SNMP agent
+    #[arg(long, default_value_t = 161)]
+    port: u16,
 
-    /// Output format (markdown or html)
-    #[arg(short, long, default_value = "markdown")]
-    format: String,
+    /// Output format [default: markdown]. pdf writes the port table only, needs --output and a
+    /// build with the pdf feature
+    #[arg(short, long, ignore_case = true)]
+    format: Option<OutputFormat>,
+
+    /// Do not pad markdown cells to line up the pipes, e.g. to keep diffs of reports small
+    #[arg(long)]
+    no_align: bool,
 
     /// Override LACP information. Format: source_interface:target_ports
-    /// Example: 26:21,22
+    /// Example: 26:21,22 or 26:21-24. Wins over config file overrides for the same target port
     #[arg(long)]
     override_lacp: Vec<String>,
+
+    /// Columns to show, in order, e.g. port,alias,vlans,lacp,mac. A list without any of port, alias,
+    /// vlans or lacp adds to those instead. Data for the selected columns is collected automatically
+    #[arg(long, value_delimiter = ',')]
+    columns: Vec<Column>,
+
+    /// Keep default columns that have no data on any port; by default they are left out
+    #[arg(long)]
+    keep_empty_columns: bool,
+
+    /// Add an 802.1X column with port control mode and authorization state
+    #[arg(long)]
+    dot1x: bool,
+
+    /// Add a column with acceptable frame types and ingress filtering flags
+    #[arg(long)]
+    frame_types: bool,
+
+    /// Add a column with each port's own MAC address (ifPhysAddress)
+    #[arg(long)]
+    mac: bool,
+
+    /// Add a column showing when each port last changed state
+    #[arg(long)]
+    last_change: bool,
+
+    /// Add ifIndex and bridgePort columns (dot1dBasePortIfIndex), highlighting ports where they differ or
+    /// no bridge port maps to the ifIndex. VLAN membership of such ports is read from the wrong bit
+    #[arg(long)]
+    debug_indices: bool,
+
+    /// Vendor profile (juniper, cisco, aruba or generic). Detected from sysDescr if omitted
+    #[arg(long)]
+    vendor: Option<Vendor>,
+
+    /// Encoding to try for aliases and VLAN names that are not UTF-8, e.g. latin1.
+    /// Strings that are still unprintable are shown in hex, e.g. 0x6162ff, with a warning
+    #[arg(long)]
+    fallback_encoding: Option<Encoding>,
+
+    /// Mark ports tagged in more than this many VLANs as likely uplinks
+    #[arg(long, default_value = "3")]
+    uplink_min_vlans: usize,
+
+    /// Flag adjacent ports with identical multi-VLAN configuration as a possible trunk
+    /// group when the device reports no LACP for them
+    #[arg(long)]
+    infer_trunks: bool,
+
+    /// Only report ranges starting at or below this port number (default: no limit)
+    #[arg(long)]
+    max_port: Option<u32>,
+
+    /// Only report these ports, e.g. 1-8,21,24
+    #[arg(long)]
+    ports: Option<PortList>,
+
+    /// Leave these ports out of the report, e.g. 47-52 (applied after --ports)
+    #[arg(long)]
+    exclude_ports: Option<PortList>,
+
+    /// Only report ports whose alias matches this regular expression
+    #[arg(long, value_parser = Regex::new)]
+    alias_filter: Option<Regex>,
+
+    /// Report ports whose alias does NOT match --alias-filter instead
+    #[arg(long, requires = "alias_filter")]
+    alias_filter_invert: bool,
+
+    /// Only report ports without an alias
+    #[arg(long, conflicts_with = "alias_filter")]
+    unaliased_only: bool,
+
+    /// Only report ports that have an alias
+    #[arg(long, conflicts_with = "unaliased_only")]
+    only_aliased: bool,
+
+    /// Hide ports with default configuration (see --default-vlan)
+    #[arg(long)]
+    hide_default: bool,
+
+    /// VLAN that unconfigured ports sit in, e.g. a quarantine VLAN
+    #[arg(long, default_value = "1")]
+    default_vlan: u32,
+
+    /// Add a capacity section with free ports and used ports per access VLAN
+    #[arg(long)]
+    capacity: bool,
+
+    /// Add a section listing each link aggregation group with its members, VLANs and whether the
+    /// membership came from the LAG MIB, ifStackTable or an LACP override. Aggregators without
+    /// members are flagged as stale
+    #[arg(long)]
+    lags: bool,
+
+    /// Add a section listing VLAN interfaces, loopbacks and other virtual interfaces with their ifName,
+    /// ifType and IP addresses. Which ifTypes count is set by virtual_if_types in the config file
+    /// [default: 136, 24, 53]
+    #[arg(long)]
+    include_virtual: bool,
+
+    /// What counts as a free port for --capacity (comma-separated: admin-down, default)
+    #[arg(long, value_delimiter = ',', default_value = "admin-down,default")]
+    free_when: Vec<FreeCriterion>,
+
+    /// Merge identical ports across gaps in the port numbering, e.g. "1-6 (except 4)"
+    #[arg(long, conflicts_with = "no_ranges")]
+    allow_gaps: bool,
+
+    /// Group adjacent ports with identical VLAN configuration even if their aliases differ
+    #[arg(long)]
+    group_ignore_alias: bool,
+
+    /// Print each distinct configuration once with all of its ports, e.g. "1-8, 11, 17-20"
+    #[arg(long, conflicts_with_all = ["no_ranges", "allow_gaps"])]
+    dedupe: bool,
+
+    /// Order the table by this column. Ranges are sorted as units;
+    /// combine with --no-ranges to sort individual ports
+    #[arg(long, ignore_case = true, default_value_t = SortKey::Port)]
+    sort: SortKey,
+
+    /// Reverse the --sort order
+    #[arg(long)]
+    reverse: bool,
+
+    /// Emit one row per port instead of grouping identical ports into ranges
+    #[arg(long)]
+    no_ranges: bool,
+
+    /// Only report ports carrying this VLAN, tagged or untagged (id or name, repeatable)
+    #[arg(long)]
+    vlan: Vec<String>,
+
+    /// Replace the alias of a port, e.g. 12="Studio A" (repeatable, wins over the config file)
+    #[arg(long, value_parser = parse_alias_override)]
+    alias_override: Vec<(u32, String)>,
+
+    /// Show LACP members with their own VLAN configuration instead of the aggregator's
+    #[arg(long)]
+    keep_member_vlans: bool,
+
+    /// Fail instead of writing a report when no physical port was collected, which usually
+    /// means a wrong community that sees a restricted view (the default)
+    #[arg(long, overrides_with = "no_fail_on_empty")]
+    fail_on_empty: bool,
+
+    /// Write the report even when no physical port was collected
+    #[arg(long, overrides_with = "fail_on_empty")]
+    no_fail_on_empty: bool,
+
+    /// Fail when fewer physical ports than this were collected, for switches known to have them
+    #[arg(long)]
+    min_ports: Option<usize>,
+
+    /// Exit with status 4 if a consistency check fails (the report is still written).
+    /// orphan-vlan only warns
+    #[arg(long)]
+    strict: bool,
+
+    /// Skip a consistency check, both its warning and --strict failure (repeatable): pvid-not-untagged,
+    /// untagged-not-egress, trunk-member-mismatch, no-vlans, orphan-vlan, duplicate-alias,
+    /// vlan-name-conflict (switches polled together naming a VLAN differently) or drift (from --desired)
+    #[arg(long, value_delimiter = ',')]
+    allow: Vec<Check>,
+
+    /// CSV with columns port,comment[,location] (header row required) joined onto the report
+    #[arg(long)]
+    metadata: Option<PathBuf>,
+
+    /// YAML file with the intended alias and access VLAN or trunk VLANs of each port (see below).
+    /// Ports configured otherwise, listed but missing, or configured but not listed are reported as
+    /// drift warnings, which --strict fails on. "{host}" in the name is replaced by the address
+    #[arg(long, value_name = "FILE")]
+    desired: Option<PathBuf>,
+
+    /// Write the report to this file instead of stdout; replaced atomically.
+    /// With several switches, "{host}" in the name is replaced by each address.
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Also write the collected per-port data, before filtering and grouping, as JSON to this file.
+    /// With several switches, "{host}" in the name is replaced by each address.
+    #[arg(long, value_name = "FILE")]
+    snapshot: Option<PathBuf>,
+
+    /// With several switches, also write a table of how many of each switch's ports carry each VLAN,
+    /// tagged and untagged, to this file: markdown, HTML or JSON by its extension (.md, .html or .json;
+    /// repeatable)
+    #[arg(long, value_name = "FILE", value_parser = parse_matrix_path, conflicts_with_all = ["trap_listen", "compare_vlans"])]
+    vlan_matrix: Vec<(PathBuf, SiteFormat)>,
+
+    /// With several switches, also read their LLDP neighbors and write the links between them, and to
+    /// devices that were not polled, to this file: a table in markdown, HTML or JSON, or a Graphviz or
+    /// Mermaid graph, by its extension (.md, .html, .json, .dot or .mmd; repeatable). Links whose ends
+    /// disagree on the tagged VLANs are flagged
+    #[arg(long, value_name = "FILE", value_parser = parse_topology_path,
+        conflicts_with_all = ["trap_listen", "compare_vlans", "anonymize"])]
+    topology: Vec<(PathBuf, SiteFormat)>,
+
+    /// Compare the collected data with a snapshot written by --snapshot and print what changed on stderr.
+    /// HTML reports also mark changed rows, with what changed as a tooltip, new ports in green, and list
+    /// removed ports struck through
+    #[arg(long, value_name = "SNAPSHOT", conflicts_with = "watch")]
+    diff: Option<PathBuf>,
+
+    /// Instead of a report, compare the VLAN ids and names of --ip with those of another switch,
+    /// polled with its own config file settings, or of a snapshot file written by --snapshot.
+    /// Prints the VLANs that differ as a table and exits with status 7 if there are any
+    #[arg(long, value_name = "HOST|SNAPSHOT",
+        conflicts_with_all = ["watch", "trap_listen", "diff", "exit_code_on_change", "git_commit", "print_config"])]
+    compare_vlans: Option<String>,
+
+    /// Instead of a report, check that --ip answers and has the tables collection relies on:
+    /// sysDescr and one row of each of ifTable, ifXTable, the Q-BRIDGE static VLAN tables,
+    /// dot1qPvid and the LAG MIB. Prints a line per table and the vendor profile, and exits
+    /// with status 8 if ifTable or a Q-BRIDGE egress or untagged table is missing
+    #[arg(long, conflicts_with_all = ["watch", "trap_listen", "diff", "exit_code_on_change", "git_commit",
+        "print_config", "compare_vlans"])]
+    check: bool,
+
+    /// Exit with status 6 if the switch differs from --diff, or from the existing --snapshot file when
+    /// --diff is not given, and 0 if it does not. A missing --snapshot file counts as a change. Any
+    /// other non-zero status means the run itself failed (see the list below)
+    #[arg(long, conflicts_with = "watch")]
+    exit_code_on_change: bool,
+
+    /// Commit --output, and --snapshot if given, to the git work tree they are in when their content
+    /// differs from HEAD. Never pushes
+    #[arg(long, requires = "output", conflicts_with = "watch")]
+    git_commit: bool,
+
+    /// Let --git-commit run even though other changes are already staged (they stay uncommitted)
+    #[arg(long, requires = "git_commit")]
+    git_allow_dirty: bool,
+
+    /// Send the rendered report to this URL after generation. Basic authentication is read
+    /// as user:password from the SWITCH_VLAN_DOC_UPLOAD_AUTH environment variable
+    #[arg(long, value_name = "URL")]
+    upload_url: Option<String>,
+
+    /// HTTP method for --upload-url: PUT or POST
+    #[arg(long, default_value = "PUT", requires = "upload_url")]
+    upload_method: UploadMethod,
+
+    /// Extra header for --upload-url, e.g. 'X-Token: abc' (repeatable)
+    #[arg(long, value_name = "HEADER", value_parser = upload::parse_header, requires = "upload_url")]
+    upload_header: Vec<(String, String)>,
+
+    /// POST a summary of what changed (host, ports, VLANs before and after) to this URL when --watch
+    /// or --diff finds differences. Failed deliveries are logged and retried once, and never fail the
+    /// run; the URL's path and query are left out of logs, as hook URLs carry their secret there
+    #[arg(long, value_name = "URL")]
+    webhook: Option<String>,
+
+    /// Body of --webhook requests: slack ({"text": ...}, also understood by Matrix hook bridges)
+    /// or generic-json
+    #[arg(long, default_value = "generic-json", requires = "webhook")]
+    webhook_format: WebhookFormat,
+
+    /// When --webhook fires: error (a poll failed), change, or always (every poll)
+    #[arg(long, default_value = "change", requires = "webhook")]
+    webhook_on: WebhookOn,
+
+    /// Re-poll every N seconds and rewrite --output each time, until interrupted
+    #[arg(long, value_name = "SECONDS", requires = "output")]
+    watch: Option<u64>,
+
+    /// Generate each switch once, then again whenever it sends a linkUp, linkDown or configuration
+    /// change trap or inform (SNMPv2c) to this address, e.g. 0.0.0.0:162, until interrupted.
+    /// Traps from other addresses or with the wrong community are logged and dropped
+    #[arg(long, value_name = "ADDRESS", requires = "output",
+        conflicts_with_all = ["watch", "diff", "exit_code_on_change", "git_commit", "print_config"])]
+    trap_listen: Option<String>,
+
+    /// Community that traps must carry [default: each switch's --community]
+    #[arg(long, requires = "trap_listen")]
+    trap_community: Option<String>,
+
+    /// Seconds to wait after a switch's first trap before regenerating it, so that a burst of
+    /// traps leads to one regeneration
+    #[arg(long, value_name = "SECONDS", default_value_t = 5, requires = "trap_listen")]
+    trap_debounce: u64,
+
+    /// No progress output on stderr, and no warnings there either; the report still lists them
+    #[arg(short, long)]
+    quiet: bool,
+
+    /// Format of messages on stderr. json writes one object per line, with fields such as host,
+    /// table, oid and duration_ms, logs every table walk and never shows progress bars
+    #[arg(long, value_name = "FORMAT", default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+
+    /// Leave the warnings section out of the report
+    #[arg(long)]
+    no_warnings: bool,
+
+    /// Print details of how the report was adjusted, e.g. overridden aliases
+    #[arg(short, long)]
+    verbose: bool,
+
+    /// Heading of the report [default: Switch Port Configuration]
+    #[arg(long)]
+    title: Option<String>,
+
+    /// Name shown for the device [default: its sysName, or the IP address]
+    #[arg(long)]
+    device_name: Option<String>,
+
+    /// Replace the device address and name, aliases and VLAN names with pseudonyms in every output,
+    /// e.g. for sharing a report with a vendor
+    #[arg(long)]
+    anonymize: bool,
+
+    /// Write the pseudonym mapping of --anonymize as JSON to this file
+    #[arg(long, value_name = "FILE", requires = "anonymize")]
+    anonymize_map: Option<PathBuf>,
+
+    /// How to label VLANs: id, name (the id if a VLAN has no name) or both
+    #[arg(long, default_value = "both")]
+    vlan_display: VlanDisplay,
+
+    /// Show ports tagged in every VLAN defined on the switch as "ALL (n VLANs)"
+    #[arg(long)]
+    compact_vlans: bool,
+
+    /// Label VLAN 1 with its name like any other VLAN instead of as a bare "1"
+    #[arg(long, conflicts_with = "hide_vlan1")]
+    show_vlan1_name: bool,
+
+    /// Leave VLAN 1 out of the tagged and untagged lists
+    #[arg(long)]
+    hide_vlan1: bool,
+
+    /// Leave out the generation timestamp, and the collection time and git description of the footer,
+    /// so that identical switch data gives byte-identical output
+    #[arg(long)]
+    deterministic: bool,
+
+    /// strftime format of the generated-on line, e.g. "%d.%m.%Y %H:%M %Z"
+    #[arg(long, default_value = timestamp::DEFAULT_TIMESTAMP_FORMAT)]
+    timestamp_format: TimestampFormat,
+
+    /// Time zone of the generated-on line: an IANA name such as Europe/Helsinki, utc or local
+    #[arg(long, default_value = "local")]
+    timezone: Zone,
+
+    /// TOML configuration file with defaults and per-host settings. Only SNMPv2c
+    /// is spoken, so hosts cannot pick an SNMP version
+    /// [default: $XDG_CONFIG_HOME/switch-vlan-doc/config.toml if it exists]
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// Print the effective configuration for --ip and exit
+    #[arg(long)]
+    print_config: bool,
 }
 
-#[derive(Debug, PartialEq, Eq)]
-pub struct PortRange {
-    first_port: u32,
-    last_port: u32,
-    alias: Option<String>,
-    pvid: u32,
-    vlan_memberships: HashSet<u32>,
-    untagged_vlans: HashSet<u32>,
-    lacp_info: Option<LacpInfo>,
+fn parse_alias_override(override_str: &str) -> Result<(u32, String), String> {
+    let (port, alias) = override_str.split_once('=')
+        .ok_or("Invalid format. Expected: PORT=TEXT")?;
+    let port = port.trim().parse::<u32>()
+        .map_err(|e| format!("Invalid port number '{}': {}", port, e))?;
+    Ok((port, alias.to_string()))
 }
 
-fn is_physical_port(port_type: u32, _ip: &str) -> bool {
-    // For other switches, only keep 100M and 1G ports
-    // ifType 6 = ethernetCsmacd (100M)
-    // ifType 117 = gigabitEthernet (1G)
-    port_type == 6 || port_type == 117
+/// Format of a --vlan-matrix or --topology file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SiteFormat {
+    Markdown,
+    Html,
+    Json,
+    /// Graphviz, for --topology
+    Dot,
+    /// Mermaid, for --topology
+    Mermaid,
 }
 
-fn parse_lacp_override(override_str: &str) -> Result<LacpOverride, String> {
-    let parts: Vec<&str> = override_str.split(':').collect();
-    if parts.len() != 2 {
-        return Err("Invalid format. Expected: source_interface:target_ports".to_string());
-    }
+/// A file in the format its extension names, one of `formats`
+fn parse_site_path(path: &str, formats: &[SiteFormat]) -> Result<(PathBuf, SiteFormat), String> {
+    let path = PathBuf::from(path);
+    let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or_default().to_ascii_lowercase();
+    let format = match extension.as_str() {
+        "md" | "markdown" => Some(SiteFormat::Markdown),
+        "html" | "htm" => Some(SiteFormat::Html),
+        "json" => Some(SiteFormat::Json),
+        "dot" | "gv" => Some(SiteFormat::Dot),
+        "mmd" | "mermaid" => Some(SiteFormat::Mermaid),
+        _ => None,
+    };
+    let Some(format) = format.filter(|format| formats.contains(format)) else {
+        let extensions = [(SiteFormat::Markdown, ".md"), (SiteFormat::Html, ".html"), (SiteFormat::Json, ".json"),
+            (SiteFormat::Dot, ".dot"), (SiteFormat::Mermaid, ".mmd")];
+        let expected: Vec<&str> = extensions.iter()
+            .filter(|(format, _)| formats.contains(format))
+            .map(|(_, extension)| *extension)
+            .collect();
+        let expected = match expected.split_last() {
+            Some((last, [])) => last.to_string(),
+            Some((last, rest)) => format!("{} or {}", rest.join(", "), last),
+            None => String::new(),
+        };
+        return Err(format!("Unknown format. Expected a {} file", expected));
+    };
+    Ok((path, format))
+}
 
-    let source_interface = parts[0].parse::<u32>()
-        .map_err(|e| format!("Invalid source interface number: {}", e))?;
-    
-    let target_ports: Vec<u32> = parts[1].split(',')
-        .map(|p| p.parse::<u32>())
-        .collect::<Result<Vec<u32>, _>>()
-        .map_err(|e| format!("Invalid target port number: {}", e))?;
+fn parse_matrix_path(path: &str) -> Result<(PathBuf, SiteFormat), String> {
+    parse_site_path(path, &[SiteFormat::Markdown, SiteFormat::Html, SiteFormat::Json])
+}
 
-    Ok(LacpOverride {
-        source_interface,
-        target_ports,
-    })
+fn parse_topology_path(path: &str) -> Result<(PathBuf, SiteFormat), String> {
+    parse_site_path(path, &[SiteFormat::Markdown, SiteFormat::Html, SiteFormat::Json, SiteFormat::Dot, SiteFormat::Mermaid])
 }
 
-fn port_in_list(port_num: u32, ports_data: &[u8]) -> bool {
-    decode_port_list(ports_data)
-        .split(", ")
-        .any(|p| p.parse::<u32>().map_or(false, |p| p == port_num))
+fn main() {
+    if let Err(e) = run() {
+        // Errors from collection carry their own exit status
+        let code = e.chain()
+            .find_map(|cause| cause.downcast_ref::<switch_vlan_diagram::Error>())
+            .map_or(EXIT_FAILURE, switch_vlan_diagram::Error::exit_code);
+        // Most likely a consequence of the interrupt, e.g. no ports collected yet
+        let code = if cancel::is_cancelled() { EXIT_INTERRUPTED } else { code };
+        Event::error("failed", format!("{:#}", e))
+            .text(format!("Error: {:?}", e))
+            .field("exit_status", code)
+            .emit();
+        std::process::exit(code);
+    }
 }
 
-fn main() -> Result<()> {
-    let args = Args::parse();
-    let timeout = Duration::from_secs(args.timeout);
-    
-    // Parse LACP overrides
-    let mut lacp_overrides = Vec::new();
-    for override_str in &args.override_lacp {
-        match parse_lacp_override(override_str) {
-            Ok(override_info) => lacp_overrides.push(override_info),
-            Err(e) => eprintln!("Warning: Invalid LACP override '{}': {}", override_str, e),
-        }
+fn run() -> Result<()> {
+    let args = Args::try_parse().unwrap_or_else(|e| {
+        // clap would exit with 2, which means an unreachable agent here
+        let _ = e.print();
+        std::process::exit(if e.use_stderr() { EXIT_FAILURE } else { 0 });
+    });
+    logging::set_format(args.log_format);
+
+    if let Some(shell) = args.generate_completions {
+        clap_complete::generate(shell, &mut Args::command(), "switch-vlan-diagram", &mut std::io::stdout());
+        return Ok(());
     }
-    
-    // Validate IP address and construct agent address
-    let agent_addr = format!("{}:161", args.ip);
-
-    let mut sess = create_session(&agent_addr, args.community.as_bytes(), timeout)?;
-    
-    eprintln!("Fetching VLAN information...\n");
-
-    // Get all tables first
-    let port_indices = get_u32_table(&mut sess, IF_INDEX)?;
-    let port_names = get_string_table(&mut sess, IF_NAME)?;
-    let port_types = get_u32_table(&mut sess, IF_TYPE)?;
-    let aliases = get_string_table(&mut sess, IF_ALIAS)?;
-    let port_aliases: HashMap<u32, String> = if !aliases.is_empty() {
-        aliases
-    } else {
-        port_names
+    if args.print_schema {
+        println!("{}", Snapshot::json_schema());
+        return Ok(());
+    }
+
+    // --watch and --trap-listen finish the poll in flight; anything else renders what it has
+    watch::handle_interrupts(args.watch.is_none() && args.trap_listen.is_none())?;
+
+    let config = match &args.config {
+        Some(path) => Config::load(path)?,
+        None => match Config::default_path().filter(|path| path.exists()) {
+            Some(path) => Config::load(&path)?,
+            None => Config::default(),
+        },
     };
 
-    let vlan_names = get_string_table(&mut sess, VLAN_STATIC_NAME)?;
-    let vlan_egress_ports = get_raw_table(&mut sess, VLAN_STATIC_EGRESS_PORTS)?;
-    let vlan_untagged_ports = get_raw_table(&mut sess, VLAN_STATIC_UNTAGGED_PORTS)?;
-    let port_vlans = get_u32_table(&mut sess, PORT_VLAN_TABLE)?;
-
-    // Get LACP information
-    let lag_selected_agg_ids = get_u32_table(&mut sess, LAG_PORT_SELECTED)?;
-    let lag_agg_names = get_string_table(&mut sess, LAG_AGG_NAME)?;
-
-    // Get VLAN information for LACP interfaces
-    let mut lag_vlan_info: HashMap<u32, (HashSet<u32>, HashSet<u32>)> = HashMap::new();
-    for agg_id in lag_selected_agg_ids.values() {
-        if *agg_id > 0 {
-            let mut tagged = HashSet::new();
-            let mut untagged = HashSet::new();
-            
-            // Check VLAN memberships for the LACP interface using the LAG interface number
-            for (vlan_id, ports_data) in &vlan_egress_ports {
-                if port_in_list(*agg_id, ports_data) {
-                    tagged.insert(*vlan_id);
-                }
-            }
+    let metadata = match &args.metadata {
+        Some(path) => Some(Metadata::load(path)?),
+        None => None,
+    };
 
-            // Check untagged VLANs for the LACP interface using the LAG interface number
-            for (vlan_id, ports_data) in &vlan_untagged_ports {
-                if port_in_list(*agg_id, ports_data) {
-                    untagged.insert(*vlan_id);
+    if let Some(other) = &args.compare_vlans {
+        return run_compare_vlans(&args, &config, other);
+    }
+    if args.check {
+        return run_check(&args, &config);
+    }
+    if let Some(listen) = &args.trap_listen {
+        return run_trap_listener(&args, &config, metadata.as_ref(), listen);
+    }
+    for (given, flag) in [(!args.vlan_matrix.is_empty(), "--vlan-matrix"), (!args.topology.is_empty(), "--topology")] {
+        if given && args.ip.len() < 2 {
+            return Err(anyhow!("{} needs several --ip", flag));
+        }
+    }
+    match args.ip.as_slice() {
+        [host] => run_host(&args, config, host, metadata.as_ref()),
+        _ => run_hosts(&args, &config, metadata.as_ref()),
+    }
+}
+
+/// The settings given by command line flags; None where a flag was not given
+fn cli_settings(args: &Args) -> Settings {
+    Settings {
+        community: args.community.clone(),
+        timeout: args.timeout,
+        format: args.format.map(|format| format.to_string()),
+        highlight_vlans: None,
+        columns: (!args.columns.is_empty()).then(|| {
+            let mut ids: Vec<String> = Vec::new();
+            for column in &args.columns {
+                if !ids.iter().any(|id| id == column.id()) {
+                    ids.push(column.id().to_string());
                 }
             }
+            ids
+        }),
+        override_lacp: (!args.override_lacp.is_empty()).then(|| args.override_lacp.clone()),
+        lacp_overrides: None,
+        alias_overrides: None,
+        vlan_names: None,
+        panel_labels: None,
+        panel_blocks: None,
+        vendor: args.vendor.map(|vendor| vendor.to_string()),
+        if_types: None,
+        virtual_if_types: None,
+        allow_no_vlans: None,
+        title: args.title.clone(),
+        device_name: args.device_name.clone(),
+        labels: None,
+        min_ports: args.min_ports,
+    }
+}
 
-            if !tagged.is_empty() || !untagged.is_empty() {
-                lag_vlan_info.insert(*agg_id, (tagged, untagged));
+/// Settings for one switch: command line flags win over its host section,
+/// which wins over global values
+fn host_settings(args: &Args, config: &Config, host: &str, metadata: Option<&Metadata>) -> Settings {
+    let mut settings = Settings {
+        community: Some("public".to_string()),
+        timeout: Some(2),
+        format: Some(OutputFormat::default().to_string()),
+        highlight_vlans: Some(vec![10, 531]),
+        columns: Some(Vec::new()),
+        override_lacp: Some(Vec::new()),
+        lacp_overrides: Some(Vec::new()),
+        alias_overrides: Some(BTreeMap::new()),
+        vlan_names: Some(BTreeMap::new()),
+        panel_labels: Some(BTreeMap::new()),
+        panel_blocks: Some(Vec::new()),
+        vendor: None,
+        if_types: Some(vec![6, 117]),
+        virtual_if_types: Some(DEFAULT_VIRTUAL_IF_TYPES.to_vec()),
+        allow_no_vlans: Some(Vec::new()),
+        title: Some(output::DEFAULT_TITLE.to_string()),
+        device_name: None,
+        labels: Some(BTreeMap::new()),
+        min_ports: None,
+    };
+    settings.overlay(&config.settings_for(host));
+    settings.overlay(&cli_settings(args));
+    // Alias overrides from the command line replace configured ones port by port
+    let aliases = settings.alias_overrides.get_or_insert_with(BTreeMap::new);
+    for (port, alias) in &args.alias_override {
+        aliases.insert(port.to_string(), alias.clone());
+    }
+    // Column flags only ever add to the configured selection
+    let columns = settings.columns.get_or_insert_with(Vec::new);
+    for (enabled, id) in [(args.dot1x, "dot1x"), (args.frame_types, "frames"), (args.mac, "mac"), (args.last_change, "last-change"),
+        (args.debug_indices, "if-index"), (args.debug_indices, "bridge-port")] {
+        if enabled && !columns.iter().any(|c| c == id) {
+            columns.push(id.to_string());
+        }
+    }
+
+    if let Some(metadata) = metadata {
+        let columns = settings.columns.get_or_insert_with(Vec::new);
+        let wanted = if metadata.has_location { &["comment", "location"][..] } else { &["comment"][..] };
+        for id in wanted {
+            if !columns.iter().any(|c| c == id) {
+                columns.push(id.to_string());
             }
         }
     }
 
-    // First, collect all individual port configurations
-    let mut port_configs: Vec<PortConfig> = Vec::new();
+    let has_panel = settings.panel_labels.iter().any(|labels| !labels.is_empty())
+        || settings.panel_blocks.iter().any(|blocks| !blocks.is_empty());
+    if has_panel && !settings.has_column("panel") {
+        settings.columns.get_or_insert_with(Vec::new).push("panel".to_string());
+    }
+    settings
+}
 
-    for port_num in port_indices.into_values() {
-        // Skip non-physical ports based on ifType
-        let port_type = port_types.get(&port_num).copied().unwrap_or(0);
-        if !is_physical_port(port_type, &args.ip) {
-            continue;
-        }
-        
-        // Only use alias if it's not just the port number
-        let alias = port_aliases.get(&port_num)
-            .filter(|&a| a != &port_num.to_string())
-            .cloned();
-
-        let pvid = port_vlans.get(&port_num)
-            .copied()
-            .unwrap_or(0);
-
-        let mut vlan_memberships = HashSet::new();
-        let mut untagged_vlans = HashSet::new();
-
-        // Add VLAN memberships
-        for (vlan_id, ports_data) in &vlan_egress_ports {
-            if port_in_list(port_num, ports_data) {
-                vlan_memberships.insert(*vlan_id);
+/// Poll one switch, with everything that compares against earlier runs
+fn run_host(args: &Args, config: Config, host: &str, metadata: Option<&Metadata>) -> Result<()> {
+    let settings = host_settings(args, &config, host, metadata);
+    if args.print_config {
+        print!("{}", toml::to_string(&settings)?);
+        return Ok(());
+    }
+
+    let options = collect_options(args, config, &settings);
+    let sessions = SessionPool::new();
+    let webhook = args.webhook.as_ref()
+        .map(|url| Webhook { url: url.clone(), format: args.webhook_format, on: args.webhook_on });
+
+    if let (Some(interval), Some(path)) = (args.watch, &args.output) {
+        // The webhook compares each cycle with the one before
+        let mut previous: Option<Snapshot> = None;
+        // Every cycle polls over the same session, until one fails
+        return watch::run(Duration::from_secs(interval), path, || {
+            let report = generate_report(args, host, &options, &settings, metadata, &sessions).and_then(|report| {
+                write_snapshot(args, &report)?;
+                upload_report(args, &report)?;
+                Ok(report)
+            });
+            if let Some(webhook) = &webhook {
+                notify_webhook(webhook, host, previous.as_ref(), report.as_ref().map(|report| &report.snapshot));
+                if let Ok(report) = &report {
+                    previous = Some(report.snapshot.clone());
+                }
             }
+            report
+        });
+    }
+
+    if args.exit_code_on_change && args.diff.is_none() && args.snapshot.is_none() {
+        return Err(anyhow!("--exit-code-on-change needs --diff or --snapshot to compare against"));
+    }
+    // Read the baseline before --snapshot replaces it
+    let baseline_path = args.diff.as_ref()
+        .or(args.snapshot.as_ref().filter(|_| args.exit_code_on_change));
+    if webhook.is_some() && baseline_path.is_none() {
+        return Err(anyhow!("--webhook needs --watch or --diff to detect changes"));
+    }
+    let baseline = match baseline_path {
+        Some(path) if args.diff.is_some() || path.exists() => Some(Snapshot::load(path)?),
+        _ => None,
+    };
+
+    let git_repo = match &args.output {
+        Some(path) if args.git_commit => {
+            let repo = GitRepo::containing(path)?;
+            repo.check_index_clean(args.git_allow_dirty)?;
+            Some(repo)
         }
+        _ => None,
+    };
+    // The committed snapshot tells how many ports the commit touches
+    let committed_snapshot = git_repo.as_ref().zip(args.snapshot.as_ref())
+        .and_then(|(repo, path)| repo.head_contents(path))
+        .and_then(|text| Snapshot::from_json(&text).ok());
 
-        // Add untagged VLANs
-        for (vlan_id, ports_data) in &vlan_untagged_ports {
-            if port_in_list(port_num, ports_data) {
-                untagged_vlans.insert(*vlan_id);
+    let mut report = match generate_report(args, host, &options, &settings, metadata, &sessions) {
+        Ok(report) => report,
+        Err(e) => {
+            if let Some(webhook) = &webhook {
+                notify_webhook(webhook, host, None, Err(&e));
             }
+            return Err(e);
         }
+    };
+    // HTML marks the rows that changed since --diff
+    if let Some(baseline) = baseline.as_ref().filter(|_| args.diff.is_some()) {
+        report.info.changes = Some(baseline.diff(&report.snapshot).ports);
+    }
+    if report.info.interrupted {
+        return write_partial(args.output.as_deref(), &report);
+    }
+    write_snapshot(args, &report)?;
+    report.write(args.output.as_deref())?;
+    upload_report(args, &report)?;
+    // Before --strict can end the run
+    if let Some(webhook) = &webhook {
+        notify_webhook(webhook, host, baseline.as_ref(), Ok(&report.snapshot));
+    }
+
+    if let (Some(repo), Some(output)) = (&git_repo, &args.output) {
+        let mut files = vec![output.as_path()];
+        files.extend(args.snapshot.as_deref());
+        let changed_ports = committed_snapshot.map(|committed| committed.diff(&report.snapshot).ports.len());
+        let device = report.snapshot.device.sys_name.clone().unwrap_or_else(|| host.to_string());
+        let message = git::commit_message(&device, changed_ports);
+        if repo.commit(&files, &message)? {
+            Event::info("git-commit", format!("Committed \"{}\"", message)).emit();
+        } else if !args.quiet {
+            Event::info("git-unchanged", "Documentation unchanged since HEAD, nothing committed").emit();
+        }
+    }
+
+    let failed = report.violations.iter().filter(|violation| violation.check.fails_strict()).count();
+    if args.strict && failed > 0 {
+        Event::error("strict", format!("Strict: {} consistency check(s) failed", failed))
+            .text(format!("Strict: {} consistency check(s) failed", failed))
+            .emit();
+        std::process::exit(EXIT_STRICT_VIOLATIONS);
+    }
 
-        // Check if port is part of an LACP trunk
-        let lacp_info = if let Some(&selected_agg_id) = lag_selected_agg_ids.get(&port_num) {
-            if selected_agg_id > 0 {
-                let agg_name = lag_agg_names.get(&selected_agg_id).cloned();
-                let agg_vlans = lag_vlan_info.get(&selected_agg_id).cloned();
-                Some(LacpInfo {
-                    selected_agg_id,
-                    agg_name,
-                    agg_vlans,
-                })
-            } else {
-                None
+    if let Some(path) = baseline_path {
+        let changed = match &baseline {
+            Some(baseline) => {
+                if baseline.device.ip != report.snapshot.device.ip {
+                    Event::warning("baseline-other-host", format!("{} was taken from {}, not {}",
+                        path.display(), baseline.device.ip, host)).emit();
+                }
+                let diff = baseline.diff(&report.snapshot);
+                Event::info("changes", format!("Changes since {}: {}", path.display(), diff))
+                    .field("ports", diff.changed_ports())
+                    .emit();
+                !diff.is_empty()
+            }
+            None => {
+                Event::info("no-baseline", format!("No earlier snapshot at {}, treating everything as changed", path.display())).emit();
+                true
             }
-        } else {
-            None
         };
+        if args.exit_code_on_change && changed {
+            std::process::exit(EXIT_CHANGES_DETECTED);
+        }
+    }
 
-        port_configs.push(PortConfig {
-            port_num,
-            alias,
-            pvid,
-            vlan_memberships,
-            untagged_vlans,
-            lacp_info,
-        });
+    Ok(())
+}
+
+/// Poll several switches at once, writing their reports in --ip order
+fn run_hosts(args: &Args, config: &Config, metadata: Option<&Metadata>) -> Result<()> {
+    let single_only = [
+        (args.watch.is_some(), "--watch"),
+        (args.diff.is_some(), "--diff"),
+        (args.webhook.is_some(), "--webhook"),
+        (args.exit_code_on_change, "--exit-code-on-change"),
+        (args.git_commit, "--git-commit"),
+        (args.print_config, "--print-config"),
+        (args.anonymize_map.is_some(), "--anonymize-map"),
+    ];
+    if let Some((_, flag)) = single_only.iter().find(|(given, _)| *given) {
+        return Err(anyhow!("{} needs a single --ip", flag));
     }
+    check_host_placeholders(args)?;
+
+    let results = parallel::poll_hosts(&args.ip, usize::from(args.jobs), |host| {
+        let settings = host_settings(args, config, host, metadata);
+        let options = collect_options(args, config.clone(), &settings);
+        // Each switch is polled once; a pooled session would only hold its socket until the end
+        generate_report(args, host, &options, &settings, metadata, &SessionPool::new())
+    });
 
-    // Apply LACP overrides
-    for override_info in &lacp_overrides {
-        // Get VLAN information for the source interface
-        let mut tagged_vlans = HashSet::new();
-        let mut untagged_vlans = HashSet::new();
+    let mut reports: Vec<Result<Report>> = results.into_iter()
+        .map(|result| result.map_err(anyhow::Error::from).and_then(|report| report))
+        .collect();
+    let mut violations = check_vlan_names(args, config, &mut reports);
 
-        // Check tagged VLANs
-        for (vlan_id, ports) in &vlan_egress_ports {
-            if port_in_list(override_info.source_interface, ports) {
-                tagged_vlans.insert(*vlan_id);
+    // Switches that failed have no column rather than one of zeros
+    let switches = || reports.iter().flatten().map(|report| (report.info.device_name.as_str(), &report.snapshot));
+    let matrix = VlanMatrix::new(switches());
+    let topology = Topology::new(switches());
+
+    let mut failures = Vec::new();
+    for (host, report) in args.ip.iter().zip(reports) {
+        let written = report.and_then(|report| write_host_report(args, host, &report).map(|_| report));
+        match written {
+            Ok(report) => violations += report.violations.iter().filter(|violation| violation.check.fails_strict()).count(),
+            Err(e) => {
+                Event::error("host-failed", format!("{:#}", e)).host(host, true).emit();
+                failures.push(e);
             }
         }
+    }
 
-        // Check untagged VLANs
-        for (vlan_id, ports) in &vlan_untagged_ports {
-            if port_in_list(override_info.source_interface, ports) {
-                untagged_vlans.insert(*vlan_id);
-            }
+    if cancel::is_cancelled() {
+        Event::warning("interrupted", "Collection interrupted; the reports are partial, nothing else was written").emit();
+        std::process::exit(EXIT_INTERRUPTED);
+    }
+    if failures.len() < args.ip.len() {
+        for (path, format) in &args.vlan_matrix {
+            write_vlan_matrix(path, *format, &matrix, !args.no_align)?;
+        }
+        for (path, format) in &args.topology {
+            write_topology(path, *format, &topology, !args.no_align)?;
         }
+    }
 
-        // Apply to all target ports
-        for target_port in &override_info.target_ports {
-            if let Some(port_config) = port_configs.iter_mut().find(|p| p.port_num == *target_port) {
-                port_config.alias = port_aliases.get(&override_info.source_interface).cloned();
-                port_config.lacp_info = Some(LacpInfo {
-                    selected_agg_id: override_info.source_interface,
-                    agg_name: Some(format!("Trk{}", override_info.source_interface)),
-                    agg_vlans: Some((tagged_vlans.clone(), untagged_vlans.clone())),
-                });
+    // When every switch failed, the first failure's class says more than "partial"
+    if failures.len() == args.ip.len() {
+        let first = failures.remove(0);
+        return Err(first.context(format!("all {} switches failed", args.ip.len())));
+    }
+    if !failures.is_empty() {
+        Event::error("partial-failure", format!("{} of {} switches failed", failures.len(), args.ip.len()))
+            .text(format!("{} of {} switches failed", failures.len(), args.ip.len()))
+            .emit();
+        std::process::exit(EXIT_PARTIAL_FAILURE);
+    }
+    if args.strict && violations > 0 {
+        Event::error("strict", format!("Strict: {} consistency check(s) failed", violations))
+            .text(format!("Strict: {} consistency check(s) failed", violations))
+            .emit();
+        std::process::exit(EXIT_STRICT_VIOLATIONS);
+    }
+    Ok(())
+}
+
+/// Warn in every report that names a VLAN differently from another switch,
+/// returning the number of conflicts. Switches whose ports could not be
+/// collected take part with their VLAN names if those can still be read.
+fn check_vlan_names(args: &Args, config: &Config, reports: &mut [Result<Report>]) -> usize {
+    if args.allow.contains(&Check::VlanNameConflict) {
+        return 0;
+    }
+    let mut switches = Vec::new();
+    for (host, report) in args.ip.iter().zip(reports.iter()) {
+        match report {
+            Ok(report) => switches.push((report.info.device_name.clone(), report.vlan_names.clone().into_iter().collect())),
+            Err(e) => {
+                // An agent that did not answer or let us in will not now
+                let unreachable = e.chain()
+                    .find_map(|cause| cause.downcast_ref::<switch_vlan_diagram::Error>())
+                    .is_some_and(|e| e.exit_code() != EXIT_FAILURE);
+                if unreachable {
+                    continue;
+                }
+                let settings = host_settings(args, config, host, None);
+                let options = collect_options(args, config.clone(), &settings);
+                let Ok(mut vlan_names) = collect_vlan_names_with(host, &options, &SessionPool::new()) else {
+                    continue;
+                };
+                let mut name = host.clone();
+                if args.anonymize {
+                    let mut anonymizer = Anonymizer::default();
+                    name = anonymizer.device(host, &[]);
+                    for (&vlan_id, vlan_name) in vlan_names.iter_mut() {
+                        *vlan_name = anonymizer.vlan_name(vlan_id, vlan_name);
+                    }
+                }
+                switches.push((name, vlan_names));
             }
         }
     }
 
-    // Update VLAN memberships based on LACP info
-    for port_config in &mut port_configs {
-        if let Some(lacp_info) = &port_config.lacp_info {
-            if let Some((tagged, untagged)) = &lacp_info.agg_vlans {
-                port_config.vlan_memberships = tagged.clone();
-                port_config.untagged_vlans = untagged.clone();
+    let mut conflicts = checks::vlan_name_conflicts(&switches);
+    conflicts.retain(|conflict| conflict.vlan.is_some_and(|vlan| !config.allow_vlan_name_conflicts.contains(&vlan)));
+    for conflict in &conflicts {
+        let warning = Warning::from(conflict);
+        if !args.quiet {
+            Event::warning(&warning.code, warning.message.clone())
+                .text(format!("Warning: {}", warning))
+                .field("vlan", conflict.vlan)
+                .emit();
+        }
+        let involved = reports.iter_mut().flatten()
+            .filter(|report| conflict.vlan.is_some_and(|vlan| report.vlan_names.contains_key(&vlan)));
+        for report in involved {
+            if !args.no_warnings {
+                report.info.warnings.push(warning.clone());
             }
+            report.snapshot.warnings.push(warning.clone());
+        }
+    }
+    conflicts.len()
+}
+
+/// Write the VLAN presence matrix to one --vlan-matrix file
+fn write_vlan_matrix(path: &Path, format: SiteFormat, matrix: &VlanMatrix, align: bool) -> Result<()> {
+    match format {
+        SiteFormat::Markdown => watch::write_atomically_with(path, |w| output::write_vlan_matrix(w, matrix, align)),
+        SiteFormat::Html => watch::write_atomically_with(path, |w| html_output::write_vlan_matrix(w, matrix)),
+        SiteFormat::Json => watch::write_atomically(path, &matrix.to_json()?),
+        SiteFormat::Dot | SiteFormat::Mermaid => Err(anyhow!("--vlan-matrix {}: a matrix is not a graph", path.display())),
+    }
+}
+
+/// Write the links between switches to one --topology file
+fn write_topology(path: &Path, format: SiteFormat, topology: &Topology, align: bool) -> Result<()> {
+    match format {
+        SiteFormat::Markdown => watch::write_atomically_with(path, |w| output::write_topology(w, topology, align)),
+        SiteFormat::Html => watch::write_atomically_with(path, |w| html_output::write_topology(w, topology)),
+        SiteFormat::Json => watch::write_atomically(path, &topology.to_json()?),
+        SiteFormat::Dot => watch::write_atomically_with(path, |w| topology.write_dot(w)),
+        SiteFormat::Mermaid => watch::write_atomically_with(path, |w| topology.write_mermaid(w)),
+    }
+}
+
+/// With several switches, every per-switch path needs the "{host}" placeholder
+fn check_host_placeholders(args: &Args) -> Result<()> {
+    for (path, flag) in [(&args.output, "--output"), (&args.snapshot, "--snapshot")] {
+        if path.as_ref().is_some_and(|path| !path.to_string_lossy().contains(HOST_PLACEHOLDER)) {
+            return Err(anyhow!("{} needs a \"{}\" placeholder when polling several switches", flag, HOST_PLACEHOLDER));
         }
     }
+    Ok(())
+}
 
-    // Sort by port number to ensure ranges are contiguous
-    port_configs.sort_by_key(|config| config.port_num);
+/// Compare the VLANs of --ip with another switch or a snapshot
+fn run_compare_vlans(args: &Args, config: &Config, other: &str) -> Result<()> {
+    let [host] = args.ip.as_slice() else {
+        return Err(anyhow!("--compare-vlans needs a single --ip"));
+    };
+    let sessions = SessionPool::new();
+    let vlans = |host: &str| -> Result<BTreeMap<u32, String>> {
+        let settings = host_settings(args, config, host, None);
+        let options = collect_options(args, config.clone(), &settings);
+        Ok(collect_switch_with(host, &options, &sessions)?.vlans.into_iter().collect())
+    };
+    let left = vlans(host)?;
+    // An existing file wins over a switch of the same name
+    let right = match Path::new(other) {
+        path if path.is_file() => Snapshot::load(path)?.vlan_names,
+        _ => vlans(other)?,
+    };
 
-    // Group ports with identical configuration into ranges
-    let mut port_ranges: Vec<PortRange> = Vec::new();
-    let mut current_config: Option<PortConfig> = None;
-    let mut current_start: u32 = 0;
-    let mut current_end: u32 = 0;
+    // Half a VLAN list would show the rest as missing
+    if cancel::is_cancelled() {
+        return Err(anyhow!("Interrupted before both sides were collected"));
+    }
+    let mismatches = snapshot::compare_vlans(&left, &right);
+    let mut stdout = BufWriter::new(io::stdout().lock());
+    output::write_vlan_comparison(&mut stdout, (host, other), left.len(), &mismatches, !args.no_align)
+        .and_then(|_| stdout.flush())
+        .map_err(|e| anyhow!("Failed to write the comparison: {}", e))?;
+    if !mismatches.is_empty() {
+        std::process::exit(EXIT_VLAN_MISMATCH);
+    }
+    Ok(())
+}
 
-    // Helper function to check if configurations match
-    let configs_match = |a: &PortConfig, b: &PortConfig| -> bool {
-        a.pvid == b.pvid && 
-        a.vlan_memberships == b.vlan_memberships && 
-        a.untagged_vlans == b.untagged_vlans &&
-        a.alias == b.alias &&
-        a.lacp_info == b.lacp_info
+fn run_check(args: &Args, config: &Config) -> Result<()> {
+    let [host] = args.ip.as_slice() else {
+        return Err(anyhow!("--check needs a single --ip"));
     };
+    let settings = host_settings(args, config, host, None);
+    let options = collect_options(args, config.clone(), &settings);
+    let probe = probe_switch_with(host, &options, &SessionPool::new())?;
+    if cancel::is_cancelled() {
+        return Err(anyhow!("Interrupted before every table was probed"));
+    }
+    let mut stdout = BufWriter::new(io::stdout().lock());
+    output::write_probe(&mut stdout, host, &probe)
+        .and_then(|_| stdout.flush())
+        .map_err(|e| anyhow!("Failed to write the check: {}", e))?;
+    if !probe.passed() {
+        std::process::exit(EXIT_CHECK_FAILED);
+    }
+    Ok(())
+}
 
-    for config in port_configs {
-        let port_num = config.port_num;
-        match &current_config {
-            Some(current) => {
-                if configs_match(current, &config) && port_num == current_end + 1 {
-                    // Extend current range
-                    current_end = port_num;
-                } else {
-                    // End current range and start new one
-                    if let Some(current) = current_config.take() {
-                        port_ranges.push(PortRange {
-                            first_port: current_start,
-                            last_port: current_end,
-                            alias: current.alias,
-                            pvid: current.pvid,
-                            vlan_memberships: current.vlan_memberships,
-                            untagged_vlans: current.untagged_vlans,
-                            lacp_info: current.lacp_info,
-                        });
-                    }
-                    current_config = Some(config);
-                    current_start = port_num;
-                    current_end = port_num;
-                }
-            }
+/// Keep the documentation of one or several switches current from their traps
+fn run_trap_listener(args: &Args, config: &Config, metadata: Option<&Metadata>, listen: &str) -> Result<()> {
+    if args.ip.len() > 1 {
+        check_host_placeholders(args)?;
+    }
+    let mut switches = HashMap::new();
+    let mut sources = HashMap::new();
+    for host in &args.ip {
+        let settings = host_settings(args, config, host, metadata);
+        let options = collect_options(args, config.clone(), &settings);
+        let community = args.trap_community.clone().unwrap_or_else(|| options.community.clone());
+        // Traps come from the switch's address, whatever name it was given by
+        let addresses = (host.as_str(), options.port).to_socket_addrs()
+            .map_err(|e| anyhow!("Failed to resolve {}: {}", host, e))?;
+        for address in addresses {
+            sources.insert(address.ip(), watch::TrapSource { host: host.clone(), community: community.clone().into_bytes() });
+        }
+        switches.insert(host.as_str(), (settings, options));
+    }
+
+    // Regenerations reuse their switch's session, like --watch cycles
+    let sessions = SessionPool::new();
+    watch::listen(listen, &sources, &args.ip, Duration::from_secs(args.trap_debounce), |host| {
+        let (settings, options) = &switches[host];
+        let report = generate_report(args, host, options, settings, metadata, &sessions)?;
+        write_host_report(args, host, &report)?;
+        Ok(report)
+    })
+}
+
+/// Write the snapshot, report and upload of a switch, to its "{host}" paths when polling several
+fn write_host_report(args: &Args, host: &str, report: &Report) -> Result<()> {
+    if report.info.interrupted {
+        return report.write(args.output.as_deref().map(|path| host_path(path, host)).as_deref());
+    }
+    if let Some(path) = &args.snapshot {
+        watch::write_atomically(&host_path(path, host), &report.snapshot.to_json()?)?;
+    }
+    report.write(args.output.as_deref().map(|path| host_path(path, host)).as_deref())?;
+    upload_report(args, report)
+}
+
+/// Write the report of an interrupted run and exit. Snapshots, uploads,
+/// commits and comparisons would take the missing rows for removed ports.
+fn write_partial(output: Option<&Path>, report: &Report) -> Result<()> {
+    report.write(output)?;
+    Event::warning("interrupted", "Collection interrupted; the report is partial, nothing else was written").emit();
+    std::process::exit(EXIT_INTERRUPTED);
+}
+
+/// Replace the "{host}" placeholder in a per-switch path
+fn host_path(path: &Path, host: &str) -> PathBuf {
+    PathBuf::from(path.to_string_lossy().replace(HOST_PLACEHOLDER, host))
+}
+
+/// One polled switch, ready to render
+pub struct Report {
+    ranges: Vec<PortRange>,
+    vlan_names: HashMap<u32, String>,
+    columns: Columns,
+    info: ReportInfo,
+    violations: Vec<Violation>,
+    snapshot: Snapshot,
+    format: OutputFormat,
+}
+
+impl Report {
+    /// Stream the rendered report into `w`
+    fn render(&self, w: &mut dyn Write) -> io::Result<()> {
+        self.format.render(w, &self.ranges, &self.vlan_names, &self.columns, &self.info)
+    }
+
+    /// Write the report to --output, or stdout
+    fn write(&self, output: Option<&Path>) -> Result<()> {
+        let write = |w: &mut dyn Write| self.render(w);
+        match output {
+            Some(path) => watch::write_atomically_with(path, write),
             None => {
-                current_config = Some(config);
-                current_start = port_num;
-                current_end = port_num;
+                let mut stdout = BufWriter::new(io::stdout().lock());
+                write(&mut stdout).and_then(|_| stdout.flush())
+                    .map_err(|e| anyhow!("Failed to write the report: {}", e))
             }
         }
     }
+}
 
-    // Add the last range if it exists
-    if let Some(current) = current_config {
-        port_ranges.push(PortRange {
-            first_port: current_start,
-            last_port: current_end,
-            alias: current.alias,
-            pvid: current.pvid,
-            vlan_memberships: current.vlan_memberships,
-            untagged_vlans: current.untagged_vlans,
-            lacp_info: current.lacp_info,
-        });
+/// Tell --webhook how the poll went compared with `before`, if there is anything to compare with
+fn notify_webhook(webhook: &Webhook, host: &str, before: Option<&Snapshot>, after: Result<&Snapshot, &anyhow::Error>) {
+    let diff = before.zip(after.ok()).map(|(before, after)| before.diff(after));
+    let outcome = match (after, before, &diff) {
+        (Err(e), _, _) => Outcome::Failed(e),
+        (Ok(after), Some(before), Some(diff)) if !diff.is_empty() => Outcome::Changed { before, after, diff },
+        (Ok(_), Some(_), _) => Outcome::Unchanged,
+        (Ok(_), None, _) => Outcome::Written,
+    };
+    webhook.notify(host, &outcome);
+}
+
+/// Send the report to --upload-url if given
+fn upload_report(args: &Args, report: &Report) -> Result<()> {
+    let Some(url) = &args.upload_url else {
+        return Ok(());
+    };
+    let mut body = Vec::new();
+    report.render(&mut body)?;
+    upload::upload(url, args.upload_method, &args.upload_header, report.format.content_type(), &body)
+}
+
+/// Write the report's snapshot if --snapshot was given
+fn write_snapshot(args: &Args, report: &Report) -> Result<()> {
+    match &args.snapshot {
+        Some(path) => watch::write_atomically(path, &report.snapshot.to_json()?),
+        None => Ok(()),
     }
+}
 
-    // Display final port information using the new table format
-    let output_format = match args.format.to_lowercase().as_str() {
-        "html" => OutputFormat::Html,
-        "markdown" => OutputFormat::Markdown,
-        _ => {
-            eprintln!("Invalid output format. Using markdown.");
-            OutputFormat::Markdown
+/// Non-default options that shape what a report shows, for its footer, in
+/// command-line syntax where there is one. Credentials, URLs and the values of
+/// overrides stay out; overrides are listed by what they apply to.
+fn option_summary(args: &Args, options: &CollectOptions) -> Vec<String> {
+    let port_list = |PortList(ports): &PortList| output::format_port_set(&ports.iter().copied().collect::<Vec<u32>>());
+    let file_name = |path: &PathBuf| path.file_name().map_or_else(|| path.display().to_string(), |name| name.to_string_lossy().into_owned());
+    let mut summary = Vec::new();
+    if let Some(vendor) = options.vendor {
+        summary.push(format!("--vendor {}", vendor));
+    }
+    if let Some(ports) = &args.ports {
+        summary.push(format!("--ports {}", port_list(ports)));
+    }
+    if let Some(ports) = &args.exclude_ports {
+        summary.push(format!("--exclude-ports {}", port_list(ports)));
+    }
+    if let Some(max_port) = args.max_port {
+        summary.push(format!("--max-port {}", max_port));
+    }
+    if let Some(regex) = &args.alias_filter {
+        // Aliases are what --anonymize hides
+        match args.anonymize {
+            true => summary.push("--alias-filter".to_string()),
+            false => summary.push(format!("--alias-filter {}", regex.as_str())),
+        }
+        if args.alias_filter_invert {
+            summary.push("--alias-filter-invert".to_string());
         }
+    }
+    if !args.vlan.is_empty() {
+        summary.push(format!("--vlan {}", args.vlan.join(",")));
+    }
+    if args.hide_default || args.default_vlan != 1 {
+        summary.push(format!("--default-vlan {}", args.default_vlan));
+    }
+    if args.uplink_min_vlans != 3 {
+        summary.push(format!("--uplink-min-vlans {}", args.uplink_min_vlans));
+    }
+    let flags = [
+        (args.unaliased_only, "--unaliased-only"),
+        (args.only_aliased, "--only-aliased"),
+        (args.hide_default, "--hide-default"),
+        (args.infer_trunks, "--infer-trunks"),
+        (args.keep_member_vlans, "--keep-member-vlans"),
+        (args.allow_gaps, "--allow-gaps"),
+        (args.group_ignore_alias, "--group-ignore-alias"),
+        (args.dedupe, "--dedupe"),
+        (args.no_ranges, "--no-ranges"),
+        (args.anonymize, "--anonymize"),
+    ];
+    summary.extend(flags.iter().filter(|(given, _)| *given).map(|(_, flag)| flag.to_string()));
+    if let Some(encoding) = args.fallback_encoding {
+        summary.push(format!("--fallback-encoding {}", encoding));
+    }
+    if options.if_types != [6, 117] {
+        let types: Vec<String> = options.if_types.iter().map(u32::to_string).collect();
+        summary.push(format!("ifTypes {}", types.join(", ")));
+    }
+    if !options.alias_overrides.is_empty() {
+        let ports: Vec<u32> = options.alias_overrides.keys().copied().collect();
+        summary.push(format!("alias overrides for ports {}", output::format_port_set(&ports)));
+    }
+    if !options.vlan_names.is_empty() {
+        let vlans: Vec<String> = options.vlan_names.keys().map(u32::to_string).collect();
+        summary.push(format!("VLAN names for {}", vlans.join(", ")));
+    }
+    if !options.lacp_overrides.is_empty() {
+        let mut ports: Vec<u32> = options.lacp_overrides.iter().flat_map(|o| o.target_ports.iter().copied()).collect();
+        ports.sort_unstable();
+        ports.dedup();
+        summary.push(format!("LACP overrides for ports {}", output::format_port_set(&ports)));
+    }
+    if let Some(path) = &args.metadata {
+        summary.push(format!("--metadata {}", file_name(path)));
+    }
+    if let Some(path) = &args.desired {
+        summary.push(format!("--desired {}", file_name(path)));
+    }
+    summary
+}
+
+/// Collection options from the merged settings. Only tables that a selected
+/// column, --capacity or a highlight rule needs are walked.
+fn collect_options(args: &Args, config: Config, settings: &Settings) -> CollectOptions {
+    let columns = settings.column_layout();
+    // Rules were validated when the config was loaded
+    let rules_use_status = config.highlight_rules().unwrap_or_default().iter().any(|rule| rule.uses_status());
+
+    // Named overrides from the config go first so that --override-lacp,
+    // applied later, wins for the same target port
+    let mut lacp_overrides: Vec<LacpOverride> = settings.lacp_overrides.iter().flatten()
+        .map(|named| LacpOverride {
+            source_interface: named.source,
+            target_ports: named.ports.clone(),
+            name: named.name.clone(),
+        })
+        .collect();
+    for override_str in settings.override_lacp.iter().flatten() {
+        match parse_lacp_override(override_str) {
+            Ok(override_info) => lacp_overrides.push(override_info),
+            Err(e) => Event::warning("invalid-lacp-override", format!("Invalid LACP override '{}': {}", override_str, e)).emit(),
+        }
+    }
+
+    // Ports and VLAN ids were validated when the config was loaded
+    let by_number = |map: &Option<BTreeMap<String, String>>| -> BTreeMap<u32, String> {
+        map.iter().flatten()
+            .map(|(key, value)| (key.parse::<u32>().unwrap_or_default(), value.clone()))
+            .collect()
     };
 
-    let output = match output_format {
-        OutputFormat::Html => generate_port_table(&port_ranges, &vlan_names, output_format, &args.ip),
-        OutputFormat::Markdown => {
-            let mut output = String::new();
-            output.push_str("\nPort Information Table:\n");
-            output.push_str(&generate_port_table(&port_ranges, &vlan_names, output_format, ""));
-            output
+    CollectOptions {
+        community: settings.community.clone().unwrap_or_default(),
+        timeout: Duration::from_secs(settings.timeout.unwrap_or_default()),
+        port: args.port,
+        // Validated when the config was loaded
+        vendor: settings.vendor.as_deref().and_then(|vendor| vendor.parse::<Vendor>().ok()),
+        config,
+        if_types: settings.if_types.clone().unwrap_or_default(),
+        virtual_if_types: settings.virtual_if_types.clone().unwrap_or_default(),
+        tables: Tables {
+            mac: columns.contains(&Column::Mac),
+            last_change: columns.contains(&Column::LastChange),
+            frames: columns.contains(&Column::Frames),
+            dot1x: columns.contains(&Column::Dot1x),
+            admin_status: args.capacity || rules_use_status,
+            lldp: !args.topology.is_empty(),
+            lags: args.lags,
+            bridge_ports: columns.contains(&Column::IfIndex) || columns.contains(&Column::BridgePort),
+            virtual_interfaces: args.include_virtual,
+        },
+        lacp_overrides,
+        vlan_names: by_number(&settings.vlan_names),
+        alias_overrides: by_number(&settings.alias_overrides),
+        fallback_encoding: args.fallback_encoding,
+        keep_member_vlans: args.keep_member_vlans,
+        uplink_min_vlans: args.uplink_min_vlans,
+        min_ports: settings.min_ports.unwrap_or_default().max(usize::from(!args.no_fail_on_empty)),
+        progress: !args.quiet,
+        verbose: args.verbose,
+        prefix_host: args.ip.len() > 1,
+    }
+}
+
+/// Which ports the report shows and how, from the flags and the host's config section
+fn report_options(args: &Args, host: &str, options: &CollectOptions, settings: &Settings) -> ReportOptions {
+    ReportOptions {
+        ports: args.ports.clone(),
+        exclude_ports: args.exclude_ports.clone(),
+        alias_filter: args.alias_filter.clone(),
+        alias_filter_invert: args.alias_filter_invert,
+        unaliased_only: args.unaliased_only,
+        only_aliased: args.only_aliased,
+        hide_default: args.hide_default,
+        default_vlan: args.default_vlan,
+        vlans: args.vlan.clone(),
+        infer_trunks: args.infer_trunks,
+        no_infer_trunk_ports: options.config.no_infer_trunk_ports(host).to_vec(),
+        per_port: args.no_ranges,
+        dedupe: args.dedupe,
+        allow_gaps: args.allow_gaps,
+        group_ignore_alias: args.group_ignore_alias,
+        max_port: args.max_port,
+        sort: args.sort,
+        reverse: args.reverse,
+        panel_labels: settings.panel_labels.clone().unwrap_or_default(),
+        panel_blocks: settings.panel_blocks.clone().unwrap_or_default(),
+    }
+}
+
+/// Poll the switch and render the report
+fn generate_report(
+    args: &Args,
+    host: &str,
+    options: &CollectOptions,
+    settings: &Settings,
+    metadata: Option<&Metadata>,
+    sessions: &SessionPool,
+) -> Result<Report> {
+    // A broken file or a format that cannot be written should not cost a poll of the switch.
+    // The format was validated when the config was loaded
+    let output_format = settings.format.as_deref()
+        .and_then(|format| format.parse::<OutputFormat>().ok())
+        .unwrap_or_default();
+    if !output_format.is_supported() {
+        return Err(anyhow!(output::PDF_UNSUPPORTED));
+    }
+    if output_format == OutputFormat::Pdf && args.output.is_none() {
+        return Err(anyhow!("--format pdf needs --output; a PDF cannot go to stdout"));
+    }
+    let desired = args.desired.as_ref()
+        .map(|path| host_path(path, host))
+        .map(|path| DesiredState::load(&path).map(|desired| (path, desired)))
+        .transpose()?;
+    let mut columns = Columns {
+        layout: settings.column_layout(),
+        labels: settings.column_labels(),
+    };
+
+    let started = Instant::now();
+    let SwitchSnapshot {
+        device,
+        vlans: mut vlan_names,
+        ports: port_configs,
+        interfaces: _,
+        mut vtp,
+        sources,
+        mut warnings,
+        mut violations,
+        neighbors,
+        lags,
+        mut virtual_interfaces,
+    } = collect_switch_with(host, options, sessions)?;
+    let collection_time = started.elapsed();
+    let interrupted = cancel::is_cancelled();
+
+    let allow_no_vlans = settings.allow_no_vlans.clone().unwrap_or_default();
+    violations.retain(|violation| {
+        let expected = violation.check == Check::NoVlans
+            && violation.ports.iter().all(|port| allow_no_vlans.contains(port));
+        !args.allow.contains(&violation.check) && !expected
+    });
+    warnings.extend(violations.iter().map(Warning::from));
+
+    // Spreadsheet rows that do not match a port are most likely typos
+    if let Some(metadata) = metadata {
+        for port in metadata.ports.keys() {
+            if !port_configs.iter().any(|p| p.port_num == *port) {
+                warnings.push("metadata-no-port", vec![*port], None, "metadata row matches no port on this switch");
+            }
+        }
+    }
+
+    // The snapshot, like capacity, covers the whole switch
+    let mut snapshot = Snapshot::new(device, &vlan_names, &port_configs);
+    snapshot.neighbors = neighbors;
+    snapshot.lags.clone_from(&lags);
+    snapshot.virtual_interfaces.clone_from(&virtual_interfaces);
+    let panel_labels = settings.panel_labels.clone().unwrap_or_default();
+    let panel_blocks = settings.panel_blocks.clone().unwrap_or_default();
+    for port in &mut snapshot.ports {
+        port.panel = panel_label(port.port, &panel_labels, &panel_blocks);
+    }
+    snapshot.sources = sources.iter()
+        .map(|source| (source.name.clone(), SnapshotSource::new(source)))
+        .collect();
+
+    // Drift, like the snapshot, covers the whole switch
+    if let Some((path, desired)) = &desired {
+        let drift = desired.drift(&snapshot, args.default_vlan);
+        let mut ports: Vec<u32> = drift.iter().flat_map(|violation| violation.ports.iter().copied()).collect();
+        ports.sort_unstable();
+        ports.dedup();
+        let summary = match ports.len() {
+            0 => "no drift".to_string(),
+            _ => format!("ports {}", output::format_port_set(&ports)),
+        };
+        Event::info("drift", format!("Drift from {}: {}", path.display(), summary))
+            .host(host, options.prefix_host)
+            .field("ports", ports)
+            .emit();
+        if !args.allow.contains(&Check::Drift) {
+            warnings.extend(drift.iter().map(Warning::from));
+            violations.extend(drift);
+        }
+    }
+
+    // Capacity covers the whole switch, so take it before any filtering
+    let capacity = args.capacity
+        .then(|| CapacityReport::new(&port_configs, &args.free_when, args.default_vlan));
+    snapshot.capacity.clone_from(&capacity);
+
+    let mut anonymizer = args.anonymize.then(Anonymizer::default);
+    let PortTable { ranges: port_ranges, notes } = report::port_table(host, port_configs, &vlan_names,
+        &report_options(args, host, options, settings), metadata, &mut warnings, anonymizer.as_mut())?;
+
+    // Columns asked for by name stay even when empty
+    if !args.keep_empty_columns {
+        let hidden = columns.hide_empty(&port_ranges, &settings.selected_columns());
+        if options.verbose && !hidden.is_empty() {
+            let ids: Vec<&str> = hidden.iter().map(|column| column.id()).collect();
+            Event::info("columns-hidden", format!("Columns without data left out: {}", ids.join(", ")))
+                .host(host, options.prefix_host)
+                .field("columns", ids)
+                .emit();
+        }
+    }
+
+    // Display final port information using the new table format
+    let mut ip_address = host.to_string();
+    let mut device_name = settings.device_name.clone()
+        .or_else(|| snapshot.device.sys_name.clone().filter(|name| !name.is_empty()))
+        .unwrap_or_else(|| host.to_string());
+
+    if !args.quiet {
+        warnings.echo(host, options.prefix_host);
+    }
+    let mut warnings = warnings.into_vec();
+
+    // Pseudonyms go in last so that checks and filters saw the real names
+    if let Some(anonymizer) = &mut anonymizer {
+        let sys_name = snapshot.device.sys_name.clone().unwrap_or_default();
+        let pseudonym = anonymizer.device(host, &[&device_name, &sys_name]);
+        anonymizer.vlan_names(&mut vlan_names);
+        anonymizer.virtual_interfaces(&mut virtual_interfaces);
+        anonymizer.snapshot(&mut snapshot, &pseudonym);
+        if let Some(vtp) = &mut vtp {
+            vtp.domain = anonymizer.vtp_domain(&vtp.domain);
+        }
+        for warning in &mut warnings {
+            warning.message = anonymizer.text(&warning.message);
+        }
+        ip_address = pseudonym.clone();
+        device_name = pseudonym;
+        if let Some(path) = &args.anonymize_map {
+            watch::write_atomically(path, &anonymizer.mapping_json()?)?;
         }
+    }
+
+    let mut meta = Meta::new(option_summary(args, options));
+    if args.deterministic {
+        // The version stays, but not what changes with every commit and run
+        meta.git_describe = None;
+    } else {
+        meta.collection_ms = Some(collection_time.as_millis() as u64);
+    }
+    let info = ReportInfo {
+        ip_address,
+        title: settings.title.clone().unwrap_or_default(),
+        device_name,
+        vlan_style: VlanStyle {
+            display: args.vlan_display,
+            show_vlan1_name: args.show_vlan1_name,
+            hide_vlan1: args.hide_vlan1,
+            compact: args.compact_vlans,
+        },
+        generated_on: (!args.deterministic)
+            .then(|| format_timestamp(Utc::now(), &args.timestamp_format, &args.timezone))
+            .transpose()?,
+        vtp,
+        sources,
+        notes,
+        capacity,
+        lags: args.lags.then_some(lags),
+        virtual_interfaces: args.include_virtual.then_some(virtual_interfaces),
+        changes: None,
+        highlight_vlans: settings.highlight_vlans.clone().unwrap_or_default(),
+        highlight_rules: options.config.highlight_rules().unwrap_or_default(),
+        warnings: if args.no_warnings { Vec::new() } else { warnings.clone() },
+        align_markdown: !args.no_align,
+        // Shown with the column, so that reports without it stay as they were
+        config_hash: snapshot.config_hash.clone().filter(|_| columns.has(Column::ConfigHash)),
+        meta: Some(meta.clone()),
+        interrupted,
     };
+    snapshot.warnings = warnings;
+    snapshot.meta = Some(meta);
 
-    println!("{}", output);
+    Ok(Report {
+        ranges: port_ranges,
+        vlan_names,
+        columns,
+        info,
+        violations,
+        snapshot,
+        format: output_format,
+    })
+}
 
-    Ok(())
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bash_completions_cover_main_flags() {
+        let mut script = Vec::new();
+        clap_complete::generate(Shell::Bash, &mut Args::command(), "switch-vlan-diagram", &mut script);
+        let script = String::from_utf8(script).unwrap();
+        assert!(!script.is_empty());
+        for flag in ["--ip", "--community", "--format", "--override-lacp", "--config"] {
+            assert!(script.contains(flag), "completion script lacks {}", flag);
+        }
+        // Value hints for --format come from the same definition
+        assert!(script.contains("markdown html"));
+    }
+
+    #[test]
+    fn format_is_parsed_at_the_command_line() {
+        let args = Args::try_parse_from(["switch-vlan-diagram", "--ip", "10.0.0.1", "--format", "HTML"]).unwrap();
+        assert_eq!(args.format, Some(OutputFormat::Html));
+        let err = Args::try_parse_from(["switch-vlan-diagram", "--ip", "10.0.0.1", "--format", "docx"]).unwrap_err();
+        assert!(err.to_string().contains("[possible values: markdown, html, pdf]"), "{}", err);
+    }
+
+    #[test]
+    fn several_switches_are_given_by_repeating_or_listing_ip() {
+        let args = Args::try_parse_from(["switch-vlan-diagram", "--ip", "10.0.0.1,10.0.0.2", "-i", "10.0.0.3"]).unwrap();
+        assert_eq!(args.ip, ["10.0.0.1", "10.0.0.2", "10.0.0.3"]);
+        assert_eq!(args.jobs, 4);
+        assert!(Args::try_parse_from(["switch-vlan-diagram", "--ip", "10.0.0.1", "--jobs", "0"]).is_err());
+        assert_eq!(host_path(Path::new("docs/{host}.md"), "10.0.0.2"), Path::new("docs/10.0.0.2.md"));
+    }
+
+    #[test]
+    fn flags_win_over_host_sections_over_global_config() {
+        let config: Config = toml::from_str(r#"
+community = "global"
+timeout = 5
+title = "Global title"
+
+[hosts."sw-core-1"]
+community = "core"
+vendor = "cisco"
+override_lacp = ["26:21,22"]
+"#).unwrap();
+        let args = Args::try_parse_from(["switch-vlan-diagram", "--ip", "sw-core-1", "--community", "cli",
+            "--vendor", "generic", "--title", "CLI title"]).unwrap();
+        let settings = host_settings(&args, &config, "sw-core-1", None);
+        assert_eq!(cli_settings(&Args::try_parse_from(["switch-vlan-diagram", "--ip", "sw-core-1"]).unwrap()), Settings::default());
+        assert_eq!(settings.community.as_deref(), Some("cli"));
+        assert_eq!(settings.vendor.as_deref(), Some("generic"));
+        assert_eq!(settings.title.as_deref(), Some("CLI title"));
+        // Flags not given leave the host section, then the global config, then the defaults
+        assert_eq!(settings.override_lacp, Some(vec!["26:21,22".to_string()]));
+        assert_eq!(settings.timeout, Some(5));
+        assert_eq!(settings.if_types, Some(vec![6, 117]));
+
+        let args = Args::try_parse_from(["switch-vlan-diagram", "--ip", "sw-core-1", "--timeout", "9",
+            "--override-lacp", "49:47,48", "--min-ports", "24", "--format", "html", "--device-name", "core"]).unwrap();
+        let settings = host_settings(&args, &config, "sw-core-1", None);
+        assert_eq!(settings.community.as_deref(), Some("core"));
+        assert_eq!(settings.timeout, Some(9));
+        assert_eq!(settings.override_lacp, Some(vec!["49:47,48".to_string()]));
+        assert_eq!(settings.min_ports, Some(24));
+        assert_eq!(settings.format.as_deref(), Some("html"));
+        assert_eq!(settings.device_name.as_deref(), Some("core"));
+        assert_eq!(host_settings(&args, &config, "10.0.0.9", None).community.as_deref(), Some("global"));
+    }
+
+    #[test]
+    fn footer_leaves_out_flags_without_effect() {
+        let args = Args::try_parse_from(["switch-vlan-diagram", "--ip", "10.0.0.1", "-n", "--group-ignore-alias"]).unwrap();
+        assert_eq!(option_summary(&args, &CollectOptions::default()), ["--group-ignore-alias"]);
+    }
 }
@@ -0,0 +1,148 @@
+//! Site-wide VLAN presence: for every VLAN, how many ports of each switch
+//! polled together carry it, to spot a VLAN that never reached a switch
+use std::collections::{BTreeMap, BTreeSet};
+use anyhow::{Result, anyhow};
+use serde::Serialize;
+use crate::snapshot::Snapshot;
+
+/// Ports of one switch carrying one VLAN
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct PortCounts {
+    pub tagged: usize,
+    pub untagged: usize,
+}
+
+impl PortCounts {
+    /// No port of the switch carries the VLAN, whether or not it is defined there
+    pub fn is_absent(&self) -> bool {
+        self.tagged == 0 && self.untagged == 0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MatrixRow {
+    pub vlan: u32,
+    /// The first name any switch gives the VLAN
+    pub name: Option<String>,
+    /// One entry per switch, in the order of `VlanMatrix::switches`
+    pub ports: Vec<PortCounts>,
+}
+
+/// VLANs as rows, switches as columns
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct VlanMatrix {
+    pub switches: Vec<String>,
+    /// Every VLAN defined on or carried by any of the switches, ascending
+    pub vlans: Vec<MatrixRow>,
+}
+
+impl VlanMatrix {
+    /// From the snapshots of switches polled together, named as in their reports
+    pub fn new<'a>(switches: impl IntoIterator<Item = (&'a str, &'a Snapshot)>) -> VlanMatrix {
+        let switches: Vec<(&str, &Snapshot)> = switches.into_iter().collect();
+        let mut vlans: BTreeSet<u32> = BTreeSet::new();
+        for (_, snapshot) in &switches {
+            vlans.extend(snapshot.vlan_names.keys());
+            vlans.extend(snapshot.ports.iter().flat_map(|port| port.vlans.union(&port.untagged)));
+        }
+        let counts: Vec<BTreeMap<u32, PortCounts>> = switches.iter()
+            .map(|(_, snapshot)| {
+                let mut counts: BTreeMap<u32, PortCounts> = BTreeMap::new();
+                for port in &snapshot.ports {
+                    for &vlan in &port.untagged {
+                        counts.entry(vlan).or_default().untagged += 1;
+                    }
+                    for &vlan in port.vlans.difference(&port.untagged) {
+                        counts.entry(vlan).or_default().tagged += 1;
+                    }
+                }
+                counts
+            })
+            .collect();
+        let vlans = vlans.into_iter()
+            .map(|vlan| MatrixRow {
+                vlan,
+                name: switches.iter()
+                    .find_map(|(_, snapshot)| snapshot.vlan_names.get(&vlan).filter(|name| !name.is_empty()))
+                    .cloned(),
+                ports: counts.iter().map(|counts| counts.get(&vlan).copied().unwrap_or_default()).collect(),
+            })
+            .collect();
+        VlanMatrix { switches: switches.iter().map(|(name, _)| name.to_string()).collect(), vlans }
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self).map_err(|e| anyhow!("Failed to serialize the VLAN matrix: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snapshot::{Device, SnapshotPort};
+
+    fn port(port: u32, vlans: &[u32], untagged: &[u32]) -> SnapshotPort {
+        SnapshotPort {
+            port,
+            alias: None,
+            raw_alias: None,
+            pvid: untagged.first().copied(),
+            vlans: vlans.iter().copied().collect(),
+            untagged: untagged.iter().copied().collect(),
+            lacp: None,
+            dot1x: None,
+            frame_policy: None,
+            uplink: false,
+            admin_down: false,
+            mac: None,
+            config_hash: None,
+            indices: None,
+            panel: None,
+        }
+    }
+
+    fn switch(vlan_names: &[(u32, &str)], ports: Vec<SnapshotPort>) -> Snapshot {
+        Snapshot {
+            schema_version: 1,
+            device: Device { ip: "192.0.2.1".to_string(), sys_name: None, sys_descr: None, vendor: "generic".to_string(),
+                chassis_id: None },
+            vlan_names: vlan_names.iter().map(|(vlan, name)| (*vlan, name.to_string())).collect(),
+            ports,
+            neighbors: Vec::new(),
+            lags: Vec::new(),
+            virtual_interfaces: Vec::new(),
+            warnings: Vec::new(),
+            sources: BTreeMap::new(),
+            capacity: None,
+            config_hash: None,
+            meta: None,
+        }
+    }
+
+    #[test]
+    fn ports_are_counted_per_vlan_and_switch() {
+        let core = switch(&[(1, "default"), (10, "studio"), (20, "")], vec![
+            port(1, &[10], &[10]),
+            port(2, &[10], &[10]),
+            port(24, &[1, 10, 20], &[1]),
+        ]);
+        // VLAN 20 never made it to the closet, and 30 is only there
+        let closet = switch(&[(1, "default"), (10, "studio"), (30, "lab")], vec![
+            port(1, &[10, 30], &[30]),
+        ]);
+        let matrix = VlanMatrix::new([("core", &core), ("closet", &closet)]);
+        assert_eq!(matrix.switches, ["core", "closet"]);
+        let names: Vec<(u32, Option<&str>)> = matrix.vlans.iter().map(|row| (row.vlan, row.name.as_deref())).collect();
+        assert_eq!(names, [(1, Some("default")), (10, Some("studio")), (20, None), (30, Some("lab"))]);
+        let counts: Vec<Vec<(usize, usize)>> = matrix.vlans.iter()
+            .map(|row| row.ports.iter().map(|counts| (counts.tagged, counts.untagged)).collect())
+            .collect();
+        assert_eq!(counts, [
+            vec![(0, 1), (0, 0)],
+            vec![(1, 2), (1, 0)],
+            vec![(1, 0), (0, 0)],
+            vec![(0, 0), (0, 1)],
+        ]);
+        assert!(matrix.vlans[0].ports[1].is_absent());
+    }
+}
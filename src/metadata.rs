@@ -0,0 +1,57 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use anyhow::{Result, anyhow};
+
+/// Extra information about a port kept outside the switch, e.g. in a cabling spreadsheet
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PortMetadata {
+    pub comment: String,
+    pub location: Option<String>,
+}
+
+/// Contents of a `--metadata` CSV with columns `port,comment[,location]`
+#[derive(Debug, Default)]
+pub struct Metadata {
+    pub ports: BTreeMap<u32, PortMetadata>,
+    /// Whether the file had a location column at all
+    pub has_location: bool,
+}
+
+impl Metadata {
+    pub fn load(path: &Path) -> Result<Metadata> {
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_path(path)
+            .map_err(|e| anyhow!("Failed to read metadata file {}: {}", path.display(), e))?;
+        let headers = reader.headers()
+            .map_err(|e| anyhow!("Failed to read metadata file {}: {}", path.display(), e))?
+            .clone();
+        let column = |name: &str| headers.iter().position(|h| h.eq_ignore_ascii_case(name));
+        let (Some(port_column), Some(comment_column)) = (column("port"), column("comment")) else {
+            return Err(anyhow!("Metadata file {} needs a header row with at least 'port' and 'comment' columns",
+                path.display()));
+        };
+        let location_column = column("location");
+
+        let mut metadata = Metadata {
+            ports: BTreeMap::new(),
+            has_location: location_column.is_some(),
+        };
+        for (line, record) in reader.records().enumerate() {
+            // Line numbers as shown in an editor, after the header
+            let line = line + 2;
+            let record = record.map_err(|e| anyhow!("{} line {}: {}", path.display(), line, e))?;
+            let port = record.get(port_column).unwrap_or_default();
+            let port = port.parse::<u32>()
+                .map_err(|_| anyhow!("{} line {}: '{}' is not a port number", path.display(), line, port))?;
+            metadata.ports.insert(port, PortMetadata {
+                comment: record.get(comment_column).unwrap_or_default().to_string(),
+                location: location_column
+                    .and_then(|column| record.get(column))
+                    .filter(|location| !location.is_empty())
+                    .map(str::to_string),
+            });
+        }
+        Ok(metadata)
+    }
+}
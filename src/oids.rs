@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+
+/// Base OIDs of every table the tool walks.
+///
+/// Defaults are the standard MIB locations; the config file can replace any
+/// of them globally, per vendor profile or per host.
+#[derive(Debug, Clone)]
+pub struct TableOids {
+    // Q-BRIDGE-MIB
+    pub vlan_names: Vec<u32>,  // dot1qVlanStaticName
+    pub egress_ports: Vec<u32>,  // dot1qVlanStaticEgressPorts
+    pub untagged_ports: Vec<u32>,  // dot1qVlanStaticUntaggedPorts
+    pub pvid: Vec<u32>,  // dot1qPvid (first column of dot1qPortVlanEntry)
+
+    // BRIDGE-MIB
+    pub base_port_if_index: Vec<u32>,  // dot1dBasePortIfIndex
+
+    // IF-MIB
+    pub if_index: Vec<u32>,  // ifIndex
+    pub if_alias: Vec<u32>,  // ifAlias
+    pub if_name: Vec<u32>,  // ifName
+    pub if_type: Vec<u32>,  // ifType
+    pub if_phys_address: Vec<u32>,  // ifPhysAddress
+    pub if_last_change: Vec<u32>,  // ifLastChange
+    pub if_admin_status: Vec<u32>,  // ifAdminStatus
+    pub if_stack_status: Vec<u32>,  // ifStackStatus
+
+    // IP-MIB
+    pub ip_ad_ent_if_index: Vec<u32>,  // ipAdEntIfIndex
+
+    // IEEE8023-LAG-MIB
+    pub lag_port_selected: Vec<u32>,  // dot3adAggPortSelectedAggID
+    pub lag_agg_name: Vec<u32>,  // ifName for LACP interfaces
+    pub lag_aggregate_or_individual: Vec<u32>,  // dot3adAggAggregateOrIndividual
+
+    // IEEE8021-PAE-MIB
+    pub dot1x_port_status: Vec<u32>,  // dot1xAuthAuthControlledPortStatus
+    pub dot1x_port_control: Vec<u32>,  // dot1xAuthAuthControlledPortControl
+
+    // LLDP-MIB
+    pub lldp_rem_sys_cap_enabled: Vec<u32>,  // lldpRemSysCapEnabled
+}
+
+impl Default for TableOids {
+    fn default() -> Self {
+        TableOids {
+            vlan_names: vec![1,3,6,1,2,1,17,7,1,4,3,1,1],
+            egress_ports: vec![1,3,6,1,2,1,17,7,1,4,3,1,2],
+            untagged_ports: vec![1,3,6,1,2,1,17,7,1,4,3,1,4],
+            pvid: vec![1,3,6,1,2,1,17,7,1,4,5,1,1],
+
+            base_port_if_index: vec![1,3,6,1,2,1,17,1,4,1,2],
+
+            if_index: vec![1,3,6,1,2,1,2,2,1,1],
+            if_alias: vec![1,3,6,1,2,1,31,1,1,1,18],
+            if_name: vec![1,3,6,1,2,1,31,1,1,1,1],
+            if_type: vec![1,3,6,1,2,1,2,2,1,3],
+            if_phys_address: vec![1,3,6,1,2,1,2,2,1,6],
+            if_last_change: vec![1,3,6,1,2,1,2,2,1,9],
+            if_admin_status: vec![1,3,6,1,2,1,2,2,1,7],
+            if_stack_status: vec![1,3,6,1,2,1,31,1,2,1,3],
+
+            ip_ad_ent_if_index: vec![1,3,6,1,2,1,4,20,1,2],
+
+            lag_port_selected: vec![1,2,840,10006,300,43,1,2,1,1,13],
+            lag_agg_name: vec![1,3,6,1,2,1,31,1,1,1,1],
+            lag_aggregate_or_individual: vec![1,2,840,10006,300,43,1,1,1,1,5],
+
+            dot1x_port_status: vec![1,0,8802,1,1,1,1,2,1,1,5],
+            dot1x_port_control: vec![1,0,8802,1,1,1,1,2,1,1,6],
+
+            lldp_rem_sys_cap_enabled: vec![1,0,8802,1,1,2,1,4,1,1,12],
+        }
+    }
+}
+
+impl TableOids {
+    /// The table entry containing dot1qPvid and its sibling columns
+    pub fn port_vlan_entry(&self) -> &[u32] {
+        &self.pvid[..self.pvid.len().saturating_sub(1)]
+    }
+
+    /// lldpRemEntry, whose columns are walked together for --topology
+    pub fn lldp_rem_entry(&self) -> &[u32] {
+        &self.lldp_rem_sys_cap_enabled[..self.lldp_rem_sys_cap_enabled.len().saturating_sub(1)]
+    }
+
+    fn table_mut(&mut self, name: &str) -> Option<&mut Vec<u32>> {
+        Some(match name {
+            "vlan_names" => &mut self.vlan_names,
+            "egress_ports" => &mut self.egress_ports,
+            "untagged_ports" => &mut self.untagged_ports,
+            "pvid" => &mut self.pvid,
+            "base_port_if_index" => &mut self.base_port_if_index,
+            "if_index" => &mut self.if_index,
+            "if_alias" => &mut self.if_alias,
+            "if_name" => &mut self.if_name,
+            "if_type" => &mut self.if_type,
+            "if_phys_address" => &mut self.if_phys_address,
+            "if_last_change" => &mut self.if_last_change,
+            "if_admin_status" => &mut self.if_admin_status,
+            "if_stack_status" => &mut self.if_stack_status,
+            "ip_ad_ent_if_index" => &mut self.ip_ad_ent_if_index,
+            "lag_port_selected" => &mut self.lag_port_selected,
+            "lag_agg_name" => &mut self.lag_agg_name,
+            "lag_aggregate_or_individual" => &mut self.lag_aggregate_or_individual,
+            "dot1x_port_status" => &mut self.dot1x_port_status,
+            "dot1x_port_control" => &mut self.dot1x_port_control,
+            "lldp_rem_sys_cap_enabled" => &mut self.lldp_rem_sys_cap_enabled,
+            _ => return None,
+        })
+    }
+
+    /// Replace table base OIDs by logical name, e.g. `egress_ports = "1.3.6.1.4.1.99999.1.2"`
+    pub fn apply_overrides(&mut self, overrides: &HashMap<String, String>) -> Result<(), String> {
+        for (name, oid) in overrides {
+            let oid = parse_base_oid(oid)
+                .map_err(|e| format!("Invalid OID for table '{}': {}", name, e))?;
+            let table = self.table_mut(name)
+                .ok_or_else(|| format!("Unknown table '{}' in OID overrides", name))?;
+            *table = oid;
+        }
+        Ok(())
+    }
+}
+
+/// Parse a dotted OID such as "1.3.6.1.2.1.17" (a leading dot is allowed)
+pub fn parse_base_oid(oid: &str) -> Result<Vec<u32>, String> {
+    let parts = oid.trim().trim_start_matches('.').split('.')
+        .map(|part| part.parse::<u32>()
+            .map_err(|_| format!("'{}' is not a numeric OID", oid)))
+        .collect::<Result<Vec<u32>, String>>()?;
+    if parts.len() < 2 {
+        return Err(format!("'{}' is too short to be a table OID", oid));
+    }
+    Ok(parts)
+}
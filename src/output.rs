@@ -1,119 +1,1255 @@
-use std::collections::HashMap;
-use crate::PortRange;
-use chrono::Local;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+use clap::ValueEnum;
+use unicode_width::UnicodeWidthStr;
+use crate::{Dot1xInfo, FramePolicy, LastChange, PortRange, SwitchSnapshot};
+use crate::capacity::CapacityReport;
+use crate::probe::Probe;
+use crate::rules::HighlightRule;
+use crate::stats::SourceStatus;
+use crate::vendor::VtpInfo;
+use crate::warnings::Warning;
+use crate::fingerprint::range_hashes;
+use crate::snapshot::{Lag, Meta, PortChange, VirtualInterface, VlanMismatch};
+use crate::matrix::VlanMatrix;
+use crate::topology::{Link, Topology};
+use crate::vlan_cell::{format_vlan_cell, format_vlan_label, VlanCell, VlanStyle};
 
+/// Column headers of the link aggregation table; the VLAN(s) cell comes from `lag_vlan_cell`
+pub const LAG_HEADERS: [&str; 5] = ["Aggregator", "ifIndex", "Members", "VLAN(s)", "Membership from"];
+
+/// Column headers of the virtual interface table, in the order of `virtual_cells`
+pub const VIRTUAL_HEADERS: [&str; 4] = ["Interface", "ifIndex", "Type", "IP address"];
+
+/// Column headers of the topology table, in the order of `topology_cells`
+pub const TOPOLOGY_HEADERS: [&str; 7] = ["Switch", "Port", "Neighbor", "Neighbor port", "Untagged", "Tagged", "Tagged VLANs differ"];
+
+/// Report formats; the command line, the config file and `--help` all take
+/// their choices from here
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
 pub enum OutputFormat {
+    #[default]
     Markdown,
     Html,
+    /// Only to --output, and only in builds with the `pdf` feature
+    Pdf,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    /// Case-insensitive, like --format
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        <OutputFormat as ValueEnum>::from_str(s, true).map_err(|_| {
+            let expected: Vec<String> = OutputFormat::value_variants().iter().map(|format| format.to_string()).collect();
+            format!("Unknown output format '{}'. Expected one of: {}", s, expected.join(", "))
+        })
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.to_possible_value() {
+            Some(value) => f.write_str(value.get_name()),
+            None => Ok(()),
+        }
+    }
+}
+
+impl OutputFormat {
+    /// Stream the report into `w`
+    pub fn render(
+        &self,
+        w: &mut dyn Write,
+        port_ranges: &[PortRange],
+        vlan_names: &HashMap<u32, String>,
+        columns: &Columns,
+        info: &ReportInfo,
+    ) -> io::Result<()> {
+        let marked;
+        let vlan_names = match info.vtp.as_ref().filter(|vtp| !vtp.suspended_vlans.is_empty()) {
+            Some(vtp) => {
+                marked = mark_suspended(vlan_names, &vtp.suspended_vlans);
+                &marked
+            }
+            None => vlan_names,
+        };
+        match self {
+            OutputFormat::Markdown => write_markdown_table(w, port_ranges, vlan_names, columns, info),
+            OutputFormat::Html => crate::html_output::write_port_table(w, port_ranges, vlan_names, columns, info),
+            #[cfg(feature = "pdf")]
+            OutputFormat::Pdf => crate::pdf_output::write_port_table(w, port_ranges, vlan_names, columns, info),
+            #[cfg(not(feature = "pdf"))]
+            OutputFormat::Pdf => Err(io::Error::new(io::ErrorKind::Unsupported, PDF_UNSUPPORTED)),
+        }
+    }
+
+    /// MIME type of the rendered report
+    pub fn content_type(self) -> &'static str {
+        match self {
+            OutputFormat::Markdown => "text/markdown; charset=utf-8",
+            OutputFormat::Html => "text/html; charset=utf-8",
+            OutputFormat::Pdf => "application/pdf",
+        }
+    }
+
+    /// Whether this build can render the format
+    pub fn is_supported(self) -> bool {
+        self != OutputFormat::Pdf || cfg!(feature = "pdf")
+    }
+}
+
+/// Why --format pdf fails in a build without the `pdf` feature
+pub const PDF_UNSUPPORTED: &str = "This build has no PDF support; rebuild with --features pdf";
+
+/// Heading used when neither --title nor the config sets one
+pub const DEFAULT_TITLE: &str = "Switch Port Configuration";
+
+/// Device-level information shown around the port table
+#[derive(Default)]
+pub struct ReportInfo {
+    pub ip_address: String,
+    pub title: String,
+    /// --device-name, else sysName, else the IP address
+    pub device_name: String,
+    pub vlan_style: VlanStyle,
+    /// Formatted generation time; None leaves the line out (--deterministic)
+    pub generated_on: Option<String>,
+    pub vtp: Option<VtpInfo>,
+    pub sources: Vec<SourceStatus>,
+    /// Remarks on what the report leaves out, e.g. suppressed ports
+    pub notes: Vec<String>,
+    pub capacity: Option<CapacityReport>,
+    /// Aggregators for the link aggregation section, None leaves it out
+    pub lags: Option<Vec<Lag>>,
+    /// Interfaces for the virtual interface section, None leaves it out
+    pub virtual_interfaces: Option<Vec<VirtualInterface>>,
+    /// Changes since the --diff snapshot by port, marked on the rows of HTML reports
+    pub changes: Option<BTreeMap<u32, PortChange>>,
+    /// Access VLANs whose rows get a background colour in HTML output
+    pub highlight_vlans: Vec<u32>,
+    /// Configured rules colouring matching rows of HTML output, first match wins
+    pub highlight_rules: Vec<HighlightRule>,
+    /// Failed consistency checks and collection problems, listed at the end
+    pub warnings: Vec<Warning>,
+    /// Pad markdown cells so that the pipes line up
+    pub align_markdown: bool,
+    /// Fingerprint of the whole switch, shown under the device line
+    pub config_hash: Option<String>,
+    /// Version, collection time and options for the footer, None leaves it out
+    pub meta: Option<Meta>,
+    /// Collection was cut short, e.g. by SIGINT; shown as a banner under the title
+    pub interrupted: bool,
+}
+
+/// Banner of a report whose collection was interrupted
+pub const PARTIAL_BANNER: &str = "PARTIAL — collection interrupted";
+
+/// VLAN names as labelled in reports, with " [suspended]" after the name of
+/// each suspended VLAN, or after its id when it has none
+fn mark_suspended(vlan_names: &HashMap<u32, String>, suspended: &BTreeSet<u32>) -> HashMap<u32, String> {
+    let mut marked = vlan_names.clone();
+    for &vlan_id in suspended {
+        let name = marked.entry(vlan_id)
+            .and_modify(|name| if name.is_empty() { *name = vlan_id.to_string() })
+            .or_insert_with(|| vlan_id.to_string());
+        name.push_str(" [suspended]");
+    }
+    marked
+}
+
+/// Port number or range, e.g. "5", "1-8", "1-6 (except 4)" or "1-8, 11, 17-20"
+pub fn format_port_range(range: &PortRange) -> String {
+    if let Some(ports) = &range.member_ports {
+        return format_port_set(ports);
+    }
+    let mut port = if range.first_port == range.last_port {
+        format!("{}", range.first_port)
+    } else {
+        format!("{}-{}", range.first_port, range.last_port)
+    };
+    if !range.except_ports.is_empty() {
+        port.push_str(&format!(" (except {})", format_port_set(&range.except_ports)));
+    }
+    port
+}
+
+/// Alias of a range. Ranges grouped across different aliases show them joined,
+/// or just a count when there are more than three.
+pub fn format_alias(range: &PortRange) -> String {
+    format_distinct(&range.port_aliases, "aliases")
+        .unwrap_or_else(|| range.alias.clone().unwrap_or_default())
+}
+
+/// Per-port texts of a range joined, e.g. "Studio A" or "Studio A / Studio B";
+/// None if no port has one
+pub fn format_distinct(values: &[(u32, String)], noun: &str) -> Option<String> {
+    let mut distinct: Vec<&str> = Vec::new();
+    for (_, value) in values {
+        if !value.is_empty() && !distinct.contains(&value.as_str()) {
+            distinct.push(value);
+        }
+    }
+    match distinct.len() {
+        0 => None,
+        1 => Some(distinct[0].to_string()),
+        2..=3 => Some(distinct.join(" / ")),
+        n => Some(format!("{} / … ({} different {})", distinct[0], n, noun)),
+    }
+}
+
+/// Compress port numbers into ranges, e.g. "1-8, 11, 17-20"
+pub fn format_port_set(ports: &[u32]) -> String {
+    let mut sorted = ports.to_vec();
+    sorted.sort_unstable();
+    sorted.dedup();
+
+    let mut parts = Vec::new();
+    let mut iter = sorted.into_iter().peekable();
+    while let Some(first) = iter.next() {
+        let mut last = first;
+        while iter.peek() == Some(&(last + 1)) {
+            last = iter.next().unwrap_or(last);
+        }
+        if first == last {
+            parts.push(first.to_string());
+        } else {
+            parts.push(format!("{}-{}", first, last));
+        }
+    }
+    parts.join(", ")
 }
 
+/// Footer lines: the tool that wrote the report, how long collection took
+/// and the options that shaped it, each only when known
+pub fn footer_lines(meta: &Meta) -> Vec<String> {
+    let mut tool = format!("Generated by {} {}", env!("CARGO_PKG_NAME"), meta.version);
+    if let Some(describe) = &meta.git_describe {
+        tool.push_str(&format!(" ({})", describe));
+    }
+    let mut lines = vec![tool];
+    if let Some(ms) = meta.collection_ms {
+        lines.push(format!("Collection took {:.1} s", ms as f64 / 1000.0));
+    }
+    if !meta.options.is_empty() {
+        lines.push(format!("Options: {}", meta.options.join("; ")));
+    }
+    lines
+}
+
+/// One-line summary of what each SNMP table returned, so blank columns can be
+/// told apart from tables the agent does not implement
+pub fn format_sources(sources: &[SourceStatus]) -> String {
+    sources.iter()
+        .map(|source| match source.resumed {
+            0 => format!("{} ({})", source.name, source.outcome),
+            1 => format!("{} ({}, resumed once)", source.name, source.outcome),
+            resumed => format!("{} ({}, resumed {} times)", source.name, source.outcome, resumed),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Device name, with the IP address when the name is something else
+pub fn format_device(info: &ReportInfo) -> String {
+    if info.device_name == info.ip_address || info.ip_address.is_empty() {
+        info.device_name.clone()
+    } else {
+        format!("{} ({})", info.device_name, info.ip_address)
+    }
+}
+
+/// A column of the port table
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Column {
+    Port,
+    Alias,
+    Vlans,
+    Lacp,
+    Dot1x,
+    Frames,
+    Mac,
+    LastChange,
+    Comment,
+    Location,
+    Panel,
+    /// Fingerprint of the row's configuration, see [`crate::fingerprint`]
+    ConfigHash,
+    /// ifIndex and bridge port number, for debugging the mapping between them
+    IfIndex,
+    BridgePort,
+}
+
+impl Column {
+    pub const ALL: [Column; 14] = [
+        Column::Port, Column::Alias, Column::Vlans, Column::Lacp, Column::Dot1x, Column::Frames,
+        Column::Mac, Column::LastChange, Column::Comment, Column::Location, Column::Panel, Column::ConfigHash,
+        Column::IfIndex, Column::BridgePort,
+    ];
+
+    /// Columns shown when nothing else is selected
+    pub const DEFAULT: [Column; 4] = [Column::Port, Column::Alias, Column::Vlans, Column::Lacp];
+
+    /// Id used in --columns and the config file
+    pub fn id(self) -> &'static str {
+        match self {
+            Column::Port => "port",
+            Column::Alias => "alias",
+            Column::Vlans => "vlans",
+            Column::Lacp => "lacp",
+            Column::Dot1x => "dot1x",
+            Column::Frames => "frames",
+            Column::Mac => "mac",
+            Column::LastChange => "last-change",
+            Column::Comment => "comment",
+            Column::Location => "location",
+            Column::Panel => "panel",
+            Column::ConfigHash => "hash",
+            Column::IfIndex => "if-index",
+            Column::BridgePort => "bridge-port",
+        }
+    }
+
+    pub fn header(self) -> &'static str {
+        match self {
+            Column::Port => "Port",
+            Column::Alias => "Alias",
+            Column::Vlans => "VLAN(s)",
+            Column::Lacp => "LACP",
+            Column::Dot1x => "802.1X",
+            Column::Frames => "Frames",
+            Column::Mac => "MAC",
+            Column::LastChange => "Last change",
+            Column::Comment => "Comment",
+            Column::Location => "Location",
+            Column::Panel => "Panel",
+            Column::ConfigHash => "Config hash",
+            Column::IfIndex => "ifIndex",
+            Column::BridgePort => "bridgePort",
+        }
+    }
+
+    /// Whether the cell of `range` in this column shows nothing. The port and
+    /// VLAN(s) columns never count as empty.
+    pub fn is_empty_for(self, range: &PortRange) -> bool {
+        match self {
+            Column::Port | Column::Vlans => false,
+            Column::Alias => format_alias(range).is_empty(),
+            Column::Lacp => range.lacp_info.is_none() && !range.inferred_trunk,
+            Column::Dot1x => range.dot1x.is_none(),
+            Column::Frames => range.frame_policy.is_none(),
+            Column::Mac => range.mac.is_none(),
+            Column::LastChange => range.last_change.is_none(),
+            Column::Comment => format_distinct(&range.comments, "comments").is_none(),
+            Column::Location => format_distinct(&range.locations, "locations").is_none(),
+            Column::Panel => range.panel_labels.is_empty(),
+            Column::ConfigHash => format_config_hash(range).is_empty(),
+            Column::IfIndex | Column::BridgePort => range.indices.is_empty(),
+        }
+    }
+}
+
+impl FromStr for Column {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Column::ALL.into_iter()
+            .find(|column| column.id() == s)
+            .ok_or_else(|| {
+                let ids: Vec<&str> = Column::ALL.iter().map(|column| column.id()).collect();
+                format!("Unknown column '{}'. Valid columns: {}", s, ids.join(", "))
+            })
+    }
+}
+
+/// The columns to render, in order, and what to call them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Columns {
+    pub layout: Vec<Column>,
+    /// Header labels replacing the built-in ones
+    pub labels: HashMap<Column, String>,
+}
+
+impl Columns {
+    pub fn new(layout: Vec<Column>) -> Columns {
+        Columns { layout, labels: HashMap::new() }
+    }
+
+    pub fn has(&self, column: Column) -> bool {
+        self.layout.contains(&column)
+    }
+
+    /// Header text, not yet escaped for any format
+    pub fn header(&self, column: Column) -> &str {
+        self.labels.get(&column).map(String::as_str).unwrap_or(column.header())
+    }
+
+    /// Drop the columns that are empty in every range, except those in `keep`,
+    /// and return what was dropped. A report without ranges keeps its columns.
+    pub fn hide_empty(&mut self, ranges: &[PortRange], keep: &[Column]) -> Vec<Column> {
+        if ranges.is_empty() {
+            return Vec::new();
+        }
+        let (hidden, shown) = self.layout.iter()
+            .partition(|&&column| !keep.contains(&column) && ranges.iter().all(|range| column.is_empty_for(range)));
+        self.layout = shown;
+        hidden
+    }
+}
+
+impl Default for Columns {
+    fn default() -> Self {
+        Columns::new(Column::DEFAULT.to_vec())
+    }
+}
+
+/// Make text safe inside a markdown table cell: a `|` would end the cell, a
+/// backslash would escape whatever follows it, and padding would be lost anyway
+pub fn escape_markdown_cell(text: &str) -> String {
+    text.trim().replace('\\', "\\\\").replace('|', "\\|")
+}
+
+/// Fingerprint of a row, or of each distinct configuration when the row
+/// groups ports with different aliases
+pub fn format_config_hash(range: &PortRange) -> String {
+    format_distinct(&range_hashes(range), "hashes").unwrap_or_default()
+}
+
+/// ifIndex of a range's first and last port, e.g. "10101-10108"
+pub fn format_if_index(range: &PortRange) -> String {
+    format_first_last(range.indices.iter().map(|(_, indices)| indices.if_index.to_string()))
+}
+
+/// Bridge port of a range's first and last port, e.g. "1-8"; "none" for ports without one
+pub fn format_bridge_port(range: &PortRange) -> String {
+    format_first_last(range.indices.iter()
+        .map(|(_, indices)| indices.bridge_port.map_or("none".to_string(), |port| port.to_string())))
+}
+
+fn format_first_last(mut values: impl DoubleEndedIterator<Item = String>) -> String {
+    match (values.next(), values.next_back()) {
+        (Some(first), Some(last)) if first != last => format!("{}-{}", first, last),
+        (Some(first), _) => first,
+        (None, _) => String::new(),
+    }
+}
+
+/// Whether any port of a range has no bridge port, or one other than its ifIndex
+pub fn indices_mismatched(range: &PortRange) -> bool {
+    range.indices.iter().any(|(_, indices)| indices.mismatched())
+}
+
+/// Render 802.1X state as e.g. "auto (authorized)"
+pub fn format_dot1x(dot1x: Option<&Dot1xInfo>) -> String {
+    let Some(info) = dot1x else {
+        return String::new();
+    };
+    let control = match info.port_control {
+        1 => "forced-unauthorized",
+        2 => "auto",
+        3 => "forced-authorized",
+        _ => "unknown",
+    };
+    let status = match info.port_status {
+        1 => "authorized",
+        2 => "unauthorized",
+        _ => "unknown",
+    };
+    format!("{} ({})", control, status)
+}
+
+/// Render the MAC of a range; multi-port ranges show the first port's MAC with an ellipsis
+pub fn format_range_mac(range: &PortRange) -> String {
+    match &range.mac {
+        Some(mac) if range.first_port != range.last_port => format!("{}…", mac),
+        Some(mac) => mac.clone(),
+        None => String::new(),
+    }
+}
+
+/// Patch-panel labels of a range, e.g. "PP2-B07" or "PP2-B01 – PP2-B08"
+pub fn format_panel(range: &PortRange) -> String {
+    let (Some((_, first)), Some((_, last))) = (range.panel_labels.first(), range.panel_labels.last()) else {
+        return String::new();
+    };
+    let mut label = if first == last {
+        first.clone()
+    } else {
+        format!("{} – {}", first, last)
+    };
+    if range.panel_labels.len() < range.ports().len() {
+        label.push_str(" (partly patched)");
+    }
+    label
+}
+
+/// Render the time since the last state change, e.g. "12 days ago"
+pub fn format_last_change(last_change: Option<LastChange>) -> String {
+    match last_change {
+        None => String::new(),
+        Some(LastChange::SinceBoot) => "since boot".to_string(),
+        Some(LastChange::Ago(secs)) => {
+            let days = secs / 86_400;
+            let hours = secs / 3_600;
+            if days == 1 {
+                "1 day ago".to_string()
+            } else if days > 1 {
+                format!("{} days ago", days)
+            } else if hours > 0 {
+                format!("{}h {}m ago", hours, secs % 3_600 / 60)
+            } else {
+                format!("{}m ago", secs / 60)
+            }
+        }
+    }
+}
+
+/// Whether the port changed state within the last 24 hours
+pub fn is_recent_change(last_change: Option<LastChange>) -> bool {
+    matches!(last_change, Some(LastChange::Ago(secs)) if secs < 86_400)
+}
+
+/// Render frame acceptance flags compactly, e.g. "tagged-only, ingress-filter"
+pub fn format_frame_policy(policy: Option<&FramePolicy>) -> String {
+    let Some(policy) = policy else {
+        return String::new();
+    };
+    let mut flags = Vec::new();
+    if policy.tagged_only {
+        flags.push("tagged-only");
+    }
+    if policy.ingress_filter {
+        flags.push("ingress-filter");
+    }
+    if flags.is_empty() {
+        "none".to_string()
+    } else {
+        flags.join(", ")
+    }
+}
+
+/// The report as a String; see `OutputFormat::render` for streaming it, and for PDFs
 pub fn generate_port_table(
     port_ranges: &[PortRange],
     vlan_names: &HashMap<u32, String>,
     format: OutputFormat,
-    ip_address: &str,
+    columns: &Columns,
+    info: &ReportInfo,
 ) -> String {
-    match format {
-        OutputFormat::Markdown => generate_markdown_table(port_ranges, vlan_names),
-        OutputFormat::Html => crate::html_output::generate_port_table(port_ranges, vlan_names, ip_address),
+    let mut buffer = Vec::new();
+    // Writing into a Vec cannot fail
+    let _ = format.render(&mut buffer, port_ranges, vlan_names, columns, info);
+    String::from_utf8_lossy(&buffer).into_owned()
+}
+
+/// Render a collected switch with one row per port. The binary filters and
+/// groups ports into ranges before calling `generate_port_table` instead.
+pub fn render_switch(switch: &SwitchSnapshot, format: OutputFormat, columns: &Columns, info: &ReportInfo) -> String {
+    let ranges: Vec<PortRange> = switch.ports.iter()
+        .map(|port| port.clone().into_range(port.port_num, port.port_num))
+        .collect();
+    generate_port_table(&ranges, &switch.vlans, format, columns, info)
+}
+
+/// The VLANs two switches define differently as a markdown table, one column
+/// per switch, or a line saying they match
+pub fn write_vlan_comparison(
+    w: &mut dyn Write,
+    names: (&str, &str),
+    vlan_count: usize,
+    mismatches: &[VlanMismatch],
+    align: bool,
+) -> io::Result<()> {
+    if mismatches.is_empty() {
+        return writeln!(w, "{} and {} define the same {} VLANs", names.0, names.1, vlan_count);
+    }
+    let headers = ["VLAN", names.0, names.1].map(escape_markdown_cell);
+    let cell = |name: &Option<String>| match name.as_deref() {
+        None => "(not defined)".to_string(),
+        Some("") => "(no name)".to_string(),
+        Some(name) => escape_markdown_cell(name),
+    };
+    let rows: Vec<Vec<String>> = mismatches.iter()
+        .map(|mismatch| vec![mismatch.vlan.to_string(), cell(&mismatch.left), cell(&mismatch.right)])
+        .collect();
+    write_markdown_rows(w, &headers, &rows, align)?;
+    let only = |side: fn(&VlanMismatch) -> bool| mismatches.iter().filter(|mismatch| side(mismatch)).count();
+    writeln!(w, "\n{} VLANs only on {}, {} only on {}, {} named differently",
+        only(|m| m.right.is_none()), names.0, only(|m| m.left.is_none()), names.1,
+        only(|m| m.left.is_some() && m.right.is_some()))
+}
+
+/// --check results, a line per table, e.g. "FAIL  LAG MIB ... (optional): unsupported"
+pub fn write_probe(w: &mut dyn Write, host: &str, probe: &Probe) -> io::Result<()> {
+    let verdict = |passed: bool| if passed { "PASS" } else { "FAIL" };
+    match &probe.sys_descr {
+        Some(sys_descr) => writeln!(w, "{}  sysDescr: {}", verdict(true), sys_descr)?,
+        None => writeln!(w, "{}  sysDescr (optional): no value", verdict(false))?,
+    }
+    for capability in &probe.capabilities {
+        let optional = if capability.mandatory { "" } else { " (optional)" };
+        match capability.passed() {
+            true => writeln!(w, "{}  {}", verdict(true), capability.name)?,
+            false => writeln!(w, "{}  {}{}: {}", verdict(false), capability.name, optional, capability.outcome)?,
+        }
+    }
+    writeln!(w, "Vendor profile: {}", probe.vendor)?;
+    match probe.passed() {
+        true => writeln!(w, "{} has every table collection needs", host),
+        false => writeln!(w, "{} lacks tables collection needs", host),
+    }
+}
+
+/// The VLAN presence matrix as a markdown table. A cell counts the tagged and
+/// untagged ports carrying the VLAN; where there are none it is a bold 0.
+pub fn write_vlan_matrix(w: &mut dyn Write, matrix: &VlanMatrix, align: bool) -> io::Result<()> {
+    write!(w, "# VLAN presence\n\nPorts carrying each VLAN, as tagged/untagged\n\n")?;
+    let headers: Vec<String> = ["VLAN", "Name"].into_iter().map(String::from)
+        .chain(matrix.switches.iter().map(|switch| escape_markdown_cell(switch)))
+        .collect();
+    let rows: Vec<Vec<String>> = matrix.vlans.iter()
+        .map(|row| {
+            let counts = row.ports.iter().map(|counts| match counts.is_absent() {
+                true => "**0**".to_string(),
+                false => format!("{}/{}", counts.tagged, counts.untagged),
+            });
+            [row.vlan.to_string(), row.name.as_deref().map(escape_markdown_cell).unwrap_or_default()]
+                .into_iter().chain(counts).collect()
+        })
+        .collect();
+    write_markdown_rows(w, &headers, &rows, align)
+}
+
+/// Cells of a topology row: both ends, the VLANs of the local port, and the
+/// tagged VLANs only one end carries, unescaped
+pub fn topology_cells(link: &Link) -> [String; 7] {
+    let vlans = |vlans: &BTreeSet<u32>| format_port_set(&vlans.iter().copied().collect::<Vec<_>>());
+    let mut differ = Vec::new();
+    for (only, end) in [(&link.only_local, &link.local), (&link.only_remote, &link.remote)] {
+        if !only.is_empty() {
+            differ.push(format!("{} only on {}", vlans(only), end.device));
+        }
+    }
+    let neighbor = match link.leaf {
+        true => format!("{} (not polled)", link.remote.device),
+        false => link.remote.device.clone(),
+    };
+    [link.local.device.clone(), link.local.port_label(), neighbor, link.remote.port_label(),
+        vlans(&link.untagged), vlans(&link.tagged), differ.join("; ")]
+}
+
+/// Cells of a LAG row other than the VLANs, which each format marks up itself
+pub fn lag_cells(lag: &Lag) -> [String; 4] {
+    let members = match lag.stale {
+        true => "none (stale)".to_string(),
+        false => format_port_set(&lag.members),
+    };
+    let sources: Vec<String> = lag.sources.iter().map(ToString::to_string).collect();
+    [lag.name.clone().unwrap_or_else(|| "Unknown".to_string()), lag.index.to_string(), members, sources.join(", ")]
+}
+
+/// The aggregator's own VLANs, shown like those of a port
+pub fn lag_vlan_cell(lag: &Lag, vlan_names: &HashMap<u32, String>, style: &VlanStyle) -> VlanCell {
+    let range = PortRange {
+        pvid: lag.pvid,
+        vlan_memberships: lag.vlans.iter().copied().collect(),
+        untagged_vlans: lag.untagged.iter().copied().collect(),
+        ..Default::default()
+    };
+    format_vlan_cell(&range, vlan_names, style)
+}
+
+/// Cells of a virtual interface row, not yet escaped for any format
+pub fn virtual_cells(interface: &VirtualInterface) -> [String; 4] {
+    let if_type = match if_type_name(interface.if_type) {
+        Some(name) => format!("{} ({})", name, interface.if_type),
+        None => interface.if_type.to_string(),
+    };
+    let addresses: Vec<String> = interface.addresses.iter().map(ToString::to_string).collect();
+    [interface.name.clone().unwrap_or_default(), interface.index.to_string(), if_type, addresses.join(", ")]
+}
+
+/// IANAifType name of the interface types a switch commonly has
+pub fn if_type_name(if_type: u32) -> Option<&'static str> {
+    Some(match if_type {
+        1 => "other",
+        6 => "ethernetCsmacd",
+        24 => "softwareLoopback",
+        53 => "propVirtual",
+        117 => "gigabitEthernet",
+        131 => "tunnel",
+        135 => "l2vlan",
+        136 => "l3ipvlan",
+        161 => "ieee8023adLag",
+        _ => return None,
+    })
+}
+
+/// The links found between switches as a markdown table
+pub fn write_topology(w: &mut dyn Write, topology: &Topology, align: bool) -> io::Result<()> {
+    write!(w, "# Topology\n\n")?;
+    if topology.links.is_empty() {
+        return writeln!(w, "No LLDP neighbors were found on {}", topology.switches.join(", "));
+    }
+    let headers = TOPOLOGY_HEADERS.map(String::from);
+    let rows: Vec<Vec<String>> = topology.links.iter()
+        .map(|link| topology_cells(link).iter().map(|cell| escape_markdown_cell(cell)).collect())
+        .collect();
+    write_markdown_rows(w, &headers, &rows, align)?;
+    let mismatches = topology.links.iter().filter(|link| link.mismatch()).count();
+    if mismatches > 0 {
+        writeln!(w, "\n{} link(s) with tagged VLANs on one end only", mismatches)?;
+    }
+    Ok(())
+}
+
+/// A markdown table of already escaped cells. With `align`, every cell is padded
+/// to the display width of the widest one in its column; otherwise the
+/// separator row just follows the header.
+fn write_markdown_rows(w: &mut dyn Write, headers: &[String], rows: &[Vec<String>], align: bool) -> io::Result<()> {
+    let widths: Vec<usize> = headers.iter().enumerate()
+        .map(|(i, header)| {
+            let cells = rows.iter().filter(|_| align).filter_map(|row| row.get(i));
+            cells.map(|cell| cell.width()).fold(header.width(), usize::max)
+        })
+        .collect();
+    let write_row = |w: &mut dyn Write, cells: &[String]| -> io::Result<()> {
+        w.write_all(b"|")?;
+        for (cell, width) in cells.iter().zip(&widths) {
+            let padding = if align { width.saturating_sub(cell.width()) } else { 0 };
+            write!(w, " {}{} |", cell, " ".repeat(padding))?;
+        }
+        w.write_all(b"\n")
+    };
+    write_row(w, headers)?;
+    w.write_all(b"|")?;
+    for width in &widths {
+        write!(w, "{}|", "-".repeat(width + 2))?;
     }
+    w.write_all(b"\n")?;
+    for row in rows {
+        write_row(w, row)?;
+    }
+    Ok(())
 }
 
-fn generate_markdown_table(
+fn write_markdown_table(
+    w: &mut dyn Write,
     port_ranges: &[PortRange],
     vlan_names: &HashMap<u32, String>,
-) -> String {
-    let mut table = String::new();
-    
-    // Add timestamp
-    let now = Local::now();
-    table.push_str(&format!("Generated on: {}\n\n", now.format("%Y-%m-%d %H:%M:%S")));
+    columns: &Columns,
+    info: &ReportInfo,
+) -> io::Result<()> {
+    // VLAN names end up in table cells, both in the port table and the capacity one
+    let vlan_names: HashMap<u32, String> = vlan_names.iter()
+        .map(|(&vlan_id, name)| (vlan_id, escape_markdown_cell(name)))
+        .collect();
+    let vlan_names = &vlan_names;
+    write!(w, "# {}\n\n", info.title)?;
+    if info.interrupted {
+        write!(w, "> **{}**\n\n", PARTIAL_BANNER)?;
+    }
+    write!(w, "Device: {}\n\n", format_device(info))?;
+    if let Some(generated_on) = &info.generated_on {
+        write!(w, "Generated on: {}\n\n", generated_on)?;
+    }
+    if let Some(config_hash) = &info.config_hash {
+        write!(w, "Config hash: {}\n\n", config_hash)?;
+    }
+    if let Some(vtp) = &info.vtp {
+        write!(w, "VTP domain: {} (mode: {})\n\n", vtp.domain, vtp.mode_name())?;
+    }
+    for note in &info.notes {
+        write!(w, "Note: {}\n\n", note)?;
+    }
     
-    // Header
-    table.push_str("| Port | Alias | VLAN(s) | LACP |\n");
-    table.push_str("|------|-------|----------|------|\n");
-
+    let headers: Vec<String> = columns.layout.iter()
+        .map(|&column| escape_markdown_cell(columns.header(column)))
+        .collect();
+    let mut rows = Vec::new();
     for range in port_ranges {
-        if range.first_port > 52 {
-            continue;
+        // Port number/range, with likely uplinks starred
+        let mut port = format_port_range(range);
+        if range.uplink {
+            port.push_str("\\*");
         }
 
-        // Port number/range
-        let port = if range.first_port == range.last_port {
-            format!("{}", range.first_port)
+        // Alias (if available)
+        let alias = escape_markdown_cell(&format_alias(range));
+
+        let vlans = format_vlan_cell(range, vlan_names, &info.vlan_style)
+            .render(|_, text| format!("**{}**", text));
+
+        // LACP information
+        let lacp = if let Some(lacp_info) = &range.lacp_info {
+            let agg_name = escape_markdown_cell(lacp_info.agg_name.as_deref().unwrap_or("Unknown"));
+            match &lacp_info.load_balance {
+                Some(mode) => format!("{} ({})", agg_name, escape_markdown_cell(mode)),
+                None => agg_name,
+            }
+        } else if range.inferred_trunk {
+            // Not read from the device, so make that obvious
+            "_possible trunk group (inferred)_".to_string()
         } else {
-            format!("{}-{}", range.first_port, range.last_port)
+            String::new()
         };
 
-        // Alias (if available)
-        let alias = range.alias.as_deref().unwrap_or_default();
-
-        // VLAN information
-        let mut vlan_info = Vec::new();
-        if !range.vlan_memberships.is_empty() {
-            let mut tagged_vlans: Vec<u32> = range.vlan_memberships.iter().copied().collect();
-            tagged_vlans.sort_unstable();
-            let tagged_vlans: Vec<String> = tagged_vlans.iter()
-                .map(|&vlan_id| {
-                    if vlan_id == 1 {
-                        vlan_id.to_string()
-                    } else if let Some(name) = vlan_names.get(&vlan_id) {
-                        format!("{} ({})", name, vlan_id)
+        let row = columns.layout.iter().map(|column| match column {
+                Column::Port => port.clone(),
+                Column::Alias => alias.clone(),
+                Column::Vlans => vlans.clone(),
+                Column::Lacp => lacp.clone(),
+                Column::Dot1x => format_dot1x(range.dot1x.as_ref()),
+                Column::Frames => {
+                    let flags = format_frame_policy(range.frame_policy.as_ref());
+                    // Bold policies that differ from the switch-wide majority
+                    if range.frame_policy.is_some_and(|p| p.deviates) {
+                        format!("**{}**", flags)
                     } else {
-                        vlan_id.to_string()
+                        flags
                     }
-                })
-                .collect();
-            vlan_info.push(format!("Tagged:[{}]", tagged_vlans.join(", ")));
-        }
-        if !range.untagged_vlans.is_empty() {
-            let mut untagged_vlans: Vec<u32> = range.untagged_vlans.iter().copied().collect();
-            untagged_vlans.sort_unstable();
-            let untagged_vlans: Vec<String> = untagged_vlans.iter()
-                .map(|&vlan_id| {
-                    if vlan_id == 1 {
-                        vlan_id.to_string()
-                    } else if let Some(name) = vlan_names.get(&vlan_id) {
-                        format!("{} ({})", name, vlan_id)
+                }
+                Column::Mac => format_range_mac(range),
+                Column::LastChange => {
+                    let changed = format_last_change(range.last_change);
+                    // Bold changes within the last 24 hours
+                    if is_recent_change(range.last_change) {
+                        format!("**{}**", changed)
+                    } else {
+                        changed
+                    }
+                }
+                Column::Comment => escape_markdown_cell(&format_distinct(&range.comments, "comments").unwrap_or_default()),
+                Column::Location => escape_markdown_cell(&format_distinct(&range.locations, "locations").unwrap_or_default()),
+                Column::Panel => escape_markdown_cell(&format_panel(range)),
+                Column::ConfigHash => format_config_hash(range),
+                Column::IfIndex | Column::BridgePort => {
+                    let index = if *column == Column::IfIndex { format_if_index(range) } else { format_bridge_port(range) };
+                    // Bold numberings that do not line up
+                    if indices_mismatched(range) {
+                        format!("**{}**", index)
                     } else {
-                        vlan_id.to_string()
+                        index
                     }
+                }
+        });
+        rows.push(row.collect());
+    }
+    write_markdown_rows(w, &headers, &rows, info.align_markdown)?;
+
+    if port_ranges.iter().any(|range| range.uplink) {
+        w.write_all(b"\n\\* likely uplink\n")?;
+    }
+
+    if !info.warnings.is_empty() {
+        w.write_all(b"\n## Warnings\n\n")?;
+        for warning in &info.warnings {
+            writeln!(w, "- {}", warning)?;
+        }
+    }
+
+    if let Some(capacity) = &info.capacity {
+        w.write_all(b"\n## Capacity\n\n")?;
+        write!(w, "Free ports: {} ({})\n\n",
+            capacity.free_ports.len(), format_port_set(&capacity.free_ports))?;
+        let headers = ["Access VLAN".to_string(), "Used ports".to_string()];
+        let rows: Vec<Vec<String>> = capacity.access_vlans.iter()
+            .map(|(vlan_id, count)| vec![format_vlan_label(*vlan_id, vlan_names, &info.vlan_style), count.to_string()])
+            .chain([vec!["Trunk/LACP".to_string(), capacity.other_used.to_string()]])
+            .collect();
+        write_markdown_rows(w, &headers, &rows, info.align_markdown)?;
+    }
+
+    if let Some(lags) = &info.lags {
+        w.write_all(b"\n## Link aggregation groups\n\n")?;
+        if lags.is_empty() {
+            w.write_all(b"No aggregators found.\n")?;
+        } else {
+            let headers = LAG_HEADERS.map(String::from);
+            let rows: Vec<Vec<String>> = lags.iter()
+                .map(|lag| {
+                    let [name, index, members, sources] = lag_cells(lag);
+                    // Stale aggregators stand out like highlighted VLANs
+                    let members = match lag.stale {
+                        true => format!("**{}**", members),
+                        false => members,
+                    };
+                    let vlans = lag_vlan_cell(lag, vlan_names, &info.vlan_style).render(|_, text| format!("**{}**", text));
+                    vec![escape_markdown_cell(&name), index, members, vlans, sources]
                 })
                 .collect();
-            vlan_info.push(format!("Untagged:[{}]", untagged_vlans.join(", ")));
-        }
-        let vlans = if range.untagged_vlans.len() == 1 
-            && range.vlan_memberships.len() <= 1  // Allow the same VLAN to be tagged and untagged
-            && range.pvid == *range.untagged_vlans.iter().next().unwrap() {
-            // If only one untagged VLAN exists and PVID matches it
-            let vlan_id = range.untagged_vlans.iter().next().unwrap();
-            if *vlan_id == 1 {
-                vlan_id.to_string()
-            } else if let Some(name) = vlan_names.get(vlan_id) {
-                format!("{} ({})", name, vlan_id)
-            } else {
-                vlan_id.to_string()
-            }
+            write_markdown_rows(w, &headers, &rows, info.align_markdown)?;
+        }
+    }
+
+    if let Some(interfaces) = &info.virtual_interfaces {
+        w.write_all(b"\n## Virtual interfaces\n\n")?;
+        if interfaces.is_empty() {
+            w.write_all(b"No virtual interfaces found.\n")?;
         } else {
-            vlan_info.join(" ")
+            let headers = VIRTUAL_HEADERS.map(String::from);
+            let rows: Vec<Vec<String>> = interfaces.iter()
+                .map(|interface| virtual_cells(interface).iter().map(|cell| escape_markdown_cell(cell)).collect())
+                .collect();
+            write_markdown_rows(w, &headers, &rows, info.align_markdown)?;
+        }
+    }
+
+    if !info.sources.is_empty() {
+        writeln!(w, "\n_Data sources: {}_", format_sources(&info.sources))?;
+    }
+
+    if let Some(meta) = &info.meta {
+        w.write_all(b"\n---\n")?;
+        for line in footer_lines(meta) {
+            writeln!(w, "\n_{}_", line)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use crate::vlan_cell::{format_tagged_vlans, format_vlan_list};
+    use crate::capacity::{CapacityReport, FreeCriterion};
+    use crate::checks::port_violations;
+    use crate::testutil::fixture_ports;
+    use crate::matrix::{MatrixRow, PortCounts};
+
+    /// Separates the fixture report from the test harness chatter on stdout
+    const REPORT_MARKER: &str = "----- fixture report -----";
+
+    /// Markdown and HTML for a fixture that exercises everything that passes
+    /// through a HashMap or HashSet on the way to the output
+    fn fixture_report() -> String {
+        let ports = fixture_ports(include_str!("../tests/fixtures/untagged-not-egress.walk"), 1..=4);
+        let warnings = port_violations(&ports).iter().map(Warning::from).collect();
+        let capacity = CapacityReport::new(&ports, &[FreeCriterion::Default], 1);
+        let ranges: Vec<PortRange> = ports.into_iter()
+            .map(|port| {
+                let port_num = port.port_num;
+                port.into_range(port_num, port_num)
+            })
+            .collect();
+        let vlan_names = HashMap::from([
+            (1, "default".to_string()),
+            (10, "office".to_string()),
+            (20, "studio".to_string()),
+        ]);
+        let columns = Columns::new(vec![Column::Port, Column::Alias, Column::Vlans, Column::Lacp,
+            Column::Dot1x, Column::Frames, Column::Mac, Column::LastChange]);
+        let info = ReportInfo {
+            ip_address: "10.1.0.23".to_string(),
+            notes: vec!["Report limited to 4 selected port(s) by --ports".to_string()],
+            capacity: Some(capacity),
+            highlight_vlans: vec![10, 20],
+            warnings,
+            ..Default::default()
         };
+        format!("{}{}",
+            generate_port_table(&ranges, &vlan_names, OutputFormat::Markdown, &columns, &info),
+            generate_port_table(&ranges, &vlan_names, OutputFormat::Html, &columns, &info))
+    }
 
-        // LACP information
-        let lacp = if let Some(lacp_info) = &range.lacp_info {
-            let agg_name = lacp_info.agg_name.as_deref().unwrap_or("Unknown");
-            agg_name.to_string()
-        } else {
-            String::new()
+    #[test]
+    fn rendering_is_deterministic() {
+        let report = fixture_report();
+        assert!(!report.contains("Generated on"));
+        assert_eq!(report, fixture_report());
+    }
+
+    /// Helper for `rendering_is_deterministic_across_processes`
+    #[test]
+    #[ignore]
+    fn print_fixture_report() {
+        println!("{}{}{}", REPORT_MARKER, fixture_report(), REPORT_MARKER);
+    }
+
+    /// Every process seeds its hashers differently, so this catches ordering
+    /// leaks that two renders within one process might not
+    #[test]
+    fn rendering_is_deterministic_across_processes() {
+        let render = || {
+            let output = std::process::Command::new(std::env::current_exe().unwrap())
+                .args(["--exact", "output::tests::print_fixture_report", "--ignored", "--nocapture"])
+                .output()
+                .unwrap();
+            let stdout = String::from_utf8(output.stdout).unwrap();
+            stdout.split(REPORT_MARKER).nth(1).expect("fixture report in test output").to_string()
         };
+        assert_eq!(render(), render());
+    }
+
+    #[test]
+    fn vlan1_can_be_named_or_hidden() {
+        let names = HashMap::from([(1, "default".to_string()), (10, "office".to_string())]);
+        let named = VlanStyle { show_vlan1_name: true, ..Default::default() };
+        let hidden = VlanStyle { hide_vlan1: true, compact: true, ..Default::default() };
+        assert_eq!(format_vlan_label(1, &names, &named), "default (1)");
+
+        let vlans = HashSet::from([1, 10]);
+        assert_eq!(format_vlan_list(&vlans, &names, &named, |_| false), "default (1), office (10)");
+        assert_eq!(format_vlan_list(&vlans, &names, &hidden, |_| false), "office (10)");
+        assert_eq!(format_vlan_list(&HashSet::from([1]), &names, &hidden, |_| false), "");
+
+        // Carrying every VLAN but 1 still counts as ALL when VLAN 1 is hidden
+        let range = PortRange { vlan_memberships: HashSet::from([10, 20]), ..Default::default() };
+        let names = HashMap::from([(1, "default".to_string()), (10, "office".to_string()), (20, "studio".to_string())]);
+        assert_eq!(format_tagged_vlans(&range, &names, &hidden), "ALL (2 VLANs)");
 
-        // Add row to table
-        table.push_str(&format!("| {} | {} | {} | {} |\n",
-            port,
-            alias,
-            vlans,
-            lacp
-        ));
+        // The collapsed single-VLAN cell and the lists agree in both formats
+        let ranges = [
+            PortRange { first_port: 1, last_port: 1, pvid: Some(1), untagged_vlans: HashSet::from([1]),
+                vlan_memberships: HashSet::from([1]), ..Default::default() },
+            PortRange { first_port: 2, last_port: 2, pvid: Some(10), untagged_vlans: HashSet::from([10]),
+                vlan_memberships: HashSet::from([1, 10, 20]), ..Default::default() },
+        ];
+        let info = ReportInfo { vlan_style: VlanStyle { hide_vlan1: true, ..Default::default() }, ..Default::default() };
+        let columns = Columns::new(vec![Column::Port, Column::Vlans]);
+        let markdown = generate_port_table(&ranges, &names, OutputFormat::Markdown, &columns, &info);
+        assert!(markdown.contains("| 1 |  |"), "{}", markdown);
+        assert!(markdown.contains("| 2 | Tagged:[office (10), studio (20)] Untagged:[office (10)] |"), "{}", markdown);
+        let html = generate_port_table(&ranges, &names, OutputFormat::Html, &columns, &info);
+        assert!(html.contains("Tagged:[office (10), studio (20)] Untagged:[office (10)]"), "{}", html);
+        assert!(!html.contains("default (1)"), "{}", html);
     }
 
-    table
-} 
\ No newline at end of file
+    #[test]
+    fn header_labels_are_escaped_per_format() {
+        let mut columns = Columns::default();
+        columns.labels.insert(Column::Alias, "Description | notes".to_string());
+        columns.labels.insert(Column::Lacp, "<Link aggregation>".to_string());
+        let info = ReportInfo::default();
+
+        let markdown = generate_port_table(&[], &HashMap::new(), OutputFormat::Markdown, &columns, &info);
+        assert!(markdown.contains("| Port | Description \\| notes | VLAN(s) | <Link aggregation> |"), "{}", markdown);
+        let html = generate_port_table(&[], &HashMap::new(), OutputFormat::Html, &columns, &info);
+        assert!(html.contains("<th>&lt;Link aggregation&gt;</th>"));
+        assert!(html.contains("<th>Description | notes</th>"));
+    }
+
+    #[test]
+    fn markdown_cells_cannot_break_the_row() {
+        let port = |port_num: u32, alias: &str| PortRange {
+            first_port: port_num,
+            last_port: port_num,
+            alias: Some(alias.to_string()),
+            pvid: Some(10),
+            untagged_vlans: HashSet::from([10]),
+            vlan_memberships: HashSet::from([10]),
+            ..Default::default()
+        };
+        let mut ranges = vec![port(1, "up|down link"), port(2, "foo\\bar"), port(3, "  padded  ")];
+        ranges[2].lacp_info = Some(crate::LacpInfo { selected_agg_id: 49, agg_name: Some("Trk|1".to_string()),
+            agg_vlans: None, load_balance: None });
+        let names = HashMap::from([(10, " office|studio ".to_string())]);
+        let columns = Columns::new(vec![Column::Port, Column::Alias, Column::Vlans, Column::Lacp]);
+        let table = generate_port_table(&ranges, &names, OutputFormat::Markdown, &columns, &ReportInfo::default());
+        let rows: Vec<&str> = table.lines().filter(|line| line.starts_with("| ") && !line.starts_with("| Port")).collect();
+
+        assert_eq!(rows, [
+            "| 1 | up\\|down link | office\\|studio (10) |  |",
+            "| 2 | foo\\\\bar | office\\|studio (10) |  |",
+            "| 3 | padded | office\\|studio (10) | Trk\\|1 |",
+        ]);
+    }
+
+    #[test]
+    fn aligned_markdown_pads_to_display_width_after_escaping() {
+        let port = |port_num: u32, alias: &str| PortRange {
+            first_port: port_num,
+            last_port: port_num,
+            alias: Some(alias.to_string()),
+            pvid: Some(10),
+            untagged_vlans: HashSet::from([10]),
+            vlan_memberships: HashSet::from([10]),
+            ..Default::default()
+        };
+        let ranges = [port(1, "studio|1"), port(12, "編集室")];
+        let names = HashMap::from([(10, "office".to_string())]);
+        let columns = Columns::new(vec![Column::Port, Column::Alias, Column::Vlans]);
+        let info = ReportInfo { align_markdown: true, ..Default::default() };
+        let table = generate_port_table(&ranges, &names, OutputFormat::Markdown, &columns, &info);
+        let lines: Vec<&str> = table.lines().filter(|line| line.starts_with('|')).collect();
+
+        // The CJK characters are two columns wide each
+        assert_eq!(lines, [
+            "| Port | Alias     | VLAN(s)     |",
+            "|------|-----------|-------------|",
+            "| 1    | studio\\|1 | office (10) |",
+            "| 12   | 編集室    | office (10) |",
+        ]);
+    }
+
+    #[test]
+    fn html_escapes_every_value_from_the_switch() {
+        let ranges = [PortRange {
+            first_port: 1,
+            last_port: 1,
+            alias: Some(r#"<a href="x">desk</a>"#.to_string()),
+            pvid: Some(20),
+            untagged_vlans: HashSet::from([10]),
+            vlan_memberships: HashSet::from([10, 20]),
+            lacp_info: Some(crate::LacpInfo { selected_agg_id: 49, agg_name: Some("Trk<1>".to_string()), agg_vlans: None,
+                load_balance: Some("src & dst".to_string()) }),
+            comments: vec![(1, "patched 'temporarily'".to_string())],
+            ..Default::default()
+        }];
+        let names = HashMap::from([(10, "R&D".to_string()), (20, "<i>guest</i>".to_string())]);
+        let columns = Columns::new(vec![Column::Port, Column::Alias, Column::Vlans, Column::Lacp, Column::Comment]);
+        let info = ReportInfo {
+            title: "Rack <A>".to_string(),
+            notes: vec!["ports <1-4>".to_string()],
+            warnings: vec![Warning::new("orphan-vlan", Vec::new(), Some(30), "named \"<none>\"")],
+            ..Default::default()
+        };
+        let html = generate_port_table(&ranges, &names, OutputFormat::Html, &columns, &info);
+
+        assert!(html.contains("<td>&lt;a href=&quot;x&quot;&gt;desk&lt;/a&gt;</td>"), "{}", html);
+        assert!(html.contains("Untagged:[R&amp;D (10)]"), "{}", html);
+        // Markup of the cell itself stays intact around the escaped name
+        assert!(html.contains(r#"<span class="pvid-mismatch">PVID:&lt;i&gt;guest&lt;/i&gt; (20)</span>"#), "{}", html);
+        assert!(html.contains("<td>Trk&lt;1&gt; (src &amp; dst)</td>"), "{}", html);
+        assert!(html.contains("<td>patched &#39;temporarily&#39;</td>"), "{}", html);
+        assert!(html.contains("<title>Rack &lt;A&gt;"), "{}", html);
+        assert!(html.contains("Note: ports &lt;1-4&gt;"), "{}", html);
+        assert!(html.contains("named &quot;&lt;none&gt;&quot;</li>"), "{}", html);
+    }
+
+    #[test]
+    fn columns_without_data_are_hidden_unless_kept() {
+        let port = |port_num: u32, alias: &str| PortRange {
+            first_port: port_num,
+            last_port: port_num,
+            alias: Some(alias.to_string()),
+            vlan_memberships: HashSet::from([10]),
+            ..Default::default()
+        };
+        let mut ranges = [port(1, ""), port(2, "")];
+        let mut columns = Columns::new(vec![Column::Port, Column::Alias, Column::Vlans, Column::Lacp, Column::Mac]);
+        let hidden = columns.hide_empty(&ranges, &[Column::Mac]);
+        assert_eq!(hidden, [Column::Alias, Column::Lacp]);
+        assert_eq!(columns.layout, [Column::Port, Column::Vlans, Column::Mac]);
+
+        // A single alias or an inferred trunk is enough to keep a column
+        ranges[1].alias = Some("desk".to_string());
+        ranges[0].inferred_trunk = true;
+        let mut columns = Columns::default();
+        assert!(columns.hide_empty(&ranges, &[]).is_empty());
+        // Nothing to judge by without any ranges
+        assert!(columns.hide_empty(&[], &[]).is_empty());
+    }
+
+    #[test]
+    fn indices_show_the_first_and_last_port() {
+        let indices = |if_index: u32, bridge_port: Option<u32>| (if_index, crate::PortIndices { if_index, bridge_port });
+        let mut range = PortRange { first_port: 1, last_port: 3, ..Default::default() };
+        range.indices = vec![indices(1, Some(1)), indices(2, Some(2)), indices(3, Some(3))];
+        assert_eq!((format_if_index(&range), format_bridge_port(&range)), ("1-3".to_string(), "1-3".to_string()));
+        assert!(!indices_mismatched(&range));
+
+        range.indices[2].1.bridge_port = None;
+        assert_eq!(format_bridge_port(&range), "1-none");
+        assert!(indices_mismatched(&range));
+        range.indices.truncate(1);
+        assert_eq!((format_if_index(&range), format_bridge_port(&range)), ("1".to_string(), "1".to_string()));
+    }
+
+    #[test]
+    fn missing_pvid_is_shown_as_unknown_and_never_collapsed() {
+        let ranges: Vec<PortRange> = fixture_ports(include_str!("../tests/fixtures/missing-pvid.walk"), 1..=4)
+            .into_iter()
+            .map(|port| {
+                let port_num = port.port_num;
+                port.into_range(port_num, port_num)
+            })
+            .collect();
+        let vlan_names = HashMap::from([(10, "office".to_string())]);
+        let columns = Columns::default();
+        let table = generate_port_table(&ranges, &vlan_names, OutputFormat::Markdown, &columns, &ReportInfo::default());
+        let rows: Vec<&str> = table.lines().filter(|line| line.starts_with("| ") && !line.starts_with("| Port")).collect();
+
+        assert_eq!(rows[0], "| 1 |  | office (10) |  |");
+        // PVID 0 is not a valid PVID, so the single VLAN is not collapsed
+        assert_eq!(rows[1], "| 2 |  | Tagged:[office (10)] Untagged:[office (10)] |  |");
+        assert_eq!(rows[2], "| 3 |  | Tagged:[office (10)] Untagged:[office (10)] PVID:unknown |  |");
+        assert_eq!(rows[3], "| 4 |  | Tagged:[office (10)] Untagged:[office (10)] PVID:unknown |  |");
+    }
+
+    #[test]
+    fn vlan_comparison_lists_only_mismatches() {
+        let mismatches = [
+            VlanMismatch { vlan: 10, left: Some("studio".to_string()), right: Some(String::new()) },
+            VlanMismatch { vlan: 20, left: Some("a|b".to_string()), right: None },
+        ];
+        let mut table = Vec::new();
+        write_vlan_comparison(&mut table, ("sw1", "sw2"), 3, &mismatches, true).unwrap();
+        assert_eq!(String::from_utf8(table).unwrap(), "\
+| VLAN | sw1    | sw2           |
+|------|--------|---------------|
+| 10   | studio | (no name)     |
+| 20   | a\\|b   | (not defined) |
+
+1 VLANs only on sw1, 0 only on sw2, 1 named differently
+");
+        let mut same = Vec::new();
+        write_vlan_comparison(&mut same, ("sw1", "sw2"), 3, &[], true).unwrap();
+        assert_eq!(String::from_utf8(same).unwrap(), "sw1 and sw2 define the same 3 VLANs\n");
+    }
+
+    #[test]
+    fn footer_shows_what_is_known() {
+        let mut meta = Meta::new(vec!["--hide-default".to_string(), "--vlan 10".to_string()]);
+        meta.git_describe = Some("v0.1.0-3-g9d15b67".to_string());
+        meta.collection_ms = Some(1450);
+        assert_eq!(footer_lines(&meta), [
+            format!("Generated by switch-vlan-diagram {} (v0.1.0-3-g9d15b67)", env!("CARGO_PKG_VERSION")),
+            "Collection took 1.4 s".to_string(),
+            "Options: --hide-default; --vlan 10".to_string(),
+        ]);
+        let meta = Meta { git_describe: None, collection_ms: None, options: Vec::new(), ..meta };
+        assert_eq!(footer_lines(&meta).len(), 1);
+    }
+
+    #[test]
+    fn reports_mark_suspended_vlans() {
+        let ranges = vec![
+            PortRange { first_port: 1, last_port: 1, pvid: Some(20), untagged_vlans: HashSet::from([20]),
+                vlan_memberships: HashSet::from([20]), ..Default::default() },
+            PortRange { first_port: 2, last_port: 2, pvid: Some(30), untagged_vlans: HashSet::from([30]),
+                vlan_memberships: HashSet::from([30]), ..Default::default() },
+        ];
+        let vlan_names = HashMap::from([(20, "backstage".to_string())]);
+        let vtp = VtpInfo { domain: "wappu".to_string(), mode: 2, suspended_vlans: BTreeSet::from([20, 30]) };
+        let info = ReportInfo { vtp: Some(vtp), ..Default::default() };
+        let table = generate_port_table(&ranges, &vlan_names, OutputFormat::Markdown, &Columns::default(), &info);
+        assert!(table.contains("| 1 |  | backstage [suspended] (20) |"), "{}", table);
+        assert!(table.contains("| 2 |  | 30 [suspended] (30) |"), "{}", table);
+    }
+
+    #[test]
+    fn vlan_matrix_marks_missing_vlans() {
+        let matrix = VlanMatrix {
+            switches: vec!["core".to_string(), "closet|1".to_string()],
+            vlans: vec![
+                MatrixRow { vlan: 10, name: Some("studio".to_string()),
+                    ports: vec![PortCounts { tagged: 1, untagged: 12 }, PortCounts { tagged: 1, untagged: 0 }] },
+                MatrixRow { vlan: 20, name: None,
+                    ports: vec![PortCounts { tagged: 1, untagged: 0 }, PortCounts::default()] },
+            ],
+        };
+        let mut table = Vec::new();
+        write_vlan_matrix(&mut table, &matrix, true).unwrap();
+        assert!(String::from_utf8(table).unwrap().ends_with("\
+| VLAN | Name   | core | closet\\|1 |
+|------|--------|------|-----------|
+| 10   | studio | 1/12 | 1/0       |
+| 20   |        | 1/0  | **0**     |
+"));
+    }
+}
@@ -0,0 +1,115 @@
+use std::collections::BTreeMap;
+use std::sync::LazyLock;
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use crate::PortList;
+
+/// `{panel}` or `{port}`, optionally zero-padded as in `{port:02}`
+static PLACEHOLDER: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\{(panel|port)(?::0(\d+))?\}").expect("valid placeholder pattern")
+});
+
+/// Patch-panel labels for a block of switch ports generated from a pattern, e.g.
+/// `{ ports = "1-24", format = "PP{panel}-B{port:02}", panel = 2 }`.
+/// The port list is parsed when the config is loaded.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "RawPanelBlock")]
+pub struct PanelBlock {
+    /// Switch ports patched to this panel, in panel order
+    pub ports: String,
+    /// Label pattern with `{panel}` and `{port}` placeholders, `{port:02}` zero-pads
+    pub format: String,
+    pub panel: Option<u32>,
+    /// Panel position of the first port in the block
+    pub start: u32,
+    #[serde(skip)]
+    port_list: PortList,
+}
+
+/// A block as written in the config file
+#[derive(Deserialize)]
+struct RawPanelBlock {
+    ports: String,
+    format: String,
+    panel: Option<u32>,
+    #[serde(default = "default_start")]
+    start: u32,
+}
+
+fn default_start() -> u32 {
+    1
+}
+
+impl TryFrom<RawPanelBlock> for PanelBlock {
+    type Error = String;
+
+    fn try_from(raw: RawPanelBlock) -> Result<Self, Self::Error> {
+        PanelBlock::new(&raw.ports, &raw.format, raw.panel, raw.start)
+    }
+}
+
+impl PanelBlock {
+    pub fn new(ports: &str, format: &str, panel: Option<u32>, start: u32) -> Result<PanelBlock, String> {
+        Ok(PanelBlock {
+            ports: ports.to_string(),
+            format: format.to_string(),
+            panel,
+            start,
+            port_list: ports.parse()?,
+        })
+    }
+
+    /// Label of a switch port, None if it is not in this block
+    fn label(&self, port: u32) -> Option<String> {
+        let PortList(ports) = &self.port_list;
+        let position = ports.iter().position(|&p| p == port)? as u32 + self.start;
+        Some(render(&self.format, self.panel.unwrap_or_default(), position))
+    }
+}
+
+fn render(format: &str, panel: u32, position: u32) -> String {
+    PLACEHOLDER.replace_all(format, |caps: &Captures| {
+        let value = if &caps[1] == "panel" { panel } else { position };
+        let width = caps.get(2).and_then(|w| w.as_str().parse().ok()).unwrap_or(0);
+        format!("{:0width$}", value, width = width)
+    }).into_owned()
+}
+
+/// Label of a switch port: explicit labels win over blocks, unpatched ports get None
+pub fn panel_label(port: u32, labels: &BTreeMap<String, String>, blocks: &[PanelBlock]) -> Option<String> {
+    labels.get(&port.to_string()).cloned()
+        .or_else(|| blocks.iter().find_map(|block| block.label(port)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block() -> PanelBlock {
+        PanelBlock::new("1-4,7", "PP{panel}-B{port:02}", Some(2), 1).unwrap()
+    }
+
+    #[test]
+    fn block_labels_follow_port_order() {
+        assert_eq!(block().label(1).as_deref(), Some("PP2-B01"));
+        assert_eq!(block().label(4).as_deref(), Some("PP2-B04"));
+        assert_eq!(block().label(7).as_deref(), Some("PP2-B05"));
+        assert_eq!(block().label(5), None);
+    }
+
+    #[test]
+    fn explicit_labels_win_over_blocks() {
+        let labels = BTreeMap::from([("2".to_string(), "spare".to_string())]);
+        assert_eq!(panel_label(2, &labels, &[block()]).as_deref(), Some("spare"));
+        assert_eq!(panel_label(3, &labels, &[block()]).as_deref(), Some("PP2-B03"));
+        assert_eq!(panel_label(9, &labels, &[block()]), None);
+    }
+
+    #[test]
+    fn blocks_are_parsed_at_load() {
+        let blocks: BTreeMap<String, PanelBlock> = toml::from_str("a = { ports = \"3-4\", format = \"{port}\", start = 10 }").unwrap();
+        assert_eq!(blocks["a"].label(4).as_deref(), Some("11"));
+        let err = toml::from_str::<BTreeMap<String, PanelBlock>>("a = { ports = \"4-x\", format = \"{port}\" }").unwrap_err();
+        assert!(err.to_string().contains("Invalid port number 'x'"), "{}", err);
+    }
+}
@@ -0,0 +1,90 @@
+//! Polling several switches at once
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use crate::error::Error;
+
+/// Run `poll` for every host on at most `jobs` threads. Results come back in
+/// the order of `hosts`, whichever host finishes first; a poll that panics
+/// becomes an error for its host instead of taking the others down.
+pub fn poll_hosts<T: Send>(hosts: &[String], jobs: usize, poll: impl Fn(&str) -> T + Sync) -> Vec<Result<T, Error>> {
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<Result<T, Error>>>> = Mutex::new(hosts.iter().map(|_| None).collect());
+    let workers = jobs.clamp(1, hosts.len().max(1));
+
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::SeqCst);
+                let Some(host) = hosts.get(index) else {
+                    break;
+                };
+                let result = panic::catch_unwind(AssertUnwindSafe(|| poll(host)))
+                    .map_err(|payload| Error::Panicked(panic_message(payload.as_ref())));
+                results.lock().unwrap_or_else(|poisoned| poisoned.into_inner())[index] = Some(result);
+            });
+        }
+    });
+
+    results.into_inner()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .into_iter()
+        .map(|result| result.unwrap_or_else(|| Err(Error::Panicked("no result".to_string()))))
+        .collect()
+}
+
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    payload.downcast_ref::<&str>().map(|message| message.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown cause".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn hosts(count: usize) -> Vec<String> {
+        (1..=count).map(|n| format!("10.0.0.{}", n)).collect()
+    }
+
+    #[test]
+    fn results_keep_host_order() {
+        let hosts = hosts(6);
+        // Early hosts take longest, so they finish last
+        let results = poll_hosts(&hosts, 6, |host| {
+            let n: u64 = host.rsplit('.').next().unwrap().parse().unwrap();
+            thread::sleep(Duration::from_millis(60 - 10 * n));
+            host.to_string()
+        });
+        let results: Vec<String> = results.into_iter().map(Result::unwrap).collect();
+        assert_eq!(results, hosts);
+    }
+
+    #[test]
+    fn jobs_limit_concurrency() {
+        let running = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+        poll_hosts(&hosts(8), 3, |_| {
+            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+            peak.fetch_max(now, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(20));
+            running.fetch_sub(1, Ordering::SeqCst);
+        });
+        assert!(peak.load(Ordering::SeqCst) <= 3);
+        assert!(peak.load(Ordering::SeqCst) > 1);
+    }
+
+    #[test]
+    fn a_panic_fails_only_its_host() {
+        let results = poll_hosts(&hosts(3), 2, |host| {
+            if host == "10.0.0.2" {
+                panic!("walk went wrong");
+            }
+            host.len()
+        });
+        assert!(results[0].is_ok() && results[2].is_ok());
+        assert!(matches!(&results[1], Err(Error::Panicked(message)) if message == "walk went wrong"));
+    }
+}
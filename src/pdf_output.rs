@@ -0,0 +1,512 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+use pdf_writer::{Content, Finish, Name, Pdf, Rect, Ref, Str, TextStr};
+use crate::PortRange;
+use crate::snapshot::PortChange;
+use crate::output::{footer_lines, PARTIAL_BANNER, format_alias, format_bridge_port, format_config_hash, format_device, format_distinct, format_dot1x,
+    format_frame_policy, format_if_index, format_last_change, format_panel, format_port_range, format_range_mac,
+    indices_mismatched, is_recent_change, Column, Columns, ReportInfo};
+use crate::vlan_cell::format_vlan_cell;
+
+/// A4 landscape, in points; port tables are wide rather than long
+const PAGE_WIDTH: f32 = 842.0;
+const PAGE_HEIGHT: f32 = 595.0;
+const MARGIN: f32 = 36.0;
+/// Room kept free at the bottom of every page for the page number
+const FOOTER_HEIGHT: f32 = 16.0;
+
+const FONT_SIZE: f32 = 8.0;
+const LEADING: f32 = 10.0;
+const CELL_PADDING: f32 = 4.0;
+
+type Rgb = (f32, f32, f32);
+
+const fn rgb(hex: u32) -> Rgb {
+    (((hex >> 16) & 0xff) as f32 / 255.0, ((hex >> 8) & 0xff) as f32 / 255.0, (hex & 0xff) as f32 / 255.0)
+}
+
+// The colours of the HTML report; a PDF has no hover, so only the plain and striped shades
+const BLACK: Rgb = rgb(0x333333);
+const GREY: Rgb = rgb(0x808080);
+const BORDER: Rgb = rgb(0xdddddd);
+const HEADER_FILL: Rgb = rgb(0xf2f2f2);
+const STRIPE_FILL: Rgb = rgb(0xf9f9f9);
+/// Plain and striped rows of highlight-1 to highlight-5
+const HIGHLIGHT_FILLS: [(Rgb, Rgb); 5] = [
+    (rgb(0xe6f3ff), rgb(0xd9edff)),
+    (rgb(0xe6ffe6), rgb(0xd9ffd9)),
+    (rgb(0xfffbe6), rgb(0xfff6cc)),
+    (rgb(0xf9e6ff), rgb(0xf2ccff)),
+    (rgb(0xe6fffb), rgb(0xccfff6)),
+];
+const MULTI_TAGGED_FILLS: (Rgb, Rgb) = (rgb(0xfff3e6), rgb(0xffe6cc));
+const LACP_FILLS: (Rgb, Rgb) = (rgb(0xe6e6ff), rgb(0xd9d9ff));
+const ADDED_FILL: Rgb = rgb(0xd9ffd9);
+const RECENT_CHANGE_FILL: Rgb = rgb(0xfff8c4);
+const DEVIATES_TEXT: Rgb = rgb(0xb30000);
+const CHANGED_MARK: Rgb = rgb(0xe69500);
+
+/// The two standard fonts every PDF reader has, so nothing needs embedding
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Font {
+    Regular,
+    Bold,
+}
+
+/// Advance widths of the printable ASCII characters, in thousandths of the font size
+const HELVETICA_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278,
+    556, 556, 556, 556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556,
+    1015, 667, 667, 722, 722, 667, 611, 778, 722, 278, 500, 667, 556, 833, 722, 778,
+    667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 278, 278, 278, 469, 556,
+    333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500, 222, 833, 556, 556,
+    556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584,
+];
+const HELVETICA_BOLD_WIDTHS: [u16; 95] = [
+    278, 333, 474, 556, 556, 889, 722, 238, 333, 333, 389, 584, 278, 333, 278, 278,
+    556, 556, 556, 556, 556, 556, 556, 556, 556, 556, 333, 333, 584, 584, 584, 611,
+    975, 722, 722, 722, 722, 667, 611, 778, 722, 278, 556, 722, 611, 833, 722, 778,
+    667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 333, 278, 333, 584, 556,
+    333, 556, 611, 556, 611, 556, 333, 611, 611, 278, 278, 556, 278, 889, 611, 611,
+    611, 611, 389, 556, 333, 611, 556, 778, 556, 556, 500, 389, 280, 389, 584,
+];
+
+impl Font {
+    fn name(self) -> Name<'static> {
+        match self {
+            Font::Regular => Name(b"F1"),
+            Font::Bold => Name(b"F2"),
+        }
+    }
+
+    /// Width of `text` at `size`; characters outside ASCII count as a digit
+    fn width(self, text: &str, size: f32) -> f32 {
+        let widths = match self {
+            Font::Regular => &HELVETICA_WIDTHS,
+            Font::Bold => &HELVETICA_BOLD_WIDTHS,
+        };
+        let units: u32 = text.chars()
+            .map(|c| (c as usize).checked_sub(32).and_then(|i| widths.get(i)).copied().unwrap_or(556) as u32)
+            .sum();
+        units as f32 * size / 1000.0
+    }
+}
+
+/// `text` in WinAnsiEncoding, which covers Latin-1; anything else becomes '?'
+fn win_ansi(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|c| match c {
+            ' '..='~' | '\u{a0}'..='\u{ff}' => c as u8,
+            '€' => 0x80,
+            '‘' => 0x91,
+            '’' => 0x92,
+            '“' => 0x93,
+            '”' => 0x94,
+            '•' => 0x95,
+            '–' => 0x96,
+            '—' => 0x97,
+            _ => b'?',
+        })
+        .collect()
+}
+
+/// Break `text` into lines no wider than `width`, between words where possible
+fn wrap(text: &str, font: Font, size: f32, width: f32) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split(' ').filter(|word| !word.is_empty()) {
+        let candidate = if line.is_empty() { word.to_string() } else { format!("{} {}", line, word) };
+        if font.width(&candidate, size) <= width {
+            line = candidate;
+            continue;
+        }
+        if !line.is_empty() {
+            lines.push(std::mem::take(&mut line));
+        }
+        // A word wider than the column is split wherever it has to be
+        for c in word.chars() {
+            line.push(c);
+            if font.width(&line, size) > width && line.chars().count() > 1 {
+                let last = line.pop().unwrap_or(c);
+                lines.push(std::mem::replace(&mut line, last.to_string()));
+            }
+        }
+    }
+    if !line.is_empty() || lines.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+struct Cell {
+    text: String,
+    fill: Option<Rgb>,
+    /// Bold red, as `td.deviates` in HTML
+    deviates: bool,
+}
+
+struct Row {
+    cells: Vec<Cell>,
+    fill: Option<Rgb>,
+    /// Changed since --diff, marked with a bar on the left
+    changed: bool,
+}
+
+fn row_for(range: &PortRange, index: usize, vlan_names: &HashMap<u32, String>, columns: &Columns, info: &ReportInfo) -> Row {
+    let striped = index % 2 == 1;
+    let pick = |(plain, even): (Rgb, Rgb)| if striped { even } else { plain };
+    // Later rules win, as in the HTML style sheet
+    let mut fill = striped.then_some(STRIPE_FILL);
+    if range.untagged_vlans.len() == 1 {
+        let untagged_vlan = *range.untagged_vlans.iter().next().unwrap();
+        if let Some(index) = info.highlight_vlans.iter().position(|&v| v == untagged_vlan) {
+            fill = Some(pick(HIGHLIGHT_FILLS[index % HIGHLIGHT_FILLS.len()]));
+        }
+    }
+    if range.vlan_memberships.len() > 1 {
+        fill = Some(pick(MULTI_TAGGED_FILLS));
+    }
+    if range.lacp_info.is_some() {
+        fill = Some(pick(LACP_FILLS));
+    }
+    let changes: Vec<&PortChange> = info.changes.iter()
+        .flat_map(|changes| range.ports().into_iter().filter_map(|port| changes.get(&port)))
+        .collect();
+    if changes.iter().any(|change| matches!(change, PortChange::Added)) {
+        fill = Some(ADDED_FILL);
+    }
+
+    let cells = columns.layout.iter().map(|&column| {
+        let mut cell = Cell { text: String::new(), fill: None, deviates: false };
+        cell.text = match column {
+            Column::Port => {
+                let port = format_port_range(range);
+                if range.uplink { format!("{} (uplink)", port) } else { port }
+            }
+            Column::Alias => format_alias(range),
+            Column::Vlans => format_vlan_cell(range, vlan_names, &info.vlan_style).render(|_, text| text.to_string()),
+            Column::Lacp => match &range.lacp_info {
+                Some(lacp_info) => {
+                    let agg_name = lacp_info.agg_name.as_deref().unwrap_or("Unknown");
+                    match &lacp_info.load_balance {
+                        Some(mode) => format!("{} ({})", agg_name, mode),
+                        None => agg_name.to_string(),
+                    }
+                }
+                None if range.inferred_trunk => "possible trunk group (inferred)".to_string(),
+                None => String::new(),
+            },
+            Column::Dot1x => format_dot1x(range.dot1x.as_ref()),
+            Column::Frames => {
+                cell.deviates = range.frame_policy.is_some_and(|p| p.deviates);
+                format_frame_policy(range.frame_policy.as_ref())
+            }
+            Column::Mac => format_range_mac(range),
+            Column::LastChange => {
+                cell.fill = is_recent_change(range.last_change).then_some(RECENT_CHANGE_FILL);
+                format_last_change(range.last_change)
+            }
+            Column::Comment => format_distinct(&range.comments, "comments").unwrap_or_default(),
+            Column::Location => format_distinct(&range.locations, "locations").unwrap_or_default(),
+            Column::Panel => format_panel(range),
+            Column::ConfigHash => format_config_hash(range),
+            Column::IfIndex | Column::BridgePort => {
+                cell.deviates = indices_mismatched(range);
+                if column == Column::IfIndex { format_if_index(range) } else { format_bridge_port(range) }
+            }
+        };
+        cell
+    }).collect();
+
+    Row { cells, fill, changed: changes.iter().any(|change| matches!(change, PortChange::Changed(_))) }
+}
+
+/// Widths that fit the page: columns narrower than an equal share keep their
+/// natural width and the rest split what is left in proportion to theirs
+fn column_widths(natural: &[f32], available: f32) -> Vec<f32> {
+    let total: f32 = natural.iter().sum();
+    if total <= available {
+        return natural.iter().map(|width| width * available / total).collect();
+    }
+    let mut fixed = vec![false; natural.len()];
+    loop {
+        let remaining = available - natural.iter().zip(&fixed).filter(|(_, &f)| f).map(|(w, _)| w).sum::<f32>();
+        let flexible: Vec<usize> = (0..natural.len()).filter(|&i| !fixed[i]).collect();
+        let fair = remaining / flexible.len() as f32;
+        let narrow: Vec<usize> = flexible.iter().copied().filter(|&i| natural[i] <= fair).collect();
+        if narrow.is_empty() {
+            let flexible_total: f32 = flexible.iter().map(|&i| natural[i]).sum();
+            return natural.iter().zip(&fixed)
+                .map(|(&w, &f)| if f { w } else { w * remaining / flexible_total })
+                .collect();
+        }
+        for i in narrow {
+            fixed[i] = true;
+        }
+    }
+}
+
+/// Pages being filled top to bottom
+struct Pages {
+    done: Vec<Content>,
+    page: Content,
+    /// Top of the free space on `page`
+    y: f32,
+}
+
+impl Pages {
+    fn new() -> Self {
+        Pages { done: Vec::new(), page: Content::new(), y: PAGE_HEIGHT - MARGIN }
+    }
+
+    fn fits(&self, height: f32) -> bool {
+        self.y - height >= MARGIN + FOOTER_HEIGHT
+    }
+
+    fn next_page(&mut self) {
+        self.done.push(std::mem::replace(&mut self.page, Content::new()));
+        self.y = PAGE_HEIGHT - MARGIN;
+    }
+
+    fn text(&mut self, x: f32, baseline: f32, font: Font, size: f32, color: Rgb, text: &str) {
+        self.page.set_fill_rgb(color.0, color.1, color.2);
+        self.page.begin_text();
+        self.page.set_font(font.name(), size);
+        self.page.next_line(x, baseline);
+        self.page.show(Str(&win_ansi(text)));
+        self.page.end_text();
+    }
+
+    fn fill(&mut self, x: f32, y: f32, width: f32, height: f32, color: Rgb) {
+        self.page.set_fill_rgb(color.0, color.1, color.2);
+        self.page.rect(x, y, width, height);
+        self.page.fill_nonzero();
+    }
+
+    /// A paragraph across the page, wrapped
+    fn paragraph(&mut self, font: Font, size: f32, text: &str) {
+        for line in wrap(text, font, size, PAGE_WIDTH - 2.0 * MARGIN) {
+            if !self.fits(size * 1.25) {
+                self.next_page();
+            }
+            self.text(MARGIN, self.y - size, font, size, BLACK, &line);
+            self.y -= size * 1.25;
+        }
+    }
+
+    /// One table row of already wrapped cells, the top at `self.y`
+    fn row(&mut self, widths: &[f32], cells: &[(Vec<String>, Option<Rgb>, Font, Rgb)], height: f32, changed: bool) {
+        let top = self.y;
+        let mut x = MARGIN;
+        for (width, (_, fill, _, _)) in widths.iter().zip(cells) {
+            if let Some(fill) = fill {
+                self.fill(x, top - height, *width, height, *fill);
+            }
+            x += width;
+        }
+        if changed {
+            self.fill(MARGIN, top - height, 3.0, height, CHANGED_MARK);
+        }
+        self.page.set_stroke_rgb(BORDER.0, BORDER.1, BORDER.2);
+        self.page.set_line_width(0.5);
+        let mut x = MARGIN;
+        for (width, (lines, _, font, color)) in widths.iter().zip(cells) {
+            self.page.rect(x, top - height, *width, height);
+            self.page.stroke();
+            for (i, line) in lines.iter().enumerate().filter(|(_, line)| !line.is_empty()) {
+                let baseline = top - CELL_PADDING - FONT_SIZE - i as f32 * LEADING + 1.0;
+                self.text(x + CELL_PADDING, baseline, *font, FONT_SIZE, *color, line);
+            }
+            x += width;
+        }
+        self.y -= height;
+    }
+
+    fn finish(mut self) -> Vec<Content> {
+        self.done.push(self.page);
+        self.done
+    }
+}
+
+/// Height of a row of wrapped cells
+fn row_height(cells: &[(Vec<String>, Option<Rgb>, Font, Rgb)]) -> f32 {
+    let lines = cells.iter().map(|(lines, ..)| lines.len()).max().unwrap_or(1);
+    lines as f32 * LEADING + 2.0 * CELL_PADDING
+}
+
+/// The device header, the port table and the footer, paginated with the table
+/// header repeated on every page. Other sections of the report are left to the
+/// Markdown and HTML formats.
+pub fn write_port_table(
+    w: &mut dyn Write,
+    port_ranges: &[PortRange],
+    vlan_names: &HashMap<u32, String>,
+    columns: &Columns,
+    info: &ReportInfo,
+) -> io::Result<()> {
+    let rows: Vec<Row> = port_ranges.iter().enumerate()
+        .map(|(index, range)| row_for(range, index, vlan_names, columns, info))
+        .collect();
+    let headers: Vec<&str> = columns.layout.iter().map(|&column| columns.header(column)).collect();
+
+    // Natural widths are the longest line a column would need, capped so one
+    // long alias does not squeeze everything else
+    let cap = (PAGE_WIDTH - 2.0 * MARGIN) / 3.0;
+    let natural: Vec<f32> = headers.iter().enumerate()
+        .map(|(i, header)| {
+            let widest = rows.iter()
+                .map(|row| Font::Regular.width(&row.cells[i].text, FONT_SIZE))
+                .fold(Font::Bold.width(header, FONT_SIZE), f32::max);
+            widest.min(cap) + 2.0 * CELL_PADDING
+        })
+        .collect();
+    let widths = column_widths(&natural, PAGE_WIDTH - 2.0 * MARGIN);
+    let wrap_cell = |text: &str, font: Font, width: f32| wrap(text, font, FONT_SIZE, width - 2.0 * CELL_PADDING);
+
+    let header_cells: Vec<_> = headers.iter().zip(&widths)
+        .map(|(header, &width)| (wrap_cell(header, Font::Bold, width), Some(HEADER_FILL), Font::Bold, BLACK))
+        .collect();
+    let header_height = row_height(&header_cells);
+
+    let mut pages = Pages::new();
+    pages.paragraph(Font::Bold, 16.0, &info.title);
+    pages.y -= 4.0;
+    if info.interrupted {
+        pages.text(MARGIN, pages.y - 14.0, Font::Bold, 14.0, DEVIATES_TEXT, PARTIAL_BANNER);
+        pages.y -= 14.0 * 1.25 + 4.0;
+    }
+    pages.paragraph(Font::Bold, 11.0, &format!("Device: {}", format_device(info)));
+    if let Some(generated_on) = &info.generated_on {
+        pages.paragraph(Font::Regular, 9.0, &format!("Generated on: {}", generated_on));
+    }
+    if let Some(config_hash) = &info.config_hash {
+        pages.paragraph(Font::Regular, 9.0, &format!("Config hash: {}", config_hash));
+    }
+    if let Some(vtp) = &info.vtp {
+        pages.paragraph(Font::Regular, 9.0, &format!("VTP domain: {} (mode: {})", vtp.domain, vtp.mode_name()));
+    }
+    for note in &info.notes {
+        pages.paragraph(Font::Regular, 9.0, &format!("Note: {}", note));
+    }
+    pages.y -= 10.0;
+
+    if !pages.fits(header_height) {
+        pages.next_page();
+    }
+    pages.row(&widths, &header_cells, header_height, false);
+    for row in &rows {
+        let cells: Vec<_> = row.cells.iter().zip(&widths)
+            .map(|(cell, &width)| {
+                let (font, color) = if cell.deviates { (Font::Bold, DEVIATES_TEXT) } else { (Font::Regular, BLACK) };
+                (wrap_cell(&cell.text, font, width), cell.fill.or(row.fill), font, color)
+            })
+            .collect();
+        let height = row_height(&cells);
+        if !pages.fits(height) {
+            pages.next_page();
+            pages.row(&widths, &header_cells, header_height, false);
+        }
+        pages.row(&widths, &cells, height, row.changed);
+    }
+    if let Some(meta) = &info.meta {
+        pages.y -= 10.0;
+        for line in footer_lines(meta) {
+            pages.paragraph(Font::Regular, FONT_SIZE, &line);
+        }
+    }
+
+    let mut contents = pages.finish();
+    let page_count = contents.len();
+    for (index, content) in contents.iter_mut().enumerate() {
+        let footer = format!("{}: page {} of {}", info.device_name, index + 1, page_count);
+        let x = PAGE_WIDTH - MARGIN - Font::Regular.width(&footer, FONT_SIZE);
+        content.set_fill_rgb(GREY.0, GREY.1, GREY.2);
+        content.begin_text();
+        content.set_font(Font::Regular.name(), FONT_SIZE);
+        content.next_line(x, MARGIN);
+        content.show(Str(&win_ansi(&footer)));
+        content.end_text();
+    }
+
+    let catalog_id = Ref::new(1);
+    let page_tree_id = Ref::new(2);
+    let regular_id = Ref::new(3);
+    let bold_id = Ref::new(4);
+    let info_id = Ref::new(5);
+    // Each page and its content stream
+    let page_ids: Vec<(Ref, Ref)> = (0..page_count as i32)
+        .map(|i| (Ref::new(6 + 2 * i), Ref::new(7 + 2 * i)))
+        .collect();
+
+    let mut pdf = Pdf::new();
+    pdf.catalog(catalog_id).pages(page_tree_id);
+    pdf.pages(page_tree_id).kids(page_ids.iter().map(|(page, _)| *page)).count(page_count as i32);
+    for ((page_id, content_id), content) in page_ids.into_iter().zip(contents) {
+        let mut page = pdf.page(page_id);
+        page.media_box(Rect::new(0.0, 0.0, PAGE_WIDTH, PAGE_HEIGHT));
+        page.parent(page_tree_id);
+        page.contents(content_id);
+        page.resources().fonts()
+            .pair(Font::Regular.name(), regular_id)
+            .pair(Font::Bold.name(), bold_id);
+        page.finish();
+        pdf.stream(content_id, &content.finish());
+    }
+    pdf.type1_font(regular_id).base_font(Name(b"Helvetica")).encoding_predefined(Name(b"WinAnsiEncoding"));
+    pdf.type1_font(bold_id).base_font(Name(b"Helvetica-Bold")).encoding_predefined(Name(b"WinAnsiEncoding"));
+    pdf.document_info(info_id)
+        .title(TextStr(&format!("{} ({})", info.title, info.device_name)))
+        .producer(TextStr("switch-vlan-diagram"));
+    w.write_all(&pdf.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn long_text_wraps_within_the_column() {
+        let width = Font::Regular.width("Tagged:[1, 10, 20]", FONT_SIZE);
+        let lines = wrap("Tagged:[1, 10, 20] Untagged:[30]", Font::Regular, FONT_SIZE, width);
+        assert_eq!(lines, ["Tagged:[1, 10, 20]", "Untagged:[30]"]);
+        // No spaces to break at
+        let lines = wrap("0123456789", Font::Regular, FONT_SIZE, Font::Regular.width("0123", FONT_SIZE));
+        assert_eq!(lines, ["0123", "4567", "89"]);
+        assert_eq!(wrap("", Font::Regular, FONT_SIZE, 10.0), [""]);
+    }
+
+    #[test]
+    fn columns_share_the_page_by_need() {
+        assert_eq!(column_widths(&[50.0, 150.0], 400.0), [100.0, 300.0]);
+        // The narrow column keeps its width, the wide ones split the rest
+        assert_eq!(column_widths(&[50.0, 300.0, 600.0], 500.0), [50.0, 150.0, 300.0]);
+    }
+
+    #[test]
+    fn long_tables_repeat_the_header_on_every_page() {
+        let ranges: Vec<PortRange> = (1..=60)
+            .map(|port| PortRange {
+                first_port: port,
+                last_port: port,
+                alias: Some(format!("desk {}", port)),
+                pvid: Some(10),
+                vlan_memberships: [10].into(),
+                untagged_vlans: [10].into(),
+                ..Default::default()
+            })
+            .collect();
+        let info = ReportInfo { title: "Ports".to_string(), device_name: "sw1".to_string(), ..ReportInfo::default() };
+        let mut pdf = Vec::new();
+        write_port_table(&mut pdf, &ranges, &HashMap::new(), &Columns::default(), &info).unwrap();
+        let text = String::from_utf8_lossy(&pdf);
+        assert!(text.contains("/Count 3"), "{}", text);
+        assert_eq!(text.matches("(Alias) Tj").count(), 3);
+        assert!(text.contains("(sw1: page 3 of 3) Tj"));
+    }
+
+    #[test]
+    fn text_is_encoded_as_win_ansi() {
+        assert_eq!(win_ansi("Sävel – 2"), b"S\xe4vel \x96 2");
+        assert_eq!(win_ansi("端口"), b"??");
+    }
+}
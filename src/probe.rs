@@ -0,0 +1,114 @@
+//! `--check`: whether a switch answers and implements the tables collection
+//! relies on, from one request per table instead of a full poll
+use crate::collector::{CollectOptions, SYS_DESCR};
+use crate::error::{Error, Result};
+use crate::session::SessionPool;
+use crate::snmp_utils::{get_string_scalar, SnmpSource};
+use crate::stats::TableOutcome;
+use crate::vendor::Vendor;
+
+/// One table the check asks for a row of
+#[derive(Debug, Clone)]
+pub struct Capability {
+    /// MIB table and column, e.g. "ifTable (ifIndex)"
+    pub name: String,
+    /// Without it there is no report worth writing
+    pub mandatory: bool,
+    /// `Rows(1)` when a row came back
+    pub outcome: TableOutcome,
+}
+
+impl Capability {
+    pub fn passed(&self) -> bool {
+        self.outcome == TableOutcome::Rows(1)
+    }
+}
+
+/// What `--check` found out about a switch
+#[derive(Debug, Clone)]
+pub struct Probe {
+    pub sys_descr: Option<String>,
+    pub vendor: Vendor,
+    pub capabilities: Vec<Capability>,
+}
+
+impl Probe {
+    /// Whether every mandatory table has rows
+    pub fn passed(&self) -> bool {
+        self.capabilities.iter().all(|capability| capability.passed() || !capability.mandatory)
+    }
+}
+
+/// Probe the switch at `host` on `options.port`
+pub fn probe_switch_with(host: &str, options: &CollectOptions, sessions: &SessionPool) -> Result<Probe> {
+    let agent_addr = format!("{}:{}", host, options.port);
+    sessions.with_session(&agent_addr, options.community.as_bytes(), options.timeout, |source, _| {
+        probe_from(source, host, options)
+    })
+}
+
+/// Probe any SNMP source. Fails only when sysDescr cannot be read, as the
+/// agent is then unreachable or refuses the community.
+pub fn probe_from(source: &mut dyn SnmpSource, host: &str, options: &CollectOptions) -> Result<Probe> {
+    let sys_descr = get_string_scalar(source, SYS_DESCR)?;
+    let vendor = match options.vendor {
+        Some(vendor) => vendor,
+        None => sys_descr.as_deref().map_or(Vendor::Generic, Vendor::detect),
+    };
+    let oids = options.config.table_oids(host, vendor);
+    let tables = [
+        ("ifTable (ifIndex)", true, &oids.if_index),
+        ("ifXTable (ifName)", false, &oids.if_name),
+        ("Q-BRIDGE dot1qVlanStaticEgressPorts", true, &oids.egress_ports),
+        ("Q-BRIDGE dot1qVlanStaticUntaggedPorts", true, &oids.untagged_ports),
+        ("Q-BRIDGE dot1qVlanStaticName", false, &oids.vlan_names),
+        ("Q-BRIDGE dot1qPvid", false, &oids.pvid),
+        ("LAG MIB dot3adAggPortSelectedAggID", false, &oids.lag_port_selected),
+    ];
+
+    let mut capabilities = Vec::new();
+    // After one timeout the rest would most likely time out too, each taking as long
+    let mut silent = false;
+    for (name, mandatory, base_oid) in tables {
+        let outcome = if silent {
+            TableOutcome::Error("skipped after an earlier timeout".to_string())
+        } else {
+            match source.get_next(base_oid) {
+                Ok(Some((oid, _))) if oid.starts_with(base_oid) => TableOutcome::Rows(1),
+                Ok(_) => TableOutcome::Unsupported,
+                Err(e) => {
+                    silent = matches!(e, Error::Timeout);
+                    TableOutcome::Error(e.to_string())
+                }
+            }
+        };
+        capabilities.push(Capability { name: name.to_string(), mandatory, outcome });
+    }
+    Ok(Probe { sys_descr, vendor, capabilities })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snmp_utils::RecordedWalk;
+
+    #[test]
+    fn a_switch_without_lags_still_passes() {
+        let mut walk = RecordedWalk::parse(include_str!("../tests/fixtures/cisco-2960-bridge-ports.walk")).unwrap();
+        let probe = probe_from(&mut walk, "sw1", &CollectOptions::default()).unwrap();
+        let failed: Vec<&str> = probe.capabilities.iter()
+            .filter(|capability| !capability.passed())
+            .map(|capability| capability.name.as_str())
+            .collect();
+        assert_eq!(failed, ["LAG MIB dot3adAggPortSelectedAggID"]);
+        assert!(probe.passed());
+    }
+
+    #[test]
+    fn missing_vlan_tables_fail_the_check() {
+        let mut walk = RecordedWalk::parse(include_str!("../tests/fixtures/restricted-view.walk")).unwrap();
+        let probe = probe_from(&mut walk, "sw1", &CollectOptions::default()).unwrap();
+        assert_eq!(probe.vendor, Vendor::Aruba);
+        assert!(!probe.passed());
+    }
+}
@@ -0,0 +1,506 @@
+//! The ports of a report: which of them it shows, how they are grouped into
+//! rows and in what order
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use clap::ValueEnum;
+use regex::Regex;
+use crate::anonymize::Anonymizer;
+use crate::error::{Error, Result};
+use crate::grouping::{GroupMode, Grouping};
+use crate::logging::Event;
+use crate::metadata::Metadata;
+use crate::panel::{panel_label, PanelBlock};
+use crate::warnings::Warnings;
+use crate::{is_default_config, PortConfig, PortIndices, PortList, PortRange};
+
+/// Column to order the table by
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum SortKey {
+    #[default]
+    Port,
+    /// Case-insensitive; ports without an alias come last either way
+    Alias,
+    Pvid,
+    /// Tagged and untagged VLANs together
+    VlanCount,
+}
+
+impl fmt::Display for SortKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.to_possible_value() {
+            Some(value) => f.write_str(value.get_name()),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Which ports a report shows and how it lays them out; the binary fills
+/// this in from its flags and the host's config section
+#[derive(Debug, Clone, Default)]
+pub struct ReportOptions {
+    /// --ports: only these ports
+    pub ports: Option<PortList>,
+    /// --exclude-ports, applied after --ports
+    pub exclude_ports: Option<PortList>,
+    /// Only ports whose alias matches; ports without an alias never match
+    pub alias_filter: Option<Regex>,
+    /// Only ports whose alias does not match `alias_filter` instead
+    pub alias_filter_invert: bool,
+    pub unaliased_only: bool,
+    pub only_aliased: bool,
+    /// Hide ports that were never configured, i.e. sit untagged in `default_vlan` only
+    pub hide_default: bool,
+    pub default_vlan: u32,
+    /// Only ports carrying one of these VLANs, by id or name
+    pub vlans: Vec<String>,
+    pub infer_trunks: bool,
+    /// Ports --infer-trunks leaves alone
+    pub no_infer_trunk_ports: Vec<u32>,
+    /// --no-ranges, which wins over `dedupe`
+    pub per_port: bool,
+    pub dedupe: bool,
+    pub allow_gaps: bool,
+    pub group_ignore_alias: bool,
+    /// Only ranges starting at or below this port
+    pub max_port: Option<u32>,
+    pub sort: SortKey,
+    pub reverse: bool,
+    pub panel_labels: BTreeMap<String, String>,
+    pub panel_blocks: Vec<PanelBlock>,
+}
+
+/// The rows of a report
+#[derive(Debug, Default)]
+pub struct PortTable {
+    pub ranges: Vec<PortRange>,
+    /// Remarks on what the report leaves out, for `ReportInfo::notes`
+    pub notes: Vec<String>,
+}
+
+/// Filter, group and order the ports of one switch into the rows of its report.
+/// `vlan_names` resolves --vlan by name. With an `anonymizer`, aliases are
+/// replaced last, so that the filters saw the real ones.
+pub fn port_table(
+    host: &str,
+    mut ports: Vec<PortConfig>,
+    vlan_names: &HashMap<u32, String>,
+    options: &ReportOptions,
+    metadata: Option<&Metadata>,
+    warnings: &mut Warnings,
+    anonymizer: Option<&mut Anonymizer>,
+) -> Result<PortTable> {
+    let mut notes = Vec::new();
+
+    // Sort by port number to ensure ranges are contiguous
+    ports.sort_by_key(|config| config.port_num);
+
+    // Trunks span the whole switch, so infer them before any filter splits one
+    if options.infer_trunks {
+        infer_trunks(&mut ports, &options.no_infer_trunk_ports);
+    }
+
+    // Restrict the report to the requested ports
+    if let Some(PortList(requested)) = &options.ports {
+        let missing: Vec<u32> = requested.iter()
+            .copied()
+            .filter(|&port| !ports.iter().any(|p| p.port_num == port))
+            .collect();
+        if !missing.is_empty() {
+            warnings.push("requested-port-missing", missing, None, "requested by --ports but not found on the device");
+        }
+        ports.retain(|p| requested.contains(&p.port_num));
+        notes.push(format!("Report limited to {} selected port(s) by --ports", ports.len()));
+    }
+
+    // Drop explicitly excluded ports
+    if let Some(PortList(excluded)) = &options.exclude_ports {
+        let before = ports.len();
+        ports.retain(|p| !excluded.contains(&p.port_num));
+        let removed = before - ports.len();
+        if removed > 0 {
+            notes.push(format!("{} port(s) excluded by --exclude-ports", removed));
+        }
+    }
+
+    // Filter on the resolved alias. Ports without an alias never match a regex.
+    if let Some(regex) = &options.alias_filter {
+        ports.retain(|p| {
+            p.alias.as_deref()
+                .filter(|alias| !alias.is_empty())
+                .is_some_and(|alias| regex.is_match(alias) != options.alias_filter_invert)
+        });
+        let verb = if options.alias_filter_invert { "not matching" } else { "matching" };
+        // The regex may well name what the aliases say, so anonymized reports leave it out
+        if anonymizer.is_some() {
+            notes.push(format!("Report limited to ports with an alias {} --alias-filter", verb));
+        } else {
+            notes.push(format!("Report limited to ports with an alias {} /{}/", verb, regex.as_str()));
+        }
+    } else if options.unaliased_only {
+        ports.retain(|p| p.alias.as_deref().unwrap_or_default().is_empty());
+        notes.push("Report limited to ports without an alias by --unaliased-only".to_string());
+    }
+
+    // Drop unlabelled ports before grouping so aliased islands stay separate
+    if options.only_aliased {
+        let before = ports.len();
+        ports.retain(|p| !p.alias.as_deref().unwrap_or_default().is_empty());
+        let removed = before - ports.len();
+        if removed > 0 {
+            notes.push(format!("{} port(s) without an alias hidden by --only-aliased", removed));
+        }
+    }
+
+    // Hide ports that were never configured
+    if options.hide_default {
+        let before = ports.len();
+        ports.retain(|p| !is_default_config(p, options.default_vlan));
+        let removed = before - ports.len();
+        if removed > 0 {
+            notes.push(format!("{} port(s) with default configuration (VLAN {}) hidden by --hide-default",
+                removed, options.default_vlan));
+        }
+    }
+
+    // Restrict the report to ports carrying the requested VLANs
+    if !options.vlans.is_empty() {
+        let vlans = options.vlans.iter()
+            .map(|arg| resolve_vlan(arg, vlan_names))
+            .collect::<Result<HashSet<u32>, String>>()
+            .map_err(Error::InvalidArgument)?;
+        ports.retain(|p| {
+            !p.vlan_memberships.is_disjoint(&vlans) || !p.untagged_vlans.is_disjoint(&vlans)
+        });
+        let mut vlans: Vec<u32> = vlans.into_iter().collect();
+        vlans.sort_unstable();
+        let vlans: Vec<String> = vlans.iter().map(|v| v.to_string()).collect();
+        notes.push(format!("Report limited to ports carrying VLAN {} by --vlan", vlans.join(", ")));
+    }
+
+    // Group ports with identical configuration into ranges, unless one row per port was requested
+    let grouping = Grouping {
+        mode: if options.per_port {
+            GroupMode::PerPort
+        } else if options.dedupe {
+            GroupMode::Dedupe
+        } else {
+            GroupMode::Adjacent
+        },
+        allow_gaps: options.allow_gaps,
+        ignore_alias: options.group_ignore_alias,
+    };
+    let mut ranges = grouping.group(&ports);
+
+    // Join metadata onto the finished ranges; it never splits a range
+    if let Some(metadata) = metadata {
+        for range in &mut ranges {
+            for port in range.ports() {
+                if let Some(entry) = metadata.ports.get(&port) {
+                    if !entry.comment.is_empty() {
+                        range.comments.push((port, entry.comment.clone()));
+                    }
+                    if let Some(location) = &entry.location {
+                        range.locations.push((port, location.clone()));
+                    }
+                }
+            }
+        }
+    }
+
+    // Indices and admin status are only collected when a column or check needs them
+    let indices: HashMap<u32, PortIndices> = ports.iter()
+        .filter_map(|config| config.indices.map(|indices| (config.port_num, indices)))
+        .collect();
+    let down: HashSet<u32> = ports.iter()
+        .filter(|config| config.admin_down)
+        .map(|config| config.port_num)
+        .collect();
+    for range in &mut ranges {
+        range.indices = range.ports().into_iter()
+            .filter_map(|port| indices.get(&port).map(|&indices| (port, indices)))
+            .collect();
+        range.admin_down = range.ports().into_iter().filter(|port| down.contains(port)).collect();
+        range.panel_labels = range.ports().into_iter()
+            .filter_map(|port| panel_label(port, &options.panel_labels, &options.panel_blocks).map(|label| (port, label)))
+            .collect();
+    }
+
+    // Apply the port limit once here so every output format agrees
+    if let Some(max_port) = options.max_port {
+        let before = ranges.len();
+        ranges.retain(|range| range.first_port <= max_port);
+        let suppressed = before - ranges.len();
+        if suppressed > 0 {
+            let note = format!("{} port range(s) above port {} suppressed by --max-port", suppressed, max_port);
+            Event::info("note", note.clone()).host(host, false).emit();
+            notes.push(note);
+        }
+    }
+
+    if options.sort != SortKey::Port || options.reverse {
+        sort_ranges(&mut ranges, options.sort, options.reverse);
+    }
+
+    // Pseudonyms go in last so that the filters saw the real aliases
+    if let Some(anonymizer) = anonymizer {
+        anonymizer.ranges(&mut ranges);
+    }
+
+    Ok(PortTable { ranges, notes })
+}
+
+/// Flag runs of adjacent ports with the same multi-VLAN configuration as
+/// trunks the LAG MIB doesn't tell us about. Real LACP data always wins.
+/// `ports` must be sorted by port number.
+fn infer_trunks(ports: &mut [PortConfig], excluded: &[u32]) {
+    let is_candidate = |p: &PortConfig| {
+        p.lacp_info.is_none() && p.vlan_memberships.len() > 1 && !excluded.contains(&p.port_num)
+    };
+    let same_trunk = |a: &PortConfig, b: &PortConfig| {
+        b.port_num == a.port_num + 1 &&
+        a.pvid == b.pvid &&
+        a.vlan_memberships == b.vlan_memberships &&
+        a.untagged_vlans == b.untagged_vlans &&
+        a.alias == b.alias
+    };
+
+    let mut start = 0;
+    while start < ports.len() {
+        let mut end = start;
+        if is_candidate(&ports[start]) {
+            while end + 1 < ports.len()
+                && is_candidate(&ports[end + 1])
+                && same_trunk(&ports[end], &ports[end + 1]) {
+                end += 1;
+            }
+            if end > start {
+                for port in &mut ports[start..=end] {
+                    port.inferred_trunk = true;
+                }
+            }
+        }
+        start = end + 1;
+    }
+}
+
+/// Sort ranges as units. Ties keep port order, and ports without an alias
+/// always sort last when sorting by alias.
+pub fn sort_ranges(ranges: &mut [PortRange], key: SortKey, reverse: bool) {
+    let vlan_count = |r: &PortRange| r.vlan_memberships.union(&r.untagged_vlans).count();
+    ranges.sort_by(|a, b| {
+        let ordering = match key {
+            SortKey::Port => a.first_port.cmp(&b.first_port),
+            SortKey::Alias => {
+                let a_alias = a.alias.as_deref().unwrap_or_default().to_lowercase();
+                let b_alias = b.alias.as_deref().unwrap_or_default().to_lowercase();
+                match (a_alias.is_empty(), b_alias.is_empty()) {
+                    (true, false) => return std::cmp::Ordering::Greater,
+                    (false, true) => return std::cmp::Ordering::Less,
+                    _ => a_alias.cmp(&b_alias),
+                }
+            }
+            SortKey::Pvid => a.pvid.cmp(&b.pvid),
+            SortKey::VlanCount => vlan_count(a).cmp(&vlan_count(b)),
+        };
+        let ordering = if reverse { ordering.reverse() } else { ordering };
+        ordering.then(a.first_port.cmp(&b.first_port))
+    });
+}
+
+/// Resolve a --vlan argument given as an id or a (case-insensitive) VLAN name
+pub fn resolve_vlan(arg: &str, vlan_names: &HashMap<u32, String>) -> Result<u32, String> {
+    if let Ok(vlan_id) = arg.parse::<u32>() {
+        return Ok(vlan_id);
+    }
+    let mut matches: Vec<u32> = vlan_names.iter()
+        .filter(|(_, name)| name.eq_ignore_ascii_case(arg))
+        .map(|(&vlan_id, _)| vlan_id)
+        .collect();
+    matches.sort_unstable();
+    match matches.as_slice() {
+        [] => Err(format!("Unknown VLAN name '{}'", arg)),
+        [vlan_id] => Ok(*vlan_id),
+        _ => Err(format!("Ambiguous VLAN name '{}' matches VLANs {}", arg,
+            matches.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(", "))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LacpInfo;
+
+    /// Ports 1-4: access ports around two identical trunks on 2 and 3
+    fn ports() -> Vec<PortConfig> {
+        let trunk = |port_num| PortConfig {
+            port_num,
+            alias: Some("uplink".to_string()),
+            pvid: Some(1),
+            vlan_memberships: HashSet::from([1, 10, 20]),
+            untagged_vlans: HashSet::from([1]),
+            ..PortConfig::default()
+        };
+        let access = |port_num| PortConfig {
+            port_num,
+            pvid: Some(10),
+            vlan_memberships: HashSet::from([10]),
+            untagged_vlans: HashSet::from([10]),
+            ..PortConfig::default()
+        };
+        vec![access(1), trunk(2), trunk(3), access(4)]
+    }
+
+    /// Ports of the table that were flagged as an inferred trunk
+    fn inferred(ports: Vec<PortConfig>, options: &ReportOptions) -> Vec<u32> {
+        let table = port_table("10.1.0.50", ports, &HashMap::new(), options, None, &mut Warnings::default(), None).unwrap();
+        table.ranges.iter()
+            .filter(|range| range.inferred_trunk)
+            .flat_map(|range| range.ports())
+            .collect()
+    }
+
+    /// Ports with an alias, without one and with an empty one
+    fn aliased_ports() -> Vec<PortConfig> {
+        [(1, Some("Studio A")), (2, None), (3, Some("")), (4, Some("office")), (5, Some("studio B"))].into_iter()
+            .map(|(port_num, alias)| PortConfig { port_num, alias: alias.map(str::to_string), ..PortConfig::default() })
+            .collect()
+    }
+
+    /// Ports the report shows, one row each, and its notes
+    fn shown(options: ReportOptions) -> (Vec<u32>, Vec<String>) {
+        let options = ReportOptions { per_port: true, ..options };
+        let table = port_table("10.1.0.50", aliased_ports(), &HashMap::new(), &options, None, &mut Warnings::default(), None)
+            .unwrap();
+        (table.ranges.iter().map(|range| range.first_port).collect(), table.notes)
+    }
+
+    #[test]
+    fn alias_filters_never_match_ports_without_an_alias() {
+        let studio = Regex::new("(?i)^studio").unwrap();
+        let (ports, notes) = shown(ReportOptions { alias_filter: Some(studio.clone()), ..ReportOptions::default() });
+        assert_eq!(ports, [1, 5]);
+        assert_eq!(notes, ["Report limited to ports with an alias matching /(?i)^studio/"]);
+        // Inverted, the unaliased ports still stay out
+        let (ports, notes) = shown(ReportOptions { alias_filter: Some(studio), alias_filter_invert: true, ..ReportOptions::default() });
+        assert_eq!(ports, [4]);
+        assert_eq!(notes, ["Report limited to ports with an alias not matching /(?i)^studio/"]);
+        // Even a regex that matches anything
+        let (ports, _) = shown(ReportOptions { alias_filter: Some(Regex::new("").unwrap()), ..ReportOptions::default() });
+        assert_eq!(ports, [1, 4, 5]);
+    }
+
+    #[test]
+    fn anonymized_notes_leave_the_alias_filter_out() {
+        let options = ReportOptions {
+            alias_filter: Some(Regex::new("Studio").unwrap()),
+            per_port: true,
+            ..ReportOptions::default()
+        };
+        let mut anonymizer = Anonymizer::default();
+        let table = port_table("10.1.0.50", aliased_ports(), &HashMap::new(), &options, None, &mut Warnings::default(),
+            Some(&mut anonymizer)).unwrap();
+        assert_eq!(table.notes, ["Report limited to ports with an alias matching --alias-filter"]);
+        assert_eq!(table.ranges[0].alias.as_deref(), Some("port-1-label"));
+    }
+
+    #[test]
+    fn unaliased_only_keeps_ports_with_no_or_an_empty_alias() {
+        let (ports, notes) = shown(ReportOptions { unaliased_only: true, ..ReportOptions::default() });
+        assert_eq!(ports, [2, 3]);
+        assert_eq!(notes, ["Report limited to ports without an alias by --unaliased-only"]);
+    }
+
+    #[test]
+    fn only_aliased_hides_ports_with_no_or_an_empty_alias() {
+        let (ports, notes) = shown(ReportOptions { only_aliased: true, ..ReportOptions::default() });
+        assert_eq!(ports, [1, 4, 5]);
+        assert_eq!(notes, ["2 port(s) without an alias hidden by --only-aliased"]);
+
+        // The hidden ports still split the rows around them
+        let ports: Vec<PortConfig> = (1..=3)
+            .map(|port_num| PortConfig { port_num, alias: (port_num != 2).then(|| "desk".to_string()), ..PortConfig::default() })
+            .collect();
+        let options = ReportOptions { only_aliased: true, ..ReportOptions::default() };
+        let table = port_table("10.1.0.50", ports, &HashMap::new(), &options, None, &mut Warnings::default(), None).unwrap();
+        assert_eq!(table.ranges.iter().map(|range| (range.first_port, range.last_port)).collect::<Vec<_>>(), [(1, 1), (3, 3)]);
+    }
+
+    #[test]
+    fn trunks_are_inferred_before_the_filters() {
+        let options = ReportOptions { infer_trunks: true, per_port: true, ..ReportOptions::default() };
+        assert_eq!(inferred(ports(), &options), [2, 3]);
+        assert_eq!(inferred(ports(), &ReportOptions { per_port: true, ..ReportOptions::default() }), [0; 0]);
+        // Port 3 alone is still half of a trunk
+        let only_3 = ReportOptions { ports: Some(PortList([3].into())), ..options };
+        assert_eq!(inferred(ports(), &only_3), [3]);
+    }
+
+    #[test]
+    fn no_infer_trunk_ports_are_left_alone() {
+        let options = ReportOptions { infer_trunks: true, no_infer_trunk_ports: vec![3], ..ReportOptions::default() };
+        assert_eq!(inferred(ports(), &options), [0; 0]);
+    }
+
+    #[test]
+    fn real_lacp_data_always_wins() {
+        let mut ports = ports();
+        ports[3] = PortConfig { port_num: 4, ..ports[2].clone() };
+        ports[1].lacp_info = Some(LacpInfo { selected_agg_id: 49, agg_name: Some("Trk1".to_string()), agg_vlans: None,
+            load_balance: None });
+        let options = ReportOptions { infer_trunks: true, ..ReportOptions::default() };
+        assert_eq!(inferred(ports, &options), [3, 4]);
+    }
+
+    /// Sort one-port ranges given as (port, alias, PVID); returns the ports in their new order
+    fn sorted(ranges: &[(u32, &str, Option<u32>)], key: SortKey, reverse: bool) -> Vec<u32> {
+        let mut ranges: Vec<PortRange> = ranges.iter()
+            .map(|&(port, alias, pvid)| PortRange {
+                first_port: port,
+                last_port: port,
+                alias: Some(alias.to_string()).filter(|alias| !alias.is_empty()),
+                pvid,
+                ..PortRange::default()
+            })
+            .collect();
+        sort_ranges(&mut ranges, key, reverse);
+        ranges.iter().map(|range| range.first_port).collect()
+    }
+
+    #[test]
+    fn sort_keys_are_parsed_like_the_other_choices() {
+        assert_eq!(<SortKey as ValueEnum>::from_str("vlan-count", false), Ok(SortKey::VlanCount));
+        assert_eq!(<SortKey as ValueEnum>::from_str("Alias", true), Ok(SortKey::Alias));
+        assert_eq!(SortKey::VlanCount.to_string(), "vlan-count");
+    }
+
+    #[test]
+    fn aliases_sort_case_insensitively_with_the_empty_ones_last() {
+        let ranges = [(1, "studio", None), (2, "", None), (3, "Booth", None), (4, "archive", None)];
+        assert_eq!(sorted(&ranges, SortKey::Alias, false), [4, 3, 1, 2]);
+        assert_eq!(sorted(&ranges, SortKey::Alias, true), [1, 3, 4, 2]);
+    }
+
+    #[test]
+    fn ties_keep_port_order() {
+        let ranges = [(3, "desk", Some(10)), (1, "Desk", Some(10)), (2, "desk", Some(20))];
+        assert_eq!(sorted(&ranges, SortKey::Alias, false), [1, 2, 3]);
+        assert_eq!(sorted(&ranges, SortKey::Pvid, false), [1, 3, 2]);
+        assert_eq!(sorted(&ranges, SortKey::Pvid, true), [2, 1, 3]);
+    }
+
+    #[test]
+    fn unknown_pvids_sort_before_known_ones() {
+        let ranges = [(1, "", Some(20)), (2, "", None), (3, "", Some(10)), (4, "", None)];
+        assert_eq!(sorted(&ranges, SortKey::Pvid, false), [2, 4, 3, 1]);
+        assert_eq!(sorted(&ranges, SortKey::Pvid, true), [1, 3, 2, 4]);
+    }
+
+    #[test]
+    fn vlans_are_given_by_id_or_name() {
+        let names = HashMap::from([(10, "Office".to_string()), (20, "voice".to_string()), (21, "VOICE".to_string())]);
+        assert_eq!(resolve_vlan("531", &names), Ok(531));
+        assert_eq!(resolve_vlan("office", &names), Ok(10));
+        assert_eq!(resolve_vlan("guest", &names), Err("Unknown VLAN name 'guest'".to_string()));
+        assert_eq!(resolve_vlan("Voice", &names), Err("Ambiguous VLAN name 'Voice' matches VLANs 20, 21".to_string()));
+    }
+}
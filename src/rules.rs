@@ -0,0 +1,289 @@
+//! Row-highlighting rules from the config file's `[[highlight_rules]]`, e.g.
+//! `when = "alias is-empty and status == up"`. A condition is clauses joined
+//! by `and`, each a field, an operator and, for most operators, a value.
+use std::collections::BTreeSet;
+use std::str::FromStr;
+use regex::{Regex, RegexBuilder};
+use crate::{PortList, PortRange};
+
+/// Fields a clause can test, with the kind of value each has
+const FIELDS: &str = "port, alias, pvid, tagged, untagged, vlans, lacp, uplink, status";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Port,
+    Alias,
+    Pvid,
+    Tagged,
+    Untagged,
+    Vlans,
+    /// The aggregator name, empty on ports outside a LAG
+    Lacp,
+    Uplink,
+    /// Admin status: "up", "down", or "mixed" for a range with both
+    Status,
+}
+
+/// How a field's value is compared
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Text,
+    Number,
+    Set,
+    Bool,
+}
+
+impl Field {
+    fn kind(self) -> Kind {
+        match self {
+            Field::Alias | Field::Lacp | Field::Status => Kind::Text,
+            Field::Pvid => Kind::Number,
+            Field::Port | Field::Tagged | Field::Untagged | Field::Vlans => Kind::Set,
+            Field::Uplink => Kind::Bool,
+        }
+    }
+}
+
+impl FromStr for Field {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "port" => Ok(Field::Port),
+            "alias" => Ok(Field::Alias),
+            "pvid" => Ok(Field::Pvid),
+            "tagged" => Ok(Field::Tagged),
+            "untagged" => Ok(Field::Untagged),
+            "vlans" => Ok(Field::Vlans),
+            "lacp" => Ok(Field::Lacp),
+            "uplink" => Ok(Field::Uplink),
+            "status" => Ok(Field::Status),
+            _ => Err(format!("unknown field '{}' (expected one of {})", s, FIELDS)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Test {
+    Equals(String),
+    NotEquals(String),
+    /// Substring of text; every listed id for sets
+    Contains(String),
+    Matches(Regex),
+    /// None of the listed ids
+    Excludes(BTreeSet<u32>),
+    /// Every member of the set is listed, and there is one
+    In(BTreeSet<u32>),
+    Empty,
+    NotEmpty,
+}
+
+#[derive(Debug, Clone)]
+struct Clause {
+    field: Field,
+    test: Test,
+}
+
+/// A rule that gives matching rows the CSS class `rule-{name}`
+#[derive(Debug, Clone)]
+pub struct HighlightRule {
+    pub name: String,
+    /// CSS background colour of matching rows
+    pub color: String,
+    clauses: Vec<Clause>,
+}
+
+/// "10,20-22" as a set of ids
+fn id_list(value: &str) -> Result<BTreeSet<u32>, String> {
+    value.parse::<PortList>().map(|PortList(ids)| ids)
+}
+
+/// `/pattern/flags`, where the only flag is `i`, or a bare pattern
+fn regex(value: &str) -> Result<Regex, String> {
+    let (pattern, case_insensitive) = match value.strip_prefix('/').and_then(|rest| rest.rsplit_once('/')) {
+        Some((pattern, "")) => (pattern, false),
+        Some((pattern, "i")) => (pattern, true),
+        Some((_, flags)) => return Err(format!("unknown regex flags '{}' (only i is supported)", flags)),
+        None => (value, false),
+    };
+    RegexBuilder::new(pattern).case_insensitive(case_insensitive).build()
+        .map_err(|e| format!("invalid regex '{}': {}", pattern, e))
+}
+
+impl FromStr for Clause {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.trim().splitn(3, ' ');
+        let field: Field = words.next().filter(|word| !word.is_empty()).ok_or("empty condition")?.parse()?;
+        let operator = words.next().ok_or_else(|| format!("missing operator after '{}'", s.trim()))?;
+        let value = words.next().map(str::trim).unwrap_or_default();
+        let kind = field.kind();
+        let needs_value = !matches!(operator, "is-empty" | "not-empty");
+        if needs_value && value.is_empty() {
+            return Err(format!("missing value after '{}'", s.trim()));
+        }
+        if !needs_value && !value.is_empty() {
+            return Err(format!("'{}' takes no value, got '{}'", operator, value));
+        }
+        let test = match (operator, kind) {
+            ("is-empty", Kind::Text | Kind::Number | Kind::Set) => Test::Empty,
+            ("not-empty", Kind::Text | Kind::Number | Kind::Set) => Test::NotEmpty,
+            ("==" | "!=", Kind::Number) => {
+                value.parse::<u32>().map_err(|_| format!("'{}' is not a number", value))?;
+                if operator == "==" { Test::Equals(value.to_string()) } else { Test::NotEquals(value.to_string()) }
+            }
+            ("==" | "!=", Kind::Bool) if !matches!(value, "true" | "false") => {
+                return Err(format!("'{}' is not true or false", value));
+            }
+            ("==", Kind::Text | Kind::Bool) => Test::Equals(value.to_string()),
+            ("!=", Kind::Text | Kind::Bool) => Test::NotEquals(value.to_string()),
+            ("contains", Kind::Text) => Test::Contains(value.to_string()),
+            ("contains", Kind::Set) => {
+                id_list(value)?;
+                Test::Contains(value.to_string())
+            }
+            ("matches", Kind::Text) => Test::Matches(regex(value)?),
+            ("excludes", Kind::Set) => Test::Excludes(id_list(value)?),
+            ("in", Kind::Set) => Test::In(id_list(value)?),
+            ("==" | "!=" | "contains" | "matches" | "excludes" | "in" | "is-empty" | "not-empty", _) => {
+                return Err(format!("operator '{}' does not apply to {}", operator, s.split(' ').next().unwrap_or_default()));
+            }
+            _ => return Err(format!("unknown operator '{}' (expected one of ==, !=, contains, excludes, in, matches, is-empty, not-empty)", operator)),
+        };
+        Ok(Clause { field, test })
+    }
+}
+
+/// A field's value for one range
+enum Value {
+    Text(Option<String>),
+    Number(Option<u32>),
+    Set(BTreeSet<u32>),
+    Bool(bool),
+}
+
+impl Clause {
+    fn value(&self, range: &PortRange) -> Value {
+        match self.field {
+            Field::Port => Value::Set(range.ports().into_iter().collect()),
+            Field::Alias => Value::Text(range.alias.clone().filter(|alias| !alias.is_empty())),
+            Field::Pvid => Value::Number(range.pvid),
+            Field::Tagged => Value::Set(range.vlan_memberships.difference(&range.untagged_vlans).copied().collect()),
+            Field::Untagged => Value::Set(range.untagged_vlans.iter().copied().collect()),
+            Field::Vlans => Value::Set(range.vlan_memberships.union(&range.untagged_vlans).copied().collect()),
+            Field::Lacp => Value::Text(range.lacp_info.as_ref().map(|lacp| lacp.agg_name.clone().unwrap_or_default())),
+            Field::Uplink => Value::Bool(range.uplink),
+            Field::Status => {
+                let status = match range.admin_down.len() {
+                    0 => "up",
+                    down if down == range.ports().len() => "down",
+                    _ => "mixed",
+                };
+                Value::Text(Some(status.to_string()))
+            }
+        }
+    }
+
+    fn matches(&self, range: &PortRange) -> bool {
+        match (self.value(range), &self.test) {
+            (Value::Text(text), Test::Empty) => text.is_none(),
+            (Value::Text(text), Test::NotEmpty) => text.is_some(),
+            (Value::Text(text), Test::Equals(value)) => text.as_deref() == Some(value.as_str()),
+            (Value::Text(text), Test::NotEquals(value)) => text.as_deref() != Some(value.as_str()),
+            (Value::Text(text), Test::Contains(value)) => text.is_some_and(|text| text.contains(value.as_str())),
+            (Value::Text(text), Test::Matches(regex)) => text.is_some_and(|text| regex.is_match(&text)),
+            (Value::Number(number), Test::Empty) => number.is_none(),
+            (Value::Number(number), Test::NotEmpty) => number.is_some(),
+            (Value::Number(number), Test::Equals(value)) => number.map(|n| n.to_string()).as_ref() == Some(value),
+            (Value::Number(number), Test::NotEquals(value)) => number.map(|n| n.to_string()).as_ref() != Some(value),
+            (Value::Set(set), Test::Empty) => set.is_empty(),
+            (Value::Set(set), Test::NotEmpty) => !set.is_empty(),
+            (Value::Set(set), Test::Contains(value)) => id_list(value).is_ok_and(|ids| ids.is_subset(&set)),
+            (Value::Set(set), Test::Excludes(ids)) => set.is_disjoint(ids),
+            (Value::Set(set), Test::In(ids)) => !set.is_empty() && set.is_subset(ids),
+            (Value::Bool(flag), Test::Equals(value)) => flag.to_string() == *value,
+            (Value::Bool(flag), Test::NotEquals(value)) => flag.to_string() != *value,
+            // Combinations parsing rejects
+            _ => false,
+        }
+    }
+}
+
+impl HighlightRule {
+    /// Parse a rule, checking its name and colour too, as both end up in the style sheet
+    pub fn parse(name: &str, when: &str, color: &str) -> Result<HighlightRule, String> {
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            return Err(format!("name '{}' may only contain letters, digits, '-' and '_'", name));
+        }
+        let is_hex = color.strip_prefix('#')
+            .is_some_and(|hex| matches!(hex.len(), 3 | 6) && hex.chars().all(|c| c.is_ascii_hexdigit()));
+        if !is_hex && (color.is_empty() || !color.chars().all(|c| c.is_ascii_alphabetic())) {
+            return Err(format!("color '{}' is neither #rgb, #rrggbb nor a CSS colour name", color));
+        }
+        let clauses = when.split(" and ").map(str::parse).collect::<Result<Vec<Clause>, String>>()?;
+        Ok(HighlightRule { name: name.to_string(), color: color.to_string(), clauses })
+    }
+
+    /// Whether every clause holds for `range`
+    pub fn matches(&self, range: &PortRange) -> bool {
+        self.clauses.iter().all(|clause| clause.matches(range))
+    }
+
+    /// Whether the rule looks at the admin status, which is only walked when needed
+    pub fn uses_status(&self) -> bool {
+        self.clauses.iter().any(|clause| clause.field == Field::Status)
+    }
+
+    /// CSS class of matching rows
+    pub fn class(&self) -> String {
+        format!("rule-{}", self.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(port: u32, alias: Option<&str>, untagged: &[u32], tagged: &[u32]) -> PortRange {
+        PortRange {
+            first_port: port,
+            last_port: port,
+            alias: alias.map(String::from),
+            pvid: untagged.first().copied(),
+            vlan_memberships: untagged.iter().chain(tagged).copied().collect(),
+            untagged_vlans: untagged.iter().copied().collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn clauses_test_fields_of_the_range() {
+        let rule = |when: &str| HighlightRule::parse("r", when, "red").unwrap();
+        let trunk = range(5, Some("Temp uplink"), &[1], &[10, 666]);
+        assert!(rule("tagged contains 666").matches(&trunk));
+        assert!(!rule("tagged contains 20,666").matches(&trunk));
+        assert!(rule("alias matches /temp/i").matches(&trunk));
+        assert!(!rule("alias matches /temp/").matches(&trunk));
+        assert!(rule("port in 1-8 and pvid == 1 and untagged excludes 10").matches(&trunk));
+        assert!(rule("lacp is-empty and uplink == false").matches(&trunk));
+
+        let mut unnamed = range(3, None, &[10], &[]);
+        assert!(rule("alias is-empty and status == up").matches(&unnamed));
+        unnamed.admin_down = vec![3];
+        assert!(rule("status == down").matches(&unnamed));
+    }
+
+    #[test]
+    fn invalid_rules_say_why() {
+        let error = |name: &str, when: &str, color: &str| HighlightRule::parse(name, when, color).unwrap_err();
+        assert!(error("r", "tagd contains 1", "red").starts_with("unknown field 'tagd'"));
+        assert_eq!(error("r", "uplink contains 1", "red"), "operator 'contains' does not apply to uplink");
+        assert_eq!(error("r", "tagged contains ten", "red"), "Invalid port number 'ten': invalid digit found in string");
+        assert_eq!(error("r", "alias", "red"), "missing operator after 'alias'");
+        assert_eq!(error("r", "pvid ~ 1", "red").split(' ').take(3).collect::<Vec<_>>(), ["unknown", "operator", "'~'"]);
+        assert!(error("r", "alias matches /a(/", "red").starts_with("invalid regex"));
+        assert!(error("red alert", "uplink == true", "red").starts_with("name 'red alert'"));
+        assert!(error("r", "uplink == true", "red; x: y").starts_with("color"));
+    }
+}
@@ -0,0 +1,36 @@
+//! `serialize_with` helpers writing VLAN sets as sorted lists, so that the
+//! JSON does not depend on hash order
+use std::collections::HashSet;
+use std::sync::Arc;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+fn sorted(set: &HashSet<u32>) -> Vec<u32> {
+    let mut sorted: Vec<u32> = set.iter().copied().collect();
+    sorted.sort_unstable();
+    sorted
+}
+
+pub fn set<S: Serializer>(set: &HashSet<u32>, serializer: S) -> Result<S::Ok, S::Error> {
+    sorted(set).serialize(serializer)
+}
+
+/// Tagged and untagged VLANs of an aggregator
+type Pair = (HashSet<u32>, HashSet<u32>);
+
+/// `LacpInfo::agg_vlans` as `{"tagged": [..], "untagged": [..]}` rather than a bare pair
+#[derive(Serialize, Deserialize)]
+struct VlanPair {
+    tagged: Vec<u32>,
+    untagged: Vec<u32>,
+}
+
+pub fn optional_pair<S: Serializer>(pair: &Option<Arc<Pair>>, serializer: S) -> Result<S::Ok, S::Error> {
+    pair.as_deref()
+        .map(|(tagged, untagged)| VlanPair { tagged: sorted(tagged), untagged: sorted(untagged) })
+        .serialize(serializer)
+}
+
+pub fn deserialize_optional_pair<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Arc<Pair>>, D::Error> {
+    Ok(Option::<VlanPair>::deserialize(deserializer)?
+        .map(|pair| Arc::new((pair.tagged.into_iter().collect(), pair.untagged.into_iter().collect()))))
+}
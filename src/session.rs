@@ -0,0 +1,220 @@
+//! SNMP sessions kept open between polls of the same agent, e.g. the cycles
+//! of --watch, together with request counts and latency for --verbose.
+//! Nothing is tuned from those yet: walks use GETNEXT with the configured timeout.
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use snmp::SyncSession;
+use crate::cancel;
+use crate::error::{Error, Result};
+use crate::snmp_utils::{create_session, SnmpSource, SnmpValue};
+
+/// What earlier polls observed of an agent, reported under --verbose
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AgentStats {
+    /// Requests answered, over all polls
+    pub requests: u64,
+    /// Smoothed round-trip time of answered requests
+    pub latency: Option<Duration>,
+}
+
+impl AgentStats {
+    fn observe(&mut self, elapsed: Duration) {
+        self.requests += 1;
+        // Weighted 7:1 towards the history, like TCP's round-trip estimate
+        self.latency = Some(match self.latency {
+            Some(latency) => (latency * 7 + elapsed) / 8,
+            None => elapsed,
+        });
+    }
+}
+
+/// One agent's idle session, if any, and its stats, which outlive sessions
+struct Agent {
+    session: Option<(SyncSession, Duration)>,
+    stats: AgentStats,
+}
+
+/// Sessions by agent address and community, shared by the threads polling
+/// several switches. A session is used by one poll at a time.
+#[derive(Default)]
+pub struct SessionPool {
+    agents: Mutex<HashMap<(String, Vec<u8>), Agent>>,
+    opened: AtomicUsize,
+}
+
+impl SessionPool {
+    pub fn new() -> SessionPool {
+        SessionPool::default()
+    }
+
+    /// Run `poll` on the agent's idle session, or on a new one when there is
+    /// none or its timeout differs. `poll` also gets what earlier polls observed.
+    ///
+    /// The session goes back into the pool only when `poll` succeeds: after an
+    /// error, a late answer may still be on its way to the socket, and the next
+    /// request would read it instead of its own.
+    pub fn with_session<T>(
+        &self,
+        agent_addr: &str,
+        community: &[u8],
+        timeout: Duration,
+        poll: impl FnOnce(&mut dyn SnmpSource, &AgentStats) -> Result<T>,
+    ) -> Result<T> {
+        let key = (agent_addr.to_string(), community.to_vec());
+        let (idle, earlier) = {
+            let mut agents = self.lock();
+            let agent = agents.entry(key.clone()).or_insert(Agent { session: None, stats: AgentStats::default() });
+            (agent.session.take().filter(|(_, idle_timeout)| *idle_timeout == timeout), agent.stats)
+        };
+        let mut session = match idle {
+            Some((session, _)) => session,
+            None => {
+                let session = create_session(agent_addr, community, timeout)?;
+                self.opened.fetch_add(1, Ordering::Relaxed);
+                session
+            }
+        };
+
+        let mut stats = earlier;
+        let result = poll(&mut Timed { session: &mut session, stats: &mut stats, agent_addr, community, timeout }, &earlier);
+
+        let mut agents = self.lock();
+        if let Some(agent) = agents.get_mut(&key) {
+            agent.stats = stats;
+            if result.is_ok() {
+                agent.session = Some((session, timeout));
+            }
+        }
+        result
+    }
+
+    /// What polls of the agent have observed so far
+    pub fn agent_stats(&self, agent_addr: &str, community: &[u8]) -> Option<AgentStats> {
+        self.lock().get(&(agent_addr.to_string(), community.to_vec())).map(|agent| agent.stats)
+    }
+
+    /// Sessions waiting to be reused
+    pub fn idle(&self) -> usize {
+        self.lock().values().filter(|agent| agent.session.is_some()).count()
+    }
+
+    /// Sessions opened so far, counting those opened again after an error
+    pub fn opened(&self) -> usize {
+        self.opened.load(Ordering::Relaxed)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<(String, Vec<u8>), Agent>> {
+        // A poll that panicked left the map itself consistent
+        self.agents.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// How long a request broken off by a signal waits for the signal's handler,
+/// which runs on a thread of its own, to have said what the signal meant
+const SIGNAL_GRACE: Duration = Duration::from_millis(50);
+
+/// A session that times the requests it answers
+struct Timed<'a> {
+    session: &'a mut SyncSession,
+    stats: &'a mut AgentStats,
+    agent_addr: &'a str,
+    community: &'a [u8],
+    timeout: Duration,
+}
+
+impl Timed<'_> {
+    /// Time `request`. Once collection is cancelled, requests are not sent and
+    /// answer None, like the end of the MIB view; so does the one in flight,
+    /// whose wait for the answer a signal broke off. Without cancellation, a
+    /// request broken off that way is sent again on a new socket, where its
+    /// late answer cannot be taken for that of the next request.
+    fn timed<T>(&mut self, mut request: impl FnMut(&mut SyncSession) -> Result<Option<T>>) -> Result<Option<T>> {
+        if cancel::is_cancelled() {
+            return Ok(None);
+        }
+        let mut started = Instant::now();
+        let mut result = request(self.session);
+        // A real timeout takes the whole timeout
+        if matches!(result, Err(Error::Timeout)) && started.elapsed() < self.timeout {
+            std::thread::sleep(SIGNAL_GRACE);
+            if cancel::is_cancelled() {
+                return Ok(None);
+            }
+            if cancel::take_signal() {
+                *self.session = create_session(self.agent_addr, self.community, self.timeout)?;
+                started = Instant::now();
+                result = request(self.session);
+            }
+        }
+        match &result {
+            Ok(_) => self.stats.observe(started.elapsed()),
+            // The answer may still come, and would be read as that of the next
+            // request, e.g. when a walk goes on after the timeout
+            Err(Error::Timeout) => {
+                if let Ok(fresh) = create_session(self.agent_addr, self.community, self.timeout) {
+                    *self.session = fresh;
+                }
+            }
+            Err(_) => {}
+        }
+        result
+    }
+}
+
+impl SnmpSource for Timed<'_> {
+    fn get(&mut self, oid: &[u32]) -> Result<Option<SnmpValue>> {
+        self.timed(|session| SnmpSource::get(session, oid))
+    }
+
+    fn get_next(&mut self, oid: &[u32]) -> Result<Option<(Vec<u32>, Option<SnmpValue>)>> {
+        self.timed(|session| session.get_next(oid))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+
+    // Nothing is sent unless a poll makes requests
+    const AGENT: &str = "127.0.0.1:9";
+
+    #[test]
+    fn sessions_are_reused_until_a_poll_fails() {
+        let pool = SessionPool::new();
+        let timeout = Duration::from_secs(1);
+        for _ in 0..3 {
+            pool.with_session(AGENT, b"public", timeout, |_, _| Ok(())).unwrap();
+        }
+        assert_eq!((pool.opened(), pool.idle()), (1, 1));
+
+        let failed: Result<()> = pool.with_session(AGENT, b"public", timeout, |_, _| Err(Error::Timeout));
+        assert!(failed.is_err());
+        assert_eq!(pool.idle(), 0);
+        pool.with_session(AGENT, b"public", timeout, |_, _| Ok(())).unwrap();
+        assert_eq!((pool.opened(), pool.idle()), (2, 1));
+    }
+
+    #[test]
+    fn sessions_are_kept_per_community_and_timeout() {
+        let pool = SessionPool::new();
+        let second = Duration::from_secs(1);
+        pool.with_session(AGENT, b"public", second, |_, _| Ok(())).unwrap();
+        pool.with_session(AGENT, b"private", second, |_, _| Ok(())).unwrap();
+        assert_eq!((pool.opened(), pool.idle()), (2, 2));
+        // A new timeout needs a new socket
+        pool.with_session(AGENT, b"public", second * 2, |_, _| Ok(())).unwrap();
+        assert_eq!((pool.opened(), pool.idle()), (3, 2));
+    }
+
+    #[test]
+    fn latency_is_smoothed() {
+        let mut stats = AgentStats::default();
+        stats.observe(Duration::from_millis(8));
+        assert_eq!(stats.latency, Some(Duration::from_millis(8)));
+        stats.observe(Duration::from_millis(16));
+        assert_eq!((stats.requests, stats.latency), (2, Some(Duration::from_millis(9))));
+    }
+}
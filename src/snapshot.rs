@@ -0,0 +1,579 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::net::Ipv4Addr;
+use std::path::Path;
+use anyhow::{Result, anyhow};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::PortConfig;
+use crate::capacity::CapacityReport;
+use crate::warnings::Warning;
+use crate::output::format_port_set;
+use crate::fingerprint::{PortFingerprint, switch_hash};
+use crate::stats::{SourceStatus, TableOutcome};
+
+/// Version written into new snapshots. Bump it whenever a field changes
+/// meaning or goes away, and teach `migrate` how to lift the old layout.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// Oldest version `load` still knows how to migrate
+const OLDEST_SUPPORTED_VERSION: u32 = 1;
+
+/// Collected state of one switch before any filtering or range grouping.
+/// Maps and sets are ordered so that the JSON is stable between runs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct Snapshot {
+    /// Layout version, raised whenever a field changes meaning or goes away
+    pub schema_version: u32,
+    pub device: Device,
+    pub vlan_names: BTreeMap<u32, String>,
+    pub ports: Vec<SnapshotPort>,
+    /// LLDP neighbors by local port, collected for --topology
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub neighbors: Vec<Neighbor>,
+    /// Link aggregation groups, collected for --lags
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lags: Vec<Lag>,
+    /// Non-physical interfaces, collected for --include-virtual
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub virtual_interfaces: Vec<VirtualInterface>,
+    /// What the report warned about; absent from snapshots of older versions
+    #[serde(default)]
+    pub warnings: Vec<Warning>,
+    /// How walking each table went, by table name, so that a blank column can be
+    /// told apart from an unsupported table; absent from snapshots of older versions
+    #[serde(default)]
+    pub sources: BTreeMap<String, SnapshotSource>,
+    /// Free and used ports, collected for --capacity
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capacity: Option<CapacityReport>,
+    /// Fingerprint of the configuration of all ports together
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config_hash: Option<String>,
+    /// What produced the snapshot; not compared by `diff`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub meta: Option<Meta>,
+}
+
+/// What came back when walking a table, like `TableOutcome`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum SourceOutcome {
+    Rows,
+    Empty,
+    Unsupported,
+    Error,
+    Interrupted,
+}
+
+/// The outcome of walking one table
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct SnapshotSource {
+    pub outcome: SourceOutcome,
+    /// Number of rows, when there were any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rows: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Times the walk went on from its last row after a timeout
+    #[serde(default)]
+    pub resumed: u32,
+}
+
+impl SnapshotSource {
+    pub fn new(source: &SourceStatus) -> SnapshotSource {
+        let (outcome, rows, error) = match &source.outcome {
+            TableOutcome::Rows(rows) => (SourceOutcome::Rows, Some(*rows), None),
+            TableOutcome::Empty => (SourceOutcome::Empty, None, None),
+            TableOutcome::Unsupported => (SourceOutcome::Unsupported, None, None),
+            TableOutcome::Error(e) => (SourceOutcome::Error, None, Some(e.clone())),
+            TableOutcome::Interrupted => (SourceOutcome::Interrupted, None, None),
+        };
+        SnapshotSource { outcome, rows, error, resumed: source.resumed }
+    }
+}
+
+/// The build and the options behind a report, for its footer
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct Meta {
+    /// Crate version
+    pub version: String,
+    /// `git describe` of the tree the binary was built from, when it was a git checkout
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git_describe: Option<String>,
+    /// How long collecting from the switch took; absent with --deterministic
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub collection_ms: Option<u64>,
+    /// Non-default options that shaped the content, e.g. "--exclude-ports 9-12".
+    /// Never credentials or URLs
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub options: Vec<String>,
+}
+
+impl Meta {
+    /// This build, with `options`
+    pub fn new(options: Vec<String>) -> Meta {
+        Meta {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            git_describe: option_env!("SWITCH_VLAN_DOC_GIT_DESCRIBE").map(String::from),
+            collection_ms: None,
+            options,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct Device {
+    pub ip: String,
+    pub sys_name: Option<String>,
+    pub sys_descr: Option<String>,
+    pub vendor: String,
+    /// lldpLocChassisId, collected for --topology; a MAC address as aa:bb:cc:dd:ee:ff
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chassis_id: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct SnapshotPort {
+    pub port: u32,
+    pub alias: Option<String>,
+    /// The alias before whitespace normalization, when that changed it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw_alias: Option<String>,
+    pub pvid: Option<u32>,
+    pub vlans: BTreeSet<u32>,
+    pub untagged: BTreeSet<u32>,
+    pub lacp: Option<SnapshotLacp>,
+    pub dot1x: Option<SnapshotDot1x>,
+    pub frame_policy: Option<SnapshotFramePolicy>,
+    pub uplink: bool,
+    pub admin_down: bool,
+    pub mac: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config_hash: Option<String>,
+    /// ifIndex and bridge port, collected for --debug-indices
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub indices: Option<SnapshotIndices>,
+    /// Patch-panel label from the config; None for ports that are not patched
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub panel: Option<String>,
+}
+
+/// A device that a port sees through LLDP (a row of lldpRemTable)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct Neighbor {
+    /// lldpRemLocalPortNum
+    pub port: u32,
+    /// lldpRemChassisId, formatted like `Device::chassis_id`
+    pub chassis_id: Option<String>,
+    /// lldpRemPortId: the neighbor's own name or number for its port
+    pub port_id: Option<String>,
+    pub sys_name: Option<String>,
+}
+
+/// Where the membership of a port in an aggregator was read from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum LagSource {
+    /// dot3adAggPortSelectedAggID
+    LagMib,
+    /// ifStackStatus, for agents without the LAG MIB
+    IfStack,
+    /// An LACP override from the command line or config file
+    Override,
+}
+
+impl fmt::Display for LagSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LagSource::LagMib => "LAG MIB",
+            LagSource::IfStack => "ifStackTable",
+            LagSource::Override => "override",
+        })
+    }
+}
+
+/// An aggregator interface and the ports that are its members
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct Lag {
+    /// ifIndex of the aggregator
+    pub index: u32,
+    pub name: Option<String>,
+    pub members: Vec<u32>,
+    pub pvid: Option<u32>,
+    /// The aggregator's own egress and untagged VLANs
+    pub vlans: BTreeSet<u32>,
+    pub untagged: BTreeSet<u32>,
+    /// Where the members' membership came from; empty when there are none
+    pub sources: Vec<LagSource>,
+    /// The aggregator exists without members, usually left over from old configuration
+    pub stale: bool,
+}
+
+/// A VLAN interface, loopback or other interface that is not a port
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct VirtualInterface {
+    /// ifIndex
+    pub index: u32,
+    /// ifName
+    pub name: Option<String>,
+    pub if_type: u32,
+    /// From ipAddrTable; empty when the agent lists none for the interface
+    pub addresses: Vec<Ipv4Addr>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct SnapshotLacp {
+    pub aggregator: u32,
+    pub name: Option<String>,
+    pub load_balance: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct SnapshotIndices {
+    pub if_index: u32,
+    /// None when dot1dBasePortIfIndex has no row for the port
+    pub bridge_port: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct SnapshotDot1x {
+    pub port_control: u32,
+    pub port_status: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct SnapshotFramePolicy {
+    pub tagged_only: bool,
+    pub ingress_filter: bool,
+}
+
+impl SnapshotPort {
+    pub fn new(port: &PortConfig) -> SnapshotPort {
+        SnapshotPort {
+            port: port.port_num,
+            alias: port.alias.clone(),
+            raw_alias: port.raw_alias.clone(),
+            pvid: port.pvid,
+            vlans: port.vlan_memberships.iter().copied().collect(),
+            untagged: port.untagged_vlans.iter().copied().collect(),
+            lacp: port.lacp_info.as_ref().map(|lacp| SnapshotLacp {
+                aggregator: lacp.selected_agg_id,
+                name: lacp.agg_name.clone(),
+                load_balance: lacp.load_balance.clone(),
+            }),
+            dot1x: port.dot1x.map(|dot1x| SnapshotDot1x {
+                port_control: dot1x.port_control,
+                port_status: dot1x.port_status,
+            }),
+            frame_policy: port.frame_policy.map(|policy| SnapshotFramePolicy {
+                tagged_only: policy.tagged_only,
+                ingress_filter: policy.ingress_filter,
+            }),
+            uplink: port.uplink,
+            admin_down: port.admin_down,
+            mac: port.mac.clone(),
+            config_hash: None,
+            indices: port.indices.map(|indices| SnapshotIndices {
+                if_index: indices.if_index,
+                bridge_port: indices.bridge_port,
+            }),
+            panel: None,
+        }
+    }
+}
+
+impl Snapshot {
+    pub fn new<'a>(device: Device, vlan_names: impl IntoIterator<Item = (&'a u32, &'a String)>,
+                   ports: &[PortConfig]) -> Snapshot {
+        let mut ports: Vec<SnapshotPort> = ports.iter().map(SnapshotPort::new).collect();
+        ports.sort_by_key(|port| port.port);
+        let mut snapshot = Snapshot {
+            schema_version: SNAPSHOT_VERSION,
+            device,
+            vlan_names: vlan_names.into_iter().map(|(&id, name)| (id, name.clone())).collect(),
+            ports,
+            neighbors: Vec::new(),
+            lags: Vec::new(),
+            virtual_interfaces: Vec::new(),
+            warnings: Vec::new(),
+            sources: BTreeMap::new(),
+            capacity: None,
+            config_hash: None,
+            meta: None,
+        };
+        snapshot.update_config_hashes();
+        snapshot
+    }
+
+    /// Recompute the port and switch fingerprints after the ports changed
+    pub fn update_config_hashes(&mut self) {
+        for port in &mut self.ports {
+            port.config_hash = Some(PortFingerprint::from(&*port).hash());
+        }
+        self.config_hash = Some(switch_hash(self.ports.iter()
+            .map(|port| (port.port, port.config_hash.as_deref().unwrap_or_default()))));
+    }
+
+    /// JSON Schema of the snapshot layout of this version, for --print-schema
+    pub fn json_schema() -> String {
+        let schema = schemars::schema_for!(Snapshot);
+        serde_json::to_string_pretty(&schema).unwrap_or_default()
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self).map_err(|e| anyhow!("Failed to serialize snapshot: {}", e))
+    }
+
+    pub fn from_json(text: &str) -> Result<Snapshot> {
+        let value: Value = serde_json::from_str(text).map_err(|e| anyhow!("Not a valid snapshot: {}", e))?;
+        let version = value.get("schema_version")
+            .and_then(Value::as_u64)
+            .ok_or_else(|| anyhow!("Not a valid snapshot: schema_version is missing"))?;
+        let value = migrate(value, version)?;
+        serde_json::from_value(value).map_err(|e| anyhow!("Not a valid snapshot: {}", e))
+    }
+
+    pub fn load(path: &Path) -> Result<Snapshot> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("Failed to read snapshot {}: {}", path.display(), e))?;
+        Snapshot::from_json(&text).map_err(|e| anyhow!("{}: {}", path.display(), e))
+    }
+
+    /// What changed going from this snapshot to `newer`
+    pub fn diff(&self, newer: &Snapshot) -> SnapshotDiff {
+        let before: BTreeMap<u32, &SnapshotPort> = self.ports.iter().map(|port| (port.port, port)).collect();
+        let after: BTreeMap<u32, &SnapshotPort> = newer.ports.iter().map(|port| (port.port, port)).collect();
+        let ports: BTreeSet<u32> = before.keys().chain(after.keys()).copied().collect();
+        // Whitespace edits to an alias leave the normalized alias, and so the report, as it was,
+        // fingerprints are missing from older snapshots and indices from runs without --debug-indices,
+        // and panel labels come from the config rather than the switch
+        let comparable = |port: Option<&&SnapshotPort>| {
+            port.map(|&port| SnapshotPort { raw_alias: None, config_hash: None, indices: None, panel: None, ..port.clone() })
+        };
+        let mut changes = BTreeMap::new();
+        for port in ports {
+            let change = match (comparable(before.get(&port)), comparable(after.get(&port))) {
+                (before, after) if before == after => continue,
+                (None, _) => PortChange::Added,
+                (Some(before), None) => PortChange::Removed(Box::new(before)),
+                (Some(before), Some(after)) => PortChange::Changed(describe_changes(&before, &after)),
+            };
+            changes.insert(port, change);
+        }
+        SnapshotDiff {
+            ports: changes,
+            vlan_names_changed: self.vlan_names != newer.vlan_names,
+        }
+    }
+}
+
+/// How one port differs from the earlier snapshot
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PortChange {
+    Added,
+    /// The port is gone; this is how it was
+    Removed(Box<SnapshotPort>),
+    /// What changed, e.g. "tagged +20" and "alias changed"
+    Changed(Vec<String>),
+}
+
+impl fmt::Display for PortChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PortChange::Added => write!(f, "new port"),
+            PortChange::Removed(_) => write!(f, "port removed"),
+            PortChange::Changed(changes) => write!(f, "{}", changes.join(", ")),
+        }
+    }
+}
+
+/// Short descriptions of what differs between two states of a port
+fn describe_changes(before: &SnapshotPort, after: &SnapshotPort) -> Vec<String> {
+    let mut changes = Vec::new();
+    if before.alias != after.alias {
+        changes.push("alias changed".to_string());
+    }
+    if before.pvid != after.pvid {
+        let pvid = |pvid: Option<u32>| pvid.map_or("unknown".to_string(), |pvid| pvid.to_string());
+        changes.push(format!("PVID {} -> {}", pvid(before.pvid), pvid(after.pvid)));
+    }
+    let tagged = |port: &SnapshotPort| -> BTreeSet<u32> { port.vlans.difference(&port.untagged).copied().collect() };
+    for (kind, before, after) in [
+        ("untagged", before.untagged.clone(), after.untagged.clone()),
+        ("tagged", tagged(before), tagged(after)),
+    ] {
+        let mut vlans: Vec<String> = after.difference(&before).map(|vlan| format!("+{}", vlan)).collect();
+        vlans.extend(before.difference(&after).map(|vlan| format!("-{}", vlan)));
+        if !vlans.is_empty() {
+            changes.push(format!("{} {}", kind, vlans.join(" ")));
+        }
+    }
+    for (changed, what) in [
+        (before.lacp != after.lacp, "LACP"),
+        (before.dot1x != after.dot1x, "802.1X"),
+        (before.frame_policy != after.frame_policy, "frame policy"),
+        (before.uplink != after.uplink, "uplink"),
+        (before.admin_down != after.admin_down, "admin status"),
+        (before.mac != after.mac, "MAC"),
+    ] {
+        if changed {
+            changes.push(format!("{} changed", what));
+        }
+    }
+    changes
+}
+
+/// A VLAN that two switches define differently
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VlanMismatch {
+    pub vlan: u32,
+    /// The name on each side, None where the VLAN is not defined
+    pub left: Option<String>,
+    pub right: Option<String>,
+}
+
+/// VLANs defined on only one side, or named differently, in ascending order
+pub fn compare_vlans(left: &BTreeMap<u32, String>, right: &BTreeMap<u32, String>) -> Vec<VlanMismatch> {
+    let vlans: BTreeSet<u32> = left.keys().chain(right.keys()).copied().collect();
+    vlans.into_iter()
+        .filter(|vlan| left.get(vlan) != right.get(vlan))
+        .map(|vlan| VlanMismatch { vlan, left: left.get(&vlan).cloned(), right: right.get(&vlan).cloned() })
+        .collect()
+}
+
+/// Differences between two snapshots of the same switch
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SnapshotDiff {
+    /// Ports whose configuration changed, appeared or disappeared
+    pub ports: BTreeMap<u32, PortChange>,
+    pub vlan_names_changed: bool,
+}
+
+impl SnapshotDiff {
+    pub fn is_empty(&self) -> bool {
+        self.ports.is_empty() && !self.vlan_names_changed
+    }
+
+    pub fn changed_ports(&self) -> Vec<u32> {
+        self.ports.keys().copied().collect()
+    }
+}
+
+impl fmt::Display for SnapshotDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        match self.changed_ports().as_slice() {
+            [] => {}
+            [port] => parts.push(format!("port {}", port)),
+            ports => parts.push(format!("ports {}", format_port_set(ports))),
+        }
+        if self.vlan_names_changed {
+            parts.push("VLAN names".to_string());
+        }
+        if parts.is_empty() {
+            write!(f, "no changes")
+        } else {
+            write!(f, "{}", parts.join(", "))
+        }
+    }
+}
+
+/// Lift a snapshot written with schema `version` to the current layout
+fn migrate(value: Value, version: u64) -> Result<Value> {
+    if version < u64::from(OLDEST_SUPPORTED_VERSION) {
+        return Err(anyhow!("Snapshot too old: schema version {} (oldest supported is {})",
+            version, OLDEST_SUPPORTED_VERSION));
+    }
+    if version > u64::from(SNAPSHOT_VERSION) {
+        return Err(anyhow!("Snapshot schema version {} is newer than this program understands ({}); \
+            upgrade switch-vlan-diagram to read it", version, SNAPSHOT_VERSION));
+    }
+    // Version 1 is the first layout; later versions add one step per bump here
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::fixture_ports;
+
+    fn fixture_snapshot() -> Snapshot {
+        let device = Device {
+            ip: "10.1.0.23".to_string(),
+            sys_name: Some("sw-studio".to_string()),
+            sys_descr: None,
+            vendor: "generic".to_string(),
+            chassis_id: None,
+        };
+        let vlan_names = BTreeMap::from([(10, "studio".to_string()), (1, "default".to_string())]);
+        let mut ports = fixture_ports(include_str!("../tests/fixtures/untagged-not-egress.walk"), 1..=4);
+        ports.reverse();
+        Snapshot::new(device, &vlan_names, &ports)
+    }
+
+    #[test]
+    fn json_is_sorted_and_round_trips() {
+        let snapshot = fixture_snapshot();
+        assert!(snapshot.ports.windows(2).all(|pair| pair[0].port < pair[1].port));
+        let json = snapshot.to_json().unwrap();
+        assert_eq!(json, fixture_snapshot().to_json().unwrap());
+        assert!(json.find("\"1\": \"default\"").unwrap() < json.find("\"10\": \"studio\"").unwrap());
+        assert_eq!(Snapshot::from_json(&json).unwrap(), snapshot);
+    }
+
+    #[test]
+    fn unsupported_versions_are_rejected_clearly() {
+        let mut value = serde_json::to_value(fixture_snapshot()).unwrap();
+        value["schema_version"] = 0.into();
+        let err = Snapshot::from_json(&value.to_string()).unwrap_err();
+        assert!(err.to_string().contains("Snapshot too old"), "{}", err);
+
+        value["schema_version"] = (SNAPSHOT_VERSION + 1).into();
+        let err = Snapshot::from_json(&value.to_string()).unwrap_err();
+        assert!(err.to_string().contains("newer"), "{}", err);
+
+        value.as_object_mut().unwrap().remove("schema_version");
+        assert!(Snapshot::from_json(&value.to_string()).is_err());
+    }
+
+    #[test]
+    fn diff_of_fixture_snapshots() {
+        let before = Snapshot::from_json(include_str!("../tests/fixtures/snapshot-before.json")).unwrap();
+        let after = Snapshot::from_json(include_str!("../tests/fixtures/snapshot-after.json")).unwrap();
+
+        let diff = before.diff(&after);
+        assert_eq!(diff.changed_ports(), vec![2, 3, 4]);
+        assert_eq!(diff.ports[&2].to_string(), "PVID 10 -> 20, untagged +20 -10");
+        assert!(matches!(&diff.ports[&3], PortChange::Removed(port) if port.alias.as_deref() == Some("Mixer")));
+        assert_eq!(diff.ports[&4].to_string(), "tagged +30");
+        assert_eq!(after.diff(&before).ports[&3], PortChange::Added);
+        assert!(diff.vlan_names_changed);
+        assert_eq!(diff.to_string(), "ports 2-4, VLAN names");
+
+        assert!(before.diff(&before).is_empty());
+        assert_eq!(after.diff(&after).to_string(), "no changes");
+
+        let mut padded = before.clone();
+        padded.ports[0].raw_alias = Some(format!("{} \r\n", padded.ports[0].alias.clone().unwrap_or_default()));
+        assert!(before.diff(&padded).is_empty());
+        let json = padded.to_json().unwrap();
+        assert!(json.contains("\"raw_alias\""));
+        assert_eq!(Snapshot::from_json(&json).unwrap(), padded);
+        assert!(!before.to_json().unwrap().contains("raw_alias"));
+    }
+
+    #[test]
+    fn vlans_are_compared_by_id_and_name() {
+        let left = BTreeMap::from([(1, "default".to_string()), (10, "studio".to_string()), (20, "office".to_string())]);
+        let right = BTreeMap::from([(1, "default".to_string()), (10, "Studio".to_string()), (30, "guest".to_string())]);
+        let name = |name: &str| Some(name.to_string());
+        assert_eq!(compare_vlans(&left, &right), vec![
+            VlanMismatch { vlan: 10, left: name("studio"), right: name("Studio") },
+            VlanMismatch { vlan: 20, left: name("office"), right: None },
+            VlanMismatch { vlan: 30, left: None, right: name("guest") },
+        ]);
+        assert!(compare_vlans(&left, &left).is_empty());
+    }
+}
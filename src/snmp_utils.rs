@@ -1,55 +1,275 @@
 use snmp::{SyncSession, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::net::Ipv4Addr;
 use std::time::Duration;
-use anyhow::{Result, anyhow};
-use std::collections::HashMap;
+use snmp::snmp::{ERRSTATUS_AUTHORIZATIONERROR, ERRSTATUS_NOACCESS};
+use crate::charset::DisplayString;
+use crate::error::{format_oid, Error, Result};
 
 pub fn create_session(agent_addr: &str, community: &[u8], timeout: Duration) -> Result<SyncSession> {
     SyncSession::new(agent_addr, community, Some(timeout), 0)
-        .map_err(|e| anyhow!("Failed to create SNMP session: {:?}", e))
+        .map_err(|e| Error::Transport(format!("{}: {}", agent_addr, e)))
 }
 
-#[derive(Debug)]
-enum SnmpValue {
+/// An object value in the two shapes the walkers care about
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SnmpValue {
     Bytes(Vec<u8>),
     Integer(u32),
 }
 
-fn get_table_values(session: &mut SyncSession, base_oid: &[u32]) -> Result<HashMap<u32, SnmpValue>> {
-    let mut results = HashMap::new();
-    let mut current_oid = base_oid.to_vec();
-    
-    loop {
-        let mut response = session.getnext(&current_oid)
-            .map_err(|e| anyhow!("Failed to get next SNMP value: {:?}", e))?;
-
-        if let Some((oid, value)) = response.varbinds.next() {
-            let oid_str = format!("{}", oid);
-            let oid_vec = parse_oid(&oid_str);
-            
-            // Check if we're still in the same table
-            if !starts_with(&oid_vec, base_oid) {
-                break;
-            }
+impl SnmpValue {
+    /// None for types no table of ours uses, e.g. OIDs or IP addresses
+    fn from_value(value: Value) -> Option<SnmpValue> {
+        match value {
+            Value::OctetString(bytes) => Some(SnmpValue::Bytes(bytes.to_vec())),
+            Value::Integer(n) => Some(SnmpValue::Integer(n as u32)),
+            Value::Unsigned32(n) | Value::Timeticks(n) | Value::Counter32(n) => Some(SnmpValue::Integer(n)),
+            _ => None,
+        }
+    }
+}
+
+/// Where the walkers get their data from: a live agent, or a recorded walk
+pub trait SnmpSource {
+    /// GET one object. Ok(None) when the agent has no such object or its
+    /// type is not one of `SnmpValue`.
+    fn get(&mut self, oid: &[u32]) -> Result<Option<SnmpValue>>;
+
+    /// GETNEXT: the first object after `oid`, with None as the value for types
+    /// that are not one of `SnmpValue`. Ok(None) past the end of the MIB view.
+    fn get_next(&mut self, oid: &[u32]) -> Result<Option<(Vec<u32>, Option<SnmpValue>)>>;
+}
+
+impl SnmpSource for SyncSession {
+    fn get(&mut self, oid: &[u32]) -> Result<Option<SnmpValue>> {
+        let mut response = SyncSession::get(self, oid).map_err(|e| Error::from_snmp(e, oid))?;
+        if matches!(response.error_status, ERRSTATUS_AUTHORIZATIONERROR | ERRSTATUS_NOACCESS) {
+            return Err(Error::AuthFailed { oid: format_oid(oid) });
+        }
+        Ok(response.varbinds.next().and_then(|(_, value)| SnmpValue::from_value(value)))
+    }
 
-            current_oid = oid_vec.clone();
-            let value = match value {
-                Value::OctetString(bytes) => SnmpValue::Bytes(bytes.to_vec()),
-                Value::Integer(n) => SnmpValue::Integer(n as u32),
-                Value::Unsigned32(n) => SnmpValue::Integer(n),
+    fn get_next(&mut self, oid: &[u32]) -> Result<Option<(Vec<u32>, Option<SnmpValue>)>> {
+        let mut response = self.getnext(oid).map_err(|e| Error::from_snmp(e, oid))?;
+        if matches!(response.error_status, ERRSTATUS_AUTHORIZATIONERROR | ERRSTATUS_NOACCESS) {
+            return Err(Error::AuthFailed { oid: format_oid(oid) });
+        }
+        if response.error_status != 0 {
+            return Ok(None);
+        }
+        Ok(response.varbinds.next()
+            .map(|(oid, value)| (parse_oid(&format!("{}", oid)), SnmpValue::from_value(value))))
+    }
+}
+
+/// Replays `snmpwalk -On` output, for testing and offline use. Understands the
+/// INTEGER, Gauge32, Counter32, Timeticks, STRING and Hex-STRING value types.
+#[derive(Debug, Clone, Default)]
+pub struct RecordedWalk {
+    objects: BTreeMap<Vec<u32>, SnmpValue>,
+}
+
+impl RecordedWalk {
+    pub fn parse(walk: &str) -> Result<RecordedWalk> {
+        let mut objects = BTreeMap::new();
+        for (number, line) in walk.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+            let parse_error = || Error::InvalidWalk { line: number + 1, text: line.to_string() };
+            let (oid, value) = line.split_once(" = ").ok_or_else(parse_error)?;
+            let (kind, value) = value.split_once(": ").unwrap_or((value, ""));
+            let value = match kind {
+                "STRING" => SnmpValue::Bytes(value.trim_matches('"').as_bytes().to_vec()),
+                "Hex-STRING" => SnmpValue::Bytes(value.split_whitespace()
+                    .map(|byte| u8::from_str_radix(byte, 16))
+                    .collect::<Result<_, _>>()
+                    .map_err(|_| parse_error())?),
+                // Timeticks read "(12345) 0:02:03.45"
+                "Timeticks" => SnmpValue::Integer(value.trim_start_matches('(')
+                    .split(')')
+                    .next()
+                    .and_then(|ticks| ticks.parse().ok())
+                    .ok_or_else(parse_error)?),
+                // Enumerations read "ethernetCsmacd(6)"
+                "INTEGER" | "Gauge32" | "Counter32" => SnmpValue::Integer(value.rsplit_once('(')
+                    .map_or(value, |(_, number)| number.trim_end_matches(')'))
+                    .trim()
+                    .parse::<i64>()
+                    .map_err(|_| parse_error())? as u32),
                 _ => continue,
             };
-            let last_id = extract_last_id(&oid_vec);
-            results.insert(last_id as u32, value);
-        } else {
+            objects.insert(parse_oid(oid), value);
+        }
+        Ok(RecordedWalk { objects })
+    }
+}
+
+impl SnmpSource for RecordedWalk {
+    fn get(&mut self, oid: &[u32]) -> Result<Option<SnmpValue>> {
+        Ok(self.objects.get(oid).cloned())
+    }
+
+    fn get_next(&mut self, oid: &[u32]) -> Result<Option<(Vec<u32>, Option<SnmpValue>)>> {
+        use std::ops::Bound::{Excluded, Unbounded};
+        Ok(self.objects.range::<[u32], _>((Excluded(oid), Unbounded))
+            .next()
+            .map(|(oid, value)| (oid.clone(), Some(value.clone()))))
+    }
+}
+
+/// Consecutive timeouts a table walk rides out, each time asking again from
+/// the last row it got rather than from the start of the table
+const WALK_RETRIES: u32 = 2;
+
+/// What a table walk got back
+#[derive(Debug)]
+pub struct Walked<T> {
+    pub rows: T,
+    /// Times the walk went on from its last row after a timeout
+    pub resumes: u32,
+}
+
+impl<T> Walked<T> {
+    fn map<U>(self, f: impl FnOnce(T) -> U) -> Walked<U> {
+        Walked { rows: f(self.rows), resumes: self.resumes }
+    }
+}
+
+/// Objects of a walk, in the order the agent returned them
+type Objects = Vec<(Vec<u32>, SnmpValue)>;
+
+fn walk_table(session: &mut dyn SnmpSource, base_oid: &[u32]) -> Result<Walked<Objects>> {
+    let mut results = Vec::new();
+    let mut current_oid = base_oid.to_vec();
+    let mut retries = 0;
+    let mut resumes = 0;
+    let mut restarted = false;
+
+    loop {
+        let (oid, value) = match session.get_next(&current_oid) {
+            Ok(Some(next)) => next,
+            Ok(None) => break,
+            Err(Error::Timeout) if retries < WALK_RETRIES => {
+                retries += 1;
+                resumes += 1;
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        retries = 0;
+        // Check if we're still in the same table
+        if !starts_with(&oid, base_oid) {
             break;
         }
+        // GETNEXT only moves forward; going back means the table changed
+        // underneath the walk, so the rows so far may no longer hold
+        if oid <= current_oid {
+            if restarted {
+                return Err(Error::Decode { oid: format_oid(&oid), reason: format!("not after {}", format_oid(&current_oid)) });
+            }
+            restarted = true;
+            results.clear();
+            current_oid = base_oid.to_vec();
+            continue;
+        }
+        current_oid = oid.clone();
+        if let Some(value) = value {
+            results.push((oid, value));
+        }
     }
 
-    Ok(results)
+    Ok(Walked { rows: results, resumes })
 }
 
-pub fn get_u32_table(session: &mut SyncSession, base_oid: &[u32]) -> Result<HashMap<u32, u32>> {
-    Ok(get_table_values(session, base_oid)?
+fn get_table_values(session: &mut dyn SnmpSource, base_oid: &[u32]) -> Result<Walked<HashMap<u32, SnmpValue>>> {
+    Ok(walk_table(session, base_oid)?.map(|rows| rows
+        .into_iter()
+        .map(|(oid, value)| (extract_last_id(&oid) as u32, value))
+        .collect()))
+}
+
+/// Walk a whole table entry at once and split the rows by column.
+/// Returns column number -> (row index -> value).
+pub fn get_u32_columns(session: &mut dyn SnmpSource, entry_oid: &[u32]) -> Result<HashMap<u32, HashMap<u32, u32>>> {
+    let mut columns: HashMap<u32, HashMap<u32, u32>> = HashMap::new();
+    for (oid, value) in walk_table(session, entry_oid)?.rows {
+        let Some(&column) = oid.get(entry_oid.len()) else {
+            continue;
+        };
+        if let SnmpValue::Integer(n) = value {
+            columns.entry(column).or_default().insert(extract_last_id(&oid) as u32, n);
+        }
+    }
+    Ok(columns)
+}
+
+/// Walk a whole table entry and split it into rows, keyed by the full row
+/// index (e.g. lldpRemTimeMark.lldpRemLocalPortNum.lldpRemIndex).
+/// Returns row index -> (column number -> value).
+pub fn get_rows(session: &mut dyn SnmpSource, entry_oid: &[u32]) -> Result<BTreeMap<Vec<u32>, HashMap<u32, SnmpValue>>> {
+    let mut rows: BTreeMap<Vec<u32>, HashMap<u32, SnmpValue>> = BTreeMap::new();
+    for (oid, value) in walk_table(session, entry_oid)?.rows {
+        let Some((&column, index)) = oid[entry_oid.len()..].split_first() else {
+            continue;
+        };
+        rows.entry(index.to_vec()).or_default().insert(column, value);
+    }
+    Ok(rows)
+}
+
+/// Active rows of a stack table such as ifStackStatus, indexed by the higher
+/// and then the lower layer. Returns higher layer -> lower layers, ascending.
+pub fn get_stack_table(session: &mut dyn SnmpSource, base_oid: &[u32]) -> Result<Walked<HashMap<u32, Vec<u32>>>> {
+    let walked = walk_table(session, base_oid)?;
+    let mut stack: HashMap<u32, Vec<u32>> = HashMap::new();
+    for (oid, value) in walked.rows {
+        // RowStatus active(1); index 0 stands for "nothing above" or "nothing below"
+        if let ([higher, lower], SnmpValue::Integer(1)) = (&oid[base_oid.len()..], value) {
+            if *higher != 0 && *lower != 0 {
+                stack.entry(*higher).or_default().push(*lower);
+            }
+        }
+    }
+    for lowers in stack.values_mut() {
+        lowers.sort_unstable();
+    }
+    Ok(Walked { rows: stack, resumes: walked.resumes })
+}
+
+/// IPv4 addresses of ipAdEntIfIndex, indexed by the address itself.
+/// Returns ifIndex -> addresses, ascending.
+pub fn get_ip_addresses(session: &mut dyn SnmpSource, base_oid: &[u32]) -> Result<Walked<HashMap<u32, Vec<Ipv4Addr>>>> {
+    let walked = walk_table(session, base_oid)?;
+    let mut addresses: HashMap<u32, Vec<Ipv4Addr>> = HashMap::new();
+    for (oid, value) in walked.rows {
+        if let (&[a, b, c, d], SnmpValue::Integer(if_index)) = (&oid[base_oid.len()..], value) {
+            let octet = |n: u32| u8::try_from(n).ok();
+            if let (Some(a), Some(b), Some(c), Some(d)) = (octet(a), octet(b), octet(c), octet(d)) {
+                addresses.entry(if_index).or_default().push(Ipv4Addr::new(a, b, c, d));
+            }
+        }
+    }
+    for list in addresses.values_mut() {
+        list.sort_unstable();
+    }
+    Ok(Walked { rows: addresses, resumes: walked.resumes })
+}
+
+pub fn get_u32_scalar(session: &mut dyn SnmpSource, oid: &[u32]) -> Result<Option<u32>> {
+    Ok(match session.get(oid)? {
+        Some(SnmpValue::Integer(n)) => Some(n),
+        _ => None,
+    })
+}
+
+pub fn get_string_scalar(session: &mut dyn SnmpSource, oid: &[u32]) -> Result<Option<String>> {
+    Ok(match session.get(oid)? {
+        Some(SnmpValue::Bytes(v)) => Some(String::from_utf8_lossy(&v).to_string()),
+        _ => None,
+    })
+}
+
+pub fn get_u32_table(session: &mut dyn SnmpSource, base_oid: &[u32]) -> Result<Walked<HashMap<u32, u32>>> {
+    Ok(get_table_values(session, base_oid)?.map(|rows| rows
         .into_iter()
         .map(|(k, v)| match v {
             SnmpValue::Integer(n) => (k, n),
@@ -59,27 +279,48 @@ pub fn get_u32_table(session: &mut SyncSession, base_oid: &[u32]) -> Result<Hash
                 0
             }),
         })
-        .collect())
+        .collect()))
 }
 
-pub fn get_string_table(session: &mut SyncSession, base_oid: &[u32]) -> Result<HashMap<u32, String>> {
-    get_table_values(session, base_oid)?
+/// DisplayString columns, undecoded; see `DisplayString::decode`
+pub fn get_string_table(session: &mut dyn SnmpSource, base_oid: &[u32]) -> Result<Walked<HashMap<u32, DisplayString>>> {
+    let walked = get_table_values(session, base_oid)?;
+    let rows = walked.rows
         .into_iter()
         .map(|(k, v)| match v {
-            SnmpValue::Bytes(v) => Ok((k, String::from_utf8_lossy(&v).to_string())),
-            SnmpValue::Integer(_) => Err(anyhow!("Expected string (OctetString) value but got integer")),
+            SnmpValue::Bytes(v) => Ok((k, DisplayString(v))),
+            SnmpValue::Integer(_) => Err(Error::Decode {
+                oid: format_oid(base_oid),
+                reason: "expected a string (OctetString) but got an integer".to_string(),
+            }),
         })
-        .collect::<Result<HashMap<u32, String>>>()
+        .collect::<Result<HashMap<u32, DisplayString>>>()?;
+    Ok(Walked { rows, resumes: walked.resumes })
 }
 
-pub fn get_raw_table(session: &mut SyncSession, base_oid: &[u32]) -> Result<HashMap<u32, Vec<u8>>> {
-    Ok(get_table_values(session, base_oid)?
+pub fn get_raw_table(session: &mut dyn SnmpSource, base_oid: &[u32]) -> Result<Walked<HashMap<u32, Vec<u8>>>> {
+    Ok(get_table_values(session, base_oid)?.map(|rows| rows
         .into_iter()
         .map(|(k, v)| match v {
             SnmpValue::Bytes(v) => (k, v),
             SnmpValue::Integer(n) => (k, n.to_be_bytes().to_vec()),
         })
-        .collect())
+        .collect()))
+}
+
+/// Like get_raw_table, but keyed by the index component at `position` after the
+/// base OID instead of the last one (e.g. lldpRemLocalPortNum in lldpRemTable)
+pub fn get_raw_table_at(session: &mut dyn SnmpSource, base_oid: &[u32], position: usize) -> Result<Walked<HashMap<u32, Vec<u8>>>> {
+    Ok(walk_table(session, base_oid)?.map(|rows| rows
+        .into_iter()
+        .filter_map(|(oid, value)| {
+            let index = *oid.get(base_oid.len() + position)?;
+            Some((index, match value {
+                SnmpValue::Bytes(v) => v,
+                SnmpValue::Integer(n) => n.to_be_bytes().to_vec(),
+            }))
+        })
+        .collect()))
 }
 
 pub fn extract_last_id(oid: &[u32]) -> u16 {
@@ -88,7 +329,7 @@ pub fn extract_last_id(oid: &[u32]) -> u16 {
         .unwrap_or(0)
 }
 
-fn parse_oid(oid_str: &str) -> Vec<u32> {
+pub(crate) fn parse_oid(oid_str: &str) -> Vec<u32> {
     oid_str.split('.')
         .filter(|s| !s.is_empty())
         .filter_map(|s| s.parse().ok())
@@ -113,4 +354,98 @@ pub fn decode_port_list(ports: &[u8]) -> String {
         }
     }
     port_list.join(", ")
-} 
\ No newline at end of file
+}
+
+/// Format a MAC address canonically (lowercase, colon-separated).
+/// Empty and all-zero addresses yield None.
+pub fn format_mac(bytes: &[u8]) -> Option<String> {
+    if bytes.is_empty() || bytes.iter().all(|&b| b == 0) {
+        return None;
+    }
+    Some(bytes.iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .join(":"))
+}
+
+/// Check whether the agent implements the table a column belongs to, i.e.
+/// whether any column of the same table entry has rows
+pub fn table_supported(session: &mut dyn SnmpSource, base_oid: &[u32]) -> Result<bool> {
+    let entry = &base_oid[..base_oid.len().saturating_sub(1)];
+    Ok(match session.get_next(entry)? {
+        Some((oid, _)) => starts_with(&oid, entry),
+        None => false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WALK: &str = "\
+.1.3.6.1.2.1.31.1.1.1.18.1 = STRING: \"mgmt\"
+.1.3.6.1.2.1.31.1.1.1.18.2 = STRING: \"printer\"
+.1.3.6.1.2.1.31.1.1.1.18.3 = STRING: \"desk 1\"
+.1.3.6.1.2.1.31.1.1.1.18.4 = STRING: \"desk 2\"
+";
+    const IF_ALIAS: &[u32] = &[1,3,6,1,2,1,31,1,1,1,18];
+
+    /// Times out on the given requests, counting from 1, and then answers
+    /// with the object after `rewind_to` instead, once
+    struct Flaky {
+        walk: RecordedWalk,
+        requests: Vec<Vec<u32>>,
+        timeouts: Vec<usize>,
+        rewind_to: Option<Vec<u32>>,
+    }
+
+    impl SnmpSource for Flaky {
+        fn get(&mut self, oid: &[u32]) -> Result<Option<SnmpValue>> {
+            self.walk.get(oid)
+        }
+
+        fn get_next(&mut self, oid: &[u32]) -> Result<Option<(Vec<u32>, Option<SnmpValue>)>> {
+            self.requests.push(oid.to_vec());
+            if self.timeouts.contains(&self.requests.len()) {
+                return Err(Error::Timeout);
+            }
+            match self.rewind_to.take_if(|_| self.requests.len() > 1) {
+                Some(earlier) => self.walk.get_next(&earlier),
+                None => self.walk.get_next(oid),
+            }
+        }
+    }
+
+    fn flaky(timeouts: &[usize], rewind_to: Option<&[u32]>) -> Flaky {
+        Flaky {
+            walk: RecordedWalk::parse(WALK).unwrap(),
+            requests: Vec::new(),
+            timeouts: timeouts.to_vec(),
+            rewind_to: rewind_to.map(<[u32]>::to_vec),
+        }
+    }
+
+    #[test]
+    fn walks_resume_after_a_timeout_from_the_last_row() {
+        let mut source = flaky(&[3, 4], None);
+        let walked = walk_table(&mut source, IF_ALIAS).unwrap();
+        assert_eq!(walked.rows.len(), 4);
+        assert_eq!(walked.resumes, 2);
+        // Both retries asked for what followed row 2, not for the whole table again
+        let row_2 = [IF_ALIAS, &[2]].concat();
+        assert_eq!(source.requests[2..5], [row_2.clone(), row_2.clone(), row_2]);
+
+        // A third timeout in a row fails the walk
+        let mut source = flaky(&[2, 3, 4], None);
+        assert!(matches!(walk_table(&mut source, IF_ALIAS), Err(Error::Timeout)));
+    }
+
+    #[test]
+    fn walks_start_over_when_the_table_changes() {
+        // The second answer is row 1 again, as from an agent that rebuilt the table
+        let mut source = flaky(&[], Some(IF_ALIAS));
+        let walked = walk_table(&mut source, IF_ALIAS).unwrap();
+        assert_eq!((walked.rows.len(), walked.resumes), (4, 0));
+        assert_eq!(source.requests.iter().filter(|oid| oid.as_slice() == IF_ALIAS).count(), 2);
+    }
+}
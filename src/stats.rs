@@ -0,0 +1,162 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io::IsTerminal;
+use std::sync::OnceLock;
+use std::time::Instant;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use crate::cancel;
+use crate::error::Result;
+use crate::logging::{self, Event, Level};
+use crate::snmp_utils::{table_supported, SnmpSource, Walked};
+
+/// A table walker from snmp_utils, e.g. get_u32_table
+pub type TableFetch<T> = fn(&mut dyn SnmpSource, &[u32]) -> Result<Walked<HashMap<u32, T>>>;
+
+/// What came back when walking one table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TableOutcome {
+    Rows(usize),
+    /// The table exists on the agent but has no rows
+    Empty,
+    /// The agent does not implement the table
+    Unsupported,
+    Error(String),
+    /// Collection was cancelled before the walk ended; rows may be missing
+    Interrupted,
+}
+
+impl fmt::Display for TableOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TableOutcome::Rows(1) => write!(f, "1 row"),
+            TableOutcome::Rows(n) => write!(f, "{} rows", n),
+            TableOutcome::Empty => write!(f, "empty"),
+            TableOutcome::Unsupported => write!(f, "unsupported"),
+            TableOutcome::Error(e) => write!(f, "error: {}", e),
+            TableOutcome::Interrupted => write!(f, "interrupted"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SourceStatus {
+    pub name: String,
+    pub outcome: TableOutcome,
+    /// Times the walk went on from its last row after a timeout
+    pub resumed: u32,
+}
+
+/// Stacks the bars of switches collected at the same time, one line each
+fn progress_lines() -> &'static MultiProgress {
+    static LINES: OnceLock<MultiProgress> = OnceLock::new();
+    LINES.get_or_init(MultiProgress::new)
+}
+
+/// Per-table results of a collection run
+#[derive(Default)]
+pub struct CollectionStats {
+    pub sources: Vec<SourceStatus>,
+    /// Table progress on stderr, only when it is a terminal
+    progress: Option<ProgressBar>,
+    /// Switch whose tables are walked, for the JSON log
+    host: String,
+    /// Log every table walk, with JSON logging and progress enabled
+    log_walks: bool,
+}
+
+impl CollectionStats {
+    /// Stats that also show a progress bar for `planned` table walks on `host`.
+    /// The bar stays hidden when stderr is not a terminal or `enabled` is false;
+    /// with JSON logging, each walk is logged instead.
+    pub fn with_progress(host: &str, planned: u64, enabled: bool) -> CollectionStats {
+        let progress = (enabled && !logging::json() && std::io::stderr().is_terminal()).then(|| {
+            let bar = ProgressBar::new(planned);
+            let style = ProgressStyle::with_template("{prefix} [{bar:30}] {pos}/{len} tables: {msg}")
+                .unwrap_or_else(|_| ProgressStyle::default_bar())
+                .progress_chars("=> ");
+            bar.set_style(style);
+            bar.set_prefix(host.to_string());
+            progress_lines().add(bar)
+        });
+        CollectionStats {
+            sources: Vec::new(),
+            progress,
+            host: host.to_string(),
+            log_walks: enabled && logging::json(),
+        }
+    }
+
+    pub fn record(&mut self, name: &str, outcome: TableOutcome) {
+        // Whatever the walk returned, it stopped at the cancellation
+        let outcome = if cancel::is_cancelled() { TableOutcome::Interrupted } else { outcome };
+        if let Some(bar) = &self.progress {
+            bar.set_message(name.to_string());
+            bar.inc(1);
+        }
+        self.sources.push(SourceStatus {
+            name: name.to_string(),
+            outcome,
+            resumed: 0,
+        });
+    }
+
+    /// Log an event without tearing the progress bar
+    pub fn log(&self, event: Event) {
+        match &self.progress {
+            // Through the bar's MultiProgress, which also knows the other switches' bars
+            Some(_) => {
+                let _ = progress_lines().println(event.render(logging::format()));
+            }
+            None => event.emit(),
+        }
+    }
+
+    /// Remove the progress bar once collection is done
+    pub fn finish(&self) {
+        if let Some(bar) = &self.progress {
+            bar.finish_and_clear();
+            progress_lines().remove(bar);
+        }
+    }
+
+    /// Walk a table with `fetch` and record how it went. Errors are passed on
+    /// to the caller, who decides whether the table was optional.
+    pub fn walk<T>(
+        &mut self,
+        session: &mut dyn SnmpSource,
+        name: &str,
+        base_oid: &[u32],
+        fetch: TableFetch<T>,
+    ) -> Result<HashMap<u32, T>> {
+        let started = Instant::now();
+        let (result, resumed) = match fetch(session, base_oid) {
+            Ok(walked) => (Ok(walked.rows), walked.resumes),
+            Err(e) => (Err(e), 0),
+        };
+        let elapsed = started.elapsed();
+        let outcome = match &result {
+            Ok(rows) if !rows.is_empty() => TableOutcome::Rows(rows.len()),
+            Ok(_) => match table_supported(session, base_oid) {
+                Ok(true) => TableOutcome::Empty,
+                Ok(false) => TableOutcome::Unsupported,
+                Err(e) => TableOutcome::Error(e.to_string()),
+            },
+            Err(e) => TableOutcome::Error(e.to_string()),
+        };
+        if self.log_walks {
+            let level = if matches!(outcome, TableOutcome::Error(_)) { Level::Warning } else { Level::Info };
+            Event::new(level, "table-walk", outcome.to_string())
+                .host(&self.host, false)
+                .table(name)
+                .oid(base_oid)
+                .duration(elapsed)
+                .field("resumed", resumed)
+                .emit();
+        }
+        self.record(name, outcome);
+        if let Some(status) = self.sources.last_mut() {
+            status.resumed = resumed;
+        }
+        result
+    }
+}
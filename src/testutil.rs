@@ -0,0 +1,53 @@
+//! Helpers for tests that replay `snmpwalk -On` output from tests/fixtures
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+use crate::oids::TableOids;
+use crate::{vlan_sets, PortConfig};
+
+/// Load the rows of one table from an `snmpwalk -On` fixture
+pub fn fixture_table(walk: &str, base_oid: &[u32]) -> Vec<(u32, String)> {
+    let prefix = format!(".{}.", base_oid.iter().map(|n| n.to_string()).collect::<Vec<_>>().join("."));
+    walk.lines()
+        .filter_map(|line| {
+            let (oid, value) = line.split_once(" = ")?;
+            let index = oid.strip_prefix(&prefix)?.parse().ok()?;
+            let value = value.split_once(": ").map_or(value, |(_, v)| v);
+            Some((index, value.trim_matches('"').to_string()))
+        })
+        .collect()
+}
+
+/// Load a table of `Hex-STRING` port bitmaps, as returned by get_raw_table
+pub fn fixture_bitmaps(walk: &str, base_oid: &[u32]) -> HashMap<u32, Vec<u8>> {
+    fixture_table(walk, base_oid).into_iter()
+        .map(|(index, hex)| {
+            let bytes = hex.split_whitespace()
+                .map(|byte| u8::from_str_radix(byte, 16).expect("hex byte in fixture"))
+                .collect();
+            (index, bytes)
+        })
+        .collect()
+}
+
+/// Port configurations as the collection would build them from the Q-BRIDGE
+/// tables of a fixture (VLAN bitmaps and PVIDs only)
+pub fn fixture_ports(walk: &str, ports: RangeInclusive<u32>) -> Vec<PortConfig> {
+    let oids = TableOids::default();
+    let egress = fixture_bitmaps(walk, &oids.egress_ports);
+    let untagged = fixture_bitmaps(walk, &oids.untagged_ports);
+    let pvids: HashMap<u32, u32> = fixture_table(walk, &oids.pvid).into_iter()
+        .map(|(port, pvid)| (port, pvid.parse().expect("PVID in fixture")))
+        .collect();
+    ports
+        .map(|port_num| {
+            let (vlan_memberships, untagged_vlans) = vlan_sets(port_num, &egress, &untagged);
+            PortConfig {
+                port_num,
+                pvid: pvids.get(&port_num).copied(),
+                vlan_memberships,
+                untagged_vlans,
+                ..PortConfig::default()
+            }
+        })
+        .collect()
+}
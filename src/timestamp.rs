@@ -0,0 +1,86 @@
+use std::str::FromStr;
+use anyhow::{Result, anyhow};
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, FixedOffset, Local, Utc};
+use tz::TimeZone;
+
+/// Format of the generated-on line when --timestamp-format is not given
+pub const DEFAULT_TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// A strftime pattern that chrono is known to understand
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimestampFormat(String);
+
+impl FromStr for TimestampFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Checked here so a typo fails at argument parsing rather than when rendering
+        if StrftimeItems::new(s).any(|item| matches!(item, Item::Error)) {
+            return Err(format!("Invalid strftime format '{}'", s));
+        }
+        Ok(TimestampFormat(s.to_string()))
+    }
+}
+
+/// Time zone for the generated-on line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Zone {
+    /// Whatever the machine running the tool is set to
+    Local,
+    Utc,
+    /// An IANA name such as Europe/Helsinki, read from the system tz database
+    Named(TimeZone),
+}
+
+impl FromStr for Zone {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "local" => Ok(Zone::Local),
+            "utc" => Ok(Zone::Utc),
+            _ => TimeZone::from_posix_tz(s)
+                .map(Zone::Named)
+                .map_err(|e| format!("Unknown time zone '{}': {}", s, e)),
+        }
+    }
+}
+
+/// Render `now` in `zone` with `format`
+pub fn format_timestamp(now: DateTime<Utc>, format: &TimestampFormat, zone: &Zone) -> Result<String> {
+    let formatted = match zone {
+        Zone::Local => now.with_timezone(&Local).format(&format.0).to_string(),
+        Zone::Utc => now.format(&format.0).to_string(),
+        Zone::Named(tz) => {
+            let offset = tz.find_local_time_type(now.timestamp())
+                .map_err(|e| anyhow!("Cannot resolve the time zone offset: {}", e))?
+                .ut_offset();
+            let offset = FixedOffset::east_opt(offset)
+                .ok_or_else(|| anyhow!("Time zone offset {}s is out of range", offset))?;
+            now.with_timezone(&offset).format(&format.0).to_string()
+        }
+    };
+    Ok(formatted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_in_the_requested_zone() {
+        let now = DateTime::parse_from_rfc3339("2024-05-01T12:00:00Z").unwrap().with_timezone(&Utc);
+        let format: TimestampFormat = "%Y-%m-%d %H:%M %z".parse().unwrap();
+        assert_eq!(format_timestamp(now, &format, &"UTC".parse().unwrap()).unwrap(), "2024-05-01 12:00 +0000");
+        // A fixed POSIX rule, so the test does not depend on the system tz database
+        let zone: Zone = "EET-2EEST,M3.5.0/3,M10.5.0/4".parse().unwrap();
+        assert_eq!(format_timestamp(now, &format, &zone).unwrap(), "2024-05-01 15:00 +0300");
+    }
+
+    #[test]
+    fn rejects_bad_formats_and_zones() {
+        assert!("%Y-%m-%d %Q".parse::<TimestampFormat>().is_err());
+        assert!("Europe/Nowhere".parse::<Zone>().is_err());
+    }
+}
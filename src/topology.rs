@@ -0,0 +1,376 @@
+//! Links between switches polled together, found by matching their LLDP
+//! neighbors against each other, for --topology
+use std::collections::{BTreeSet, HashSet};
+use std::io::{self, Write};
+use anyhow::{Result, anyhow};
+use serde::Serialize;
+use crate::snapshot::{Neighbor, Snapshot, SnapshotPort};
+
+/// One end of a link
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Endpoint {
+    /// A polled switch as named in its report, or what the neighbor calls itself
+    pub device: String,
+    /// None when the neighbor's port id names no port of the polled switch
+    pub port: Option<u32>,
+    /// The port as the neighbor advertised it, for ends that were not polled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub port_id: Option<String>,
+    /// Name of the LACP aggregator the port is a member of
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub aggregator: Option<String>,
+}
+
+impl Endpoint {
+    /// The port, as a number where known, with its aggregator
+    pub fn port_label(&self) -> String {
+        let port = match (self.port, &self.port_id) {
+            (Some(port), _) => port.to_string(),
+            (None, Some(port_id)) => port_id.clone(),
+            (None, None) => String::new(),
+        };
+        match &self.aggregator {
+            Some(aggregator) => format!("{} ({})", port, aggregator),
+            None => port,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Link {
+    /// A polled switch
+    pub local: Endpoint,
+    pub remote: Endpoint,
+    /// The remote device was not polled
+    pub leaf: bool,
+    /// VLANs of the local port
+    pub untagged: BTreeSet<u32>,
+    pub tagged: BTreeSet<u32>,
+    /// Tagged VLANs of only one end, when both ends were polled
+    #[serde(skip_serializing_if = "BTreeSet::is_empty")]
+    pub only_local: BTreeSet<u32>,
+    #[serde(skip_serializing_if = "BTreeSet::is_empty")]
+    pub only_remote: BTreeSet<u32>,
+}
+
+impl Link {
+    /// The two ends disagree on the tagged VLANs
+    pub fn mismatch(&self) -> bool {
+        !self.only_local.is_empty() || !self.only_remote.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Topology {
+    /// The polled switches, named as in their reports
+    pub switches: Vec<String>,
+    /// Each link between two polled switches once, then the neighbors that were not polled
+    pub links: Vec<Link>,
+}
+
+impl Topology {
+    /// From the snapshots of switches polled together with their LLDP neighbors
+    pub fn new<'a>(switches: impl IntoIterator<Item = (&'a str, &'a Snapshot)>) -> Topology {
+        let switches: Vec<(&str, &Snapshot)> = switches.into_iter().collect();
+        let mut links = Vec::new();
+        let mut leaves = Vec::new();
+        // Both ends see each other; the link is listed from the first to have seen it
+        let mut seen: HashSet<((usize, u32), (usize, u32))> = HashSet::new();
+        for (local, (name, snapshot)) in switches.iter().enumerate() {
+            for neighbor in &snapshot.neighbors {
+                let port = find_port(snapshot, neighbor.port);
+                let remote = switches.iter().enumerate()
+                    .position(|(other, (other_name, other_snapshot))| other != local && identifies(neighbor, other_name, other_snapshot));
+                let Some(remote) = remote else {
+                    leaves.push(Link {
+                        local: endpoint(name, neighbor.port, port),
+                        remote: Endpoint {
+                            device: neighbor.sys_name.clone().or_else(|| neighbor.chassis_id.clone())
+                                .unwrap_or_else(|| format!("unknown neighbor of {} port {}", name, neighbor.port)),
+                            port: None,
+                            port_id: neighbor.port_id.clone(),
+                            aggregator: None,
+                        },
+                        leaf: true,
+                        untagged: port.map(|port| port.untagged.clone()).unwrap_or_default(),
+                        tagged: port.map(tagged).unwrap_or_default(),
+                        only_local: BTreeSet::new(),
+                        only_remote: BTreeSet::new(),
+                    });
+                    continue;
+                };
+
+                let (remote_name, remote_snapshot) = switches[remote];
+                let remote_port_num = remote_port(neighbor, (name, snapshot), remote_snapshot);
+                if let Some(remote_port_num) = remote_port_num {
+                    if seen.contains(&((remote, remote_port_num), (local, neighbor.port))) {
+                        continue;
+                    }
+                    seen.insert(((local, neighbor.port), (remote, remote_port_num)));
+                }
+                let remote_port = remote_port_num.and_then(|port| find_port(remote_snapshot, port));
+                let (only_local, only_remote) = match (port, remote_port) {
+                    (Some(port), Some(remote_port)) => {
+                        let (ours, theirs) = (tagged(port), tagged(remote_port));
+                        (ours.difference(&theirs).copied().collect(), theirs.difference(&ours).copied().collect())
+                    }
+                    _ => (BTreeSet::new(), BTreeSet::new()),
+                };
+                let mut remote_end = match remote_port_num {
+                    Some(remote_port_num) => endpoint(remote_name, remote_port_num, remote_port),
+                    None => Endpoint { device: remote_name.to_string(), port: None, port_id: neighbor.port_id.clone(), aggregator: None },
+                };
+                if remote_end.port.is_some() {
+                    remote_end.port_id = None;
+                }
+                links.push(Link {
+                    local: endpoint(name, neighbor.port, port),
+                    remote: remote_end,
+                    leaf: false,
+                    untagged: port.map(|port| port.untagged.clone()).unwrap_or_default(),
+                    tagged: port.map(tagged).unwrap_or_default(),
+                    only_local,
+                    only_remote,
+                });
+            }
+        }
+        links.extend(leaves);
+        Topology { switches: switches.iter().map(|(name, _)| name.to_string()).collect(), links }
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self).map_err(|e| anyhow!("Failed to serialize the topology: {}", e))
+    }
+
+    /// Graphviz: polled switches as boxes, other neighbors as dashed ellipses
+    /// and links whose tagged VLANs disagree in red
+    pub fn write_dot(&self, w: &mut dyn Write) -> io::Result<()> {
+        let quote = |text: &str| format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""));
+        writeln!(w, "graph topology {{")?;
+        writeln!(w, "    node [shape=box];")?;
+        for switch in &self.switches {
+            writeln!(w, "    {};", quote(switch))?;
+        }
+        for leaf in self.leaves() {
+            writeln!(w, "    {} [shape=ellipse, style=dashed];", quote(leaf))?;
+        }
+        for link in &self.links {
+            let style = if link.mismatch() { ", color=red, fontcolor=red" } else { "" };
+            writeln!(w, "    {} -- {} [label={}{}];", quote(&link.local.device), quote(&link.remote.device),
+                quote(&link_label(link)), style)?;
+        }
+        writeln!(w, "}}")
+    }
+
+    /// Mermaid flowchart, drawn like `write_dot`
+    pub fn write_mermaid(&self, w: &mut dyn Write) -> io::Result<()> {
+        let quote = |text: &str| format!("\"{}\"", text.replace('"', "#quot;"));
+        let nodes: Vec<&str> = self.switches.iter().map(String::as_str).chain(self.leaves()).collect();
+        let node = |device: &str| nodes.iter().position(|node| *node == device).unwrap_or_default();
+        writeln!(w, "graph LR")?;
+        for (index, device) in nodes.iter().enumerate() {
+            match index < self.switches.len() {
+                true => writeln!(w, "    n{}[{}]", index, quote(device))?,
+                false => writeln!(w, "    n{}([{}])", index, quote(device))?,
+            }
+        }
+        for link in &self.links {
+            writeln!(w, "    n{} ---|{}| n{}", node(&link.local.device), quote(&link_label(link)), node(&link.remote.device))?;
+        }
+        for (index, _) in self.links.iter().enumerate().filter(|(_, link)| link.mismatch()) {
+            writeln!(w, "    linkStyle {} stroke:#b00020", index)?;
+        }
+        Ok(())
+    }
+
+    /// Neighbors that were not polled, each once
+    fn leaves(&self) -> impl Iterator<Item = &str> {
+        let mut seen = HashSet::new();
+        self.links.iter()
+            .filter(|link| link.leaf)
+            .map(|link| link.remote.device.as_str())
+            .filter(move |device| seen.insert(*device))
+    }
+}
+
+/// Both ports of a link, e.g. "9 (Trk1) - 5", or the local one alone
+fn link_label(link: &Link) -> String {
+    match link.remote.port_label() {
+        remote if remote.is_empty() => link.local.port_label(),
+        remote => format!("{} - {}", link.local.port_label(), remote),
+    }
+}
+
+fn endpoint(device: &str, port_num: u32, port: Option<&SnapshotPort>) -> Endpoint {
+    Endpoint {
+        device: device.to_string(),
+        port: Some(port_num),
+        port_id: None,
+        aggregator: port.and_then(|port| port.lacp.as_ref())
+            .map(|lacp| lacp.name.clone().unwrap_or_else(|| lacp.aggregator.to_string())),
+    }
+}
+
+fn find_port(snapshot: &Snapshot, port: u32) -> Option<&SnapshotPort> {
+    snapshot.ports.iter().find(|candidate| candidate.port == port)
+}
+
+fn tagged(port: &SnapshotPort) -> BTreeSet<u32> {
+    port.vlans.difference(&port.untagged).copied().collect()
+}
+
+/// Whether the neighbor is the polled switch: by chassis id, or by name
+fn identifies(neighbor: &Neighbor, name: &str, snapshot: &Snapshot) -> bool {
+    let same_chassis = matches!((&neighbor.chassis_id, &snapshot.device.chassis_id),
+        (Some(theirs), Some(ours)) if theirs.eq_ignore_ascii_case(ours));
+    let names = [Some(name), snapshot.device.sys_name.as_deref()];
+    same_chassis || neighbor.sys_name.as_deref()
+        .is_some_and(|sys_name| names.iter().flatten().any(|name| name.eq_ignore_ascii_case(sys_name)))
+}
+
+/// The port of `remote` at the far end of a link `local` sees `neighbor` on:
+/// the port id when it is a port number of the remote switch, else the remote
+/// switch's own LLDP entry for the link
+fn remote_port(neighbor: &Neighbor, local: (&str, &Snapshot), remote: &Snapshot) -> Option<u32> {
+    let by_number = |port_id: Option<&str>, snapshot: &Snapshot| {
+        port_id?.trim().parse::<u32>().ok().filter(|&port| find_port(snapshot, port).is_some())
+    };
+    if let Some(port) = by_number(neighbor.port_id.as_deref(), remote) {
+        return Some(port);
+    }
+    let back: Vec<&Neighbor> = remote.neighbors.iter()
+        .filter(|back| identifies(back, local.0, local.1))
+        .collect();
+    if let Some(back) = back.iter().find(|back| by_number(back.port_id.as_deref(), local.1) == Some(neighbor.port)) {
+        return Some(back.port);
+    }
+    // A single link each way can only be this one
+    let forward = local.1.neighbors.iter().filter(|forward| forward.sys_name == neighbor.sys_name
+        && forward.chassis_id == neighbor.chassis_id).count();
+    match (back.as_slice(), forward) {
+        ([back], 1) => Some(back.port),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snapshot::{Device, SnapshotLacp};
+
+    fn port(port: u32, vlans: &[u32], untagged: &[u32], aggregator: Option<&str>) -> SnapshotPort {
+        SnapshotPort {
+            port,
+            alias: None,
+            raw_alias: None,
+            pvid: untagged.first().copied(),
+            vlans: vlans.iter().copied().collect(),
+            untagged: untagged.iter().copied().collect(),
+            lacp: aggregator.map(|name| SnapshotLacp { aggregator: 289, name: Some(name.to_string()), load_balance: None }),
+            dot1x: None,
+            frame_policy: None,
+            uplink: false,
+            admin_down: false,
+            mac: None,
+            config_hash: None,
+            indices: None,
+            panel: None,
+        }
+    }
+
+    fn neighbor(port: u32, chassis_id: &str, port_id: &str, sys_name: &str) -> Neighbor {
+        let text = |text: &str| Some(text.to_string()).filter(|text| !text.is_empty());
+        Neighbor { port, chassis_id: text(chassis_id), port_id: text(port_id), sys_name: text(sys_name) }
+    }
+
+    fn switch(sys_name: &str, chassis_id: &str, ports: Vec<SnapshotPort>, neighbors: Vec<Neighbor>) -> Snapshot {
+        Snapshot {
+            schema_version: 1,
+            device: Device { ip: "192.0.2.1".to_string(), sys_name: Some(sys_name.to_string()), sys_descr: None,
+                vendor: "generic".to_string(), chassis_id: Some(chassis_id.to_string()) },
+            vlan_names: Default::default(),
+            ports,
+            neighbors,
+            lags: Vec::new(),
+            virtual_interfaces: Vec::new(),
+            warnings: Vec::new(),
+            sources: Default::default(),
+            capacity: None,
+            config_hash: None,
+            meta: None,
+        }
+    }
+
+    fn core_and_closet() -> (Snapshot, Snapshot) {
+        let core = switch("core", "00:00:5e:00:53:01", vec![
+            port(9, &[10, 20, 30], &[], Some("Trk1")),
+            port(10, &[10, 20, 30], &[], Some("Trk1")),
+            port(12, &[10], &[10], None),
+        ], vec![
+            // The closet calls its ports by name, so the core's entries say "A1"
+            neighbor(9, "00:00:5e:00:53:02", "A1", "closet"),
+            neighbor(10, "00:00:5e:00:53:02", "A2", "closet"),
+            neighbor(12, "", "", "phone-12"),
+        ]);
+        let closet = switch("closet.example.net", "00:00:5E:00:53:02", vec![
+            port(1, &[1, 10, 20], &[1], None),
+            port(2, &[1, 10, 20], &[1], None),
+        ], vec![
+            neighbor(1, "00:00:5e:00:53:01", "9", "core"),
+            neighbor(2, "00:00:5e:00:53:01", "10", "core"),
+        ]);
+        (core, closet)
+    }
+
+    #[test]
+    fn links_are_matched_from_either_end() {
+        let (core, closet) = core_and_closet();
+        let topology = Topology::new([("core", &core), ("closet", &closet)]);
+        let ends: Vec<(String, String, bool)> = topology.links.iter()
+            .map(|link| (format!("{} {}", link.local.device, link.local.port_label()),
+                format!("{} {}", link.remote.device, link.remote.port_label()), link.mismatch()))
+            .collect();
+        assert_eq!(ends, [
+            ("core 9 (Trk1)".to_string(), "closet 1".to_string(), true),
+            ("core 10 (Trk1)".to_string(), "closet 2".to_string(), true),
+            ("core 12".to_string(), "phone-12 ".to_string(), false),
+        ]);
+        assert_eq!((topology.links[0].only_local.iter().collect::<Vec<_>>(), topology.links[0].only_remote.len()), (vec![&30], 0));
+        assert!(topology.links[2].leaf);
+    }
+
+    #[test]
+    fn a_single_link_pairs_without_port_numbers() {
+        let (mut core, mut closet) = core_and_closet();
+        core.neighbors.truncate(1);
+        closet.neighbors.truncate(1);
+        closet.neighbors[0].port_id = Some("Gi1/0/9".to_string());
+        let topology = Topology::new([("core", &core), ("closet", &closet)]);
+        assert_eq!(topology.links.len(), 1);
+        assert_eq!(topology.links[0].remote.port, Some(1));
+    }
+
+    #[test]
+    fn graphs_mark_leaves_and_mismatches() {
+        let (core, closet) = core_and_closet();
+        let topology = Topology::new([("core", &core), ("closet", &closet)]);
+        let mut dot = Vec::new();
+        topology.write_dot(&mut dot).unwrap();
+        let dot = String::from_utf8(dot).unwrap();
+        assert!(dot.contains("    \"phone-12\" [shape=ellipse, style=dashed];\n"), "{}", dot);
+        assert!(dot.contains("    \"core\" -- \"closet\" [label=\"9 (Trk1) - 1\", color=red, fontcolor=red];\n"), "{}", dot);
+        let mut mermaid = Vec::new();
+        topology.write_mermaid(&mut mermaid).unwrap();
+        assert_eq!(String::from_utf8(mermaid).unwrap(), "\
+graph LR
+    n0[\"core\"]
+    n1[\"closet\"]
+    n2([\"phone-12\"])
+    n0 ---|\"9 (Trk1) - 1\"| n1
+    n0 ---|\"10 (Trk1) - 2\"| n1
+    n0 ---|\"12\"| n2
+    linkStyle 0 stroke:#b00020
+    linkStyle 1 stroke:#b00020
+");
+    }
+}
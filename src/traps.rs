@@ -0,0 +1,161 @@
+//! SNMPv2c notifications (traps and informs) that tell a switch's ports or
+//! configuration may have changed, so its documentation can be refreshed
+use snmp::{asn1, snmp::{MSG_INFORM, MSG_RESPONSE}, AsnReader, SnmpError, SnmpMessageType, SnmpPdu, Value};
+use crate::snmp_utils::parse_oid;
+
+/// snmpTrapOID.0, the varbind naming the notification
+const SNMP_TRAP_OID: [u32; 11] = [1, 3, 6, 1, 6, 3, 1, 1, 4, 1, 0];
+
+/// Notifications after which the port table may look different
+const TRIGGERS: &[(&str, &[u32])] = &[
+    ("linkDown", &[1, 3, 6, 1, 6, 3, 1, 1, 5, 3]),
+    ("linkUp", &[1, 3, 6, 1, 6, 3, 1, 1, 5, 4]),
+    // ENTITY-MIB
+    ("entConfigChange", &[1, 3, 6, 1, 2, 1, 47, 2, 0, 1]),
+    // CISCO-CONFIG-MAN-MIB
+    ("ciscoConfigManEvent", &[1, 3, 6, 1, 4, 1, 9, 9, 43, 2, 0, 1]),
+    // JUNIPER-CFGMGMT-MIB
+    ("jnxCmCfgChange", &[1, 3, 6, 1, 4, 1, 2636, 4, 5, 0, 1]),
+];
+
+/// A received trap or inform
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notification {
+    pub community: Vec<u8>,
+    /// An inform, which the sender repeats until it gets `inform_response`
+    pub inform: bool,
+    /// The value of snmpTrapOID.0
+    pub trap_oid: Vec<u32>,
+}
+
+impl Notification {
+    /// Decode a datagram; the error says why it is not a v2c notification
+    pub fn parse(datagram: &[u8]) -> Result<Notification, String> {
+        let pdu = SnmpPdu::from_bytes(datagram).map_err(|e| match e {
+            SnmpError::UnsupportedVersion => "not SNMPv2c".to_string(),
+            e => format!("malformed message ({:?})", e),
+        })?;
+        let inform = match pdu.message_type {
+            SnmpMessageType::Trap => false,
+            SnmpMessageType::InformRequest => true,
+            other => return Err(format!("a {:?}, not a notification", other)),
+        };
+        let trap_oid = pdu.varbinds.clone()
+            .find(|(name, _)| parse_oid(&name.to_string()) == SNMP_TRAP_OID)
+            .and_then(|(_, value)| match value {
+                Value::ObjectIdentifier(oid) => Some(parse_oid(&oid.to_string())),
+                _ => None,
+            })
+            .ok_or("no snmpTrapOID.0 varbind")?;
+        Ok(Notification { community: community(datagram).ok_or("malformed message")?.to_vec(), inform, trap_oid })
+    }
+
+    /// Name of the notification if it is one that changes ports or configuration
+    pub fn trigger(&self) -> Option<&'static str> {
+        TRIGGERS.iter().find(|(_, oid)| *oid == self.trap_oid.as_slice()).map(|(name, _)| *name)
+    }
+}
+
+/// The community of a message, which SnmpPdu keeps to itself
+fn community(datagram: &[u8]) -> Option<&[u8]> {
+    let mut message = AsnReader::from_bytes(AsnReader::from_bytes(datagram).read_raw(asn1::TYPE_SEQUENCE).ok()?);
+    message.read_asn_integer().ok()?;
+    message.read_asn_octetstring().ok()
+}
+
+/// The acknowledgement of an inform: the same message with a Response PDU,
+/// which keeps the request id, a zero error status and the varbinds (RFC 3416)
+pub fn inform_response(datagram: &[u8]) -> Option<Vec<u8>> {
+    // The PDU follows the message header, the version and the community
+    let (header, _) = tlv_header(datagram)?;
+    let mut offset = header;
+    for _ in 0..2 {
+        let (header, length) = tlv_header(datagram.get(offset..)?)?;
+        offset += header + length;
+    }
+    if *datagram.get(offset)? != MSG_INFORM {
+        return None;
+    }
+    let mut response = datagram.to_vec();
+    response[offset] = MSG_RESPONSE;
+    Some(response)
+}
+
+/// Length of the type and length octets of a BER value, and of its content
+fn tlv_header(bytes: &[u8]) -> Option<(usize, usize)> {
+    let first = *bytes.get(1)?;
+    if first & 0x80 == 0 {
+        return Some((2, first.into()));
+    }
+    let octets = usize::from(first & 0x7f);
+    let length = bytes.get(2..2 + octets)?.iter()
+        .try_fold(0usize, |length, &b| length.checked_mul(256).map(|length| length + usize::from(b)))?;
+    Some((2 + octets, length))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use snmp::snmp::{MSG_GET, MSG_TRAP};
+
+    fn tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+        assert!(content.len() < 0x80);
+        [&[tag, content.len() as u8][..], content].concat()
+    }
+
+    fn oid(oid: &[u32]) -> Vec<u8> {
+        // Every subidentifier of these tests fits into two octets
+        let mut content = vec![(oid[0] * 40 + oid[1]) as u8];
+        for &subid in &oid[2..] {
+            if subid >= 0x80 {
+                content.push((subid >> 7) as u8 | 0x80);
+            }
+            content.push((subid & 0x7f) as u8);
+        }
+        tlv(asn1::TYPE_OBJECTIDENTIFIER, &content)
+    }
+
+    /// sysUpTime.0 and snmpTrapOID.0, as every v2c notification starts
+    fn notification(version: u8, tag: u8, community: &[u8], trap_oid: &[u32]) -> Vec<u8> {
+        let uptime = tlv(asn1::TYPE_SEQUENCE, &[oid(&[1, 3, 6, 1, 2, 1, 1, 3, 0]), tlv(0x43, &[0x10])].concat());
+        let name = tlv(asn1::TYPE_SEQUENCE, &[oid(&SNMP_TRAP_OID), oid(trap_oid)].concat());
+        let pdu = tlv(tag, &[
+            tlv(asn1::TYPE_INTEGER, &[7]),
+            tlv(asn1::TYPE_INTEGER, &[0]),
+            tlv(asn1::TYPE_INTEGER, &[0]),
+            tlv(asn1::TYPE_SEQUENCE, &[uptime, name].concat()),
+        ].concat());
+        tlv(asn1::TYPE_SEQUENCE, &[tlv(asn1::TYPE_INTEGER, &[version]), tlv(asn1::TYPE_OCTETSTRING, community), pdu].concat())
+    }
+
+    #[test]
+    fn link_and_config_changes_trigger() {
+        let link_down = Notification::parse(&notification(1, MSG_TRAP, b"public", &[1, 3, 6, 1, 6, 3, 1, 1, 5, 3])).unwrap();
+        assert_eq!((link_down.community.as_slice(), link_down.inform, link_down.trigger()), (&b"public"[..], false, Some("linkDown")));
+        let juniper = Notification::parse(&notification(1, MSG_INFORM, b"public", &[1, 3, 6, 1, 4, 1, 2636, 4, 5, 0, 1])).unwrap();
+        assert_eq!((juniper.inform, juniper.trigger()), (true, Some("jnxCmCfgChange")));
+        // coldStart
+        let cold_start = Notification::parse(&notification(1, MSG_TRAP, b"public", &[1, 3, 6, 1, 6, 3, 1, 1, 5, 1])).unwrap();
+        assert_eq!(cold_start.trigger(), None);
+    }
+
+    #[test]
+    fn other_messages_are_refused_with_a_reason() {
+        let link_up = [1, 3, 6, 1, 6, 3, 1, 1, 5, 4];
+        assert_eq!(Notification::parse(&notification(0, MSG_TRAP, b"public", &link_up)), Err("not SNMPv2c".to_string()));
+        assert_eq!(Notification::parse(&notification(1, MSG_GET, b"public", &link_up)), Err("a GetRequest, not a notification".to_string()));
+        assert!(Notification::parse(b"\x30\x03\x02\x01").is_err());
+        assert!(Notification::parse(&[]).is_err());
+    }
+
+    #[test]
+    fn informs_are_answered_with_the_same_message_as_a_response() {
+        let inform = notification(1, MSG_INFORM, b"public", &[1, 3, 6, 1, 6, 3, 1, 1, 5, 4]);
+        let response = inform_response(&inform).unwrap();
+        let pdu = SnmpPdu::from_bytes(&response).unwrap();
+        assert_eq!((pdu.message_type, pdu.req_id, pdu.error_status), (SnmpMessageType::Response, 7, 0));
+        assert_eq!(pdu.varbinds.count(), 2);
+        assert_eq!(inform_response(&notification(1, MSG_TRAP, b"public", &[1, 3, 6, 1, 6, 3, 1, 1, 5, 4])), None);
+        assert_eq!(inform_response(&inform[..5]), None);
+    }
+}
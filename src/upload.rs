@@ -0,0 +1,147 @@
+use std::str::FromStr;
+use std::thread::sleep;
+use std::time::Duration;
+use anyhow::{Result, anyhow};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use switch_vlan_diagram::logging::Event;
+use crate::webhook::redact_url;
+
+/// Environment variable holding `user:password` for basic authentication
+pub const UPLOAD_AUTH_ENV: &str = "SWITCH_VLAN_DOC_UPLOAD_AUTH";
+
+/// How long one attempt may take, connecting included
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Delays before each retry of a transient failure
+const RETRY_DELAYS: [Duration; 2] = [Duration::from_secs(1), Duration::from_secs(3)];
+
+/// How much of an error response body to show
+const BODY_EXCERPT_CHARS: usize = 200;
+
+/// HTTP method of --upload-url
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UploadMethod {
+    Put,
+    Post,
+}
+
+impl UploadMethod {
+    fn as_str(self) -> &'static str {
+        match self {
+            UploadMethod::Put => "PUT",
+            UploadMethod::Post => "POST",
+        }
+    }
+}
+
+impl FromStr for UploadMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "PUT" => Ok(UploadMethod::Put),
+            "POST" => Ok(UploadMethod::Post),
+            _ => Err(format!("Unknown upload method '{}'. Expected: PUT or POST", s)),
+        }
+    }
+}
+
+/// Parse a "Name: value" header
+pub fn parse_header(s: &str) -> Result<(String, String), String> {
+    let (name, value) = s.split_once(':')
+        .ok_or_else(|| format!("Expected 'Name: value', got '{}'", s))?;
+    let name = name.trim();
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        return Err(format!("Invalid header name '{}'", name));
+    }
+    Ok((name.to_string(), value.trim().to_string()))
+}
+
+/// Send the rendered report to `url`. 5xx responses and connection problems
+/// are retried a couple of times; anything else fails straight away.
+pub fn upload(url: &str, method: UploadMethod, headers: &[(String, String)], content_type: &str, body: &[u8]) -> Result<()> {
+    let auth = match std::env::var(UPLOAD_AUTH_ENV) {
+        Ok(credentials) if credentials.contains(':') => Some(format!("Basic {}", STANDARD.encode(credentials))),
+        Ok(_) => return Err(anyhow!("{} must be in the form user:password", UPLOAD_AUTH_ENV)),
+        Err(_) => None,
+    };
+
+    let agent = ureq::AgentBuilder::new().timeout(TIMEOUT).build();
+    // The URL may carry credentials as user info
+    let shown_url = redact_url(url);
+    let mut delays = RETRY_DELAYS.iter();
+    loop {
+        let mut request = agent.request(method.as_str(), url).set("Content-Type", content_type);
+        if let Some(auth) = &auth {
+            request = request.set("Authorization", auth);
+        }
+        // Explicit headers win, including over the content type
+        for (name, value) in headers {
+            request = request.set(name, value);
+        }
+
+        let error = match request.send_bytes(body) {
+            Ok(_) => return Ok(()),
+            Err(ureq::Error::Status(status, response)) => {
+                let reason = response.status_text().to_string();
+                let excerpt: String = response.into_string().unwrap_or_default()
+                    .trim()
+                    .chars()
+                    .take(BODY_EXCERPT_CHARS)
+                    .collect();
+                let error = anyhow!("Upload to {} failed: HTTP {} {}: {}", shown_url, status, reason, excerpt);
+                if status < 500 {
+                    return Err(error);
+                }
+                error
+            }
+            // Transport errors quote the URL, secrets and all
+            Err(ureq::Error::Transport(transport)) => {
+                let reason = transport.to_string().replace(url, &shown_url);
+                anyhow!("Upload to {} failed: {}", shown_url, reason)
+            }
+        };
+
+        match delays.next() {
+            Some(&delay) => {
+                Event::warning("upload-retry", format!("{}; retrying in {}s", error, delay.as_secs()))
+                    .field("retry_in_s", delay.as_secs())
+                    .emit();
+                sleep(delay);
+            }
+            None => return Err(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use super::*;
+
+    #[test]
+    fn parses_headers_and_methods() {
+        assert_eq!(parse_header("X-Token: abc: def"), Ok(("X-Token".to_string(), "abc: def".to_string())));
+        assert!(parse_header("no colon").is_err());
+        assert!(parse_header("Bad Name: x").is_err());
+        assert_eq!("put".parse::<UploadMethod>(), Ok(UploadMethod::Put));
+        assert!("PATCH".parse::<UploadMethod>().is_err());
+    }
+
+    #[test]
+    fn errors_leave_credentials_out() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://user:secret@{}/reports/sw1.md", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let _ = stream.read(&mut [0; 4096]);
+            stream.write_all(b"HTTP/1.1 403 Forbidden\r\nContent-Length: 4\r\n\r\nnope").unwrap();
+        });
+        let error = upload(&url, UploadMethod::Put, &[], "text/markdown", b"report").unwrap_err().to_string();
+        server.join().unwrap();
+        assert!(error.starts_with("Upload to http://127.0.0.1:"), "{}", error);
+        assert!(error.ends_with("/[redacted] failed: HTTP 403 Forbidden: nope"), "{}", error);
+    }
+}
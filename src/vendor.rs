@@ -0,0 +1,188 @@
+use std::collections::{BTreeSet, HashMap};
+use std::str::FromStr;
+use std::fmt;
+
+// JUNIPER-VLAN-MIB OIDs (jnxExVlanTable, indexed by the internal VLAN index)
+pub const JNX_EX_VLAN_NAME: &[u32] = &[1,3,6,1,4,1,2636,3,40,1,5,1,5,1,2];  // jnxExVlanName
+pub const JNX_EX_VLAN_TAG: &[u32] = &[1,3,6,1,4,1,2636,3,40,1,5,1,5,1,5];  // jnxExVlanTag
+
+// CISCO-VTP-MIB OIDs
+pub const VTP_DOMAIN_NAME: &[u32] = &[1,3,6,1,4,1,9,9,46,1,2,1,1,2];  // managementDomainName
+pub const VTP_DOMAIN_LOCAL_MODE: &[u32] = &[1,3,6,1,4,1,9,9,46,1,2,1,1,3];  // managementDomainLocalMode
+pub const VTP_VLAN_STATE: &[u32] = &[1,3,6,1,4,1,9,9,46,1,3,1,1,2];  // vtpVlanState
+
+// CISCO-LAG-MIB OIDs (system-wide EtherChannel hashing)
+pub const CLAG_DISTRIBUTION_PROTOCOL: &[u32] = &[1,3,6,1,4,1,9,9,225,1,1,1,0];  // clagAggDistributionProtocol.0
+pub const CLAG_DISTRIBUTION_ADDRESS_MODE: &[u32] = &[1,3,6,1,4,1,9,9,225,1,1,2,0];  // clagAggDistributionAddressMode.0
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Vendor {
+    Juniper,
+    Cisco,
+    Aruba,
+    Generic,
+}
+
+impl Vendor {
+    /// Guess the vendor from sysDescr
+    pub fn detect(sys_descr: &str) -> Vendor {
+        let descr = sys_descr.to_lowercase();
+        if descr.contains("juniper") || descr.contains("junos") {
+            Vendor::Juniper
+        } else if descr.contains("cisco") {
+            Vendor::Cisco
+        } else if descr.contains("procurve") || descr.contains("aruba") || descr.starts_with("hp ") {
+            Vendor::Aruba
+        } else {
+            Vendor::Generic
+        }
+    }
+}
+
+impl FromStr for Vendor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "juniper" => Ok(Vendor::Juniper),
+            "cisco" => Ok(Vendor::Cisco),
+            "aruba" | "procurve" | "hp" => Ok(Vendor::Aruba),
+            "generic" => Ok(Vendor::Generic),
+            _ => Err(format!("Unknown vendor '{}'. Expected: juniper, cisco, aruba or generic", s)),
+        }
+    }
+}
+
+impl fmt::Display for Vendor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Vendor::Juniper => "juniper",
+            Vendor::Cisco => "cisco",
+            Vendor::Aruba => "aruba",
+            Vendor::Generic => "generic",
+        };
+        f.write_str(name)
+    }
+}
+
+/// VTP management domain of a Cisco switch
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct VtpInfo {
+    pub domain: String,
+    pub mode: u32,  // client(1), server(2), transparent(3), off(4)
+    /// VLANs whose vtpVlanState is suspended; reports mark them after the name
+    pub suspended_vlans: BTreeSet<u32>,
+}
+
+impl VtpInfo {
+    pub fn mode_name(&self) -> &'static str {
+        match self.mode {
+            1 => "client",
+            2 => "server",
+            3 => "transparent",
+            4 => "off",
+            _ => "unknown",
+        }
+    }
+}
+
+/// Describe Cisco's EtherChannel load-balancing mode, e.g. "src-dst-ip"
+pub fn cisco_load_balance_mode(protocol: u32, address_mode: u32) -> Option<String> {
+    let protocol = match protocol {
+        1 => "ip",
+        2 => "mac",
+        3 => "port",
+        _ => return None,
+    };
+    let addresses = match address_mode {
+        1 => "src",
+        2 => "dst",
+        3 => "src-dst",
+        _ => return None,
+    };
+    Some(format!("{}-{}", addresses, protocol))
+}
+
+/// vtpVlanState value for a suspended VLAN
+pub const VTP_VLAN_SUSPENDED: u32 = 2;
+
+/// Translates Juniper EX internal VLAN indices into real 802.1Q tags.
+///
+/// On EX switches the Q-BRIDGE tables are keyed by an internal index; the
+/// tag and name for each index live in jnxExVlanTable.
+pub struct JuniperVlanMap {
+    tags: HashMap<u32, u32>,  // internal index -> 802.1Q tag
+}
+
+impl JuniperVlanMap {
+    pub fn new(tags: HashMap<u32, u32>) -> JuniperVlanMap {
+        JuniperVlanMap { tags }
+    }
+
+    /// Map an internal index to its tag; unknown indices are left unchanged
+    pub fn tag(&self, index: u32) -> u32 {
+        self.tags.get(&index).copied().unwrap_or(index)
+    }
+
+    /// Re-key a Q-BRIDGE table from internal indices to tags
+    pub fn translate_keys<V>(&self, table: HashMap<u32, V>) -> HashMap<u32, V> {
+        table.into_iter()
+            .map(|(index, value)| (self.tag(index), value))
+            .collect()
+    }
+
+    /// Build the tag -> name map from jnxExVlanName
+    pub fn names(&self, jnx_names: &HashMap<u32, String>) -> HashMap<u32, String> {
+        jnx_names.iter()
+            .map(|(&index, name)| (self.tag(index), name.clone()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::fixture_table;
+
+    #[test]
+    fn detects_vendor_from_sys_descr() {
+        assert_eq!(Vendor::detect("Juniper Networks, Inc. ex2300-48p Ethernet Switch, kernel JUNOS 20.4R3"), Vendor::Juniper);
+        assert_eq!(Vendor::detect("Cisco IOS Software, C2960X Software"), Vendor::Cisco);
+        assert_eq!(Vendor::detect("HP J9727A 2920-24G-PoE+ Switch, revision WB.16.10"), Vendor::Aruba);
+        assert_eq!(Vendor::detect("Linux whitebox 5.10"), Vendor::Generic);
+    }
+
+    #[test]
+    fn describes_cisco_load_balancing() {
+        assert_eq!(cisco_load_balance_mode(1, 3).as_deref(), Some("src-dst-ip"));
+        assert_eq!(cisco_load_balance_mode(2, 1).as_deref(), Some("src-mac"));
+        assert_eq!(cisco_load_balance_mode(4, 1), None);
+        assert_eq!(cisco_load_balance_mode(1, 0), None);
+    }
+
+    #[test]
+    fn translates_juniper_ex_internal_indices() {
+        let walk = include_str!("../tests/fixtures/juniper-ex2300.walk");
+        let tags = fixture_table(walk, JNX_EX_VLAN_TAG).into_iter()
+            .map(|(index, tag)| (index, tag.parse().unwrap()))
+            .collect();
+        let jnx_names = fixture_table(walk, JNX_EX_VLAN_NAME).into_iter().collect();
+        let map = JuniperVlanMap::new(tags);
+
+        let names = map.names(&jnx_names);
+        assert_eq!(names.get(&100).map(String::as_str), Some("vlan100"));
+        assert_eq!(names.get(&531).map(String::as_str), Some("storage"));
+        assert_eq!(names.get(&1).map(String::as_str), Some("default"));
+        assert!(!names.contains_key(&12));
+
+        let egress: HashMap<u32, String> = fixture_table(walk, &[1,3,6,1,2,1,17,7,1,4,3,1,2]).into_iter().collect();
+        let mut vlans: Vec<u32> = map.translate_keys(egress).into_keys().collect();
+        vlans.sort_unstable();
+        assert_eq!(vlans, vec![1, 100, 531]);
+
+        let pvids: Vec<u32> = fixture_table(walk, &[1,3,6,1,2,1,17,7,1,4,5,1,1]).into_iter()
+            .map(|(_, pvid)| map.tag(pvid.parse().unwrap()))
+            .collect();
+        assert!(pvids.iter().all(|pvid| [1, 100, 531].contains(pvid)));
+    }
+}
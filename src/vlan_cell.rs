@@ -0,0 +1,296 @@
+//! The VLAN(s) cell shared by all output formats: labels, lists and the
+//! rules for collapsing a port to its single VLAN
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use crate::PortRange;
+
+/// How VLANs are labelled in every table
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VlanDisplay {
+    Id,
+    /// Name only, or the id for VLANs without a name
+    Name,
+    /// "office (10)"; VLAN 1 is just "1" unless --show-vlan1-name
+    #[default]
+    Both,
+}
+
+impl FromStr for VlanDisplay {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "id" => Ok(VlanDisplay::Id),
+            "name" => Ok(VlanDisplay::Name),
+            "both" => Ok(VlanDisplay::Both),
+            _ => Err(format!("Unknown VLAN display '{}'. Expected: id, name or both", s)),
+        }
+    }
+}
+
+/// Everything that decides how VLAN lists are rendered
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VlanStyle {
+    pub display: VlanDisplay,
+    /// Label VLAN 1 like any other VLAN instead of as a bare "1"
+    pub show_vlan1_name: bool,
+    /// Leave VLAN 1 out of tagged and untagged lists
+    pub hide_vlan1: bool,
+    /// Show ranges tagged in every VLAN as "ALL (n VLANs)"
+    pub compact: bool,
+}
+
+impl VlanStyle {
+    fn hides(&self, vlan_id: u32) -> bool {
+        self.hide_vlan1 && vlan_id == 1
+    }
+}
+
+/// VLAN label, e.g. "office (10)", shared by all output formats
+pub fn format_vlan_label(vlan_id: u32, vlan_names: &HashMap<u32, String>, style: &VlanStyle) -> String {
+    // Agents report unnamed VLANs with an empty name as often as with no row
+    match (vlan_names.get(&vlan_id).filter(|name| !name.is_empty()), style.display) {
+        (Some(name), VlanDisplay::Name) => name.clone(),
+        (Some(name), VlanDisplay::Both) if vlan_id != 1 || style.show_vlan1_name => format!("{} ({})", name, vlan_id),
+        _ => vlan_id.to_string(),
+    }
+}
+
+/// Sorted VLAN labels. Runs of consecutive VLANs that render as bare ids
+/// collapse to e.g. "100-180"; a named or `flagged` VLAN (suffixed "!") breaks the run.
+/// Empty when every VLAN is hidden by --hide-vlan1.
+pub fn format_vlan_list(
+    vlans: &HashSet<u32>,
+    vlan_names: &HashMap<u32, String>,
+    style: &VlanStyle,
+    flagged: impl Fn(u32) -> bool,
+) -> String {
+    let mut sorted: Vec<u32> = vlans.iter().copied().filter(|&vlan_id| !style.hides(vlan_id)).collect();
+    sorted.sort_unstable();
+    let label = |vlan_id: u32| {
+        let label = format_vlan_label(vlan_id, vlan_names, style);
+        if flagged(vlan_id) { format!("{}!", label) } else { label }
+    };
+    let is_bare = |vlan_id: u32| label(vlan_id) == vlan_id.to_string();
+
+    let mut parts = Vec::new();
+    let mut iter = sorted.into_iter().peekable();
+    while let Some(first) = iter.next() {
+        if !is_bare(first) {
+            parts.push(label(first));
+            continue;
+        }
+        let mut last = first;
+        while let Some(&next) = iter.peek() {
+            if next != last + 1 || !is_bare(next) {
+                break;
+            }
+            last = next;
+            iter.next();
+        }
+        if first == last {
+            parts.push(first.to_string());
+        } else {
+            parts.push(format!("{}-{}", first, last));
+        }
+    }
+    parts.join(", ")
+}
+
+/// The tagged list of a range, or "ALL (n VLANs)" with --compact-vlans when
+/// the range carries every VLAN defined on the switch
+pub fn format_tagged_vlans(range: &PortRange, vlan_names: &HashMap<u32, String>, style: &VlanStyle) -> String {
+    let defined: Vec<u32> = vlan_names.keys().copied().filter(|&vlan_id| !style.hides(vlan_id)).collect();
+    let carries_all = defined.len() > 1 && defined.iter().all(|vlan_id| range.vlan_memberships.contains(vlan_id));
+    if style.compact && carries_all {
+        let count = range.vlan_memberships.iter().filter(|&&vlan_id| !style.hides(vlan_id)).count();
+        format!("ALL ({} VLANs)", count)
+    } else {
+        format_vlan_list(&range.vlan_memberships, vlan_names, style, |_| false)
+    }
+}
+
+/// The PVID if it is set but none of the untagged VLANs; such rows are never
+/// collapsed to a single VLAN and show the PVID explicitly
+pub fn pvid_mismatch(range: &PortRange) -> Option<u32> {
+    range.pvid.filter(|&pvid| pvid != 0 && !range.untagged_vlans.contains(&pvid))
+}
+
+/// Whether the collapsed single-VLAN display fits: one untagged VLAN that is
+/// also the (valid, non-zero) PVID and no other egress VLAN
+pub fn is_single_vlan(range: &PortRange) -> bool {
+    range.untagged_vlans.len() == 1
+        && range.vlan_memberships.len() <= 1  // Allow the same VLAN to be tagged and untagged
+        && range.untagged_vlans.is_subset(&range.vlan_memberships)
+        && range.pvid.filter(|&pvid| pvid != 0).is_some_and(|pvid| range.untagged_vlans.contains(&pvid))
+}
+
+/// Whether the port carries no VLAN at all, rendered as a dash
+pub fn has_no_vlans(range: &PortRange) -> bool {
+    range.vlan_memberships.is_empty() && range.untagged_vlans.is_empty() && matches!(range.pvid, None | Some(0))
+}
+
+/// What the VLAN(s) cell of a range shows, before any format-specific markup
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VlanCell {
+    /// The range carries no VLAN at all
+    NoVlans,
+    /// Collapsed to the one untagged VLAN that is also the PVID; blank when
+    /// that VLAN is hidden by --hide-vlan1
+    Single(String),
+    /// Each list is None when empty, e.g. when --hide-vlan1 removed its only VLAN
+    Lists {
+        tagged: Option<String>,
+        untagged: Option<String>,
+        pvid: Option<PvidNote>,
+    },
+}
+
+/// Why the PVID is shown next to the lists
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PvidNote {
+    /// Set, but not one of the untagged VLANs; holds the label
+    Mismatch(String),
+    /// dot1qPvid has no row for the port
+    Unknown,
+}
+
+impl VlanCell {
+    /// The cell as text. `mark(class, text)` emphasizes what needs attention:
+    /// the "no-vlans" dash and a "pvid-mismatch".
+    pub fn render(&self, mark: impl Fn(&str, &str) -> String) -> String {
+        match self {
+            VlanCell::NoVlans => mark("no-vlans", "—"),
+            VlanCell::Single(label) => label.clone(),
+            VlanCell::Lists { tagged, untagged, pvid } => {
+                let mut parts = Vec::new();
+                if let Some(tagged) = tagged {
+                    parts.push(format!("Tagged:[{}]", tagged));
+                }
+                if let Some(untagged) = untagged {
+                    parts.push(format!("Untagged:[{}]", untagged));
+                }
+                match pvid {
+                    Some(PvidNote::Mismatch(label)) => parts.push(mark("pvid-mismatch", &format!("PVID:{}", label))),
+                    Some(PvidNote::Unknown) => parts.push("PVID:unknown".to_string()),
+                    None => {}
+                }
+                parts.join(" ")
+            }
+        }
+    }
+}
+
+pub fn format_vlan_cell(range: &PortRange, vlan_names: &HashMap<u32, String>, style: &VlanStyle) -> VlanCell {
+    if has_no_vlans(range) {
+        return VlanCell::NoVlans;
+    }
+    if is_single_vlan(range) {
+        return VlanCell::Single(format_vlan_list(&range.untagged_vlans, vlan_names, style, |_| false));
+    }
+    let non_empty = |list: String| Some(list).filter(|list| !list.is_empty());
+    let pvid = match pvid_mismatch(range) {
+        Some(pvid) => Some(PvidNote::Mismatch(format_vlan_label(pvid, vlan_names, style))),
+        None => range.pvid.is_none().then_some(PvidNote::Unknown),
+    };
+    VlanCell::Lists {
+        tagged: non_empty(format_tagged_vlans(range, vlan_names, style)),
+        // Untagged VLANs must also be egress VLANs; flag agents that disagree
+        untagged: non_empty(format_vlan_list(&range.untagged_vlans, vlan_names, style,
+            |vlan_id| !range.vlan_memberships.contains(&vlan_id))),
+        pvid,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(pvid: Option<u32>, tagged: &[u32], untagged: &[u32]) -> PortRange {
+        PortRange {
+            pvid,
+            vlan_memberships: tagged.iter().copied().collect(),
+            untagged_vlans: untagged.iter().copied().collect(),
+            ..Default::default()
+        }
+    }
+
+    fn names() -> HashMap<u32, String> {
+        HashMap::from([(1, "default".to_string()), (10, "office".to_string()), (20, "studio".to_string())])
+    }
+
+    #[test]
+    fn vlan_labels_follow_the_display_mode() {
+        let names = HashMap::from([(1, "default".to_string()), (10, "office".to_string())]);
+        let label = |vlan_id, display| format_vlan_label(vlan_id, &names, &VlanStyle { display, ..Default::default() });
+        assert_eq!(label(10, VlanDisplay::Both), "office (10)");
+        assert_eq!(label(1, VlanDisplay::Both), "1");
+        assert_eq!(label(10, VlanDisplay::Id), "10");
+        assert_eq!(label(10, VlanDisplay::Name), "office");
+        assert_eq!(label(1, VlanDisplay::Name), "default");
+        assert_eq!(label(20, VlanDisplay::Name), "20");
+    }
+
+    #[test]
+    fn unnamed_vlan_runs_collapse() {
+        let names = HashMap::from([(150, "media".to_string())]);
+        let vlans: HashSet<u32> = (100..=180).chain([5, 7, 8]).collect();
+        assert_eq!(format_vlan_list(&vlans, &names, &VlanStyle::default(), |_| false),
+            "5, 7-8, 100-149, media (150), 151-180");
+        assert_eq!(format_vlan_list(&vlans, &names, &VlanStyle { display: VlanDisplay::Id, ..Default::default() },
+            |vlan_id| vlan_id == 120),
+            "5, 7-8, 100-119, 120!, 121-180");
+
+        let mut range = PortRange { vlan_memberships: vlans, ..Default::default() };
+        let all_names: HashMap<u32, String> = range.vlan_memberships.iter().map(|&v| (v, String::new())).collect();
+        let style = VlanStyle { compact: true, ..Default::default() };
+        assert_eq!(format_tagged_vlans(&range, &all_names, &style), "ALL (84 VLANs)");
+        range.vlan_memberships.remove(&5);
+        assert_eq!(format_tagged_vlans(&range, &all_names, &style), "7-8, 100-180");
+    }
+
+    #[test]
+    fn access_ports_collapse_to_their_vlan() {
+        let style = VlanStyle::default();
+        assert_eq!(format_vlan_cell(&range(Some(10), &[10], &[10]), &names(), &style),
+            VlanCell::Single("office (10)".to_string()));
+        // Untagged without the egress bit is an agent bug worth showing
+        assert_eq!(format_vlan_cell(&range(Some(10), &[], &[10]), &names(), &style),
+            VlanCell::Lists { tagged: None, untagged: Some("office (10)!".to_string()), pvid: None });
+        // A second VLAN, or a PVID elsewhere, keeps the lists
+        assert!(matches!(format_vlan_cell(&range(Some(10), &[10, 20], &[10]), &names(), &style), VlanCell::Lists { .. }));
+        assert!(matches!(format_vlan_cell(&range(Some(0), &[10], &[10]), &names(), &style), VlanCell::Lists { .. }));
+        assert_eq!(format_vlan_cell(&range(Some(0), &[], &[]), &names(), &style), VlanCell::NoVlans);
+    }
+
+    #[test]
+    fn same_vlan_tagged_and_untagged() {
+        let cell = format_vlan_cell(&range(Some(20), &[10, 20], &[20]), &names(), &VlanStyle::default());
+        assert_eq!(cell, VlanCell::Lists {
+            tagged: Some("office (10), studio (20)".to_string()),
+            untagged: Some("studio (20)".to_string()),
+            pvid: None,
+        });
+        assert_eq!(cell.render(|_, text| text.to_string()), "Tagged:[office (10), studio (20)] Untagged:[studio (20)]");
+    }
+
+    #[test]
+    fn vlan1_and_unnamed_vlans_in_cells() {
+        let hidden = VlanStyle { hide_vlan1: true, ..Default::default() };
+        assert_eq!(format_vlan_cell(&range(Some(1), &[1], &[1]), &names(), &VlanStyle::default()),
+            VlanCell::Single("1".to_string()));
+        assert_eq!(format_vlan_cell(&range(Some(1), &[1], &[1]), &names(), &hidden), VlanCell::Single(String::new()));
+        let cell = format_vlan_cell(&range(Some(1), &[1, 30, 31], &[1]), &names(), &hidden);
+        assert_eq!(cell, VlanCell::Lists { tagged: Some("30-31".to_string()), untagged: None, pvid: None });
+    }
+
+    #[test]
+    fn pvid_problems_are_marked() {
+        let mark = |class: &str, text: &str| format!("<{}>{}", class, text);
+        let mismatch = format_vlan_cell(&range(Some(20), &[10], &[10]), &names(), &VlanStyle::default());
+        assert_eq!(mismatch.render(mark), "Tagged:[office (10)] Untagged:[office (10)] <pvid-mismatch>PVID:studio (20)");
+        let unknown = format_vlan_cell(&range(None, &[10], &[10, 30]), &names(), &VlanStyle::default());
+        assert_eq!(unknown.render(mark), "Tagged:[office (10)] Untagged:[office (10), 30!] PVID:unknown");
+        assert_eq!(VlanCell::NoVlans.render(mark), "<no-vlans>—");
+    }
+}
@@ -0,0 +1,134 @@
+//! Problems found while collecting and checking a switch. They end up in the
+//! report's warnings section and the snapshot, not just on stderr.
+use std::fmt;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use crate::checks::Violation;
+use crate::logging::Event;
+
+/// One warning about the switch or the run
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct Warning {
+    /// Machine-readable kind, e.g. "no-vlans" or "missing-pvid"
+    pub code: String,
+    pub ports: Vec<u32>,
+    pub vlan: Option<u32>,
+    pub message: String,
+}
+
+impl Warning {
+    pub fn new(code: &str, ports: Vec<u32>, vlan: Option<u32>, message: impl Into<String>) -> Warning {
+        Warning { code: code.to_string(), ports, vlan, message: message.into() }
+    }
+}
+
+impl From<&Violation> for Warning {
+    fn from(violation: &Violation) -> Warning {
+        Warning::new(violation.check.name(), violation.ports.clone(), violation.vlan, violation.detail.clone())
+    }
+}
+
+/// "code: ports 1, 2, VLAN 10: message", leaving out what does not apply
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut subject = Vec::new();
+        if !self.ports.is_empty() {
+            let ports: Vec<String> = self.ports.iter().map(u32::to_string).collect();
+            let noun = if self.ports.len() == 1 { "port" } else { "ports" };
+            subject.push(format!("{} {}", noun, ports.join(", ")));
+        }
+        if let Some(vlan) = self.vlan {
+            subject.push(format!("VLAN {}", vlan));
+        }
+        write!(f, "{}: ", self.code)?;
+        if !subject.is_empty() {
+            write!(f, "{}: ", subject.join(", "))?;
+        }
+        f.write_str(&self.message)
+    }
+}
+
+/// Warnings in the order they were found
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Warnings(Vec<Warning>);
+
+impl Warnings {
+    pub fn push(&mut self, code: &str, ports: Vec<u32>, vlan: Option<u32>, message: impl Into<String>) {
+        self.0.push(Warning::new(code, ports, vlan, message));
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Warning> {
+        self.0.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Log every warning about `host`, with the host starting each text line if `prefix_host`
+    pub fn echo(&self, host: &str, prefix_host: bool) {
+        for warning in &self.0 {
+            let mut event = Event::warning(&warning.code, warning.message.clone())
+                .host(host, prefix_host)
+                .text(format!("Warning: {}", warning));
+            if !warning.ports.is_empty() {
+                event = event.field("ports", warning.ports.clone());
+            }
+            if let Some(vlan) = warning.vlan {
+                event = event.field("vlan", vlan);
+            }
+            event.emit();
+        }
+    }
+
+    pub fn into_vec(self) -> Vec<Warning> {
+        self.0
+    }
+}
+
+impl Extend<Warning> for Warnings {
+    fn extend<I: IntoIterator<Item = Warning>>(&mut self, warnings: I) {
+        self.0.extend(warnings);
+    }
+}
+
+impl<'a> IntoIterator for &'a Warnings {
+    type Item = &'a Warning;
+    type IntoIter = std::slice::Iter<'a, Warning>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checks::Check;
+
+    #[test]
+    fn display_leaves_out_missing_subjects() {
+        let mut warnings = Warnings::default();
+        warnings.push("missing-pvid", vec![3, 4], None, "no dot1qPvid row; PVID shown as unknown");
+        warnings.push("orphan-vlan", Vec::new(), Some(30), "named but carried by no port");
+        warnings.push("metadata-no-port", Vec::new(), None, "row for port 99 matches no port");
+        let lines: Vec<String> = warnings.iter().map(Warning::to_string).collect();
+        assert_eq!(lines, [
+            "missing-pvid: ports 3, 4: no dot1qPvid row; PVID shown as unknown",
+            "orphan-vlan: VLAN 30: named but carried by no port",
+            "metadata-no-port: row for port 99 matches no port",
+        ]);
+    }
+
+    #[test]
+    fn violations_keep_their_check_name_and_text() {
+        let violation = Violation { check: Check::PvidNotUntagged, ports: vec![8], vlan: Some(20), detail: "PVID 20".to_string() };
+        let warning = Warning::from(&violation);
+        assert_eq!(warning.code, "pvid-not-untagged");
+        assert_eq!(warning.to_string(), violation.to_string());
+    }
+}
@@ -0,0 +1,273 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{self, BufWriter, ErrorKind, Write};
+use std::net::{IpAddr, UdpSocket};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use anyhow::{Result, anyhow};
+use chrono::Local;
+use switch_vlan_diagram::cancel;
+use switch_vlan_diagram::error::EXIT_INTERRUPTED;
+use switch_vlan_diagram::logging::{Event, Level};
+use switch_vlan_diagram::traps::{inform_response, Notification};
+use crate::output::format_port_set;
+use switch_vlan_diagram::{Dot1xInfo, LacpInfo};
+use crate::{PortRange, Report};
+
+/// Replace `path` with `contents` and a newline without readers ever seeing a half-written file
+pub fn write_atomically(path: &Path, contents: &str) -> Result<()> {
+    write_atomically_with(path, |w| writeln!(w, "{}", contents))
+}
+
+/// Like write_atomically, with the contents streamed in by `write`
+pub fn write_atomically_with(path: &Path, write: impl FnOnce(&mut dyn Write) -> io::Result<()>) -> Result<()> {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    let temp = Path::new(&temp);
+    File::create(temp)
+        .and_then(|file| {
+            let mut file = BufWriter::new(file);
+            write(&mut file)?;
+            file.flush()
+        })
+        .map_err(|e| anyhow!("Failed to write {}: {}", temp.display(), e))?;
+    std::fs::rename(temp, path)
+        .map_err(|e| anyhow!("Failed to replace {}: {}", path.display(), e))
+}
+
+/// Poll and rewrite `output` every `interval` until SIGINT. A failed cycle is
+/// logged and leaves the previous file in place.
+pub fn run(interval: Duration, output: &Path, mut poll: impl FnMut() -> Result<Report>) -> Result<()> {
+    let mut previous: Option<Vec<PortRange>> = None;
+    while !stopped() {
+        let started = Instant::now();
+        let (level, status) = cycle(&mut previous, || poll().and_then(|report| report.write(Some(output)).map(|_| report)));
+        let elapsed = started.elapsed();
+        Event::new(level, "watch-cycle", status.clone())
+            .duration(elapsed)
+            .text(format!("{} {} in {:.1}s", Local::now().format("%Y-%m-%d %H:%M:%S"), status, elapsed.as_secs_f64()))
+            .emit();
+
+        // Sleep in small steps so an interrupt does not wait for the whole interval
+        let next = started + interval;
+        while !stopped() && Instant::now() < next {
+            std::thread::sleep(Duration::from_millis(100));
+        }
+    }
+    Ok(())
+}
+
+/// A switch that may send traps to --trap-listen
+pub struct TrapSource {
+    /// The switch as given with --ip
+    pub host: String,
+    pub community: Vec<u8>,
+}
+
+/// Notifications received by --trap-listen, by what became of them
+#[derive(Debug, Default)]
+struct TrapCounts {
+    received: u64,
+    /// Link or configuration changes, which led to a regeneration
+    triggers: u64,
+    /// Well-formed and authorized, but about nothing the report shows
+    ignored: u64,
+    unparseable: u64,
+    /// From an address not given with --ip, or with the wrong community
+    unauthorized: u64,
+}
+
+/// Traps from one switch waiting for its regeneration
+struct Pending {
+    due: Instant,
+    traps: Vec<&'static str>,
+}
+
+/// Generate every switch once, then regenerate a switch whenever it sends a
+/// linkUp, linkDown or configuration change trap to `listen`, until SIGINT.
+/// Traps arriving within `debounce` of a switch's first one are handled by the
+/// same regeneration. `regenerate` writes the switch's outputs.
+pub fn listen(
+    listen: &str,
+    sources: &HashMap<IpAddr, TrapSource>,
+    hosts: &[String],
+    debounce: Duration,
+    mut regenerate: impl FnMut(&str) -> Result<Report>,
+) -> Result<()> {
+    let socket = UdpSocket::bind(listen).map_err(|e| anyhow!("Failed to listen for traps on {}: {}", listen, e))?;
+    // Wake up now and then to notice an interrupt or a regeneration that is due
+    socket.set_read_timeout(Some(Duration::from_millis(100)))
+        .map_err(|e| anyhow!("Failed to listen for traps on {}: {}", listen, e))?;
+    let address = socket.local_addr().map_or_else(|_| listen.to_string(), |address| address.to_string());
+    Event::info("trap-listening", format!("Listening for traps on {}", address))
+        .field("address", address.as_str())
+        .emit();
+
+    let mut previous: HashMap<String, Option<Vec<PortRange>>> = HashMap::new();
+    let mut regenerate_host = |host: &str, traps: &[&str]| {
+        let started = Instant::now();
+        let (level, status) = cycle(previous.entry(host.to_string()).or_default(), || regenerate(host));
+        let status = match traps {
+            [] => status,
+            traps => format!("{} after {}", status, traps.join(", ")),
+        };
+        let elapsed = started.elapsed();
+        Event::new(level, "trap-cycle", status.clone())
+            .host(host, false)
+            .duration(elapsed)
+            .text(format!("{} {}: {} in {:.1}s", Local::now().format("%Y-%m-%d %H:%M:%S"), host, status, elapsed.as_secs_f64()))
+            .emit();
+    };
+    for host in hosts {
+        regenerate_host(host, &[]);
+    }
+
+    let mut counts = TrapCounts::default();
+    let mut pending: HashMap<IpAddr, Pending> = HashMap::new();
+    let mut datagram = [0u8; 65535];
+    while !stopped() {
+        match socket.recv_from(&mut datagram) {
+            Ok((len, peer)) => {
+                counts.received += 1;
+                let rejected = |reason: String| Event::warning("trap-rejected", format!("Trap from {} rejected: {}", peer.ip(), reason))
+                    .field("source", peer.ip().to_string())
+                    .field("reason", reason)
+                    .emit();
+                let Some(source) = sources.get(&peer.ip()) else {
+                    counts.unauthorized += 1;
+                    rejected("not one of the switches given with --ip".to_string());
+                    continue;
+                };
+                let notification = match Notification::parse(&datagram[..len]) {
+                    Ok(notification) => notification,
+                    Err(reason) => {
+                        counts.unparseable += 1;
+                        rejected(reason);
+                        continue;
+                    }
+                };
+                if notification.community != source.community {
+                    counts.unauthorized += 1;
+                    rejected("wrong community".to_string());
+                    continue;
+                }
+                // Answer informs even when they change nothing, or the switch keeps resending them
+                if notification.inform {
+                    if let Some(response) = inform_response(&datagram[..len]) {
+                        let _ = socket.send_to(&response, peer);
+                    }
+                }
+                match notification.trigger() {
+                    Some(trap) => {
+                        counts.triggers += 1;
+                        let waiting = pending.entry(peer.ip()).or_insert_with(|| Pending { due: Instant::now() + debounce, traps: Vec::new() });
+                        if !waiting.traps.contains(&trap) {
+                            waiting.traps.push(trap);
+                        }
+                    }
+                    None => counts.ignored += 1,
+                }
+            }
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::Interrupted) => {}
+            // E.g. an ICMP error for an earlier inform response; the socket is still fine
+            Err(e) => Event::warning("trap-receive", format!("Failed to receive a trap: {}", e)).emit(),
+        }
+
+        let now = Instant::now();
+        let due: Vec<IpAddr> = pending.iter().filter(|(_, waiting)| waiting.due <= now).map(|(ip, _)| *ip).collect();
+        for ip in due {
+            if let (Some(waiting), Some(source)) = (pending.remove(&ip), sources.get(&ip)) {
+                regenerate_host(&source.host, &waiting.traps);
+            }
+        }
+    }
+
+    Event::info("trap-summary", format!("{} traps received: {} changes, {} ignored, {} unparseable, {} unauthorized",
+            counts.received, counts.triggers, counts.ignored, counts.unparseable, counts.unauthorized))
+        .field("received", counts.received)
+        .field("triggers", counts.triggers)
+        .field("ignored", counts.ignored)
+        .field("unparseable", counts.unparseable)
+        .field("unauthorized", counts.unauthorized)
+        .emit();
+    Ok(())
+}
+
+/// Set by the first SIGINT
+static STOP: AtomicBool = AtomicBool::new(false);
+
+/// Handle SIGINT. The first one ends --watch and --trap-listen once the poll in
+/// flight is written; otherwise, with `cancel_collection`, it cuts collection
+/// short so that what was fetched can be rendered. A second one exits at once.
+pub fn handle_interrupts(cancel_collection: bool) -> Result<()> {
+    ctrlc::set_handler(move || {
+        if STOP.swap(true, Ordering::SeqCst) {
+            std::process::exit(EXIT_INTERRUPTED);
+        }
+        if cancel_collection {
+            cancel::cancel();
+        } else {
+            cancel::signalled();
+        }
+    })
+    .map_err(|e| anyhow!("Failed to install the interrupt handler: {}", e))
+}
+
+fn stopped() -> bool {
+    STOP.load(Ordering::SeqCst)
+}
+
+/// Run one poll and describe its outcome against the one before
+fn cycle(previous: &mut Option<Vec<PortRange>>, poll: impl FnOnce() -> Result<Report>) -> (Level, String) {
+    match poll() {
+        Ok(report) => {
+            let status = match previous {
+                None => "written".to_string(),
+                Some(previous) => match changed_ports(previous, &report.ranges).as_slice() {
+                    [] => "unchanged".to_string(),
+                    ports => format!("changed (ports {})", format_port_set(ports)),
+                },
+            };
+            *previous = Some(report.ranges);
+            (Level::Info, status)
+        }
+        Err(e) => (Level::Error, format!("error: {}", e)),
+    }
+}
+
+/// Ports whose configuration differs between two polls, including ports that
+/// appeared or disappeared
+fn changed_ports(before: &[PortRange], after: &[PortRange]) -> Vec<u32> {
+    let before = port_signatures(before);
+    let after = port_signatures(after);
+    let mut ports: Vec<u32> = before.keys().chain(after.keys())
+        .filter(|port| before.get(port) != after.get(port))
+        .copied()
+        .collect();
+    ports.sort_unstable();
+    ports.dedup();
+    ports
+}
+
+/// What a port looks like in the report, independent of how it was grouped
+type PortSignature<'a> = (Option<u32>, Vec<u32>, Vec<u32>, Option<&'a str>, Option<&'a LacpInfo>, Option<Dot1xInfo>);
+
+fn port_signatures(ranges: &[PortRange]) -> BTreeMap<u32, PortSignature<'_>> {
+    let mut signatures = BTreeMap::new();
+    for range in ranges {
+        let mut tagged: Vec<u32> = range.vlan_memberships.iter().copied().collect();
+        tagged.sort_unstable();
+        let mut untagged: Vec<u32> = range.untagged_vlans.iter().copied().collect();
+        untagged.sort_unstable();
+        for port in range.ports() {
+            let alias = range.port_aliases.iter()
+                .find(|(p, _)| *p == port)
+                .map(|(_, alias)| alias.as_str());
+            // Compared as values: the Debug text of the aggregator's VLAN sets depends on their hash order
+            let signature = (range.pvid, tagged.clone(), untagged.clone(), alias, range.lacp_info.as_ref(), range.dot1x);
+            signatures.insert(port, signature);
+        }
+    }
+    signatures
+}
@@ -0,0 +1,238 @@
+use std::collections::BTreeSet;
+use std::str::FromStr;
+use std::thread::sleep;
+use std::time::Duration;
+use serde_json::{json, Value};
+use switch_vlan_diagram::logging::Event;
+use switch_vlan_diagram::output::format_port_set;
+use switch_vlan_diagram::snapshot::{PortChange, Snapshot, SnapshotDiff, SnapshotPort};
+
+/// How long one delivery may take, connecting included
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Delay before the one retry of a transient failure
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// Body of a --webhook request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WebhookFormat {
+    /// `{"text": ...}`, as Slack and Matrix hook bridges expect
+    Slack,
+    /// The host, event and changed ports with their VLANs before and after
+    GenericJson,
+}
+
+impl FromStr for WebhookFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "slack" => Ok(WebhookFormat::Slack),
+            "generic-json" => Ok(WebhookFormat::GenericJson),
+            _ => Err(format!("Unknown webhook format '{}'. Expected: slack or generic-json", s)),
+        }
+    }
+}
+
+/// When --webhook fires
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WebhookOn {
+    /// Only when a poll fails
+    Error,
+    /// Only when the switch differs from the previous poll or the --diff snapshot
+    Change,
+    /// After every poll, changed, unchanged or failed
+    Always,
+}
+
+impl FromStr for WebhookOn {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "error" => Ok(WebhookOn::Error),
+            "change" => Ok(WebhookOn::Change),
+            "always" => Ok(WebhookOn::Always),
+            _ => Err(format!("Unknown webhook trigger '{}'. Expected: error, change or always", s)),
+        }
+    }
+}
+
+/// What a poll found, compared with the one before
+pub enum Outcome<'a> {
+    Changed { before: &'a Snapshot, after: &'a Snapshot, diff: &'a SnapshotDiff },
+    /// Nothing to compare with, e.g. the first --watch cycle
+    Written,
+    Unchanged,
+    Failed(&'a anyhow::Error),
+}
+
+impl Outcome<'_> {
+    fn event(&self) -> &'static str {
+        match self {
+            Outcome::Changed { .. } => "change",
+            Outcome::Written => "written",
+            Outcome::Unchanged => "unchanged",
+            Outcome::Failed(_) => "error",
+        }
+    }
+}
+
+pub struct Webhook {
+    pub url: String,
+    pub format: WebhookFormat,
+    pub on: WebhookOn,
+}
+
+impl Webhook {
+    /// POST `outcome` if --webhook-on asks for it. A failed delivery is logged, never fatal.
+    pub fn notify(&self, host: &str, outcome: &Outcome) {
+        let fires = match self.on {
+            WebhookOn::Always => true,
+            WebhookOn::Change => matches!(outcome, Outcome::Changed { .. }),
+            WebhookOn::Error => matches!(outcome, Outcome::Failed(_)),
+        };
+        if !fires {
+            return;
+        }
+        let body = match self.format {
+            WebhookFormat::Slack => json!({ "text": slack_text(host, outcome) }),
+            WebhookFormat::GenericJson => generic_json(host, outcome),
+        };
+        if let Err(reason) = self.send(&body.to_string()) {
+            Event::warning("webhook-failed", format!("Webhook to {} failed: {}", redact_url(&self.url), reason))
+                .host(host, false)
+                .emit();
+        }
+    }
+
+    /// Post `body`, retrying once after a connection problem or a 5xx response
+    fn send(&self, body: &str) -> Result<(), String> {
+        let agent = ureq::AgentBuilder::new().timeout(TIMEOUT).build();
+        let mut retried = false;
+        loop {
+            let (reason, transient) = match agent.post(&self.url).set("Content-Type", "application/json").send_string(body) {
+                Ok(_) => return Ok(()),
+                Err(ureq::Error::Status(status, response)) => {
+                    (format!("HTTP {} {}", status, response.status_text()), status >= 500)
+                }
+                // Transport errors quote the URL, secrets and all
+                Err(ureq::Error::Transport(transport)) => match transport.message() {
+                    Some(message) => (format!("{}: {}", transport.kind(), message), true),
+                    None => (transport.kind().to_string(), true),
+                },
+            };
+            let reason = reason.replace(&self.url, &redact_url(&self.url));
+            if retried || !transient {
+                return Err(reason);
+            }
+            retried = true;
+            sleep(RETRY_DELAY);
+        }
+    }
+}
+
+/// Scheme and host of `url` only; hook URLs carry their token in the path,
+/// the query or the user info
+pub fn redact_url(url: &str) -> String {
+    let (scheme, rest) = url.split_once("://").unwrap_or(("", url));
+    let end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+    let authority = &rest[..end];
+    let host = authority.rsplit_once('@').map_or(authority, |(_, host)| host);
+    let hidden = if end < rest.len() { "/[redacted]" } else { "" };
+    match scheme {
+        "" => format!("{}{}", host, hidden),
+        scheme => format!("{}://{}{}", scheme, host, hidden),
+    }
+}
+
+/// PVID, untagged and tagged VLANs, e.g. "PVID 10, untagged 10, tagged 20-22"
+fn describe_vlans(port: &SnapshotPort) -> String {
+    let untagged: Vec<u32> = port.untagged.iter().copied().collect();
+    let tagged: Vec<u32> = port.vlans.difference(&port.untagged).copied().collect();
+    let pvid = port.pvid.map_or_else(|| "unknown".to_string(), |pvid| pvid.to_string());
+    let list = |vlans: &[u32]| if vlans.is_empty() { "none".to_string() } else { format_port_set(vlans) };
+    format!("PVID {}, untagged {}, tagged {}", pvid, list(&untagged), list(&tagged))
+}
+
+fn vlans_json(port: &SnapshotPort) -> Value {
+    let tagged: BTreeSet<u32> = port.vlans.difference(&port.untagged).copied().collect();
+    json!({ "pvid": port.pvid, "untagged": port.untagged, "tagged": tagged })
+}
+
+/// The port as it was and as it is, None where it did not exist
+fn before_after<'a>(port: u32, change: &'a PortChange, before: &'a Snapshot, after: &'a Snapshot)
+    -> (Option<&'a SnapshotPort>, Option<&'a SnapshotPort>) {
+    let find = |snapshot: &'a Snapshot| snapshot.ports.iter().find(|p| p.port == port);
+    match change {
+        PortChange::Added => (None, find(after)),
+        PortChange::Removed(was) => (Some(was.as_ref()), None),
+        PortChange::Changed(_) => (find(before), find(after)),
+    }
+}
+
+fn slack_text(host: &str, outcome: &Outcome) -> String {
+    match outcome {
+        Outcome::Changed { before, after, diff } => {
+            let mut lines = vec![format!("*{}*: {}", host, diff)];
+            for (&port, change) in &diff.ports {
+                let vlans = match before_after(port, change, before, after) {
+                    (Some(was), Some(now)) => format!("{} -> {}", describe_vlans(was), describe_vlans(now)),
+                    (None, Some(now)) => describe_vlans(now),
+                    (Some(was), None) => format!("was {}", describe_vlans(was)),
+                    (None, None) => String::new(),
+                };
+                lines.push(format!("• port {}: {} ({})", port, change, vlans));
+            }
+            lines.join("\n")
+        }
+        Outcome::Written => format!("*{}*: report written", host),
+        Outcome::Unchanged => format!("*{}*: unchanged", host),
+        Outcome::Failed(error) => format!("*{}*: poll failed: {}", host, error),
+    }
+}
+
+fn generic_json(host: &str, outcome: &Outcome) -> Value {
+    let mut body = json!({ "host": host, "event": outcome.event() });
+    match outcome {
+        Outcome::Changed { before, after, diff } => {
+            let ports: Vec<Value> = diff.ports.iter()
+                .map(|(&port, change)| {
+                    let (was, now) = before_after(port, change, before, after);
+                    json!({
+                        "port": port,
+                        "change": change.to_string(),
+                        "before": was.map(vlans_json),
+                        "after": now.map(vlans_json),
+                    })
+                })
+                .collect();
+            body["ports"] = Value::from(ports);
+            body["vlan_names_changed"] = Value::from(diff.vlan_names_changed);
+        }
+        Outcome::Failed(error) => body["error"] = Value::from(error.to_string()),
+        Outcome::Written | Outcome::Unchanged => {}
+    }
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secrets_are_left_out_of_logged_urls() {
+        assert_eq!(redact_url("https://hooks.slack.com/services/T0/B0/secret"), "https://hooks.slack.com/[redacted]");
+        assert_eq!(redact_url("https://bot:pw@matrix.example:8448?access_token=x"), "https://matrix.example:8448/[redacted]");
+        assert_eq!(redact_url("http://10.0.0.5:8080"), "http://10.0.0.5:8080");
+    }
+
+    #[test]
+    fn parses_formats_and_triggers() {
+        assert_eq!("Slack".parse::<WebhookFormat>(), Ok(WebhookFormat::Slack));
+        assert_eq!("generic-json".parse::<WebhookFormat>(), Ok(WebhookFormat::GenericJson));
+        assert!("teams".parse::<WebhookFormat>().is_err());
+        assert_eq!("always".parse::<WebhookOn>(), Ok(WebhookOn::Always));
+        assert!("sometimes".parse::<WebhookOn>().is_err());
+    }
+}
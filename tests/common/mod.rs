@@ -0,0 +1,233 @@
+//! A simulated SNMPv2c agent for integration tests: a UDP responder on a free
+//! localhost port that answers GET, GETNEXT and GETBULK from a recorded walk
+use std::fs;
+use std::net::UdpSocket;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use snmp::{asn1, AsnReader, SnmpMessageType, SnmpPdu};
+use switch_vlan_diagram::snmp_utils::{RecordedWalk, SnmpSource, SnmpValue};
+
+pub const COMMUNITY: &str = "public";
+
+// Exceptions in a response varbind (RFC 3416)
+const NO_SUCH_INSTANCE: u8 = 0x81;
+const END_OF_MIB_VIEW: u8 = 0x82;
+
+pub fn fixture_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("fixtures").join(name)
+}
+
+/// Serves one fixture until dropped
+pub struct SimulatedAgent {
+    pub port: u16,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl SimulatedAgent {
+    /// Serve tests/fixtures/`fixture` to requests with the community "public"
+    pub fn start(fixture: &str) -> SimulatedAgent {
+        SimulatedAgent::start_at(fixture, "127.0.0.1:0")
+    }
+
+    /// Like `start`, on another address, e.g. a second loopback address with
+    /// the port of a first agent so that one --port reaches both
+    pub fn start_at(fixture: &str, address: &str) -> SimulatedAgent {
+        SimulatedAgent::serve_at(fixture, address, Duration::ZERO)
+    }
+
+    /// Like `start`, answering every request `delay` late, like a busy switch
+    #[allow(dead_code)]  // Only the exit status tests interrupt a slow poll
+    pub fn start_slow(fixture: &str, delay: Duration) -> SimulatedAgent {
+        SimulatedAgent::serve_at(fixture, "127.0.0.1:0", delay)
+    }
+
+    fn serve_at(fixture: &str, address: &str, delay: Duration) -> SimulatedAgent {
+        let text = fs::read_to_string(fixture_path(fixture)).expect("fixture walk");
+        let walk = RecordedWalk::parse(&text).expect("valid fixture walk");
+        let socket = UdpSocket::bind(address).expect("free localhost port");
+        // Wake up now and then to notice a stop request
+        socket.set_read_timeout(Some(Duration::from_millis(50))).unwrap();
+        let port = socket.local_addr().unwrap().port();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = thread::spawn({
+            let stop = Arc::clone(&stop);
+            move || serve(&socket, walk, &stop, delay)
+        });
+        SimulatedAgent { port, stop, thread: Some(thread) }
+    }
+}
+
+impl Drop for SimulatedAgent {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn serve(socket: &UdpSocket, mut walk: RecordedWalk, stop: &AtomicBool, delay: Duration) {
+    let mut request = [0u8; 4096];
+    while !stop.load(Ordering::SeqCst) {
+        let Ok((len, peer)) = socket.recv_from(&mut request) else {
+            continue;
+        };
+        thread::sleep(delay);
+        if let Some(response) = respond(&mut walk, &request[..len]) {
+            let _ = socket.send_to(&response, peer);
+        }
+    }
+}
+
+/// The encoded response, or None for requests a real agent would drop
+/// silently, such as those with a wrong community
+fn respond(walk: &mut RecordedWalk, request: &[u8]) -> Option<Vec<u8>> {
+    let mut message = AsnReader::from_bytes(request);
+    let mut message = AsnReader::from_bytes(message.read_raw(asn1::TYPE_SEQUENCE).ok()?);
+    message.read_asn_integer().ok()?;
+    if message.read_asn_octetstring().ok()? != COMMUNITY.as_bytes() {
+        return None;
+    }
+
+    let pdu = SnmpPdu::from_bytes(request).ok()?;
+    let names: Vec<Vec<u32>> = pdu.varbinds.map(|(name, _)| parse_oid(&name.to_string())).collect();
+    let mut bindings = Vec::new();
+    match pdu.message_type {
+        SnmpMessageType::GetRequest => for name in names {
+            let value = walk.get(&name).ok()?.map_or_else(|| exception(NO_SUCH_INSTANCE), |value| encode_value(&value));
+            bindings.push((name, value));
+        },
+        SnmpMessageType::GetNextRequest => for name in &names {
+            bindings.push(next_binding(walk, name)?);
+        },
+        // For GETBULK the error status and index fields carry non-repeaters and max-repetitions
+        SnmpMessageType::GetBulkRequest => {
+            let non_repeaters = (pdu.error_status as usize).min(names.len());
+            for name in &names[..non_repeaters] {
+                bindings.push(next_binding(walk, name)?);
+            }
+            let mut cursors = names[non_repeaters..].to_vec();
+            for _ in 0..pdu.error_index {
+                for cursor in &mut cursors {
+                    let (name, value) = next_binding(walk, cursor)?;
+                    *cursor = name.clone();
+                    bindings.push((name, value));
+                }
+            }
+        }
+        _ => return None,
+    }
+    Some(encode_response(pdu.req_id, &bindings))
+}
+
+/// An SNMPv2c trap or inform (`tag`) naming `trap_oid` in snmpTrapOID.0
+#[allow(dead_code)]  // Only the trap listener tests send notifications
+pub fn encode_notification(tag: u8, community: &str, req_id: i32, trap_oid: &[u32]) -> Vec<u8> {
+    let bindings = [
+        // sysUpTime.0, as TimeTicks
+        (vec![1, 3, 6, 1, 2, 1, 1, 3, 0], tlv(snmp::snmp::TYPE_TIMETICKS, &[0x10])),
+        // snmpTrapOID.0
+        (vec![1, 3, 6, 1, 6, 3, 1, 1, 4, 1, 0], encode_oid(trap_oid)),
+    ];
+    let varbinds: Vec<u8> = bindings.iter()
+        .flat_map(|(name, value)| tlv(asn1::TYPE_SEQUENCE, &[encode_oid(name), value.clone()].concat()))
+        .collect();
+    let pdu = tlv(tag, &[
+        encode_integer(req_id.into()),
+        encode_integer(0),
+        encode_integer(0),
+        tlv(asn1::TYPE_SEQUENCE, &varbinds),
+    ].concat());
+    tlv(asn1::TYPE_SEQUENCE, &[
+        encode_integer(snmp::snmp::VERSION_2),
+        tlv(asn1::TYPE_OCTETSTRING, community.as_bytes()),
+        pdu,
+    ].concat())
+}
+
+fn next_binding(walk: &mut RecordedWalk, name: &[u32]) -> Option<(Vec<u32>, Vec<u8>)> {
+    Some(match walk.get_next(name).ok()? {
+        Some((next, Some(value))) => (next, encode_value(&value)),
+        _ => (name.to_vec(), exception(END_OF_MIB_VIEW)),
+    })
+}
+
+fn parse_oid(oid: &str) -> Vec<u32> {
+    oid.split('.').filter_map(|part| part.parse().ok()).collect()
+}
+
+fn encode_response(req_id: i32, bindings: &[(Vec<u32>, Vec<u8>)]) -> Vec<u8> {
+    let varbinds: Vec<u8> = bindings.iter()
+        .flat_map(|(name, value)| tlv(asn1::TYPE_SEQUENCE, &[encode_oid(name), value.clone()].concat()))
+        .collect();
+    let pdu = tlv(snmp::snmp::MSG_RESPONSE, &[
+        encode_integer(req_id.into()),
+        encode_integer(0),  // error status
+        encode_integer(0),  // error index
+        tlv(asn1::TYPE_SEQUENCE, &varbinds),
+    ].concat());
+    tlv(asn1::TYPE_SEQUENCE, &[
+        encode_integer(snmp::snmp::VERSION_2),
+        tlv(asn1::TYPE_OCTETSTRING, COMMUNITY.as_bytes()),
+        pdu,
+    ].concat())
+}
+
+fn encode_value(value: &SnmpValue) -> Vec<u8> {
+    match value {
+        SnmpValue::Integer(n) => encode_integer((*n).into()),
+        SnmpValue::Bytes(bytes) => tlv(asn1::TYPE_OCTETSTRING, bytes),
+    }
+}
+
+fn exception(tag: u8) -> Vec<u8> {
+    vec![tag, 0]
+}
+
+/// Type, BER length and content
+fn tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut encoded = vec![tag];
+    if content.len() < 0x80 {
+        encoded.push(content.len() as u8);
+    } else {
+        let length: Vec<u8> = content.len().to_be_bytes().into_iter().skip_while(|&b| b == 0).collect();
+        encoded.push(0x80 | length.len() as u8);
+        encoded.extend(length);
+    }
+    encoded.extend_from_slice(content);
+    encoded
+}
+
+/// Two's complement in as few bytes as keep the sign
+fn encode_integer(n: i64) -> Vec<u8> {
+    let bytes = n.to_be_bytes();
+    let mut start = 0;
+    while start < bytes.len() - 1 {
+        let redundant = (bytes[start] == 0x00 && bytes[start + 1] & 0x80 == 0)
+            || (bytes[start] == 0xff && bytes[start + 1] & 0x80 != 0);
+        if !redundant {
+            break;
+        }
+        start += 1;
+    }
+    tlv(asn1::TYPE_INTEGER, &bytes[start..])
+}
+
+fn encode_oid(oid: &[u32]) -> Vec<u8> {
+    let mut content = vec![(oid[0] * 40 + oid[1]) as u8];
+    for &subid in &oid[2..] {
+        // Base 128, most significant group first, continuation bit on all but the last
+        let mut groups = vec![(subid & 0x7f) as u8];
+        let mut rest = subid >> 7;
+        while rest > 0 {
+            groups.push((rest & 0x7f) as u8 | 0x80);
+            rest >>= 7;
+        }
+        content.extend(groups.iter().rev());
+    }
+    tlv(asn1::TYPE_OBJECTIDENTIFIER, &content)
+}
@@ -0,0 +1,183 @@
+//! The exit status of the binary for each class of failure
+mod common;
+
+use std::fs;
+use std::net::UdpSocket;
+use std::path::PathBuf;
+use std::process::{Child, Command, Output};
+use std::thread;
+use std::time::{Duration, Instant};
+use common::{fixture_path, SimulatedAgent, COMMUNITY};
+
+/// A localhost UDP port nothing listens on; the kernel refuses datagrams to it
+fn refused_port() -> u16 {
+    UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
+
+fn switch_vlan_diagram(args: &[&str]) -> Output {
+    let scratch = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("exit-codes");
+    fs::create_dir_all(&scratch).unwrap();
+    Command::new(env!("CARGO_BIN_EXE_switch-vlan-diagram"))
+        .args(["--community", COMMUNITY, "--timeout", "1", "--quiet"])
+        .args(args)
+        .env("XDG_CONFIG_HOME", &scratch)
+        .output()
+        .unwrap()
+}
+
+fn poll(ips: &str, port: u16, extra: &[&str]) -> Output {
+    switch_vlan_diagram(&[&["--ip", ips, "--port", &port.to_string()], extra].concat())
+}
+
+#[test]
+fn success_is_zero() {
+    let agent = SimulatedAgent::start("procurve-2530-lacp.walk");
+    assert_eq!(poll("127.0.0.1", agent.port, &[]).status.code(), Some(0));
+}
+
+#[test]
+fn usage_errors_are_one() {
+    let output = switch_vlan_diagram(&["--ip", "127.0.0.1", "--no-such-flag"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--no-such-flag"));
+    // Help is not an error
+    let help = switch_vlan_diagram(&["--help"]);
+    assert_eq!(help.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&help.stdout).contains("Exit status:"));
+}
+
+#[test]
+fn unreachable_agents_are_two() {
+    let output = poll("127.0.0.1", refused_port(), &[]);
+    assert_eq!(output.status.code(), Some(2), "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+#[test]
+fn strict_violations_are_four() {
+    let agent = SimulatedAgent::start("pvid-not-untagged.walk");
+    let output = poll("127.0.0.1", agent.port, &["--strict"]);
+    assert_eq!(output.status.code(), Some(4), "{}", String::from_utf8_lossy(&output.stderr));
+    // The report is still written
+    assert!(String::from_utf8_lossy(&output.stdout).contains("- pvid-not-untagged: port 3"));
+    assert_eq!(poll("127.0.0.1", agent.port, &[]).status.code(), Some(0));
+}
+
+#[test]
+fn orphan_vlans_warn_without_failing_strict() {
+    let agent = SimulatedAgent::start("orphan-vlan.walk");
+    let output = poll("127.0.0.1", agent.port, &["--strict"]);
+    assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("- orphan-vlan: VLAN 99: \"legacy\" is defined but carried on no port"));
+}
+
+#[test]
+fn some_failed_switches_are_five_and_all_take_the_failure_class() {
+    // The agent only listens on 127.0.0.1
+    let agent = SimulatedAgent::start("procurve-2530-lacp.walk");
+    let scratch = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("exit-codes-hosts");
+    fs::create_dir_all(&scratch).unwrap();
+    let output = scratch.join("{host}.md");
+    let output = output.to_str().unwrap();
+
+    let partial = poll("127.0.0.1,127.0.0.2", agent.port, &["--output", output]);
+    assert_eq!(partial.status.code(), Some(5), "{}", String::from_utf8_lossy(&partial.stderr));
+    assert!(scratch.join("127.0.0.1.md").exists());
+
+    let all = poll("127.0.0.2,127.0.0.3", agent.port, &["--output", output]);
+    assert_eq!(all.status.code(), Some(2), "{}", String::from_utf8_lossy(&all.stderr));
+}
+
+#[test]
+fn changes_are_six() {
+    let agent = SimulatedAgent::start("procurve-2530-lacp.walk");
+    let scratch = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("exit-codes-changes");
+    fs::create_dir_all(&scratch).unwrap();
+    let snapshot = scratch.join("snapshot.json");
+    let _ = fs::remove_file(&snapshot);
+    let snapshot = snapshot.to_str().unwrap();
+
+    let first = poll("127.0.0.1", agent.port, &["--snapshot", snapshot, "--exit-code-on-change"]);
+    assert_eq!(first.status.code(), Some(6), "{}", String::from_utf8_lossy(&first.stderr));
+    let again = poll("127.0.0.1", agent.port, &["--snapshot", snapshot, "--exit-code-on-change"]);
+    assert_eq!(again.status.code(), Some(0), "{}", String::from_utf8_lossy(&again.stderr));
+}
+
+#[test]
+fn vlan_mismatches_are_seven() {
+    let agent = SimulatedAgent::start("procurve-2530-lacp.walk");
+    let same = poll("127.0.0.1", agent.port, &["--compare-vlans", "127.0.0.1"]);
+    assert_eq!(same.status.code(), Some(0), "{}", String::from_utf8_lossy(&same.stderr));
+    assert!(String::from_utf8_lossy(&same.stdout).contains("define the same"));
+
+    let snapshot = fixture_path("cisco-2960-bridge-ports.json");
+    let other = poll("127.0.0.1", agent.port, &["--compare-vlans", snapshot.to_str().unwrap()]);
+    assert_eq!(other.status.code(), Some(7), "{}", String::from_utf8_lossy(&other.stderr));
+    let table = String::from_utf8_lossy(&other.stdout);
+    assert!(table.starts_with("| VLAN | 127.0.0.1 "), "{}", table);
+}
+
+/// Start polling a switch that answers every request late, writing to `output`
+fn spawn_slow(agent: &SimulatedAgent, output: &str, extra: &[&str]) -> Child {
+    Command::new(env!("CARGO_BIN_EXE_switch-vlan-diagram"))
+        .args(["--ip", "127.0.0.1", "--port", &agent.port.to_string(), "--community", COMMUNITY])
+        .args(["--timeout", "5", "--quiet", "--deterministic", "--output", output])
+        .args(extra)
+        .env("XDG_CONFIG_HOME", env!("CARGO_TARGET_TMPDIR"))
+        .spawn()
+        .unwrap()
+}
+
+fn interrupt(child: &Child) {
+    let status = Command::new("kill").args(["-INT", &child.id().to_string()]).status().unwrap();
+    assert!(status.success());
+}
+
+/// A fresh scratch directory and the path of a report in it
+fn scratch_report(name: &str) -> PathBuf {
+    let scratch = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name);
+    fs::create_dir_all(&scratch).unwrap();
+    let output = scratch.join("report.md");
+    let _ = fs::remove_file(&output);
+    output
+}
+
+#[test]
+fn interrupts_write_a_partial_report_and_are_130() {
+    let agent = SimulatedAgent::start_slow("procurve-2530-lacp.walk", Duration::from_millis(20));
+    let output = scratch_report("exit-codes-interrupted");
+    let mut child = spawn_slow(&agent, output.to_str().unwrap(), &[]);
+    thread::sleep(Duration::from_millis(500));
+    interrupt(&child);
+    assert_eq!(child.wait().unwrap().code(), Some(130));
+    let report = fs::read_to_string(&output).unwrap();
+    assert!(report.contains("> **PARTIAL — collection interrupted**"), "{}", report);
+    assert!(report.contains("(interrupted)"), "{}", report);
+}
+
+#[test]
+fn watch_finishes_the_poll_in_flight() {
+    let agent = SimulatedAgent::start_slow("procurve-2530-lacp.walk", Duration::from_millis(20));
+    let output = scratch_report("exit-codes-watch-interrupted");
+    let mut child = spawn_slow(&agent, output.to_str().unwrap(), &["--watch", "60"]);
+    thread::sleep(Duration::from_millis(500));
+    interrupt(&child);
+    assert_eq!(child.wait().unwrap().code(), Some(0));
+    let report = fs::read_to_string(&output).unwrap();
+    assert!(!report.contains("PARTIAL") && report.contains("| 9-10\\* "), "{}", report);
+}
+
+#[test]
+fn a_second_interrupt_exits_at_once() {
+    // Each request takes longer than the test waits for the exit
+    let agent = SimulatedAgent::start_slow("procurve-2530-lacp.walk", Duration::from_secs(3));
+    let output = scratch_report("exit-codes-interrupted-twice");
+    let mut child = spawn_slow(&agent, output.to_str().unwrap(), &["--watch", "60"]);
+    thread::sleep(Duration::from_millis(500));
+    interrupt(&child);
+    thread::sleep(Duration::from_millis(100));
+    let started = Instant::now();
+    interrupt(&child);
+    assert_eq!(child.wait().unwrap().code(), Some(130));
+    assert!(started.elapsed() < Duration::from_secs(2));
+    assert!(!output.exists());
+}
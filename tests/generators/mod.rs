@@ -0,0 +1,73 @@
+//! Random switch data for the property tests and the benchmarks
+// Each test and benchmark uses only some of the generators
+#![allow(dead_code)]
+use std::collections::HashMap;
+use proptest::collection::{btree_set, hash_set, vec};
+use proptest::option;
+use proptest::prelude::*;
+use proptest::strategy::ValueTree;
+use proptest::test_runner::TestRunner;
+use switch_vlan_diagram::{LacpInfo, PortConfig};
+
+/// A configuration drawn from few enough values that neighbours often match
+pub fn port_config(port_num: u32) -> impl Strategy<Value = PortConfig> {
+    (
+        option::of(prop::sample::select(vec![1u32, 10, 20])),
+        hash_set(prop::sample::select(vec![1u32, 10, 20, 30]), 0..3),
+        hash_set(prop::sample::select(vec![1u32, 10]), 0..2),
+        option::of(prop::sample::select(vec!["desk", "uplink"])),
+        option::of(prop::sample::select(vec![49u32, 50])),
+        any::<bool>(),
+    )
+        .prop_map(move |(pvid, vlans, untagged, alias, agg_id, uplink)| PortConfig {
+            port_num,
+            alias: alias.map(str::to_string),
+            pvid,
+            vlan_memberships: vlans.into_iter().chain(untagged.iter().copied()).collect(),
+            untagged_vlans: untagged,
+            lacp_info: agg_id.map(|selected_agg_id| LacpInfo {
+                selected_agg_id,
+                agg_name: Some(format!("Trk{}", selected_agg_id - 48)),
+                agg_vlans: None,
+                load_balance: None,
+            }),
+            uplink,
+            ..PortConfig::default()
+        })
+}
+
+/// Up to 40 ports sorted by number, with gaps between some of them
+pub fn port_configs() -> impl Strategy<Value = Vec<PortConfig>> {
+    btree_set(1u32..64, 0..40).prop_flat_map(|ports| {
+        ports.into_iter().map(port_config).collect::<Vec<_>>()
+    })
+}
+
+/// A PortList of `len` bytes with about one port in eight set, as in a
+/// dot1qVlanStaticEgressPorts row
+pub fn port_list(len: usize) -> impl Strategy<Value = Vec<u8>> {
+    // Each bit survives three random masks with probability 1/8
+    vec((any::<u8>(), any::<u8>(), any::<u8>()).prop_map(|(a, b, c)| a & b & c), len)
+}
+
+/// Egress and untagged PortLists keyed by VLAN id for a switch with `ports`
+/// ports and `vlans` VLANs; untagged ports are a subset of the egress ones
+pub fn vlan_bitmaps(ports: u32, vlans: u32) -> impl Strategy<Value = (HashMap<u32, Vec<u8>>, HashMap<u32, Vec<u8>>)> {
+    let len = ports.div_ceil(8) as usize;
+    vec((port_list(len), vec(any::<u8>(), len)), vlans as usize).prop_map(|rows| {
+        let mut egress = HashMap::new();
+        let mut untagged = HashMap::new();
+        for (vlan_id, (members, mask)) in (1..).zip(rows) {
+            untagged.insert(vlan_id, members.iter().zip(&mask).map(|(member, mask)| member & mask).collect());
+            egress.insert(vlan_id, members);
+        }
+        (egress, untagged)
+    })
+}
+
+/// One value of `strategy`, the same on every run, for benchmark inputs
+pub fn sample<S: Strategy>(strategy: S) -> S::Value {
+    strategy.new_tree(&mut TestRunner::deterministic())
+        .expect("strategy yields a value")
+        .current()
+}
@@ -0,0 +1,103 @@
+//! Invariants of range grouping over random port configurations
+mod generators;
+
+use proptest::prelude::*;
+use switch_vlan_diagram::{GroupMode, Grouping, PortConfig, PortRange};
+use generators::port_configs;
+
+fn grouping() -> impl Strategy<Value = Grouping> {
+    (prop::sample::select(vec![GroupMode::Adjacent, GroupMode::Dedupe, GroupMode::PerPort]), any::<bool>(), any::<bool>())
+        .prop_map(|(mode, allow_gaps, ignore_alias)| Grouping { mode, allow_gaps, ignore_alias })
+}
+
+/// The configuration a range documents for one of its ports
+fn ungroup(range: &PortRange, port_num: u32) -> PortConfig {
+    PortConfig {
+        port_num,
+        alias: range.port_aliases.iter().find(|(port, _)| *port == port_num).map(|(_, alias)| alias.clone()),
+        pvid: range.pvid,
+        vlan_memberships: range.vlan_memberships.clone(),
+        untagged_vlans: range.untagged_vlans.clone(),
+        lacp_info: range.lacp_info.clone(),
+        dot1x: range.dot1x,
+        frame_policy: range.frame_policy,
+        uplink: range.uplink,
+        inferred_trunk: range.inferred_trunk,
+        ..PortConfig::default()
+    }
+}
+
+/// Everything but the port number and, if ignored, the alias
+fn same_config(a: &PortConfig, b: &PortConfig, ignore_alias: bool) -> bool {
+    let without = |config: &PortConfig| PortConfig {
+        port_num: 0,
+        alias: if ignore_alias { None } else { config.alias.clone() },
+        ..config.clone()
+    };
+    without(a) == without(b)
+}
+
+proptest! {
+    #[test]
+    fn ungrouping_reproduces_the_input(ports in port_configs(), grouping in grouping()) {
+        let mut ungrouped: Vec<PortConfig> = grouping.group(&ports).iter()
+            .flat_map(|range| range.ports().into_iter().map(|port| ungroup(range, port)))
+            .collect();
+        ungrouped.sort_by_key(|config| config.port_num);
+        prop_assert_eq!(ungrouped, ports);
+    }
+
+    #[test]
+    fn every_member_matches_its_range(ports in port_configs(), grouping in grouping()) {
+        for range in grouping.group(&ports) {
+            let first = ungroup(&range, range.ports()[0]);
+            for port in range.ports() {
+                let input = ports.iter().find(|config| config.port_num == port).unwrap();
+                prop_assert!(same_config(input, &first, grouping.ignore_alias), "port {} differs from its range", port);
+            }
+        }
+    }
+
+    #[test]
+    fn adjacent_ranges_are_contiguous_ordered_and_maximal(ports in port_configs(), ignore_alias in any::<bool>()) {
+        let grouping = Grouping { ignore_alias, ..Grouping::default() };
+        let ranges = grouping.group(&ports);
+        for range in &ranges {
+            prop_assert!(range.first_port <= range.last_port);
+            prop_assert!(range.except_ports.is_empty() && range.member_ports.is_none());
+        }
+        for pair in ranges.windows(2) {
+            prop_assert!(pair[0].last_port < pair[1].first_port, "ranges overlap or are out of order");
+            // Neighbouring ranges would have been one range if their configurations matched
+            if pair[0].last_port + 1 == pair[1].first_port {
+                let left = ungroup(&pair[0], pair[0].last_port);
+                let right = ungroup(&pair[1], pair[1].first_port);
+                prop_assert!(!same_config(&left, &right, ignore_alias));
+            }
+        }
+    }
+
+    #[test]
+    fn gaps_only_skip_missing_ports(ports in port_configs(), ignore_alias in any::<bool>()) {
+        let grouping = Grouping { allow_gaps: true, ignore_alias, ..Grouping::default() };
+        let ranges = grouping.group(&ports);
+        for range in &ranges {
+            prop_assert!(range.except_ports.iter().all(|port| ports.iter().all(|config| config.port_num != *port)));
+        }
+        for pair in ranges.windows(2) {
+            prop_assert!(pair[0].last_port < pair[1].first_port);
+        }
+    }
+
+    #[test]
+    fn dedupe_gives_each_configuration_one_row(ports in port_configs(), ignore_alias in any::<bool>()) {
+        let grouping = Grouping { mode: GroupMode::Dedupe, ignore_alias, ..Grouping::default() };
+        let ranges = grouping.group(&ports);
+        for (i, a) in ranges.iter().enumerate() {
+            for b in &ranges[i + 1..] {
+                let (a, b) = (ungroup(a, a.ports()[0]), ungroup(b, b.ports()[0]));
+                prop_assert!(!same_config(&a, &b, ignore_alias));
+            }
+        }
+    }
+}
@@ -0,0 +1,713 @@
+//! Full collections against a simulated agent, compared byte for byte with
+//! golden files in tests/fixtures. Run with UPDATE_GOLDEN=1 to rewrite them.
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::time::Duration;
+use snmp::SyncSession;
+use common::{fixture_path, SimulatedAgent, COMMUNITY};
+use switch_vlan_diagram::session::SessionPool;
+use switch_vlan_diagram::{collect_switch_with, CollectOptions};
+
+fn scratch_dir(name: &str) -> PathBuf {
+    let scratch = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name);
+    fs::create_dir_all(&scratch).unwrap();
+    scratch
+}
+
+/// Poll the agent with the binary
+fn poll(agent: &SimulatedAgent, scratch: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_switch-vlan-diagram"))
+        .args(["--ip", "127.0.0.1", "--port", &agent.port.to_string(), "--community", COMMUNITY])
+        .args(["--timeout", "5", "--deterministic"])
+        .args(args)
+        // Keep a developer's own config out of the way
+        .env("XDG_CONFIG_HOME", scratch)
+        .output()
+        .unwrap()
+}
+
+/// Poll quietly; returns the report and the --snapshot JSON
+fn run(agent: &SimulatedAgent, name: &str, extra: &[&str]) -> (String, String) {
+    let scratch = scratch_dir(name);
+    let snapshot = scratch.join("snapshot.json");
+    let snapshot_arg = snapshot.to_str().unwrap();
+    let output = poll(agent, &scratch, &[&["--quiet", "--snapshot", snapshot_arg], extra].concat());
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    (String::from_utf8(output.stdout).unwrap(), fs::read_to_string(&snapshot).unwrap())
+}
+
+fn assert_golden(actual: &str, golden: &str) {
+    let path = fixture_path(golden);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&path, actual).unwrap();
+    }
+    let expected = fs::read_to_string(&path).unwrap_or_default();
+    assert!(actual == expected, "output differs from {}:\n{}", path.display(), actual);
+}
+
+#[test]
+fn procurve_with_lacp_matches_golden_files() {
+    let agent = SimulatedAgent::start("procurve-2530-lacp.walk");
+    let (report, snapshot) = run(&agent, "procurve", &[]);
+    assert_golden(&report, "procurve-2530-lacp.md");
+    assert_golden(&snapshot, "procurve-2530-lacp.json");
+}
+
+#[test]
+fn cisco_without_lacp_matches_golden_files() {
+    let agent = SimulatedAgent::start("cisco-2960-bridge-ports.walk");
+    let (report, snapshot) = run(&agent, "cisco", &[]);
+    // Port lists count bridge ports 1-8, but the ports are ifIndex 10101-10108
+    // and dot1dBasePortIfIndex is not consulted, so no port shows a VLAN
+    assert_golden(&report, "cisco-2960-bridge-ports.md");
+    assert_golden(&snapshot, "cisco-2960-bridge-ports.json");
+}
+
+#[test]
+fn empty_columns_stay_when_asked_for() {
+    let agent = SimulatedAgent::start("cisco-2960-bridge-ports.walk");
+    let header = |extra: &[&str]| {
+        let (report, _) = run(&agent, "empty-columns", &[&["--no-align"], extra].concat());
+        report.lines().find(|line| line.starts_with("| Port")).unwrap().to_string()
+    };
+    assert_eq!(header(&[]), "| Port | Alias | VLAN(s) |");
+    assert_eq!(header(&["--keep-empty-columns"]), "| Port | Alias | VLAN(s) | LACP |");
+    assert_eq!(header(&["--columns", "port,lacp,mac"]), "| Port | LACP | MAC |");
+
+    let scratch = scratch_dir("empty-columns");
+    let output = poll(&agent, &scratch, &["--verbose"]);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Columns without data left out: lacp"));
+}
+
+#[test]
+fn debug_indices_show_both_numberings() {
+    let agent = SimulatedAgent::start("cisco-2960-bridge-ports.walk");
+    let (report, snapshot) = run(&agent, "debug-indices", &["--debug-indices", "--no-align", "--group-ignore-alias"]);
+    // Every port is bridge port 1-8 but ifIndex 10101-10108, so none lines up
+    assert!(report.contains("| Port | Alias | VLAN(s) | ifIndex | bridgePort |"), "{}", report);
+    assert!(report.contains("| hall (10) | **10102-10105** | **2-5** |"), "{}", report);
+    let snapshot: serde_json::Value = serde_json::from_str(&snapshot).unwrap();
+    assert_eq!(snapshot["ports"][2]["indices"], serde_json::json!({"if_index": 10103, "bridge_port": 3}));
+
+    let (report, _) = run(&agent, "debug-indices", &["--debug-indices", "--format", "html"]);
+    assert!(report.contains(r#"<td class="deviates">10104</td>"#), "{}", report);
+    assert!(report.contains(r#"<td class="deviates">4</td>"#), "{}", report);
+}
+
+#[test]
+fn html_report_matches_golden_file() {
+    let agent = SimulatedAgent::start("procurve-2530-lacp.walk");
+    let (report, _) = run(&agent, "procurve-html", &["--format", "html"]);
+    assert_golden(&report, "procurve-2530-lacp.html");
+}
+
+#[test]
+fn highlight_rules_colour_matching_rows() {
+    let agent = SimulatedAgent::start("procurve-2530-lacp.walk");
+    let scratch = scratch_dir("highlight-rules");
+    let config = scratch.join("config.toml");
+    let poll_with = |rules: &str| {
+        fs::write(&config, rules).unwrap();
+        poll(&agent, &scratch, &["--quiet", "--format", "html", "--config", config.to_str().unwrap()])
+    };
+    let rules = r##"
+[[highlight_rules]]
+name = "stream"
+when = "tagged contains 30"
+color = "#fdd"
+
+[[highlight_rules]]
+name = "desks"
+when = "alias matches /DESK/i and status == up"
+color = "lightyellow"
+
+[[highlight_rules]]
+name = "trunk"
+when = "lacp not-empty and uplink == true"
+color = "#ddf"
+"##;
+    let output = poll_with(rules);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let report = String::from_utf8(output.stdout).unwrap();
+    // The first rule's colour comes last, so that it wins
+    let style = ".port-table tr.rule-trunk td {\n        background-color: #ddf;\n    }\n    \
+        .port-table tr.rule-desks td {\n        background-color: lightyellow;\n    }\n    \
+        .port-table tr.rule-stream td {\n        background-color: #fdd;\n    }";
+    assert!(report.contains(style), "{}", report);
+    let rows: Vec<&str> = report.lines().filter(|line| line.contains("data-rules")).collect();
+    assert_eq!(rows.len(), 6, "{}", report);
+    assert!(rows[0].contains(r#" rule-desks" data-rules="desks""#), "{}", rows[0]);
+    assert!(rows[4].contains(r#"data-rules="stream""#), "{}", rows[4]);
+    assert!(rows[5].contains(r#"rule-stream rule-trunk" data-rules="stream trunk""#), "{}", rows[5]);
+
+    let output = poll_with(&rules.replace("lacp not-empty", "lacp >= 1"));
+    assert_eq!(output.status.code(), Some(1), "{}", String::from_utf8_lossy(&output.stderr));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("highlight_rules #3 'trunk': unknown operator '>='"), "{}", stderr);
+}
+
+#[test]
+fn html_sections_are_one_element_per_line() {
+    let agent = SimulatedAgent::start("cisco-2960-bridge-ports.walk");
+    let (report, _) = run(&agent, "cisco-html", &["--format", "html", "--capacity"]);
+    assert_golden(&report, "cisco-2960-bridge-ports.html");
+    // Everything after the stylesheet is newline-terminated and nested in steps of four spaces
+    let body = report.split("</style>\n").nth(1).unwrap();
+    assert!(body.ends_with('\n'));
+    for line in body.lines() {
+        let indent = line.len() - line.trim_start().len();
+        assert!(indent % 4 == 0 && line.trim_start().starts_with('<'), "{:?}", line);
+        assert!(!line.contains("><tr") && !line.contains("</tr> "), "{:?}", line);
+    }
+}
+
+#[test]
+fn quiet_keeps_warnings_in_the_report_only() {
+    let agent = SimulatedAgent::start("cisco-2960-bridge-ports.walk");
+    let scratch = scratch_dir("warnings");
+
+    let loud = poll(&agent, &scratch, &[]);
+    let stderr = String::from_utf8_lossy(&loud.stderr);
+    assert!(stderr.contains("Warning: pvid-not-untagged: port 10108, "), "{}", stderr);
+
+    let quiet = poll(&agent, &scratch, &["--quiet"]);
+    assert!(!String::from_utf8_lossy(&quiet.stderr).contains("Warning:"));
+    assert_eq!(quiet.stdout, loud.stdout);
+    assert!(String::from_utf8_lossy(&quiet.stdout).contains("\n## Warnings\n"));
+
+    let without = poll(&agent, &scratch, &["--quiet", "--no-warnings"]);
+    assert!(!String::from_utf8_lossy(&without.stdout).contains("Warnings"));
+}
+
+#[test]
+fn getbulk_returns_what_getnext_walks() {
+    let agent = SimulatedAgent::start("procurve-2530-lacp.walk");
+    let mut session = SyncSession::new(("127.0.0.1", agent.port), COMMUNITY.as_bytes(), Some(Duration::from_secs(5)), 0).unwrap();
+    let if_name: &[u32] = &[1, 3, 6, 1, 2, 1, 31, 1, 1, 1, 1];
+
+    let bulk: Vec<String> = session.getbulk(&[if_name], 0, 5).unwrap().varbinds
+        .map(|(oid, _)| oid.to_string())
+        .collect();
+    let mut walked = Vec::new();
+    let mut cursor = if_name.to_vec();
+    for _ in 0..5 {
+        let response = session.getnext(&cursor).unwrap();
+        let (oid, _) = response.varbinds.clone().next().unwrap();
+        walked.push(oid.to_string());
+        cursor = oid.to_string().split('.').map(|n| n.parse().unwrap()).collect();
+    }
+    assert_eq!(bulk, walked);
+    assert_eq!(bulk[0], "1.3.6.1.2.1.31.1.1.1.1.1");
+}
+
+#[test]
+fn wrong_community_goes_unanswered() {
+    let agent = SimulatedAgent::start("procurve-2530-lacp.walk");
+    let mut session = SyncSession::new(("127.0.0.1", agent.port), b"private", Some(Duration::from_millis(200)), 0).unwrap();
+    assert!(session.get(&[1, 3, 6, 1, 2, 1, 1, 5, 0]).is_err());
+}
+
+#[test]
+fn html_report_escapes_strings_from_the_switch() {
+    let agent = SimulatedAgent::start("hostile-strings.walk");
+    let (report, _) = run(&agent, "hostile-html", &["--format", "html"]);
+    for raw in ["<script>", "<b>office", "<img", "<&lab>", r#""b""#] {
+        assert!(!report.contains(raw), "{} left unescaped:\n{}", raw, report);
+    }
+    assert!(report.contains("<td>&lt;script&gt;alert(1)&lt;/script&gt;</td>"), "{}", report);
+    assert!(report.contains("<td>a &amp; &quot;b&quot; printer</td>"), "{}", report);
+    assert!(report.contains("&lt;b&gt;office&lt;/b&gt; (10)"), "{}", report);
+    assert!(report.contains("Trk1&lt;img src=x&gt;"), "{}", report);
+    assert!(report.contains("studio-sw1 &lt;&amp;lab&gt;"), "{}", report);
+}
+
+#[test]
+fn aliases_in_legacy_encodings_fall_back_or_turn_hex() {
+    let agent = SimulatedAgent::start("legacy-encodings.walk");
+    let (report, _) = run(&agent, "utf8-only", &["--no-align"]);
+    assert!(report.contains("| 1 | 0x53e476656c |"), "{}", report);
+    assert!(report.contains("- undecodable-string: port 1: ifAlias is not printable UTF-8 text; shown in hex\n"), "{}", report);
+
+    let (report, snapshot) = run(&agent, "latin1", &["--fallback-encoding", "latin1", "--no-align"]);
+    assert!(report.contains("| 1 | Sävel |"), "{}", report);
+    assert!(report.contains("| 2 | 0x6162ff01 |"), "{}", report);
+    assert!(!report.contains("port 1: ifAlias"), "{}", report);
+    assert!(report.contains("- undecodable-string: port 2: ifAlias is not printable UTF-8 or latin1 text; shown in hex\n"), "{}", report);
+    assert!(!report.contains('\u{fffd}') && !snapshot.contains('\u{fffd}'));
+}
+
+#[test]
+fn empty_collections_fail_unless_allowed() {
+    let agent = SimulatedAgent::start("restricted-view.walk");
+    let scratch = scratch_dir("restricted");
+    let failed = poll(&agent, &scratch, &["--quiet"]);
+    let stderr = String::from_utf8_lossy(&failed.stderr);
+    assert_eq!(failed.status.code(), Some(1), "{}", stderr);
+    assert!(failed.stdout.is_empty());
+    assert!(stderr.contains("collected 0 physical port(s), expected at least 1; no rows from ifName (unsupported), ifType (empty), ifAlias (unsupported)"),
+        "{}", stderr);
+
+    let allowed = poll(&agent, &scratch, &["--quiet", "--no-fail-on-empty"]);
+    assert!(allowed.status.success(), "{}", String::from_utf8_lossy(&allowed.stderr));
+
+    let agent = SimulatedAgent::start("procurve-2530-lacp.walk");
+    let short = poll(&agent, &scratch, &["--quiet", "--min-ports", "24"]);
+    assert_eq!(short.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&short.stderr).contains("collected 10 physical port(s), expected at least 24"));
+}
+
+#[test]
+fn config_hashes_change_only_with_the_configuration() {
+    let hashes = |fixture: &str| {
+        let agent = SimulatedAgent::start(fixture);
+        let (report, snapshot) = run(&agent, fixture, &["--columns", "port,hash", "--no-align"]);
+        let snapshot: serde_json::Value = serde_json::from_str(&snapshot).unwrap();
+        let switch = snapshot["config_hash"].as_str().unwrap().to_string();
+        assert!(report.contains(&format!("Config hash: {}\n", switch)), "{}", report);
+        let ports: Vec<String> = snapshot["ports"].as_array().unwrap().iter()
+            .map(|port| port["config_hash"].as_str().unwrap().to_string())
+            .collect();
+        // Each port on its own row shows the same fingerprint as in the snapshot
+        assert!(report.contains(&format!("| 1 | {} |", ports[0])), "{}", report);
+        (switch, ports)
+    };
+    let (switch, ports) = hashes("procurve-2530-lacp.walk");
+    assert_eq!(hashes("procurve-2530-lacp.walk"), (switch.clone(), ports.clone()));
+
+    // The same switch with the PVID of port 3 changed
+    let (changed_switch, changed_ports) = hashes("pvid-not-untagged.walk");
+    assert_ne!(changed_switch, switch);
+    let changed: Vec<usize> = (0..ports.len()).filter(|&i| ports[i] != changed_ports[i]).collect();
+    assert_eq!(changed, [2]);
+}
+
+#[test]
+fn pooled_sessions_carry_over_between_polls() {
+    let agent = SimulatedAgent::start("procurve-2530-lacp.walk");
+    let options = CollectOptions { port: agent.port, timeout: Duration::from_secs(5), ..CollectOptions::default() };
+    let agent_addr = format!("127.0.0.1:{}", agent.port);
+    let sessions = SessionPool::new();
+
+    let first = collect_switch_with("127.0.0.1", &options, &sessions).unwrap();
+    let requests = sessions.agent_stats(&agent_addr, COMMUNITY.as_bytes()).unwrap().requests;
+    assert!(requests > 0);
+    let second = collect_switch_with("127.0.0.1", &options, &sessions).unwrap();
+    assert_eq!(second.ports, first.ports);
+    let stats = sessions.agent_stats(&agent_addr, COMMUNITY.as_bytes()).unwrap();
+    assert_eq!(stats.requests, 2 * requests);
+    assert!(stats.latency.is_some());
+    assert_eq!(sessions.opened(), 1);
+
+    // A failed poll does not leave its session behind for the next one
+    let demanding = CollectOptions { min_ports: 1000, port: agent.port, timeout: options.timeout, ..CollectOptions::default() };
+    assert!(collect_switch_with("127.0.0.1", &demanding, &sessions).is_err());
+    assert_eq!(sessions.idle(), 0);
+    collect_switch_with("127.0.0.1", &options, &sessions).unwrap();
+    assert_eq!(sessions.opened(), 2);
+}
+
+#[test]
+fn json_logging_writes_one_object_per_line() {
+    let agent = SimulatedAgent::start("pvid-not-untagged.walk");
+    let scratch = scratch_dir("json-logging");
+    let events = |extra: &[&str]| -> Vec<serde_json::Value> {
+        let output = poll(&agent, &scratch, &[&["--log-format", "json"], extra].concat());
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8(output.stderr).unwrap().lines()
+            .map(|line| serde_json::from_str(line).unwrap_or_else(|e| panic!("{}: {}", e, line)))
+            .collect()
+    };
+
+    let all = events(&[]);
+    let walk = all.iter().find(|event| event["kind"] == "table-walk" && event["table"] == "ifAlias").unwrap();
+    assert_eq!(walk["host"], "127.0.0.1");
+    assert_eq!(walk["oid"], "1.3.6.1.2.1.31.1.1.1.18");
+    assert!(walk["duration_ms"].is_u64());
+    let warning = all.iter().find(|event| event["kind"] == "pvid-not-untagged").unwrap();
+    assert_eq!((&warning["level"], &warning["ports"]), (&"warning".into(), &serde_json::json!([3])));
+
+    // --quiet keeps progress and warnings out, as in text
+    assert_eq!(events(&["--quiet"]), Vec::<serde_json::Value>::new());
+}
+
+#[test]
+fn check_probes_tables_without_a_report() {
+    let agent = SimulatedAgent::start("procurve-2530-lacp.walk");
+    let scratch = scratch_dir("check");
+    let output = poll(&agent, &scratch, &["--check"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("PASS  sysDescr: HP J9777A"), "{}", stdout);
+    assert!(stdout.contains("PASS  Q-BRIDGE dot1qVlanStaticEgressPorts\n"), "{}", stdout);
+    assert!(stdout.ends_with("Vendor profile: aruba\n127.0.0.1 has every table collection needs\n"), "{}", stdout);
+    assert!(!stdout.contains("| Port"));
+
+    let agent = SimulatedAgent::start("restricted-view.walk");
+    let output = poll(&agent, &scratch, &["--check"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(output.status.code(), Some(8), "{}", stdout);
+    assert!(stdout.contains("FAIL  ifXTable (ifName) (optional): unsupported\n"), "{}", stdout);
+    assert!(stdout.contains("127.0.0.1 lacks tables collection needs"), "{}", stdout);
+}
+
+#[test]
+fn vlan_names_are_compared_across_switches() {
+    let first = SimulatedAgent::start("procurve-2530-lacp.walk");
+    let second = SimulatedAgent::start_at("procurve-2530-lacp.walk", &format!("127.0.0.2:{}", first.port));
+    assert_eq!(second.port, first.port);
+    let scratch = scratch_dir("vlan-name-conflicts");
+    let config = scratch.join("config.toml");
+    let snapshot = scratch.join("{host}.json");
+    let poll_both = |config_text: &str| {
+        let hosts = r#"
+[hosts."127.0.0.1"]
+device_name = "sw-1"
+
+[hosts."127.0.0.2"]
+device_name = "sw-3"
+vlan_names = { "20" = " STUDIO", "30" = "streaming" }
+"#;
+        fs::write(&config, format!("{}{}", config_text, hosts)).unwrap();
+        poll(&first, &scratch, &["--ip", "127.0.0.2", "--quiet", "--config", config.to_str().unwrap(),
+            "--snapshot", snapshot.to_str().unwrap()])
+    };
+    let conflict = r#"vlan-name-conflict: VLAN 30: "stream" on sw-1 but "streaming" on sw-3"#;
+
+    let output = poll_both("");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let reports = String::from_utf8(output.stdout).unwrap();
+    // In both reports; VLAN 20 differs only in case and whitespace
+    assert_eq!(reports.matches(conflict).count(), 2, "{}", reports);
+    assert!(!reports.contains("VLAN 20:"));
+    let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(scratch.join("127.0.0.1.json")).unwrap()).unwrap();
+    assert!(json["warnings"].as_array().unwrap().iter().any(|warning| warning["code"] == "vlan-name-conflict" && warning["vlan"] == 30));
+
+    let allowed = poll_both("allow_vlan_name_conflicts = [30]\n");
+    assert!(!String::from_utf8(allowed.stdout).unwrap().contains("vlan-name-conflict"));
+
+    // The VLAN names of a switch whose ports fail still count
+    let failing = poll_both("[hosts.\"127.0.0.2\".oids]\nif_index = \"1.3.6.1.4.1.99999\"\n");
+    assert_eq!(failing.status.code(), Some(5), "{}", String::from_utf8_lossy(&failing.stderr));
+    let report = String::from_utf8(failing.stdout).unwrap();
+    assert!(report.contains(r#"VLAN 30: "stream" on sw-1 but "streaming" on 127.0.0.2"#), "{}", report);
+}
+
+#[test]
+fn vlan_matrix_covers_every_switch() {
+    let first = SimulatedAgent::start("procurve-2530-lacp.walk");
+    let _second = SimulatedAgent::start_at("generic-8port.walk", &format!("127.0.0.2:{}", first.port));
+    let scratch = scratch_dir("vlan-matrix");
+    let (markdown, html, json) = (scratch.join("matrix.md"), scratch.join("matrix.html"), scratch.join("matrix.json"));
+    let output = poll(&first, &scratch, &["--ip", "127.0.0.2", "--quiet", "--vlan-matrix", markdown.to_str().unwrap(),
+        "--vlan-matrix", html.to_str().unwrap(), "--vlan-matrix", json.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    // VLAN 30 never made it to the second switch
+    let markdown = fs::read_to_string(markdown).unwrap();
+    assert!(markdown.contains("| VLAN | Name         | studio-sw1 | sw-test |\n"), "{}", markdown);
+    assert!(markdown.contains("| 10   | office       | 2/4        | 2/4     |\n"), "{}", markdown);
+    assert!(markdown.contains("| 30   | stream       | 3/0        | **0**   |\n"), "{}", markdown);
+    let html = fs::read_to_string(html).unwrap();
+    assert_eq!(html.matches(r#"<td class="count absent">0</td>"#).count(), 1, "{}", html);
+    let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(json).unwrap()).unwrap();
+    assert_eq!(json["switches"], serde_json::json!(["studio-sw1", "sw-test"]));
+    assert_eq!(json["vlans"][3]["vlan"], 30);
+    assert_eq!(json["vlans"][3]["ports"], serde_json::json!([{ "tagged": 3, "untagged": 0 }, { "tagged": 0, "untagged": 0 }]));
+
+    let single = poll(&first, &scratch, &["--quiet", "--vlan-matrix", scratch.join("single.md").to_str().unwrap()]);
+    assert_eq!(single.status.code(), Some(1));
+    let unknown = poll(&first, &scratch, &["--ip", "127.0.0.2", "--vlan-matrix", "matrix.txt"]);
+    assert!(String::from_utf8_lossy(&unknown.stderr).contains("Expected a .md, .html or .json file"));
+}
+
+#[test]
+fn topology_links_switches_by_lldp() {
+    let first = SimulatedAgent::start("procurve-2530-lacp.walk");
+    let _second = SimulatedAgent::start_at("generic-8port-lldp.walk", &format!("127.0.0.2:{}", first.port));
+    let scratch = scratch_dir("topology");
+    let files = ["topology.md", "topology.html", "topology.json", "topology.dot", "topology.mmd"].map(|name| scratch.join(name));
+    let mut args = vec!["--ip", "127.0.0.2", "--quiet"];
+    for file in &files {
+        args.extend(["--topology", file.to_str().unwrap()]);
+    }
+    let output = poll(&first, &scratch, &args);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let [markdown, html, json, dot, mermaid] = files.map(|file| fs::read_to_string(file).unwrap());
+
+    // Both ends of the trunk see each other; it is listed once, and VLAN 30 stops at the first switch
+    assert!(markdown.contains("| studio-sw1 | 9 (Trk1)  | sw-test               | 5 (Trk1)          | 1        | 10, 20, 30 | 30 only on studio-sw1 |\n"), "{}", markdown);
+    assert!(markdown.contains("| studio-sw1 | 7         | mixer-01 (not polled) | 00:00:5e:00:53:42 |"), "{}", markdown);
+    assert_eq!(markdown.matches("| sw-test    |").count(), 0, "{}", markdown);
+    assert_eq!(html.matches(r#"<tr class="mismatch">"#).count(), 2, "{}", html);
+    let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(json["links"][1]["remote"], serde_json::json!({ "device": "sw-test", "port": 6, "aggregator": "Trk1" }));
+    assert_eq!(json["links"][1]["only_local"], serde_json::json!([30]));
+    assert!(dot.contains("    \"mixer-01\" [shape=ellipse, style=dashed];\n"), "{}", dot);
+    assert!(mermaid.contains("    n0 ---|\"9 (Trk1) - 5 (Trk1)\"| n1\n"), "{}", mermaid);
+
+    let unknown = poll(&first, &scratch, &["--ip", "127.0.0.2", "--topology", "topology.svg"]);
+    assert!(String::from_utf8_lossy(&unknown.stderr).contains("Expected a .md, .html, .json, .dot or .mmd file"));
+}
+
+#[test]
+fn desired_state_drift_fails_strict_runs() {
+    let agent = SimulatedAgent::start("generic-8port.walk");
+    let scratch = scratch_dir("desired");
+    let desired = scratch.join("desired.yaml");
+    let desired_arg = desired.to_str().unwrap();
+    // Port 4 moved to the wrong VLAN, 9 does not exist and 7 is not listed; 8 is unconfigured
+    fs::write(&desired, "\
+ports:
+  1-3:
+    access: 10
+  4:
+    alias: desk 4
+    access: 20
+  5-6:
+    alias: uplink
+    trunk: [10, 20]
+    native: 1
+  9:
+    access: 10
+").unwrap();
+    let output = poll(&agent, &scratch, &["--desired", desired_arg, "--strict"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(4), "{}", stderr);
+    assert!(stderr.contains(&format!("Drift from {}: ports 4, 7, 9", desired.display())), "{}", stderr);
+    let report = String::from_utf8(output.stdout).unwrap();
+    for warning in [
+        "- drift: port 4: access VLAN 10, expected access VLAN 20; PVID 10, expected 20\n",
+        "- drift: port 9: in the desired state but not on the switch\n",
+        "- drift: port 7: configured on the switch but not in the desired state\n",
+    ] {
+        assert!(report.contains(warning), "{}", report);
+    }
+
+    // Errors point into the file, and come before the switch is polled
+    fs::write(&desired, "ports:\n  1:\n    access: 10\n  2:\n    trunk: [10]\n    access: 20\n").unwrap();
+    let broken = poll(&agent, &scratch, &["--quiet", "--desired", desired_arg]);
+    assert_eq!(broken.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&broken.stderr)
+        .contains("ports.2: give either access or trunk, not both at line 5 column 5"));
+}
+
+#[test]
+fn lag_section_lists_every_aggregator() {
+    // Trk1 is in the LAG MIB, ae1 only stacks ports 7 and 8, and Trk2 has no members left
+    let agent = SimulatedAgent::start("lag-sources.walk");
+    let (report, snapshot) = run(&agent, "lags", &["--lags", "--override-lacp", "9:4", "--no-align"]);
+    let section = report.split("## Link aggregation groups").nth(1).unwrap_or_default();
+    for row in [
+        "| Trk1 | 9 | 4-6 | Tagged:[1, staff (10), studio (20)] Untagged:[1] | LAG MIB, override |",
+        "| Trk2 | 10 | **none (stale)** | 1 |  |",
+        "| ae1 | 11 | 7-8 | studio (20) | ifStackTable |",
+    ] {
+        assert!(section.contains(row), "{}", report);
+    }
+    // Members found through ifStackTable show their aggregator in the port table too
+    assert!(report.contains("| 8\\* |  | studio (20) | ae1 |"), "{}", report);
+
+    let snapshot: serde_json::Value = serde_json::from_str(&snapshot).unwrap();
+    let lags = snapshot["lags"].as_array().unwrap();
+    assert_eq!(lags.len(), 3);
+    assert_eq!(lags[1]["stale"], true);
+    assert_eq!(lags[2]["sources"], serde_json::json!(["if-stack"]));
+
+    let (html, _) = run(&agent, "lags-html", &["--lags", "--format", "html"]);
+    assert!(html.contains("<h2>Link aggregation groups</h2>"), "{}", html);
+    assert!(html.contains(r#"<tr class="stale">"#), "{}", html);
+}
+
+#[test]
+fn virtual_interfaces_get_their_own_section() {
+    let agent = SimulatedAgent::start("virtual-interfaces.walk");
+    let (report, snapshot) = run(&agent, "virtual", &["--include-virtual", "--capacity", "--no-align"]);
+    let section = report.split("## Virtual interfaces").nth(1).unwrap_or_else(|| panic!("{}", report));
+    let rows: Vec<&str> = section.lines().filter(|line| line.starts_with("| ")).collect();
+    assert_eq!(rows, [
+        "| Interface | ifIndex | Type | IP address |",
+        "| Vlan10 | 100 | l3ipvlan (136) | 10.0.10.1 |",
+        "| Vlan20 | 101 | l3ipvlan (136) | 10.0.20.1, 10.0.20.254 |",
+        "| lo0 | 200 | softwareLoopback (24) | 192.0.2.1 |",
+        "| mgmt0 | 300 | propVirtual (53) |  |",
+    ]);
+    // Neither a port row nor counted as a port
+    let (plain, _) = run(&agent, "virtual", &["--capacity", "--no-align"]);
+    assert_eq!(report.split("\n## Virtual interfaces").next(), plain.split("\n_Data sources").next());
+    let snapshot: serde_json::Value = serde_json::from_str(&snapshot).unwrap();
+    assert_eq!(snapshot["virtual_interfaces"][1]["addresses"], serde_json::json!(["10.0.20.1", "10.0.20.254"]));
+    assert_eq!(snapshot["capacity"],
+        serde_json::json!({"free_ports": [8], "access_vlans": {"10": 4, "20": 1}, "other_used": 2}));
+
+    let (html, snapshot) = run(&agent, "virtual", &["--include-virtual", "--format", "html"]);
+    assert!(!snapshot.contains("\"capacity\""), "{}", snapshot);
+    assert!(html.contains("<h2>Virtual interfaces</h2>"), "{}", html);
+    assert!(html.contains("<td>softwareLoopback (24)</td>"), "{}", html);
+}
+
+#[test]
+fn anonymized_virtual_interfaces_keep_no_address() {
+    let agent = SimulatedAgent::start("virtual-interfaces.walk");
+    let (report, snapshot) = run(&agent, "virtual-anonymized", &["--include-virtual", "--anonymize", "--no-align"]);
+    for original in ["10.0.10.1", "10.0.20.1", "10.0.20.254", "192.0.2.1", "Vlan10", "Vlan20", "lo0", "mgmt0"] {
+        assert!(!report.contains(original), "{} in {}", original, report);
+        assert!(!snapshot.contains(original), "{} in {}", original, snapshot);
+    }
+    assert!(report.contains("| interface-101 | 101 | l3ipvlan (136) | 198.18.0.2, 198.18.0.3 |"), "{}", report);
+    let snapshot: serde_json::Value = serde_json::from_str(&snapshot).unwrap();
+    assert_eq!(snapshot["virtual_interfaces"][1]["addresses"], serde_json::json!(["198.18.0.2", "198.18.0.3"]));
+}
+
+#[test]
+fn html_marks_changes_since_the_diff_snapshot() {
+    let agent = SimulatedAgent::start("generic-8port.walk");
+    let (_, snapshot) = run(&agent, "html-diff", &[]);
+    // An earlier state: port 1 had another alias and VLAN 30 tagged, port 3 did not exist, port 12 did
+    let mut baseline: serde_json::Value = serde_json::from_str(&snapshot).unwrap();
+    let ports = baseline["ports"].as_array_mut().unwrap();
+    ports[0]["alias"] = "old desk".into();
+    ports[0]["vlans"] = serde_json::json!([10, 30]);
+    let mut gone = ports.remove(2);
+    gone["port"] = 12.into();
+    gone["alias"] = "printer".into();
+    ports.push(gone);
+    let baseline_path = scratch_dir("html-diff").join("baseline.json");
+    fs::write(&baseline_path, baseline.to_string()).unwrap();
+
+    let (html, _) = run(&agent, "html-diff", &["--format", "html", "--diff", baseline_path.to_str().unwrap()]);
+    assert!(html.contains(r#"<tr class="highlight-1 changed" title="alias changed, tagged -30">"#), "{}", html);
+    assert!(html.contains(r#"<tr class="highlight-1 added" title="new port">"#), "{}", html);
+    let removed = html.split("<h2>Removed ports</h2>").nth(1).unwrap_or_else(|| panic!("{}", html));
+    assert!(removed.starts_with("\n<table class=\"port-table removed-table\">"), "{}", removed);
+    assert!(removed.contains("<td>12</td>\n            <td>printer</td>"), "{}", removed);
+
+    // Without --diff the rows are as always
+    let (plain, _) = run(&agent, "html-diff", &["--format", "html"]);
+    assert!(!plain.contains("title=") && !plain.contains("Removed ports"), "{}", plain);
+}
+
+/// Accept one HTTP request on a local port, answer 200 and hand over its body
+fn webhook_receiver() -> (String, std::sync::mpsc::Receiver<String>) {
+    use std::io::{BufRead, BufReader, Read, Write};
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/hooks/T0/secret-token", listener.local_addr().unwrap());
+    let (sender, receiver) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if let Some((_, value)) = line.split_once(':').filter(|(name, _)| name.eq_ignore_ascii_case("content-length")) {
+                length = value.trim().parse().unwrap();
+            }
+            if line.trim().is_empty() {
+                break;
+            }
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).unwrap();
+        reader.get_mut().write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n").unwrap();
+        sender.send(String::from_utf8(body).unwrap()).unwrap();
+    });
+    (url, receiver)
+}
+
+#[test]
+fn webhook_reports_changes_and_keeps_its_secret() {
+    let agent = SimulatedAgent::start("generic-8port.walk");
+    let (_, snapshot) = run(&agent, "webhook", &[]);
+    let mut baseline: serde_json::Value = serde_json::from_str(&snapshot).unwrap();
+    baseline["ports"][0]["vlans"] = serde_json::json!([10, 30]);
+    let baseline_path = scratch_dir("webhook").join("baseline.json");
+    fs::write(&baseline_path, baseline.to_string()).unwrap();
+    let diff = ["--diff", baseline_path.to_str().unwrap()];
+
+    let (url, received) = webhook_receiver();
+    run(&agent, "webhook", &[&diff[..], &["--webhook", &url]].concat());
+    let body: serde_json::Value = serde_json::from_str(&received.recv_timeout(Duration::from_secs(5)).unwrap()).unwrap();
+    assert_eq!((body["host"].as_str(), body["event"].as_str()), (Some("127.0.0.1"), Some("change")), "{}", body);
+    assert_eq!(body["ports"][0]["port"], 1, "{}", body);
+    assert_eq!(body["ports"][0]["before"]["tagged"], serde_json::json!([30]), "{}", body);
+    assert_eq!(body["ports"][0]["after"]["tagged"], serde_json::json!([]), "{}", body);
+
+    // Nobody listening: logged without the token, and the run still succeeds
+    let closed = "http://127.0.0.1:1/hooks/T0/secret-token";
+    let scratch = scratch_dir("webhook");
+    let output = poll(&agent, &scratch, &[&diff[..], &["--webhook", closed, "--webhook-on", "always"]].concat());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(stderr.contains("Webhook to http://127.0.0.1:1/[redacted] failed"), "{}", stderr);
+    assert!(!stderr.contains("secret-token"), "{}", stderr);
+}
+
+#[cfg(feature = "pdf")]
+#[test]
+fn pdf_goes_to_the_output_file() {
+    let agent = SimulatedAgent::start("generic-8port.walk");
+    let scratch = scratch_dir("pdf");
+    let pdf_path = scratch.join("report.pdf");
+    let output = poll(&agent, &scratch, &["--quiet", "--format", "pdf", "--output", pdf_path.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let pdf = fs::read(&pdf_path).unwrap();
+    let text = String::from_utf8_lossy(&pdf);
+    assert!(text.starts_with("%PDF-") && text.trim_end().ends_with("%%EOF"), "{}", text);
+    assert!(text.contains("/Count 1"), "{}", text);
+    for shown in ["(Switch Port Configuration) Tj", "(Port) Tj", "(desk 1) Tj", "(sw-test: page 1 of 1) Tj"] {
+        assert!(text.contains(shown), "{} missing:\n{}", shown, text);
+    }
+
+    let output = poll(&agent, &scratch, &["--quiet", "--format", "pdf"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--format pdf needs --output"));
+}
+
+#[cfg(not(feature = "pdf"))]
+#[test]
+fn pdf_needs_the_feature() {
+    let agent = SimulatedAgent::start("generic-8port.walk");
+    let scratch = scratch_dir("pdf-unsupported");
+    let pdf_path = scratch.join("report.pdf");
+    let output = poll(&agent, &scratch, &["--quiet", "--format", "pdf", "--output", pdf_path.to_str().unwrap()]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("rebuild with --features pdf"));
+    assert!(!pdf_path.exists());
+}
+
+#[test]
+fn footer_names_the_version_and_the_options() {
+    let agent = SimulatedAgent::start("generic-8port.walk");
+    let (report, snapshot) = run(&agent, "footer", &["--exclude-ports", "7-8", "--alias-override", "3=printer", "--hide-default"]);
+    let options = "--exclude-ports 7-8; --default-vlan 1; --hide-default; alias overrides for ports 3";
+    let footer = report.split("\n---\n").nth(1).unwrap_or_else(|| panic!("{}", report));
+    assert_eq!(footer, format!("\n_Generated by switch-vlan-diagram {}_\n\n_Options: {}_\n", env!("CARGO_PKG_VERSION"), options));
+
+    // --deterministic keeps the version but leaves out the collection time
+    let meta = &serde_json::from_str::<serde_json::Value>(&snapshot).unwrap()["meta"];
+    assert_eq!(meta["version"], env!("CARGO_PKG_VERSION"));
+    assert_eq!(meta["options"][3], "alias overrides for ports 3");
+    assert!(meta.get("collection_ms").is_none() && meta.get("git_describe").is_none(), "{}", meta);
+}
+
+#[test]
+fn panel_labels_reach_the_report_and_the_snapshot() {
+    let agent = SimulatedAgent::start("generic-8port.walk");
+    let config = scratch_dir("panel-config").join("config.toml");
+    fs::write(&config, r#"
+panel_labels = { 8 = "spare" }
+panel_blocks = [{ ports = "1-4", format = "PP{panel}-B{port:02}", panel = 2, start = 7 }]
+"#).unwrap();
+    let (report, snapshot) = run(&agent, "panel", &["--no-align", "--config", config.to_str().unwrap()]);
+    assert!(report.contains("| Panel |"), "{}", report);
+    assert!(report.contains("PP2-B07"), "{}", report);
+    let snapshot: serde_json::Value = serde_json::from_str(&snapshot).unwrap();
+    let panels: Vec<serde_json::Value> = snapshot["ports"].as_array().unwrap().iter()
+        .map(|port| port["panel"].clone())
+        .collect();
+    assert_eq!(serde_json::Value::from(panels),
+        serde_json::json!(["PP2-B07", "PP2-B08", "PP2-B09", "PP2-B10", null, null, null, "spare"]));
+}
@@ -0,0 +1,109 @@
+//! Snapshots validate against the schema printed by --print-schema, and the
+//! schema only changes on purpose
+use std::fs;
+use std::path::{Path, PathBuf};
+use regex::Regex;
+use serde_json::Value;
+use switch_vlan_diagram::snapshot::Snapshot;
+
+fn fixture_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("fixtures").join(name)
+}
+
+/// Checks the keywords the generated schema uses; annotations such as
+/// "format" and "description" are ignored. Returns the first violation.
+fn validate(schema: &Value, value: &Value, root: &Value, path: &str) -> Result<(), String> {
+    if let Some(reference) = schema["$ref"].as_str() {
+        let name = reference.strip_prefix("#/$defs/").ok_or_else(|| format!("unsupported $ref {}", reference))?;
+        return validate(&root["$defs"][name], value, root, path);
+    }
+    if let Some(options) = schema["anyOf"].as_array() {
+        if !options.iter().any(|option| validate(option, value, root, path).is_ok()) {
+            return Err(format!("{}: matches none of anyOf", path));
+        }
+    }
+    let types: Vec<&str> = match &schema["type"] {
+        Value::String(name) => vec![name],
+        Value::Array(names) => names.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    };
+    let type_matches = |name: &str| match name {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "integer" => value.is_u64() || value.is_i64(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        _ => false,
+    };
+    if !types.is_empty() && !types.iter().any(|name| type_matches(name)) {
+        return Err(format!("{}: {} is not of type {:?}", path, value, types));
+    }
+    if let (Some(minimum), Some(number)) = (schema["minimum"].as_i64(), value.as_i64()) {
+        if number < minimum {
+            return Err(format!("{}: {} is below {}", path, number, minimum));
+        }
+    }
+    if let Some(items) = value.as_array() {
+        for (index, item) in items.iter().enumerate() {
+            validate(&schema["items"], item, root, &format!("{}[{}]", path, index))?;
+        }
+    }
+    if let Some(object) = value.as_object() {
+        for required in schema["required"].as_array().into_iter().flatten().filter_map(Value::as_str) {
+            if !object.contains_key(required) {
+                return Err(format!("{}: {} is missing", path, required));
+            }
+        }
+        for (key, member) in object {
+            let member_path = format!("{}.{}", path, key);
+            if let Some(property) = schema["properties"].get(key) {
+                validate(property, member, root, &member_path)?;
+                continue;
+            }
+            let pattern = schema["patternProperties"].as_object().into_iter().flatten()
+                .find(|(pattern, _)| Regex::new(pattern).unwrap().is_match(key));
+            match (pattern, &schema["additionalProperties"]) {
+                (Some((_, property)), _) => validate(property, member, root, &member_path)?,
+                (None, Value::Bool(false)) => return Err(format!("{}: unexpected property", member_path)),
+                (None, Value::Object(_)) => validate(&schema["additionalProperties"], member, root, &member_path)?,
+                (None, _) => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+fn schema() -> Value {
+    serde_json::from_str(&Snapshot::json_schema()).unwrap()
+}
+
+#[test]
+fn schema_changes_are_deliberate() {
+    let golden = fs::read_to_string(fixture_path("snapshot.schema.json")).unwrap();
+    assert!(Snapshot::json_schema() == golden.trim_end(),
+        "The snapshot layout changed. If that was intended, bump SNAPSHOT_VERSION for a field that \
+         changed meaning or went away, and write the new schema to tests/fixtures/snapshot.schema.json \
+         with --print-schema");
+}
+
+#[test]
+fn snapshots_validate_against_the_schema() {
+    let schema = schema();
+    for fixture in ["procurve-2530-lacp.json", "cisco-2960-bridge-ports.json", "snapshot-before.json", "snapshot-after.json"] {
+        let snapshot: Value = serde_json::from_str(&fs::read_to_string(fixture_path(fixture)).unwrap()).unwrap();
+        assert_eq!(validate(&schema, &snapshot, &schema, fixture), Ok(()));
+    }
+}
+
+#[test]
+fn the_schema_rejects_a_malformed_snapshot() {
+    let schema = schema();
+    let mut snapshot: Value = serde_json::from_str(&fs::read_to_string(fixture_path("procurve-2530-lacp.json")).unwrap()).unwrap();
+    snapshot["ports"][0]["port"] = "1".into();
+    assert_eq!(validate(&schema, &snapshot, &schema, "snapshot"),
+        Err("snapshot.ports[0].port: \"1\" is not of type [\"integer\"]".to_string()));
+    snapshot["ports"][0]["port"] = 1.into();
+    snapshot["vlan_names"]["default"] = "x".into();
+    assert!(validate(&schema, &snapshot, &schema, "snapshot").is_err());
+}
@@ -0,0 +1,98 @@
+//! --trap-listen against the simulated agent, with traps sent from the test
+mod common;
+
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::net::UdpSocket;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Duration;
+use serde_json::Value;
+use snmp::snmp::{MSG_INFORM, MSG_TRAP};
+use snmp::{SnmpMessageType, SnmpPdu};
+use common::{encode_notification, SimulatedAgent, COMMUNITY};
+
+const LINK_DOWN: [u32; 10] = [1, 3, 6, 1, 6, 3, 1, 1, 5, 3];
+const COLD_START: [u32; 10] = [1, 3, 6, 1, 6, 3, 1, 1, 5, 1];
+
+/// The listener, killed when dropped, and its stderr lines as JSON
+struct Listener {
+    child: Child,
+    events: Receiver<Value>,
+}
+
+impl Listener {
+    fn start(agent: &SimulatedAgent, output: &str) -> Listener {
+        let scratch = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("trap-listener");
+        fs::create_dir_all(&scratch).unwrap();
+        let mut child = Command::new(env!("CARGO_BIN_EXE_switch-vlan-diagram"))
+            .args(["--ip", "127.0.0.1", "--port", &agent.port.to_string(), "--community", COMMUNITY, "--timeout", "1"])
+            .args(["--quiet", "--log-format", "json", "--output", output])
+            .args(["--trap-listen", "127.0.0.1:0", "--trap-debounce", "0"])
+            .env("XDG_CONFIG_HOME", &scratch)
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let stderr = BufReader::new(child.stderr.take().unwrap());
+        let (sender, events) = mpsc::channel();
+        thread::spawn(move || {
+            for line in stderr.lines().map_while(Result::ok) {
+                let event = serde_json::from_str(&line).unwrap_or_else(|_| panic!("not JSON: {}", line));
+                if sender.send(event).is_err() {
+                    break;
+                }
+            }
+        });
+        Listener { child, events }
+    }
+
+    /// The next event of `kind`, skipping others
+    fn next(&self, kind: &str) -> Value {
+        loop {
+            let event = self.events.recv_timeout(Duration::from_secs(10))
+                .unwrap_or_else(|_| panic!("no {} event", kind));
+            if event["kind"] == kind {
+                return event;
+            }
+        }
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[test]
+fn traps_regenerate_the_switch_and_bad_ones_are_dropped() {
+    let agent = SimulatedAgent::start("procurve-2530-lacp.walk");
+    let output = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("trap-listener").join("report.md");
+    let _ = fs::remove_file(&output);
+    let listener = Listener::start(&agent, output.to_str().unwrap());
+
+    let address = listener.next("trap-listening")["address"].as_str().unwrap().to_string();
+    assert_eq!(listener.next("trap-cycle")["message"], "written");
+    assert!(fs::read_to_string(&output).unwrap().contains("| Port"));
+
+    let switch = UdpSocket::bind("127.0.0.1:0").unwrap();
+    switch.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+    switch.send_to(b"\x30\x03\x02\x01", &address).unwrap();
+    assert!(listener.next("trap-rejected")["reason"].as_str().unwrap().starts_with("malformed message"));
+    switch.send_to(&encode_notification(MSG_TRAP, "private", 1, &LINK_DOWN), &address).unwrap();
+    assert_eq!(listener.next("trap-rejected")["reason"], "wrong community");
+    // Neither regenerates, and a cold start is about nothing the report shows
+    switch.send_to(&encode_notification(MSG_TRAP, COMMUNITY, 2, &COLD_START), &address).unwrap();
+
+    switch.send_to(&encode_notification(MSG_INFORM, COMMUNITY, 3, &LINK_DOWN), &address).unwrap();
+    let mut response = [0u8; 1500];
+    let len = switch.recv(&mut response).unwrap();
+    let response = SnmpPdu::from_bytes(&response[..len]).unwrap();
+    assert_eq!((response.message_type, response.req_id), (SnmpMessageType::Response, 3));
+
+    let cycle = listener.next("trap-cycle");
+    assert_eq!((&cycle["message"], &cycle["host"]), (&"unchanged after linkDown".into(), &"127.0.0.1".into()));
+}